@@ -5,3 +5,10 @@ cd "$(dirname $0)"
 
 cargo build --all --target wasm32-unknown-unknown --release
 cp $TARGET/wasm32-unknown-unknown/release/ukrainian_magicals_nft.wasm ./res/
+cp $TARGET/wasm32-unknown-unknown/release/receiver_example.wasm ./res/
+
+# faucet methods of `--features testnet` builds must never reach the mainnet wasm
+if grep -qa nft_mint_test ./res/ukrainian_magicals_nft.wasm; then
+  echo "res/ukrainian_magicals_nft.wasm exports testnet methods, build it without --features testnet" >&2
+  exit 1
+fi
@@ -0,0 +1,51 @@
+/*!
+Minting many tokens to many accounts in one call.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::events;
+use crate::{Contract, ContractExt};
+
+/// Bounds gas used by a single airdrop
+const MAX_AIRDROP_TOKENS: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Airdrop {
+    pub token_id: TokenId,
+    pub receiver_id: AccountId,
+    pub token_metadata: TokenMetadata,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mint a token to every receiver. Mint events are grouped per receiver.
+    /// The runtime caps the total length of logs, so very long token IDs need smaller batches
+    #[payable]
+    pub fn nft_airdrop(&mut self, drops: Vec<Airdrop>) {
+        self.assert_owner();
+        require!(drops.len() <= MAX_AIRDROP_TOKENS, "Too many tokens");
+        let initial_storage = env::storage_usage();
+        let mut minted = Vec::with_capacity(drops.len());
+        for drop in drops {
+            self.internal_mint_with_royalties(
+                drop.token_id.clone(),
+                drop.receiver_id.clone(),
+                drop.token_metadata,
+                None,
+            );
+            minted.push((drop.receiver_id, drop.token_id));
+        }
+        refund_deposit_to_account(
+            env::storage_usage() - initial_storage,
+            env::predecessor_account_id(),
+        );
+        for (_, token_id) in &minted {
+            self.internal_audit("mint", Some(token_id), None);
+        }
+        events::emit_mint_batch(&minted);
+    }
+}
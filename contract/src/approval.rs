@@ -0,0 +1,472 @@
+/*!
+Approval management. Storage released by revoking approvals is refunded to the token owner.
+
+An approval may carry conditions (minimum price, expiry, number of uses) checked whenever
+the approved account transfers the token.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::approval::{
+    ext_nft_approval_receiver, NonFungibleTokenApproval,
+};
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Gas, Promise, StorageUsage,
+};
+
+use crate::events::{
+    self, DefaultMarketplaceUpdateLog, NftApproveLog, NftRevokeAllLog, NftRevokeLog,
+};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+/// Bounds gas used by a single batch approval
+const MAX_BATCH_APPROVE_TOKENS: usize = 50;
+/// Gas kept by an approval besides the `nft_on_approve` call
+const GAS_FOR_NFT_APPROVE: Gas = Gas(10_000_000_000_000);
+/// Gas attached to `nft_on_approve`. Paras stores the listing in it, checking the seller's
+/// storage deposit, which takes about 10 TGas, the rest is margin
+const GAS_FOR_NFT_ON_APPROVE: Gas = Gas(30_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ApprovalConditions {
+    /// Lowest price in yoctoNEAR the approved account may transfer the token for
+    pub min_price: Option<U128>,
+    /// Block timestamp in seconds the approval is valid until
+    pub expires_at: Option<u64>,
+    /// Number of transfers left, a used up approval isn't restored by a reverted transfer
+    pub max_uses: Option<u32>,
+}
+
+impl ApprovalConditions {
+    fn is_expired(&self) -> bool {
+        let now = env::block_timestamp() / 1_000_000_000;
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+}
+
+impl Contract {
+    /// Check the conditions of `account_id` approval for a transfer at `price` and count the use
+    pub(crate) fn internal_use_approval_conditions(
+        &mut self,
+        token_id: &TokenId,
+        account_id: &AccountId,
+        price: Option<Balance>,
+    ) {
+        let key = (token_id.clone(), account_id.clone());
+        let mut conditions = match self.conditional_approvals.get(&key) {
+            Some(conditions) => conditions,
+            None => return,
+        };
+        require!(!conditions.is_expired(), "Approval expired");
+        if let Some(min_price) = conditions.min_price {
+            require!(
+                matches!(price, Some(price) if price >= min_price.0),
+                format!("Approval requires a price of at least {}", min_price.0)
+            );
+        }
+        if let Some(max_uses) = conditions.max_uses {
+            require!(max_uses > 0, "Approval is used up");
+            conditions.max_uses = Some(max_uses - 1);
+            self.conditional_approvals.insert(&key, &conditions);
+        }
+    }
+
+    /// Drop approvals used up by a transfer from `approved_account_ids` of `token_id`
+    pub(crate) fn internal_retain_usable_approvals(
+        &self,
+        token_id: &TokenId,
+        approved_account_ids: &mut HashMap<AccountId, u64>,
+    ) {
+        approved_account_ids.retain(|account_id, _| {
+            self.conditional_approvals
+                .get(&(token_id.clone(), account_id.clone()))
+                .and_then(|conditions| conditions.max_uses)
+                != Some(0)
+        });
+    }
+
+    /// Remove conditions of the given approvals of `token_id`, returns the released storage
+    pub(crate) fn internal_remove_approval_conditions<'a>(
+        &mut self,
+        token_id: &TokenId,
+        account_ids: impl IntoIterator<Item = &'a AccountId>,
+    ) -> StorageUsage {
+        let initial_storage = env::storage_usage();
+        for account_id in account_ids {
+            self.conditional_approvals
+                .remove(&(token_id.clone(), account_id.clone()));
+        }
+        initial_storage - env::storage_usage()
+    }
+
+    /// Approve `account_id` for `token_id` without checks, deposit and `nft_on_approve` call.
+    /// Returns the approval ID
+    pub(crate) fn internal_approve(&mut self, token_id: &TokenId, account_id: &AccountId) -> u64 {
+        let approvals_by_id = self
+            .tokens
+            .approvals_by_id
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"));
+        let next_approval_id_by_id = self
+            .tokens
+            .next_approval_id_by_id
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"));
+        let mut approved_account_ids = approvals_by_id.get(token_id).unwrap_or_default();
+        let approval_id = next_approval_id_by_id.get(token_id).unwrap_or(1u64);
+        approved_account_ids.insert(account_id.clone(), approval_id);
+        approvals_by_id.insert(token_id, &approved_account_ids);
+        next_approval_id_by_id.insert(token_id, &(approval_id + 1));
+        approval_id
+    }
+
+    /// Revoke the approval of `account_id` for `token_id` owned by `owner_id`, see `nft_revoke`.
+    /// The released storage is refunded to the owner in a single transfer
+    pub(crate) fn internal_revoke(
+        &mut self,
+        token_id: &TokenId,
+        account_id: &AccountId,
+        owner_id: AccountId,
+    ) {
+        let initial_storage = env::storage_usage();
+        self.internal_remove_approval_conditions(token_id, std::iter::once(account_id));
+        let approvals_by_id = self
+            .tokens
+            .approvals_by_id
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"));
+        if let Some(mut approved_account_ids) = approvals_by_id.get(token_id) {
+            if approved_account_ids.remove(account_id).is_some() {
+                if approved_account_ids.is_empty() {
+                    approvals_by_id.remove(token_id);
+                } else {
+                    approvals_by_id.insert(token_id, &approved_account_ids);
+                }
+            }
+        }
+        refund_released_storage(initial_storage - env::storage_usage(), owner_id.clone());
+        self.internal_audit("revoke", Some(token_id), None);
+        events::emit(vec![NftRevokeLog {
+            token_id: token_id.clone(),
+            owner_id,
+            account_id: account_id.clone(),
+        }]);
+    }
+
+    /// Call `nft_on_approve` of `account_id` approved for `token_id` with `msg`
+    fn internal_on_approve(
+        token_id: TokenId,
+        owner_id: AccountId,
+        account_id: AccountId,
+        approval_id: u64,
+        msg: String,
+    ) -> Promise {
+        require!(
+            env::prepaid_gas() >= GAS_FOR_NFT_APPROVE + GAS_FOR_NFT_ON_APPROVE,
+            "Not enough gas attached for nft_on_approve"
+        );
+        ext_nft_approval_receiver::ext(account_id)
+            .with_static_gas(GAS_FOR_NFT_ON_APPROVE)
+            .nft_on_approve(token_id, owner_id, approval_id, msg)
+    }
+
+    /// Approve the default marketplace, if configured, for the freshly minted `token_id`.
+    /// Returns the marketplace along with its approval ID
+    pub(crate) fn internal_approve_default_marketplace(
+        &mut self,
+        token_id: &TokenId,
+    ) -> Option<(AccountId, u64)> {
+        let marketplace_id = self.default_marketplace.clone()?;
+        let approval_id = self.internal_approve(token_id, &marketplace_id);
+        Some((marketplace_id, approval_id))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the marketplace approved for every newly minted token, `None` disables it.
+    /// The approval storage is charged to the minter
+    pub fn set_default_marketplace(&mut self, default_marketplace: Option<AccountId>) {
+        self.assert_owner();
+        self.default_marketplace = default_marketplace.clone();
+        self.internal_audit("set_default_marketplace", None, None);
+        events::emit(vec![DefaultMarketplaceUpdateLog {
+            default_marketplace,
+        }]);
+    }
+
+    pub fn default_marketplace(&self) -> Option<AccountId> {
+        self.default_marketplace.clone()
+    }
+
+    /// Approve `approved_account_id` to transfer `token_id` only while `conditions` are met.
+    /// Replaces an existing approval of the account, storage is paid by the owner
+    #[payable]
+    pub fn nft_set_approval_with_conditions(
+        &mut self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        conditions: ApprovalConditions,
+    ) -> u64 {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+        require!(conditions.max_uses != Some(0), "Max uses must be positive");
+        require!(!conditions.is_expired(), "Approval expired");
+
+        let initial_storage = env::storage_usage();
+        let approval_id = self.internal_approve(&token_id, &approved_account_id);
+        self.conditional_approvals.insert(
+            &(token_id.clone(), approved_account_id.clone()),
+            &conditions,
+        );
+        refund_deposit_to_account(
+            env::storage_usage().saturating_sub(initial_storage),
+            owner_id.clone(),
+        );
+        self.internal_audit("approve", Some(&token_id), None);
+        events::emit(vec![NftApproveLog {
+            token_id,
+            owner_id,
+            approved_account_id,
+            approval_id: approval_id.into(),
+            conditions: Some(conditions),
+        }]);
+        approval_id
+    }
+
+    /// Approve `account_id` for each of `token_ids` owned by the caller without calling
+    /// `nft_on_approve`. Returns the approval IDs, storage of all approvals is paid at once
+    #[payable]
+    pub fn nft_batch_approve(
+        &mut self,
+        token_ids: Vec<TokenId>,
+        account_id: AccountId,
+    ) -> Vec<u64> {
+        require!(
+            token_ids.len() <= MAX_BATCH_APPROVE_TOKENS,
+            "Too many tokens"
+        );
+        let owner_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        let mut approvals = Vec::with_capacity(token_ids.len());
+        for token_id in token_ids {
+            let token_owner_id = self
+                .tokens
+                .owner_by_id
+                .get(&token_id)
+                .unwrap_or_else(|| env::panic_str("Token not found"));
+            require!(
+                token_owner_id == owner_id,
+                "Predecessor must be token owner."
+            );
+            // a plain approval drops the conditions of a previous one
+            self.internal_remove_approval_conditions(&token_id, std::iter::once(&account_id));
+            let approval_id = self.internal_approve(&token_id, &account_id);
+            approvals.push(NftApproveLog {
+                token_id,
+                owner_id: owner_id.clone(),
+                approved_account_id: account_id.clone(),
+                approval_id: approval_id.into(),
+                conditions: None,
+            });
+        }
+        refund_deposit_to_account(
+            env::storage_usage().saturating_sub(initial_storage),
+            owner_id,
+        );
+        for approval in &approvals {
+            self.internal_audit("approve", Some(&approval.token_id), None);
+        }
+
+        let approval_ids = approvals.iter().map(|log| log.approval_id.0).collect();
+        events::emit(approvals);
+        approval_ids
+    }
+
+    /// `nft_approve` refunding the excess deposit and any released storage to `refund_to`,
+    /// the caller if `None`, e.g. a user whose approval is relayed
+    #[payable]
+    pub fn nft_approve_with_refund(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        msg: Option<String>,
+        refund_to: Option<AccountId>,
+    ) -> Option<Promise> {
+        require!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+        let refund_to = refund_to.unwrap_or_else(env::predecessor_account_id);
+        if let Some(msg) = &msg {
+            self.assert_approve_msg(&account_id, msg);
+        }
+
+        // a plain approval drops the conditions of a previous one, they were paid by the owner
+        let storage_released =
+            self.internal_remove_approval_conditions(&token_id, std::iter::once(&account_id));
+        refund_released_storage(storage_released, owner_id.clone());
+        let initial_storage = env::storage_usage();
+        let approval_id = self.internal_approve(&token_id, &account_id);
+        refund_deposit_to_account(
+            env::storage_usage().saturating_sub(initial_storage),
+            refund_to,
+        );
+        self.internal_audit("approve", Some(&token_id), None);
+        events::emit(vec![NftApproveLog {
+            owner_id: owner_id.clone(),
+            token_id: token_id.clone(),
+            approved_account_id: account_id.clone(),
+            approval_id: approval_id.into(),
+            conditions: None,
+        }]);
+        msg.map(|msg| Self::internal_on_approve(token_id, owner_id, account_id, approval_id, msg))
+    }
+
+    /// Conditions of the `approved_account_id` approval of `token_id`
+    pub fn nft_approval_conditions(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+    ) -> Option<ApprovalConditions> {
+        self.conditional_approvals
+            .get(&(token_id, approved_account_id))
+    }
+
+    /// Tokens of `owner` that `operator` is approved to transfer. Pages go through the owner's
+    /// tokens as `nft_token_ids_for_owner` does, reading the approvals of every token in the page,
+    /// so a page may hold fewer than `limit` tokens and gas grows with `limit` rather than
+    /// with the tokens returned
+    pub fn nft_approved_tokens_for(
+        &self,
+        owner: AccountId,
+        operator: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenId> {
+        self.nft_token_ids_for_owner(owner, from_index, limit)
+            .into_iter()
+            .filter(|token_id| self.nft_is_approved(token_id.clone(), operator.clone(), None))
+            .collect()
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenApproval for Contract {
+    #[payable]
+    fn nft_approve(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        msg: Option<String>,
+    ) -> Option<Promise> {
+        if let Some(msg) = &msg {
+            self.assert_approve_msg(&account_id, msg);
+        }
+        let owner_id = self.assert_token_owner(&token_id);
+        // a plain approval drops the conditions of a previous one, they were paid by the owner
+        let storage_released =
+            self.internal_remove_approval_conditions(&token_id, std::iter::once(&account_id));
+        refund_released_storage(storage_released, owner_id);
+        // `nft_on_approve` is called below with gas enough for marketplaces
+        self.tokens
+            .nft_approve(token_id.clone(), account_id.clone(), None);
+        self.internal_audit("approve", Some(&token_id), None);
+        let approval_id = self
+            .tokens
+            .approvals_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+            .and_then(|approved_account_ids| approved_account_ids.get(&account_id).copied())
+            .unwrap_or_else(|| env::panic_str("Approval not found"));
+        events::emit(vec![NftApproveLog {
+            owner_id: env::predecessor_account_id(),
+            token_id: token_id.clone(),
+            approved_account_id: account_id.clone(),
+            approval_id: approval_id.into(),
+            conditions: None,
+        }]);
+        msg.map(|msg| {
+            Self::internal_on_approve(
+                token_id,
+                env::predecessor_account_id(),
+                account_id,
+                approval_id,
+                msg,
+            )
+        })
+    }
+
+    #[payable]
+    fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
+        assert_one_yocto();
+        let owner_id = self.assert_token_owner(&token_id);
+        self.internal_revoke(&token_id, &account_id, owner_id);
+    }
+
+    /// Revoke all approvals of the token, the released storage is refunded to the token owner
+    #[payable]
+    fn nft_revoke_all(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+
+        let initial_storage = env::storage_usage();
+        let approved_account_ids = self
+            .tokens
+            .approvals_by_id
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"))
+            .remove(&token_id);
+        if let Some(approved_account_ids) = approved_account_ids {
+            self.internal_remove_approval_conditions(&token_id, approved_account_ids.keys());
+        }
+        refund_released_storage(initial_storage - env::storage_usage(), owner_id.clone());
+        self.internal_audit("revoke_all", Some(&token_id), None);
+        events::emit(vec![NftRevokeAllLog { token_id, owner_id }]);
+    }
+
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    ) -> bool {
+        let expired = matches!(
+            self.conditional_approvals.get(&(token_id.clone(), approved_account_id.clone())),
+            Some(conditions) if conditions.is_expired()
+        );
+        !expired
+            && self
+                .tokens
+                .nft_is_approved(token_id, approved_account_id, approval_id)
+    }
+}
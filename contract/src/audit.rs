@@ -0,0 +1,162 @@
+/*!
+Per-token activity tracking, a joined view of tokens for accounting and an append-only log
+of every state change for compliance.
+
+Audit entries are appended after the storage used by a call is charged, so they don't change
+what callers attach. Their storage is paid from a pool of NEAR the owner tops up, entries the
+pool can't cover are paid by the contract. Entries are never overwritten or removed.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, StorageUsage};
+
+use crate::events::{self, AuditStoragePoolTopUpLog};
+use crate::sale::Sale;
+use crate::{Contract, ContractExt};
+
+/// Bounds gas used by a single page of the audit views
+const MAX_AUDIT_PAGE_LEN: u64 = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenActivity {
+    /// Mint time in seconds
+    pub minted_at: u64,
+    pub transfer_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenAudit {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub royalties: HashMap<AccountId, u16>,
+    pub sale: Option<Sale>,
+    pub transfer_count: u64,
+    /// Mint time in seconds, unknown for tokens minted before activity was tracked
+    pub minted_at: Option<u64>,
+}
+
+/// A state change made by `actor`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuditEntry {
+    pub action: String,
+    pub actor: AccountId,
+    pub token_id: Option<TokenId>,
+    pub amount: Option<U128>,
+    /// Block time in seconds
+    pub timestamp: u64,
+}
+
+impl Contract {
+    /// Append an `action` made by the predecessor to the audit log, its storage is paid
+    /// from the audit storage pool
+    pub(crate) fn internal_audit(
+        &mut self,
+        action: &str,
+        token_id: Option<&TokenId>,
+        amount: Option<Balance>,
+    ) {
+        let initial_storage = env::storage_usage();
+        self.audit_log.push(&AuditEntry {
+            action: action.to_string(),
+            actor: env::predecessor_account_id(),
+            token_id: token_id.cloned(),
+            amount: amount.map(U128),
+            timestamp: env::block_timestamp() / 1_000_000_000,
+        });
+        let storage_cost = Balance::from(env::storage_usage().saturating_sub(initial_storage))
+            * env::storage_byte_cost();
+        self.audit_storage_pool = self.audit_storage_pool.saturating_sub(storage_cost);
+    }
+
+    pub(crate) fn internal_record_mint(&mut self, token_id: &TokenId) {
+        self.token_activity.insert(
+            token_id,
+            &TokenActivity {
+                minted_at: env::block_timestamp() / 1_000_000_000,
+                transfer_count: 0,
+            },
+        );
+        self.internal_record_owner(token_id);
+    }
+
+    /// Returns the storage taken by the provenance record of the new owner
+    pub(crate) fn internal_record_transfer(&mut self, token_id: &TokenId) -> StorageUsage {
+        if let Some(mut activity) = self.token_activity.get(token_id) {
+            activity.transfer_count += 1;
+            self.token_activity.insert(token_id, &activity);
+        }
+        self.internal_record_owner(token_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn token_activity(&self, token_id: TokenId) -> Option<TokenActivity> {
+        self.token_activity.get(&token_id)
+    }
+
+    /// Tokens in enumeration order joined with their royalties, listing and activity.
+    /// At most 50 tokens are returned per call
+    pub fn audit_bundle(&self, from_index: U128, limit: u64) -> Vec<TokenAudit> {
+        let start = u128::from(from_index);
+        require!(
+            (self.tokens.owner_by_id.len() as u128) >= start,
+            "Out of bounds, please use a smaller from_index."
+        );
+        require!(limit != 0, "Cannot provide limit of 0.");
+        self.tokens
+            .owner_by_id
+            .iter()
+            .skip(start as usize)
+            .take(limit.min(MAX_AUDIT_PAGE_LEN) as usize)
+            .map(|(token_id, owner_id)| {
+                let activity = self.token_activity.get(&token_id);
+                TokenAudit {
+                    royalties: self.internal_token_extras(&token_id).royalties,
+                    sale: self.sales.get(&token_id),
+                    transfer_count: activity.as_ref().map_or(0, |a| a.transfer_count),
+                    minted_at: activity.map(|a| a.minted_at),
+                    token_id,
+                    owner_id,
+                }
+            })
+            .collect()
+    }
+
+    /// Entries of the audit log from the oldest, at most 50 per call
+    pub fn nft_audit_log(&self, from_index: Option<U64>, limit: Option<u64>) -> Vec<AuditEntry> {
+        let start = from_index.map(u64::from).unwrap_or_default();
+        let limit = limit.unwrap_or(MAX_AUDIT_PAGE_LEN);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        (start..self.audit_log.len())
+            .take(limit.min(MAX_AUDIT_PAGE_LEN) as usize)
+            .filter_map(|index| self.audit_log.get(index))
+            .collect()
+    }
+
+    /// Add the attached deposit to the pool paying for the storage of audit entries
+    #[payable]
+    pub fn audit_storage_pool_top_up(&mut self) {
+        self.assert_owner();
+        let amount = env::attached_deposit();
+        require!(amount > 0, "Requires attached deposit");
+        self.audit_storage_pool += amount;
+        self.internal_audit("audit_storage_pool_top_up", None, Some(amount));
+        events::emit(vec![AuditStoragePoolTopUpLog {
+            amount: U128(amount),
+            pool: U128(self.audit_storage_pool),
+        }]);
+    }
+
+    /// yoctoNEAR left to pay for the storage of audit entries
+    pub fn audit_storage_pool(&self) -> U128 {
+        U128(self.audit_storage_pool)
+    }
+}
@@ -0,0 +1,123 @@
+/*!
+Bridge custody for tokens wrapped on another chain, e.g. Aurora, by a partner bridge.
+
+The owner locks a token for a destination address on the other chain: the token is transferred
+to the contract and the relayer of the bridge picks up the `bridge_lock` event. Only the bridge
+account releases it, to whoever the token was unwrapped by. A locked token is owned by the
+contract, so it leaves the holder's enumeration and can't be moved by anyone but the bridge.
+
+Lock records are paid by the contract as the calls carry one yoctoNEAR, `destination` is bounded.
+*/
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::{self, BridgeAccountUpdateLog, BridgeLockLog, BridgeUnlockLog};
+use crate::{Contract, ContractExt};
+
+/// Longest destination address in bytes
+const MAX_DESTINATION_LEN: usize = 128;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeLock {
+    /// Owner the token was locked by
+    pub owner_id: AccountId,
+    /// Address on the other chain
+    pub destination: String,
+}
+
+impl Contract {
+    pub(crate) fn is_bridged(&self, token_id: &TokenId) -> bool {
+        self.bridge_locks.get(token_id).is_some()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Account allowed to release bridged tokens, `None` disables locking new ones
+    pub fn set_bridge_account(&mut self, bridge_account_id: Option<AccountId>) {
+        self.assert_owner();
+        self.bridge_account_id = bridge_account_id.clone();
+        self.internal_audit("set_bridge_account", None, None);
+        events::emit(vec![BridgeAccountUpdateLog { bridge_account_id }]);
+    }
+
+    pub fn bridge_account(&self) -> Option<AccountId> {
+        self.bridge_account_id.clone()
+    }
+
+    /// Move the caller's `token_id` into custody of the contract to be wrapped
+    /// for `destination` on the other chain
+    #[payable]
+    pub fn bridge_lock(&mut self, token_id: TokenId, destination: String) {
+        assert_one_yocto();
+        require!(self.bridge_account_id.is_some(), "Bridge is not configured");
+        let owner_id = self.assert_token_owner(&token_id);
+        require!(!destination.is_empty(), "Destination is empty");
+        require!(
+            destination.len() <= MAX_DESTINATION_LEN,
+            "Destination is too long"
+        );
+
+        let (previous_owner_id, approved_account_ids) = self.internal_transfer(
+            &owner_id,
+            &env::current_account_id(),
+            &token_id,
+            None,
+            None,
+            None,
+        );
+        self.internal_refund_cleared_approvals(&token_id, approved_account_ids, previous_owner_id);
+        let lock = BridgeLock {
+            owner_id,
+            destination,
+        };
+        self.bridge_locks.insert(&token_id, &lock);
+        self.internal_audit("bridge_lock", Some(&token_id), None);
+        events::emit(vec![BridgeLockLog {
+            token_id,
+            owner_id: lock.owner_id,
+            destination: lock.destination,
+        }]);
+    }
+
+    /// Release the bridged `token_id` to `to`, callable by the bridge account only
+    #[payable]
+    pub fn bridge_unlock(&mut self, token_id: TokenId, to: AccountId) {
+        assert_one_yocto();
+        require!(
+            self.bridge_account_id.as_ref() == Some(&env::predecessor_account_id()),
+            "Predecessor must be the bridge"
+        );
+        let lock = self
+            .bridge_locks
+            .remove(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token is not bridged"));
+        self.internal_transfer(&env::current_account_id(), &to, &token_id, None, None, None);
+        self.internal_audit("bridge_unlock", Some(&token_id), None);
+        events::emit(vec![BridgeUnlockLog {
+            token_id,
+            owner_id: lock.owner_id,
+            receiver_id: to,
+        }]);
+    }
+
+    /// Tokens in custody of the bridge along with who locked them and where to
+    pub fn bridged_tokens(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<(TokenId, BridgeLock)> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        self.bridge_locks
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+}
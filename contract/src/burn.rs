@@ -0,0 +1,131 @@
+/*!
+Burning tokens. Every record kept for a burned token is removed, including its approvals,
+and the released storage is refunded to the token owner.
+
+The owner may configure a null account: the token owner's `nft_transfer` to it burns the token
+instead, for flows using a transfer to a null account as the burn signal. No other transfer or
+mint reaches the null account, so it never holds tokens.
+*/
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::{self, NullAccountUpdateLog};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+impl Contract {
+    /// Remove `token_id` owned by `owner_id` along with its metadata, enumeration, approval,
+    /// royalty, collaborator, activity, reveal, resale cap, soulbound, series, pending FT payout
+    /// and tag records
+    pub(crate) fn internal_burn(&mut self, token_id: &TokenId, owner_id: &AccountId) {
+        self.tokens.owner_by_id.remove(token_id);
+        if let Some(token_metadata_by_id) = self.tokens.token_metadata_by_id.as_mut() {
+            token_metadata_by_id.remove(token_id);
+        }
+        if let Some(tokens_per_owner) = self.tokens.tokens_per_owner.as_mut() {
+            if let Some(mut token_ids) = tokens_per_owner.get(owner_id) {
+                token_ids.remove(token_id);
+                if token_ids.is_empty() {
+                    tokens_per_owner.remove(owner_id);
+                } else {
+                    tokens_per_owner.insert(owner_id, &token_ids);
+                }
+            }
+        }
+
+        if let Some(approved_account_ids) = self
+            .tokens
+            .approvals_by_id
+            .as_mut()
+            .and_then(|by_id| by_id.remove(token_id))
+        {
+            self.internal_remove_approval_conditions(token_id, approved_account_ids.keys());
+        }
+        if let Some(next_approval_id_by_id) = self.tokens.next_approval_id_by_id.as_mut() {
+            next_approval_id_by_id.remove(token_id);
+        }
+
+        let extras = self.internal_remove_token_extras(token_id);
+        for account_id in extras.royalties.keys() {
+            if let Some(mut token_ids) = self.royalty_tokens_per_account.get(account_id) {
+                token_ids.remove(token_id);
+                if token_ids.is_empty() {
+                    self.royalty_tokens_per_account.remove(account_id);
+                } else {
+                    self.royalty_tokens_per_account
+                        .insert(account_id, &token_ids);
+                }
+            }
+        }
+        self.token_activity.remove(token_id);
+        self.revealed_tokens.remove(token_id);
+        self.max_resale_prices.remove(token_id);
+        self.soulbound_tokens.remove(token_id);
+        self.token_series.remove(token_id);
+        self.pending_ft_payouts.remove(token_id);
+        self.internal_clear_provenance(token_id);
+        self.internal_remove_tags(token_id);
+    }
+
+    /// Burn `token_id` owned by the caller, see `nft_burn`
+    pub(crate) fn internal_burn_by_owner(&mut self, token_id: TokenId, memo: Option<String>) {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+        if let Some(reason) = self.internal_transfer_lock(&token_id) {
+            env::panic_str(&reason);
+        }
+        require!(!self.has_children(&token_id), "Token has attached children");
+
+        self.internal_remove_sale(&token_id);
+        self.internal_remove_lease(&token_id);
+        self.internal_remove_event_drop(&token_id);
+        let initial_storage = env::storage_usage();
+        self.internal_burn(&token_id, &owner_id);
+        refund_released_storage(initial_storage - env::storage_usage(), owner_id.clone());
+        self.internal_audit("burn", Some(&token_id), None);
+        events::emit_burn(&owner_id, std::slice::from_ref(&token_id), memo.as_deref());
+    }
+
+    pub(crate) fn is_null_account(&self, account_id: &AccountId) -> bool {
+        self.null_account.as_ref() == Some(account_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Burn `token_id` owned by the caller. A listing or drop record of the token is removed
+    /// and all storage released is refunded to the caller
+    #[payable]
+    pub fn nft_burn(&mut self, token_id: TokenId, memo: Option<String>) {
+        assert_one_yocto();
+        self.internal_burn_by_owner(token_id, memo);
+    }
+
+    /// Account the token owner's transfers to burn the token, `None` disables it.
+    /// The account must hold no tokens
+    pub fn set_null_account(&mut self, null_account: Option<AccountId>) {
+        self.assert_owner();
+        if let (Some(account_id), Some(tokens_per_owner)) =
+            (&null_account, &self.tokens.tokens_per_owner)
+        {
+            require!(
+                !tokens_per_owner.contains_key(account_id),
+                "Null account holds tokens"
+            );
+        }
+        self.null_account = null_account.clone();
+        self.internal_audit("set_null_account", None, None);
+        events::emit(vec![NullAccountUpdateLog { null_account }]);
+    }
+
+    pub fn null_account(&self) -> Option<AccountId> {
+        self.null_account.clone()
+    }
+}
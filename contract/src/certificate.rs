@@ -0,0 +1,83 @@
+/*!
+Certificates of the current owner and media of tokens.
+
+A certificate isn't signed: contract state is public, so the contract can't hold a secret key,
+and its digest is only a checksum anyone can compute. A certificate proves nothing by itself,
+`nft_verify_certificate` checks it against the token as this contract holds it now.
+*/
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::{Contract, ContractExt};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Certificate {
+    pub token_id: TokenId,
+    pub owner: AccountId,
+    pub contract_id: AccountId,
+    /// Block timestamp in seconds
+    pub issued_at: u64,
+    /// Base64 SHA-256 of the token media
+    pub media_hash: Option<String>,
+    /// `sha256(contract_id || token_id || owner || issued_at || media_hash)`
+    pub digest: Vec<u8>,
+}
+
+impl Certificate {
+    pub(crate) fn compute_digest(&self) -> Vec<u8> {
+        let mut message = Vec::new();
+        message.extend_from_slice(self.contract_id.as_bytes());
+        message.extend_from_slice(self.token_id.as_bytes());
+        message.extend_from_slice(self.owner.as_bytes());
+        message.extend_from_slice(&self.issued_at.to_le_bytes());
+        if let Some(media_hash) = &self.media_hash {
+            message.extend_from_slice(media_hash.as_bytes());
+        }
+        env::sha256(&message)
+    }
+}
+
+impl Contract {
+    /// Base64 SHA-256 of the media of `token_id`
+    fn internal_certificate_media_hash(&self, token_id: &TokenId) -> Option<String> {
+        self.internal_token_metadata(token_id)
+            .media_hash
+            .map(|media_hash| near_sdk::base64::encode(media_hash.0))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Certificate of `token_id` held by its current owner, issued now
+    pub fn nft_token_certificate(&self, token_id: TokenId) -> Certificate {
+        let owner = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        let contract_id = env::current_account_id();
+        let issued_at = env::block_timestamp() / 1_000_000_000;
+        let media_hash = self.internal_certificate_media_hash(&token_id);
+        let mut cert = Certificate {
+            token_id,
+            owner,
+            contract_id,
+            issued_at,
+            media_hash,
+            digest: Vec::new(),
+        };
+        cert.digest = cert.compute_digest();
+        cert
+    }
+
+    /// Whether `cert` matches its digest and still describes the owner and media of its token
+    /// on this contract. It doesn't prove the certificate was issued by this contract
+    pub fn nft_verify_certificate(&self, cert: Certificate) -> bool {
+        cert.contract_id == env::current_account_id()
+            && cert.digest == cert.compute_digest()
+            && self.tokens.owner_by_id.get(&cert.token_id).as_ref() == Some(&cert.owner)
+            && cert.media_hash == self.internal_certificate_media_hash(&cert.token_id)
+    }
+}
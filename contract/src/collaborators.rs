@@ -0,0 +1,65 @@
+/*!
+Tokens created by several creators, each paid a share of every sale like a perpetual royalty.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, Token, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::events;
+use crate::royalty::{FULL_BASIS_POINTS, MAX_ROYALTY_PAYEES};
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Collaborator {
+    pub account_id: AccountId,
+    pub royalty_bps: u32,
+    pub role: String,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mint a new token with ID=`token_id` to the contract owner, credited to `collaborators`
+    /// who share its sales by their basis points
+    #[payable]
+    pub fn nft_mint_with_collaborators(
+        &mut self,
+        token_id: TokenId,
+        metadata: TokenMetadata,
+        collaborators: Vec<Collaborator>,
+    ) -> Token {
+        self.assert_owner();
+        require!(
+            collaborators.len() <= MAX_ROYALTY_PAYEES,
+            "Too many collaborators"
+        );
+        let total_bps = collaborators.iter().try_fold(0u32, |total, collaborator| {
+            total.checked_add(collaborator.royalty_bps)
+        });
+        require!(
+            matches!(total_bps, Some(total_bps) if total_bps <= FULL_BASIS_POINTS),
+            "Collaborator royalties can't exceed 10000 basis points"
+        );
+
+        let initial_storage = env::storage_usage();
+        let token = self.internal_mint_with_collaborators(
+            token_id,
+            self.tokens.owner_id.clone(),
+            metadata,
+            None,
+            collaborators,
+        );
+        let storage_used = env::storage_usage() - initial_storage;
+        refund_deposit_to_account(storage_used, env::predecessor_account_id());
+        self.internal_audit("mint", Some(&token.token_id), None);
+        events::emit_mint(&token.owner_id, std::slice::from_ref(&token.token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        token
+    }
+
+    pub fn nft_token_collaborators(&self, token_id: TokenId) -> Vec<Collaborator> {
+        self.internal_token_extras(&token_id).collaborators
+    }
+}
@@ -0,0 +1,164 @@
+/*!
+Collateral locks letting a lending contract accept a token as collateral without custody.
+
+The owner locks the token for a lender contract. From then on only the lender acts on it:
+it either releases the token once the loan is repaid or liquidates it to an account of its
+choice. The owner can't unlock it unilaterally, and a locked token can't be transferred,
+listed, staked, leased or burned. A liquidation is settled like a sale at the price given
+by the lender, which receives the NEP-199 payout to distribute.
+
+The lock record is paid by the owner locking the token and refunded to the owner once
+the lock is released or liquidated.
+*/
+use near_contract_standards::non_fungible_token::refund_deposit_to_account;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, StorageUsage};
+
+use crate::events::{
+    self, NftCollateralLiquidateLog, NftCollateralLockLog, NftCollateralReleaseLog,
+};
+use crate::royalty::Payout;
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+/// Longest loan reference in bytes
+const MAX_LOAN_REF_LEN: usize = 64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Collateral {
+    pub lender_contract: AccountId,
+    /// Loan of the lender the token secures
+    pub loan_ref: String,
+}
+
+impl Contract {
+    pub(crate) fn is_collateralized(&self, token_id: &TokenId) -> bool {
+        self.collateral.contains_key(token_id)
+    }
+
+    /// Remove the lock of `token_id` held by the predecessor.
+    /// Returns it along with the storage it released
+    fn internal_take_collateral(&mut self, token_id: &TokenId) -> (Collateral, StorageUsage) {
+        let collateral = self
+            .collateral
+            .get(token_id)
+            .unwrap_or_else(|| env::panic_str("Token is not collateralized"));
+        require!(
+            env::predecessor_account_id() == collateral.lender_contract,
+            "Predecessor must be the lender"
+        );
+        let initial_storage = env::storage_usage();
+        self.collateral.remove(token_id);
+        (collateral, initial_storage - env::storage_usage())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Lock the caller's `token_id` as collateral of `loan_ref` taken from `lender_contract`,
+    /// the deposit must cover the lock storage. A listing of the token is removed
+    #[payable]
+    pub fn nft_lock_as_collateral(
+        &mut self,
+        token_id: TokenId,
+        lender_contract: AccountId,
+        loan_ref: String,
+    ) {
+        let owner_id = self.assert_token_owner(&token_id);
+        require!(lender_contract != owner_id, "Cannot borrow from the owner");
+        require!(
+            loan_ref.len() <= MAX_LOAN_REF_LEN,
+            "Loan reference is too long"
+        );
+        if let Some(reason) = self.internal_transfer_lock(&token_id) {
+            env::panic_str(&reason);
+        }
+
+        self.internal_remove_sale(&token_id);
+        let collateral = Collateral {
+            lender_contract,
+            loan_ref,
+        };
+        let initial_storage = env::storage_usage();
+        self.collateral.insert(&token_id, &collateral);
+        refund_deposit_to_account(env::storage_usage() - initial_storage, owner_id.clone());
+        self.internal_audit("lock_collateral", Some(&token_id), None);
+        events::emit(vec![NftCollateralLockLog {
+            token_id,
+            owner_id,
+            lender_contract: collateral.lender_contract,
+            loan_ref: collateral.loan_ref,
+        }]);
+    }
+
+    /// Unlock `token_id` once its loan is repaid, callable by the lender only.
+    /// The lock storage is refunded to the owner
+    #[payable]
+    pub fn nft_release_collateral(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let (collateral, storage_released) = self.internal_take_collateral(&token_id);
+        if let Some(owner_id) = self.tokens.owner_by_id.get(&token_id) {
+            refund_released_storage(storage_released, owner_id);
+        }
+        self.internal_audit("release_collateral", Some(&token_id), None);
+        events::emit(vec![NftCollateralReleaseLog {
+            token_id,
+            lender_contract: collateral.lender_contract,
+            loan_ref: collateral.loan_ref,
+        }]);
+    }
+
+    /// Transfer `token_id` to `to` settling its loan, callable by the lender only.
+    /// `price` is what the token was sold for, the returned payout of it is distributed
+    /// by the lender. The lock storage is refunded to the previous owner
+    #[payable]
+    pub fn nft_liquidate_collateral(
+        &mut self,
+        token_id: TokenId,
+        to: AccountId,
+        price: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        assert_one_yocto();
+        let (collateral, storage_released) = self.internal_take_collateral(&token_id);
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        let (previous_owner_id, approved_account_ids) = self.internal_transfer(
+            &owner_id,
+            &to,
+            &token_id,
+            None,
+            Some(collateral.loan_ref.clone()),
+            Some(price.0),
+        );
+        let payout =
+            self.internal_payout_with_limit(&token_id, &previous_owner_id, price.0, max_len_payout);
+        self.internal_refund_cleared_approvals(
+            &token_id,
+            approved_account_ids,
+            previous_owner_id.clone(),
+        );
+        refund_released_storage(storage_released, previous_owner_id.clone());
+        self.internal_audit("liquidate_collateral", Some(&token_id), Some(price.0));
+        events::emit(vec![NftCollateralLiquidateLog {
+            token_id,
+            owner_id: previous_owner_id,
+            receiver_id: to,
+            lender_contract: collateral.lender_contract,
+            loan_ref: collateral.loan_ref,
+            price,
+        }]);
+        payout
+    }
+
+    pub fn nft_collateral(&self, token_id: TokenId) -> Option<Collateral> {
+        self.collateral.get(&token_id)
+    }
+}
@@ -0,0 +1,157 @@
+/*!
+Composable tokens: the owner attaches child tokens, e.g. AR accessories, to a parent token.
+
+An attached child can't be transferred, listed, staked, leased or burned on its own, it follows
+the parent on every transfer, dropping its approvals. A parent with children can't be burned
+and can't be attached itself. Attachment storage is paid by the owner and refunded on detaching.
+*/
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, StorageUsage};
+
+use crate::events::{self, NftAttachLog, NftDetachLog};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Bounds gas used to move the children of a transferred parent
+pub(crate) const MAX_CHILDREN_PER_PARENT: u64 = 10;
+
+impl Contract {
+    pub(crate) fn is_attached(&self, token_id: &TokenId) -> bool {
+        self.token_parents.contains_key(token_id)
+    }
+
+    pub(crate) fn has_children(&self, token_id: &TokenId) -> bool {
+        self.token_children.get(token_id).is_some()
+    }
+
+    /// Move children of `parent_id` owned by `from` to `to` along with the parent.
+    /// Approvals of the children are dropped, returns the storage they released to refund
+    /// to `from` in a single transfer
+    pub(crate) fn internal_move_children(
+        &mut self,
+        parent_id: &TokenId,
+        from: &AccountId,
+        to: &AccountId,
+        sender_id: Option<&AccountId>,
+    ) -> StorageUsage {
+        let child_ids: Vec<TokenId> = match self.token_children.get(parent_id) {
+            Some(child_ids) => child_ids
+                .iter()
+                .filter(|child_id| self.tokens.owner_by_id.get(child_id).as_ref() == Some(from))
+                .collect(),
+            None => return 0,
+        };
+        let mut storage_released = 0;
+        for child_id in &child_ids {
+            let approved_account_ids = self
+                .tokens
+                .approvals_by_id
+                .as_mut()
+                .and_then(|by_id| by_id.remove(child_id));
+            storage_released += self.internal_clear_approvals(child_id, approved_account_ids);
+            self.tokens.internal_transfer_unguarded(child_id, from, to);
+            self.internal_record_transfer(child_id);
+        }
+        if !child_ids.is_empty() {
+            events::emit_transfer(from, to, &child_ids, sender_id, None);
+        }
+        storage_released
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Attach the caller's `child_id` to their `parent_id`, removing a listing of the child.
+    /// The deposit must cover the attachment storage
+    #[payable]
+    pub fn nft_attach(&mut self, child_id: TokenId, parent_id: TokenId) {
+        let owner_id = self.assert_token_owner(&child_id);
+        self.assert_token_owner(&parent_id);
+        require!(child_id != parent_id, "Token can't be attached to itself");
+        require!(!self.is_attached(&parent_id), "Parent token is attached");
+        require!(!self.has_children(&child_id), "Token has attached children");
+        if let Some(reason) = self.internal_transfer_lock(&child_id) {
+            env::panic_str(&reason);
+        }
+
+        self.internal_remove_sale(&child_id);
+        let initial_storage = env::storage_usage();
+        let mut child_ids = self.token_children.get(&parent_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::TokenChildrenPerParent {
+                token_hash: env::sha256(parent_id.as_bytes()),
+            })
+        });
+        require!(
+            child_ids.len() < MAX_CHILDREN_PER_PARENT,
+            "Too many children"
+        );
+        child_ids.insert(&child_id);
+        self.token_children.insert(&parent_id, &child_ids);
+        self.token_parents.insert(&child_id, &parent_id);
+        refund_deposit_to_account(env::storage_usage() - initial_storage, owner_id.clone());
+        self.internal_audit("attach", Some(&child_id), None);
+        events::emit(vec![NftAttachLog {
+            child_id,
+            parent_id,
+            owner_id,
+        }]);
+    }
+
+    /// Detach the caller's `child_id` from its parent, the attachment storage is refunded
+    #[payable]
+    pub fn nft_detach(&mut self, child_id: TokenId) {
+        assert_one_yocto();
+        let owner_id = self.assert_token_owner(&child_id);
+        let initial_storage = env::storage_usage();
+        let parent_id = self
+            .token_parents
+            .remove(&child_id)
+            .unwrap_or_else(|| env::panic_str("Token is not attached"));
+        if let Some(mut child_ids) = self.token_children.get(&parent_id) {
+            child_ids.remove(&child_id);
+            if child_ids.is_empty() {
+                self.token_children.remove(&parent_id);
+            } else {
+                self.token_children.insert(&parent_id, &child_ids);
+            }
+        }
+        refund_released_storage(
+            initial_storage.saturating_sub(env::storage_usage()),
+            owner_id.clone(),
+        );
+        self.internal_audit("detach", Some(&child_id), None);
+        events::emit(vec![NftDetachLog {
+            child_id,
+            parent_id,
+            owner_id,
+        }]);
+    }
+
+    /// Tokens attached to `parent_id`
+    pub fn nft_children(
+        &self,
+        parent_id: TokenId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenId> {
+        let child_ids = match self.token_children.get(&parent_id) {
+            Some(child_ids) => child_ids,
+            None => return vec![],
+        };
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        child_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+
+    /// Token `token_id` is attached to
+    pub fn nft_parent(&self, token_id: TokenId) -> Option<TokenId> {
+        self.token_parents.get(&token_id)
+    }
+}
@@ -0,0 +1,149 @@
+/*!
+Bonding curve drop: the mint price rises with the total supply.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, Balance, Promise};
+
+use crate::events::{self, MintCurveUpdateLog};
+use crate::{Contract, ContractExt, NFT_DESCRIPTION, NFT_NAME};
+
+/// Most IDs `peek_next_ids` returns
+const MAX_PEEK_IDS: u64 = 100;
+
+/// Price of the next token is `base_price + steps * increment` where `steps` is the total supply
+/// for a linear curve or the number of whole `step_size` batches sold for a stepwise one
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintCurve {
+    pub base_price: U128,
+    pub increment: U128,
+    /// Tokens sold per price step, `None` for a linear curve
+    pub step_size: Option<u64>,
+}
+
+impl MintCurve {
+    pub(crate) fn price_at(&self, total_supply: u64) -> Balance {
+        let steps = match self.step_size {
+            Some(step_size) => total_supply / step_size,
+            None => total_supply,
+        };
+        self.base_price.0 + Balance::from(steps) * self.increment.0
+    }
+}
+
+impl Contract {
+    /// Smallest numeric token ID not taken yet, starting from the total supply
+    pub(crate) fn next_curve_token_id(&self) -> TokenId {
+        self.next_free_token_id(self.tokens.owner_by_id.len(), &[])
+    }
+
+    /// Smallest numeric token ID from `index` neither minted nor in `taken`
+    fn next_free_token_id(&self, mut index: u64, taken: &[TokenId]) -> TokenId {
+        loop {
+            let token_id = index.to_string();
+            if !self.tokens.owner_by_id.contains_key(&token_id) && !taken.contains(&token_id) {
+                return token_id;
+            }
+            index += 1;
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Configure the bonding curve, `None` closes the curve drop
+    pub fn set_mint_curve(&mut self, mint_curve: Option<MintCurve>) {
+        self.assert_owner();
+        if let Some(mint_curve) = &mint_curve {
+            require!(
+                mint_curve.step_size != Some(0),
+                "Step size must be positive"
+            );
+        }
+        self.mint_curve = mint_curve.clone();
+        self.internal_audit("set_mint_curve", None, None);
+        events::emit(vec![MintCurveUpdateLog { mint_curve }]);
+    }
+
+    pub fn mint_curve(&self) -> Option<MintCurve> {
+        self.mint_curve.clone()
+    }
+
+    /// Price in yoctoNEAR of the next token minted by `nft_mint_curve`
+    pub fn current_curve_price(&self) -> U128 {
+        let mint_curve = self
+            .mint_curve
+            .as_ref()
+            .unwrap_or_else(|| env::panic_str("Mint curve is not configured"));
+        U128(mint_curve.price_at(self.tokens.owner_by_id.len()))
+    }
+
+    /// IDs the next `count` sequential mints would get. They aren't reserved,
+    /// mints made in the meantime take them first
+    pub fn peek_next_ids(&self, count: u64) -> Vec<TokenId> {
+        require!(count <= MAX_PEEK_IDS, "Can't peek more than 100 IDs");
+        let total_supply = self.tokens.owner_by_id.len();
+        let mut token_ids = vec![];
+        for minted in 0..count {
+            let token_id = self.next_free_token_id(total_supply + minted, &token_ids);
+            token_ids.push(token_id);
+        }
+        token_ids
+    }
+
+    /// Mint the next token to the caller charging the current curve price plus storage.
+    /// The price is donated to the charity account if configured, else split between the mint
+    /// payout accounts or paid to the contract owner. The excess deposit is refunded
+    #[payable]
+    pub fn nft_mint_curve(&mut self) -> Token {
+        let price = self.current_curve_price().0;
+        let token_id = self.next_curve_token_id();
+        let receiver_id = env::predecessor_account_id();
+
+        let initial_storage = env::storage_usage();
+        self.internal_count_daily_mint(&receiver_id);
+        let token = self.internal_mint_with_royalties(
+            token_id.clone(),
+            receiver_id.clone(),
+            TokenMetadata {
+                title: Some(format!("{} #{}", NFT_NAME, token_id)),
+                description: Some(NFT_DESCRIPTION.into()),
+                media: None,
+                media_hash: None,
+                copies: Some(1u64),
+                issued_at: Some(format!("{}", env::block_timestamp() / 1_000_000_000u64)),
+                expires_at: None,
+                starts_at: None,
+                updated_at: None,
+                extra: None,
+                reference: None,
+                reference_hash: None,
+            },
+            None,
+        );
+        let storage_used = env::storage_usage() - initial_storage;
+        let storage_cost = Balance::from(storage_used) * env::storage_byte_cost();
+
+        let required = price + storage_cost;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= required,
+            format!(
+                "Must attach {} yoctoNEAR to cover price and storage",
+                required
+            )
+        );
+        self.internal_pay_mint_price(&receiver_id, price);
+        if deposit > required {
+            Promise::new(receiver_id.clone()).transfer(deposit - required);
+        }
+        self.internal_audit("mint", Some(&token_id), Some(price));
+        events::emit_mint(&receiver_id, std::slice::from_ref(&token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        token
+    }
+}
@@ -0,0 +1,86 @@
+/*!
+Post-deployment smoke test run by the owner in a single transaction: a throwaway token is minted
+to the caller through the regular mint path, approved for the contract account with
+`nft_approve`, revoked the way `nft_revoke` does it and burned again. The approval events and
+audit entries of those steps are kept, no NEP-171 mint or burn events are logged for indexers
+and the token leaves nothing else behind.
+
+The caller attaches the storage of the approval, its record is refunded once revoked.
+`nft_revoke` itself requires exactly 1 yoctoNEAR, so the shared `internal_revoke` behind it
+is called instead. Once minting is closed the diagnostics fail at the mint.
+*/
+use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::{env, near_bindgen, require};
+
+use crate::events::{self, IntegrationDiagnosticsLog};
+use crate::{Contract, ContractExt};
+
+/// ID of the throwaway token, never minted otherwise
+const DIAGNOSTICS_TOKEN_ID: &str = "__diagnostics__";
+
+#[near_bindgen]
+impl Contract {
+    /// Mint, approve, revoke and burn a throwaway token, panicking at the first step that fails.
+    /// The attached deposit has to cover the approval
+    #[payable]
+    pub fn nft_run_integration_diagnostics(&mut self) -> bool {
+        self.assert_owner();
+        let token_id: TokenId = DIAGNOSTICS_TOKEN_ID.into();
+        require!(
+            !self.tokens.owner_by_id.contains_key(&token_id),
+            "Diagnostics token already exists"
+        );
+        let operator_id = env::predecessor_account_id();
+        let approved_id = env::current_account_id();
+        let initial_supply = self.tokens.owner_by_id.len();
+        let has_minted = self.has_minted;
+
+        let metadata = TokenMetadata {
+            title: Some("Diagnostics".into()),
+            description: None,
+            media: None,
+            media_hash: None,
+            copies: Some(1u64),
+            issued_at: None,
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: None,
+            reference: None,
+            reference_hash: None,
+        };
+        self.internal_mint(token_id.clone(), operator_id.clone(), metadata);
+        require!(
+            self.nft_token(token_id.clone())
+                .is_some_and(|token| token.owner_id == operator_id),
+            "Diagnostics failed: mint"
+        );
+
+        self.nft_approve(token_id.clone(), approved_id.clone(), None);
+        require!(
+            self.nft_is_approved(token_id.clone(), approved_id.clone(), None),
+            "Diagnostics failed: approve"
+        );
+
+        self.internal_revoke(&token_id, &approved_id, operator_id.clone());
+        require!(
+            !self.nft_is_approved(token_id.clone(), approved_id, None),
+            "Diagnostics failed: revoke"
+        );
+
+        self.internal_burn(&token_id, &operator_id);
+        // the throwaway token isn't the first mint of the collection
+        self.has_minted = has_minted;
+        require!(
+            self.nft_token(token_id).is_none() && self.tokens.owner_by_id.len() == initial_supply,
+            "Diagnostics failed: burn"
+        );
+
+        self.internal_audit("run_integration_diagnostics", None, None);
+        events::emit(vec![IntegrationDiagnosticsLog { success: true }]);
+        true
+    }
+}
@@ -0,0 +1,481 @@
+/*!
+Donations forwarded to the charity account.
+
+The running total counts a donation as soon as its transfer is scheduled and is corrected
+by the callback if the transfer fails. The callback also records the outcome in the charity
+ledger, paid by the contract, so donors can check their donation arrived. Anyone may retry
+a failed transfer by its index in the ledger.
+Donors are credited in the donor ledger once their donation arrives.
+
+An FT sent by `ft_transfer_call` with the `donate` message is forwarded to the charity account
+as well, if the contract owner accepts it for donations. Once it arrives the donor receives
+a soulbound badge, its storage paid by the contract. Badges are never sold, so neither the
+default marketplace approval nor the default resale cap applies to them. If forwarding fails
+the FT is returned to the donor by `ft_resolve_transfer`.
+
+Once configured, the charity account can't change silently: a change is announced and takes
+effect `CHARITY_CHANGE_DELAY` later. Donations keep going to the current account in the meantime.
+A due change is executed by the first donation after it or by anyone calling
+`execute_charity_change`.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    env, is_promise_success, near_bindgen, require, serde_json, AccountId, Balance, Gas, Promise,
+    PromiseOrValue,
+};
+
+use crate::events::{
+    self, CharityAccountUpdateLog, CharityChangeExecutedLog, CharityChangeScheduledLog,
+    CharityRetryLog, DonationFailedLog, DonationFtTokenUpdateLog, DonationLog, FtDonationFailedLog,
+    FtDonationLog,
+};
+use crate::ft::PaymentToken;
+use crate::{Contract, ContractExt};
+
+const GAS_FOR_DONATION_CALLBACK: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_FT_DONATION_CALLBACK: Gas = Gas(20_000_000_000_000);
+/// Seconds between scheduling a change of the charity account and its taking effect
+pub(crate) const CHARITY_CHANGE_DELAY: u64 = 48 * 60 * 60;
+
+/// Where a donation comes from
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum DonationContext {
+    /// Proceeds of a mint
+    Mint,
+    /// Royalty share of a sale
+    Royalty,
+    /// Match of a donation by the sponsor of a matching campaign
+    Matching,
+}
+
+/// Outcome of a NEAR transfer to the charity account
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityTransfer {
+    pub donor_id: AccountId,
+    pub amount: U128,
+    pub context: DonationContext,
+    pub success: bool,
+    /// Block timestamp in seconds of the last outcome
+    pub timestamp: u64,
+    /// Whether a retry of the failed transfer is in flight
+    pub retry_pending: bool,
+}
+
+/// Change of the charity account waiting for its delay to pass
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingCharityChange {
+    /// Account receiving donations once the change is executed, `None` stops them
+    pub charity_account_id: Option<AccountId>,
+    /// Block timestamp in seconds the change takes effect at
+    pub effective_at: U64,
+}
+
+impl PendingCharityChange {
+    fn is_due(&self) -> bool {
+        env::block_timestamp() / 1_000_000_000 >= self.effective_at.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityAccount {
+    /// Account donations are sent to
+    pub charity_account_id: Option<AccountId>,
+    pub pending_change: Option<PendingCharityChange>,
+}
+
+/// NEAR confirmed sent to the charity account and failed to reach it, not yet retried
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityTotals {
+    pub sent: U128,
+    pub failed: U128,
+}
+
+impl Contract {
+    /// Account donations are sent to, executing the pending change first if it is due
+    pub(crate) fn internal_charity_account(&mut self) -> Option<AccountId> {
+        if self
+            .pending_charity_change
+            .as_ref()
+            .is_some_and(PendingCharityChange::is_due)
+        {
+            let change = self.pending_charity_change.take().unwrap();
+            let previous_account_id = std::mem::replace(
+                &mut self.charity_account_id,
+                change.charity_account_id.clone(),
+            );
+            self.internal_audit("charity_change_executed", None, None);
+            events::emit(vec![CharityChangeExecutedLog {
+                previous_account_id,
+                charity_account_id: change.charity_account_id,
+            }]);
+        }
+        self.charity_account_id.clone()
+    }
+
+    fn expect_charity_account(&mut self) -> AccountId {
+        self.internal_charity_account()
+            .unwrap_or_else(|| env::panic_str("Charity account is not configured"))
+    }
+
+    /// Transfer `amount` donated by `donor_id` to the charity account
+    fn internal_send_to_charity(
+        &mut self,
+        donor_id: AccountId,
+        amount: Balance,
+        context: DonationContext,
+    ) {
+        Promise::new(self.expect_charity_account())
+            .transfer(amount)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_DONATION_CALLBACK)
+                    .on_charity_transfer(donor_id, U128(amount), context),
+            );
+    }
+
+    /// Forward `amount` paid by `donor_id` to the charity account along with its match
+    /// by the running matching campaign, if any
+    pub(crate) fn internal_donate(
+        &mut self,
+        donor_id: AccountId,
+        amount: Balance,
+        context: DonationContext,
+    ) {
+        self.internal_send_to_charity(donor_id.clone(), amount, context);
+        let matched = self.internal_match_donation(amount);
+        if let Some((sponsor, matched)) = &matched {
+            self.internal_send_to_charity(sponsor.clone(), *matched, DonationContext::Matching);
+        }
+        let matched = matched.map(|(_, matched)| matched);
+        self.total_donated += amount + matched.unwrap_or(0);
+        self.internal_audit("donation", None, Some(amount));
+        events::emit(vec![DonationLog {
+            donor_id,
+            amount: U128(amount),
+            context,
+            matched: matched.map(U128),
+            total_donated: U128(self.total_donated),
+        }]);
+    }
+
+    pub(crate) fn is_soulbound(&self, token_id: &TokenId) -> bool {
+        self.soulbound_tokens.contains(token_id)
+    }
+
+    /// Forward `amount` of `ft_token_id` donated by `donor_id` to the charity account,
+    /// the callback resolves the amount the FT contract returns to the donor
+    pub(crate) fn internal_ft_donate(
+        &mut self,
+        ft_token_id: AccountId,
+        donor_id: AccountId,
+        amount: Balance,
+    ) -> PromiseOrValue<U128> {
+        // a donation mints a badge and counts towards the totals, so only known FTs are taken
+        require!(
+            self.donation_ft_tokens.contains(&ft_token_id),
+            format!("FT {} is not accepted for donations", ft_token_id)
+        );
+        // the badge can't be minted once the FT is forwarded, so the donation is refused upfront
+        self.assert_minting_open();
+        PaymentToken::Ft(ft_token_id.clone())
+            .send(self.expect_charity_account(), amount)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_FT_DONATION_CALLBACK)
+                    .on_ft_donation(donor_id, ft_token_id, U128(amount)),
+            )
+            .into()
+    }
+
+    /// Mint the soulbound badge of a donation of `amount` of `ft_token_id` to `donor_id`
+    fn internal_mint_donation_badge(
+        &mut self,
+        donor_id: AccountId,
+        ft_token_id: &AccountId,
+        amount: Balance,
+    ) -> TokenId {
+        let token_id = format!("donation-{}", self.next_donation_badge_id);
+        self.next_donation_badge_id += 1;
+        let extra = serde_json::json!({
+            "ft_token_id": ft_token_id,
+            "amount": U128(amount),
+        });
+        let token_metadata = TokenMetadata {
+            title: Some("Ukrainian Magicals Donation Badge".into()),
+            description: Some("Proof of donation to the charity of Ukrainian Magicals".into()),
+            media: None,
+            media_hash: None,
+            copies: Some(1),
+            issued_at: Some((env::block_timestamp() / 1_000_000_000).to_string()),
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: Some(extra.to_string()),
+            reference: None,
+            reference_hash: None,
+        };
+        let token = self.internal_mint_without_defaults(token_id, donor_id, token_metadata);
+        self.soulbound_tokens.insert(&token.token_id);
+        events::emit_mint(&token.owner_id, std::slice::from_ref(&token.token_id), None);
+        token.token_id
+    }
+
+    /// Pay `price` of a token minted by `minter_id` to the charity account if configured,
+    /// else split it between the mint payout accounts or pay it to the contract owner
+    pub(crate) fn internal_pay_mint_price(&mut self, minter_id: &AccountId, price: Balance) {
+        if price == 0 {
+            return;
+        }
+        if self.internal_charity_account().is_some() {
+            self.internal_donate(minter_id.clone(), price, DonationContext::Mint);
+        } else if !self.internal_split_mint_price(price) {
+            Promise::new(self.tokens.owner_id.clone()).transfer(price);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the account receiving donations, `None` stops them. The first account takes effect
+    /// at once, a change of it is scheduled to take effect in 48 hours replacing a pending one.
+    /// Scheduling the current account cancels the pending change
+    pub fn set_charity_account(&mut self, charity_account_id: Option<AccountId>) {
+        self.assert_owner();
+        let current_account_id = self.internal_charity_account();
+        if current_account_id.is_none() {
+            self.charity_account_id = charity_account_id.clone();
+            self.internal_audit("set_charity_account", None, None);
+            events::emit(vec![CharityAccountUpdateLog { charity_account_id }]);
+            return;
+        }
+
+        let now = env::block_timestamp() / 1_000_000_000;
+        let effective_at = if charity_account_id == current_account_id {
+            self.pending_charity_change = None;
+            U64(now)
+        } else {
+            let effective_at = U64(now + CHARITY_CHANGE_DELAY);
+            self.pending_charity_change = Some(PendingCharityChange {
+                charity_account_id: charity_account_id.clone(),
+                effective_at,
+            });
+            effective_at
+        };
+        self.internal_audit("set_charity_account", None, None);
+        events::emit(vec![CharityChangeScheduledLog {
+            charity_account_id,
+            effective_at,
+        }]);
+    }
+
+    /// Execute the pending change of the charity account once it is due, callable by anyone
+    pub fn execute_charity_change(&mut self) {
+        let change = self
+            .pending_charity_change
+            .as_ref()
+            .unwrap_or_else(|| env::panic_str("No charity change is pending"));
+        require!(change.is_due(), "Charity change is not due yet");
+        self.internal_charity_account();
+    }
+
+    /// Account donations are sent to along with the change waiting for its delay, if any
+    pub fn charity_account(&self) -> CharityAccount {
+        match &self.pending_charity_change {
+            Some(change) if change.is_due() => CharityAccount {
+                charity_account_id: change.charity_account_id.clone(),
+                pending_change: None,
+            },
+            pending_change => CharityAccount {
+                charity_account_id: self.charity_account_id.clone(),
+                pending_change: pending_change.clone(),
+            },
+        }
+    }
+
+    /// Total yoctoNEAR forwarded to the charity account
+    pub fn total_donated(&self) -> U128 {
+        U128(self.total_donated)
+    }
+
+    /// Total amount of `ft_token_id` forwarded to the charity account
+    pub fn total_donated_ft(&self, ft_token_id: AccountId) -> U128 {
+        U128(self.ft_donations.get(&ft_token_id).unwrap_or(0))
+    }
+
+    /// Accept or refuse `ft_token_id` for donations
+    pub fn set_donation_ft_token(&mut self, ft_token_id: AccountId, accepted: bool) {
+        self.assert_owner();
+        if accepted {
+            self.donation_ft_tokens.insert(&ft_token_id);
+        } else {
+            self.donation_ft_tokens.remove(&ft_token_id);
+        }
+        self.internal_audit("set_donation_ft_token", None, None);
+        events::emit(vec![DonationFtTokenUpdateLog {
+            ft_token_id,
+            accepted,
+        }]);
+    }
+
+    /// FTs accepted for donations
+    pub fn donation_ft_tokens(&self) -> Vec<AccountId> {
+        self.donation_ft_tokens.to_vec()
+    }
+
+    /// Count a forwarded FT donation and mint the donor's badge. Returns the amount
+    /// the FT contract refunds to the donor, all of it if forwarding failed
+    #[private]
+    pub fn on_ft_donation(
+        &mut self,
+        donor_id: AccountId,
+        ft_token_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        if !is_promise_success() {
+            self.internal_audit("ft_donation_failed", None, Some(amount.0));
+            events::emit(vec![FtDonationFailedLog {
+                donor_id,
+                ft_token_id,
+                amount,
+            }]);
+            return amount;
+        }
+        let total_donated = self.ft_donations.get(&ft_token_id).unwrap_or(0) + amount.0;
+        self.ft_donations.insert(&ft_token_id, &total_donated);
+        self.internal_credit_ft_donor(&donor_id, &ft_token_id, amount.0);
+        let badge_id = self.internal_mint_donation_badge(donor_id.clone(), &ft_token_id, amount.0);
+        self.internal_audit("ft_donation", Some(&badge_id), Some(amount.0));
+        events::emit(vec![FtDonationLog {
+            donor_id,
+            ft_token_id,
+            amount,
+            badge_id,
+        }]);
+        U128(0)
+    }
+
+    /// Record the outcome of a donation in the charity ledger. A failed one is taken out
+    /// of the total, the amount stays on the contract until the transfer is retried
+    #[private]
+    pub fn on_charity_transfer(
+        &mut self,
+        donor_id: AccountId,
+        amount: U128,
+        context: DonationContext,
+    ) {
+        let success = is_promise_success();
+        let index = self.charity_ledger.len();
+        self.charity_ledger.push(&CharityTransfer {
+            donor_id: donor_id.clone(),
+            amount,
+            context,
+            success,
+            timestamp: env::block_timestamp() / 1_000_000_000,
+            retry_pending: false,
+        });
+        if success {
+            self.charity_sent += amount.0;
+            self.internal_credit_donor(&donor_id, amount.0);
+            return;
+        }
+        self.charity_failed += amount.0;
+        self.total_donated -= amount.0;
+        self.internal_audit("donation_failed", None, Some(amount.0));
+        events::emit(vec![DonationFailedLog {
+            index: U64(index),
+            donor_id,
+            amount,
+            context,
+            total_donated: U128(self.total_donated),
+        }]);
+    }
+
+    /// Callback name of `on_charity_transfer` before it was renamed, donations scheduled
+    /// by the previous release still resolve through it
+    #[private]
+    pub fn on_donation(&mut self, donor_id: AccountId, amount: U128, context: DonationContext) {
+        self.on_charity_transfer(donor_id, amount, context);
+    }
+
+    /// Send the failed transfer at `index` of the charity ledger again, anyone may call it
+    pub fn retry_charity_transfer(&mut self, index: U64) {
+        let mut transfer = self
+            .charity_ledger
+            .get(index.0)
+            .unwrap_or_else(|| env::panic_str("Charity transfer not found"));
+        require!(!transfer.success, "Charity transfer succeeded");
+        require!(!transfer.retry_pending, "Retry is already pending");
+        transfer.retry_pending = true;
+        self.charity_ledger.replace(index.0, &transfer);
+        Promise::new(self.expect_charity_account())
+            .transfer(transfer.amount.0)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_DONATION_CALLBACK)
+                    .on_charity_retry(index),
+            );
+        self.internal_audit("retry_charity_transfer", None, Some(transfer.amount.0));
+        events::emit(vec![CharityRetryLog {
+            index,
+            amount: transfer.amount,
+            success: None,
+        }]);
+    }
+
+    /// Record the outcome of a retried charity transfer, a failed one may be retried again
+    #[private]
+    pub fn on_charity_retry(&mut self, index: U64) {
+        let mut transfer = self.charity_ledger.get(index.0).unwrap();
+        let success = is_promise_success();
+        transfer.retry_pending = false;
+        transfer.timestamp = env::block_timestamp() / 1_000_000_000;
+        if success {
+            transfer.success = true;
+            self.charity_failed -= transfer.amount.0;
+            self.charity_sent += transfer.amount.0;
+            self.total_donated += transfer.amount.0;
+            self.internal_credit_donor(&transfer.donor_id, transfer.amount.0);
+        }
+        self.charity_ledger.replace(index.0, &transfer);
+        self.internal_audit("charity_retry", None, Some(transfer.amount.0));
+        events::emit(vec![CharityRetryLog {
+            index,
+            amount: transfer.amount,
+            success: Some(success),
+        }]);
+    }
+
+    /// Every charity transfer oldest first, its index is the one to retry it with
+    pub fn charity_ledger(
+        &self,
+        from_index: Option<U64>,
+        limit: Option<u64>,
+    ) -> Vec<CharityTransfer> {
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index = from_index.map(u64::from).unwrap_or_default();
+        (start_index..self.charity_ledger.len())
+            .take(limit)
+            .filter_map(|index| self.charity_ledger.get(index))
+            .collect()
+    }
+
+    pub fn charity_totals(&self) -> CharityTotals {
+        CharityTotals {
+            sent: U128(self.charity_sent),
+            failed: U128(self.charity_failed),
+        }
+    }
+}
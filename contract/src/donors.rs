@@ -0,0 +1,86 @@
+/*!
+Donor ledger: what each account donated to the charity account and a leaderboard of the top
+donors by NEAR donated.
+
+A donation is credited to its donor once it reaches the charity account, including a failed
+transfer that was retried successfully, so a donor's total never decreases. That keeps the
+leaderboard exact while only the top 100 are stored, sorted on write. FT donations are credited
+per FT contract and stay out of the leaderboard. Records are written by callbacks and paid
+by the contract.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, AccountId, Balance};
+
+use crate::{Contract, ContractExt};
+
+/// Donors kept on the leaderboard
+pub(crate) const MAX_TOP_DONORS: usize = 100;
+
+impl Contract {
+    /// Credit `amount` of NEAR that reached the charity account to `donor_id`
+    pub(crate) fn internal_credit_donor(&mut self, donor_id: &AccountId, amount: Balance) {
+        let total = self.donations_by_account.get(donor_id).unwrap_or(0) + amount;
+        self.donations_by_account.insert(donor_id, &total);
+
+        let mut top_donors = self.top_donors.get().unwrap_or_default();
+        top_donors.retain(|(_, account_id)| account_id != donor_id);
+        // ranked after donors who donated as much earlier
+        let position = top_donors.partition_point(|(donated, _)| *donated >= total);
+        if position < MAX_TOP_DONORS {
+            top_donors.insert(position, (total, donor_id.clone()));
+            top_donors.truncate(MAX_TOP_DONORS);
+            self.top_donors.set(&top_donors);
+        }
+    }
+
+    /// Credit `amount` of `ft_token_id` that reached the charity account to `donor_id`
+    pub(crate) fn internal_credit_ft_donor(
+        &mut self,
+        donor_id: &AccountId,
+        ft_token_id: &AccountId,
+        amount: Balance,
+    ) {
+        let key = (donor_id.clone(), ft_token_id.clone());
+        let total = self.ft_donations_by_account.get(&key).unwrap_or(0) + amount;
+        self.ft_donations_by_account.insert(&key, &total);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// yoctoNEAR donated by `account_id` that reached the charity account
+    pub fn donated_by(&self, account_id: AccountId) -> U128 {
+        U128(self.donations_by_account.get(&account_id).unwrap_or(0))
+    }
+
+    /// Amount of `ft_token_id` donated by `account_id`
+    pub fn donated_ft_by(&self, account_id: AccountId, ft_token_id: AccountId) -> U128 {
+        U128(
+            self.ft_donations_by_account
+                .get(&(account_id, ft_token_id))
+                .unwrap_or(0),
+        )
+    }
+
+    /// Up to `limit` of the top 100 donors by NEAR donated, highest first
+    pub fn top_donors(&self, limit: Option<u64>) -> Vec<(AccountId, U128)> {
+        let limit = limit.map(|v| v as usize).unwrap_or(MAX_TOP_DONORS);
+        self.top_donors
+            .get()
+            .unwrap_or_default()
+            .into_iter()
+            .take(limit)
+            .map(|(donated, account_id)| (account_id, U128(donated)))
+            .collect()
+    }
+
+    /// Rank of `account_id` among donors starting at 1, `None` outside the top 100
+    pub fn donor_rank(&self, account_id: AccountId) -> Option<u32> {
+        self.top_donors
+            .get()
+            .unwrap_or_default()
+            .iter()
+            .position(|(_, donor_id)| *donor_id == account_id)
+            .map(|index| index as u32 + 1)
+    }
+}
@@ -0,0 +1,69 @@
+/*!
+Enumeration methods returning tokens as they are shown by `nft_token`.
+*/
+use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl NonFungibleTokenEnumeration for Contract {
+    fn nft_total_supply(&self) -> U128 {
+        self.tokens.nft_total_supply()
+    }
+
+    fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
+        self.tokens
+            .nft_tokens(from_index, limit)
+            .into_iter()
+            .map(|token| self.with_default_media(self.with_reveal(token)))
+            .collect()
+    }
+
+    fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        self.tokens.nft_supply_for_owner(account_id)
+    }
+
+    fn nft_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        self.tokens
+            .nft_tokens_for_owner(account_id, from_index, limit)
+            .into_iter()
+            .map(|token| self.with_default_media(self.with_reveal(token)))
+            .collect()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// IDs of the tokens owned by `account_id`, paged like `nft_tokens_for_owner`
+    /// but without reading their metadata
+    pub fn nft_token_ids_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenId> {
+        let tokens_per_owner = self.tokens.tokens_per_owner.as_ref().unwrap_or_else(|| {
+            env::panic_str("Could not find tokens_per_owner when calling a method on the enumeration standard.")
+        });
+        let token_ids = match tokens_per_owner.get(&account_id) {
+            Some(token_ids) => token_ids,
+            None => return vec![],
+        };
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        token_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+}
@@ -0,0 +1,1555 @@
+/*!
+NEP-297 events of the NEP-171 standard for token changes made outside the standard implementation,
+and the contract's own events.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::events::{NftBurn, NftMint, NftTransfer};
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, serde_json, AccountId, Balance, StorageUsage};
+
+use crate::approval::ApprovalConditions;
+use crate::curve::MintCurve;
+use crate::donation::DonationContext;
+use crate::ft::FtMintPrice;
+use crate::info::BuildInfo;
+use crate::mint_split::MintPayoutAccount;
+use crate::sale::{DutchAuction, PriceUpdate};
+use crate::swap::ExternalSwap;
+use crate::upgrade::StagedCode;
+
+/// Standard name of the NEP-171 token events
+pub const NEP171_STANDARD: &str = "nep171";
+/// Version of every NEP-171 event logged by the contract, 1.2.0 adds `nft_metadata_update`
+pub const NEP171_VERSION: &str = "1.2.0";
+
+/// Standard name of the contract's own events
+pub const EVENT_STANDARD: &str = "uamag";
+/// Version of the contract's own events, bumped on any change of a payload
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// NEP-297 envelope of the contract's own events
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventLog<T> {
+    pub standard: String,
+    pub version: String,
+    pub event: String,
+    pub data: Vec<T>,
+}
+
+/// Data entry of an event, tied to its event name
+pub trait EventPayload: Serialize {
+    const EVENT: &'static str;
+}
+
+fn log_event<T: Serialize>(standard: &str, version: &str, event: &str, data: Vec<T>) {
+    let log = EventLog {
+        standard: standard.to_string(),
+        version: version.to_string(),
+        event: event.to_string(),
+        data,
+    };
+    env::log_str(&format!(
+        "EVENT_JSON:{}",
+        serde_json::to_string(&log).unwrap_or_else(|_| env::panic_str("Cannot serialize event"))
+    ));
+}
+
+/// Log the `data` entries as a single `event` of the contract's own standard. Entries may be
+/// `serde_json::Value`s for an event without a payload type
+pub(crate) fn emit_event<T: Serialize>(event: &str, data: Vec<T>) {
+    log_event(EVENT_STANDARD, EVENT_VERSION, event, data);
+}
+
+/// Log the `data` entries as a single event of the contract's own standard
+pub(crate) fn emit<T: EventPayload>(data: Vec<T>) {
+    emit_event(T::EVENT, data);
+}
+
+/// Log the `data` entries as a single NEP-171 `event`
+fn emit_nep171<T: Serialize>(event: &str, data: Vec<T>) {
+    log_event(NEP171_STANDARD, NEP171_VERSION, event, data);
+}
+
+/// A token is listed for sale or its listing is replaced
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftListLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub price: U128,
+    /// FT contract the price is set in, omitted for NEAR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ft_token_id: Option<AccountId>,
+    /// Block timestamp in seconds the listing expires at, omitted for listings without expiry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Whether offers below the price are accepted, omitted if not
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub accept_counter_offers: bool,
+}
+
+impl EventPayload for NftListLog {
+    const EVENT: &'static str = "nft_list";
+}
+
+/// A storage refund failed, its amount is counted as revenue
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefundFailedLog {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+impl EventPayload for RefundFailedLog {
+    const EVENT: &'static str = "refund_failed";
+}
+
+/// The contract owner lists metadata of a token minted when bought
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LazyListLog {
+    pub listing_id: U64,
+    pub price: U128,
+}
+
+impl EventPayload for LazyListLog {
+    const EVENT: &'static str = "nft_lazy_list";
+}
+
+/// A buyer offers an amount for a listed token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftOfferLog {
+    pub token_id: TokenId,
+    pub buyer_id: AccountId,
+    pub amount: U128,
+}
+
+impl EventPayload for NftOfferLog {
+    const EVENT: &'static str = "nft_offer";
+}
+
+/// A buyer withdraws their offer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftOfferWithdrawLog {
+    pub token_id: TokenId,
+    pub buyer_id: AccountId,
+    pub amount: U128,
+}
+
+impl EventPayload for NftOfferWithdrawLog {
+    const EVENT: &'static str = "nft_offer_withdraw";
+}
+
+/// An owner offers their token for lease
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftLeaseLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub lessee_id: AccountId,
+    pub until: U64,
+    pub fee: U128,
+}
+
+impl EventPayload for NftLeaseLog {
+    const EVENT: &'static str = "nft_lease";
+}
+
+/// A lessee pays the fee and starts the lease
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftLeaseAcceptLog {
+    pub token_id: TokenId,
+    pub lessee_id: AccountId,
+    pub until: U64,
+    pub fee: U128,
+}
+
+impl EventPayload for NftLeaseAcceptLog {
+    const EVENT: &'static str = "nft_lease_accept";
+}
+
+/// A token is attached to a parent token of the same owner
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftAttachLog {
+    pub child_id: TokenId,
+    pub parent_id: TokenId,
+    pub owner_id: AccountId,
+}
+
+impl EventPayload for NftAttachLog {
+    const EVENT: &'static str = "nft_attach";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftDetachLog {
+    pub child_id: TokenId,
+    pub parent_id: TokenId,
+    pub owner_id: AccountId,
+}
+
+impl EventPayload for NftDetachLog {
+    const EVENT: &'static str = "nft_detach";
+}
+
+/// A holder stakes their token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftStakeLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+}
+
+impl EventPayload for NftStakeLog {
+    const EVENT: &'static str = "nft_stake";
+}
+
+/// A holder unstakes their token and is credited `points`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftUnstakeLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub points: U64,
+}
+
+impl EventPayload for NftUnstakeLog {
+    const EVENT: &'static str = "nft_unstake";
+}
+
+/// The contract owner changes the points credited per day staked
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakePointsRateUpdateLog {
+    pub points_per_day: U64,
+}
+
+impl EventPayload for StakePointsRateUpdateLog {
+    const EVENT: &'static str = "stake_points_rate_update";
+}
+
+/// A token is listed on a Dutch auction
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftDutchListLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub auction: DutchAuction,
+}
+
+impl EventPayload for NftDutchListLog {
+    const EVENT: &'static str = "nft_list_dutch";
+}
+
+/// A listing is removed by the seller, a transfer or a sale
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftDelistLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+}
+
+impl EventPayload for NftDelistLog {
+    const EVENT: &'static str = "nft_delist";
+}
+
+/// Several listings are removed by the seller in one call
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftBulkDelistLog {
+    pub owner_id: AccountId,
+    pub token_ids: Vec<TokenId>,
+}
+
+impl EventPayload for NftBulkDelistLog {
+    const EVENT: &'static str = "nft_bulk_delist";
+}
+
+/// A listed token is bought
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftSaleLog {
+    pub token_id: TokenId,
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    pub price: U128,
+    /// FT contract the price is paid in, omitted for NEAR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ft_token_id: Option<AccountId>,
+}
+
+impl EventPayload for NftSaleLog {
+    const EVENT: &'static str = "nft_sale";
+}
+
+/// New prices set by `nft_bulk_update_sale_prices`
+impl EventPayload for PriceUpdate {
+    const EVENT: &'static str = "nft_bulk_price_update";
+}
+
+/// The contract owner changes the minimum listing price
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MinSalePriceUpdateLog {
+    pub min_sale_price: U128,
+}
+
+impl EventPayload for MinSalePriceUpdateLog {
+    const EVENT: &'static str = "min_sale_price_update";
+}
+
+/// The contract owner changes the default media
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DefaultMediaUpdateLog {
+    pub default_media: Option<String>,
+}
+
+impl EventPayload for DefaultMediaUpdateLog {
+    const EVENT: &'static str = "default_media_update";
+}
+
+/// The contract owner records how the deployed code was built
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuildInfoSetLog {
+    #[serde(flatten)]
+    pub build_info: BuildInfo,
+}
+
+impl EventPayload for BuildInfoSetLog {
+    const EVENT: &'static str = "build_info_set";
+}
+
+/// The contract owner changes the price of minting for an FT
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMintPriceUpdateLog {
+    pub ft_mint_price: Option<FtMintPrice>,
+}
+
+impl EventPayload for FtMintPriceUpdateLog {
+    const EVENT: &'static str = "ft_mint_price_update";
+}
+
+/// The contract owner adds `amount` to the pool paying for the storage of FT mints
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMintStoragePoolTopUpLog {
+    pub amount: U128,
+    /// Pool after the top-up
+    pub pool: U128,
+}
+
+impl EventPayload for FtMintStoragePoolTopUpLog {
+    const EVENT: &'static str = "ft_mint_storage_pool_top_up";
+}
+
+/// The contract owner adds `amount` to the pool paying for the storage of audit entries
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuditStoragePoolTopUpLog {
+    pub amount: U128,
+    /// Pool after the top-up
+    pub pool: U128,
+}
+
+impl EventPayload for AuditStoragePoolTopUpLog {
+    const EVENT: &'static str = "audit_storage_pool_top_up";
+}
+
+/// The contract owner hides or reveals the collection, or changes the reveal fee
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RevealConfigUpdateLog {
+    pub hidden: bool,
+    pub reveal_fee: Option<U128>,
+}
+
+impl EventPayload for RevealConfigUpdateLog {
+    const EVENT: &'static str = "reveal_config_update";
+}
+
+/// A holder pays to reveal their token early
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenRevealLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub fee: U128,
+}
+
+impl EventPayload for TokenRevealLog {
+    const EVENT: &'static str = "nft_reveal";
+}
+
+/// The contract owner locks transfers of the collection until `unlock_at`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GlobalTransferUnlockLog {
+    pub unlock_at: u64,
+}
+
+impl EventPayload for GlobalTransferUnlockLog {
+    const EVENT: &'static str = "global_transfer_unlock_set";
+}
+
+/// The contract owner runs the post-deployment smoke test
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntegrationDiagnosticsLog {
+    pub success: bool,
+}
+
+impl EventPayload for IntegrationDiagnosticsLog {
+    const EVENT: &'static str = "integration_diagnostics";
+}
+
+/// An owner moves `count` tokens of their portfolio to `receiver_id` in one call,
+/// the tokens are listed in the accompanying `nft_transfer` event
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftTransferAllLog {
+    pub owner_id: AccountId,
+    pub receiver_id: AccountId,
+    pub count: u32,
+}
+
+impl EventPayload for NftTransferAllLog {
+    const EVENT: &'static str = "nft_transfer_all";
+}
+
+/// The contract owner changes the resale cap of tokens minted without one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DefaultResaleCapUpdateLog {
+    pub default_max_resale_price: Option<U128>,
+}
+
+impl EventPayload for DefaultResaleCapUpdateLog {
+    const EVENT: &'static str = "default_resale_cap_update";
+}
+
+/// The contract owner changes the fee charged on sales
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PlatformFeeUpdateLog {
+    pub platform_fee_bps: u16,
+}
+
+impl EventPayload for PlatformFeeUpdateLog {
+    const EVENT: &'static str = "platform_fee_update";
+}
+
+/// The contract owner changes the minimum royalty or the default royalties of mints
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoyaltyPolicyUpdateLog {
+    pub min_royalty_bps: u16,
+    pub default_royalties: HashMap<AccountId, u16>,
+}
+
+impl EventPayload for RoyaltyPolicyUpdateLog {
+    const EVENT: &'static str = "royalty_policy_update";
+}
+
+/// The contract owner changes the marketplace approved on mint
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DefaultMarketplaceUpdateLog {
+    pub default_marketplace: Option<AccountId>,
+}
+
+impl EventPayload for DefaultMarketplaceUpdateLog {
+    const EVENT: &'static str = "default_marketplace_update";
+}
+
+/// The contract owner allows or disallows sales in an FT in approve msgs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ParasFtTokenUpdateLog {
+    pub ft_token_id: AccountId,
+    pub accepted: bool,
+}
+
+impl EventPayload for ParasFtTokenUpdateLog {
+    const EVENT: &'static str = "paras_ft_token_update";
+}
+
+/// The contract owner changes the Paras marketplace whose approve msgs are checked
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ParasMarketplaceUpdateLog {
+    pub paras_marketplace: Option<AccountId>,
+}
+
+impl EventPayload for ParasMarketplaceUpdateLog {
+    const EVENT: &'static str = "paras_marketplace_update";
+}
+
+/// The contract owner changes how many mints an account may receive per day
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MaxDailyMintsUpdateLog {
+    pub max_daily_mints_per_account: u32,
+}
+
+impl EventPayload for MaxDailyMintsUpdateLog {
+    const EVENT: &'static str = "max_daily_mints_update";
+}
+
+/// The contract owner configures the bonding curve drop
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintCurveUpdateLog {
+    pub mint_curve: Option<MintCurve>,
+}
+
+impl EventPayload for MintCurveUpdateLog {
+    const EVENT: &'static str = "mint_curve_update";
+}
+
+/// The contract owner changes how mint prices are split between team accounts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintPayoutAccountsUpdateLog {
+    pub payout_accounts: Vec<MintPayoutAccount>,
+}
+
+impl EventPayload for MintPayoutAccountsUpdateLog {
+    const EVENT: &'static str = "mint_payout_accounts_update";
+}
+
+/// The contract owner hands the contract over to `new_owner_id`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractOwnershipTransferredLog {
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+}
+
+impl EventPayload for ContractOwnershipTransferredLog {
+    const EVENT: &'static str = "contract_ownership_transferred";
+}
+
+/// The contract owner accepts or refuses an FT for donations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DonationFtTokenUpdateLog {
+    pub ft_token_id: AccountId,
+    pub accepted: bool,
+}
+
+impl EventPayload for DonationFtTokenUpdateLog {
+    const EVENT: &'static str = "donation_ft_token_update";
+}
+
+/// The contract owner changes the charity account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityAccountUpdateLog {
+    pub charity_account_id: Option<AccountId>,
+}
+
+impl EventPayload for CharityAccountUpdateLog {
+    const EVENT: &'static str = "charity_account_update";
+}
+
+/// The contract owner schedules a change of the charity account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityChangeScheduledLog {
+    pub charity_account_id: Option<AccountId>,
+    /// Block timestamp in seconds the change takes effect at
+    pub effective_at: U64,
+}
+
+impl EventPayload for CharityChangeScheduledLog {
+    const EVENT: &'static str = "charity_change_scheduled";
+}
+
+/// Donations go to the new charity account from now on
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityChangeExecutedLog {
+    pub previous_account_id: Option<AccountId>,
+    pub charity_account_id: Option<AccountId>,
+}
+
+impl EventPayload for CharityChangeExecutedLog {
+    const EVENT: &'static str = "charity_change_executed";
+}
+
+/// Funds are forwarded to the charity account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DonationLog {
+    pub donor_id: AccountId,
+    pub amount: U128,
+    pub context: DonationContext,
+    /// Amount matched by the running matching campaign, omitted if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<U128>,
+    /// Total donated including this donation and its match
+    pub total_donated: U128,
+}
+
+impl EventPayload for DonationLog {
+    const EVENT: &'static str = "donation";
+}
+
+/// A sponsor escrows `cap` to match donations until `ends_at` in seconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchingCampaignStartLog {
+    pub sponsor: AccountId,
+    pub cap: U128,
+    pub ends_at: U64,
+}
+
+impl EventPayload for MatchingCampaignStartLog {
+    const EVENT: &'static str = "matching_campaign_start";
+}
+
+/// A matching campaign is closed, the unspent escrow is refunded to the sponsor
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchingCampaignCloseLog {
+    pub sponsor: AccountId,
+    pub matched: U128,
+    pub refunded: U128,
+}
+
+impl EventPayload for MatchingCampaignCloseLog {
+    const EVENT: &'static str = "matching_campaign_close";
+}
+
+/// A donation transfer failed and is taken out of the total
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DonationFailedLog {
+    /// Index `retry_charity_transfer` takes
+    pub index: U64,
+    pub donor_id: AccountId,
+    pub amount: U128,
+    pub context: DonationContext,
+    /// Total donated without this donation
+    pub total_donated: U128,
+}
+
+impl EventPayload for DonationFailedLog {
+    const EVENT: &'static str = "donation_failed";
+}
+
+/// A retry of the failed charity transfer at `index` of the ledger, `success` is `None`
+/// until its outcome is known
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityRetryLog {
+    pub index: U64,
+    pub amount: U128,
+    pub success: Option<bool>,
+}
+
+impl EventPayload for CharityRetryLog {
+    const EVENT: &'static str = "charity_retry";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalSwapProposeLog {
+    pub swap_id: U64,
+    #[serde(flatten)]
+    pub swap: ExternalSwap,
+}
+
+impl EventPayload for ExternalSwapProposeLog {
+    const EVENT: &'static str = "external_swap_propose";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalSwapCompleteLog {
+    pub swap_id: U64,
+}
+
+impl EventPayload for ExternalSwapCompleteLog {
+    const EVENT: &'static str = "external_swap_complete";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalSwapCancelLog {
+    pub swap_id: U64,
+}
+
+impl EventPayload for ExternalSwapCancelLog {
+    const EVENT: &'static str = "external_swap_cancel";
+}
+
+/// The counterparty's token of a completed swap couldn't be forwarded to the proposer
+/// and is held by this contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalSwapForwardFailedLog {
+    pub swap_id: U64,
+    #[serde(flatten)]
+    pub swap: ExternalSwap,
+}
+
+impl EventPayload for ExternalSwapForwardFailedLog {
+    const EVENT: &'static str = "external_swap_forward_failed";
+}
+
+/// The proposer retries forwarding the counterparty's token of a completed swap
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalSwapForwardRetryLog {
+    pub swap_id: U64,
+}
+
+impl EventPayload for ExternalSwapForwardRetryLog {
+    const EVENT: &'static str = "external_swap_forward_retry";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventDropLog {
+    pub keypom_contract: AccountId,
+    pub token_ids: Vec<TokenId>,
+}
+
+impl EventPayload for EventDropLog {
+    const EVENT: &'static str = "event_drop_create";
+}
+
+/// Keypom returned `token_id` sent to its drop
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventDropFailedLog {
+    pub keypom_contract: AccountId,
+    pub token_id: TokenId,
+}
+
+impl EventPayload for EventDropFailedLog {
+    const EVENT: &'static str = "event_drop_failed";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SocialConfigUpdateLog {
+    pub contract_id: AccountId,
+    pub enabled: bool,
+}
+
+impl EventPayload for SocialConfigUpdateLog {
+    const EVENT: &'static str = "social_config_update";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SocialPoolTopUpLog {
+    pub amount: U128,
+    /// Pool after the top-up
+    pub pool: U128,
+}
+
+impl EventPayload for SocialPoolTopUpLog {
+    const EVENT: &'static str = "social_pool_top_up";
+}
+
+/// A social DB post about `token_id` failed, its `deposit` went back to the pool
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SocialPostFailedLog {
+    pub token_id: TokenId,
+    pub deposit: U128,
+}
+
+impl EventPayload for SocialPostFailedLog {
+    const EVENT: &'static str = "social_post_failed";
+}
+
+/// The contract owner lets `operator_id` mint tokens of `series_id`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeriesOperatorApproveLog {
+    pub series_id: String,
+    pub operator_id: AccountId,
+}
+
+impl EventPayload for SeriesOperatorApproveLog {
+    const EVENT: &'static str = "series_operator_approve";
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeriesOperatorRevokeLog {
+    pub series_id: String,
+    pub operator_id: AccountId,
+}
+
+impl EventPayload for SeriesOperatorRevokeLog {
+    const EVENT: &'static str = "series_operator_revoke";
+}
+
+/// The owner locks a token as loan collateral
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftCollateralLockLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub lender_contract: AccountId,
+    pub loan_ref: String,
+}
+
+impl EventPayload for NftCollateralLockLog {
+    const EVENT: &'static str = "nft_collateral_lock";
+}
+
+/// The lender unlocks a token once its loan is repaid
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftCollateralReleaseLog {
+    pub token_id: TokenId,
+    pub lender_contract: AccountId,
+    pub loan_ref: String,
+}
+
+impl EventPayload for NftCollateralReleaseLog {
+    const EVENT: &'static str = "nft_collateral_release";
+}
+
+/// The lender transfers a locked token to `receiver_id` settling its loan
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftCollateralLiquidateLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub receiver_id: AccountId,
+    pub lender_contract: AccountId,
+    pub loan_ref: String,
+    pub price: U128,
+}
+
+impl EventPayload for NftCollateralLiquidateLog {
+    const EVENT: &'static str = "nft_collateral_liquidate";
+}
+
+/// The contract owner changes the account releasing bridged tokens
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeAccountUpdateLog {
+    pub bridge_account_id: Option<AccountId>,
+}
+
+impl EventPayload for BridgeAccountUpdateLog {
+    const EVENT: &'static str = "bridge_account_update";
+}
+
+/// The contract owner changes the account transfers to which burn the token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NullAccountUpdateLog {
+    pub null_account: Option<AccountId>,
+}
+
+impl EventPayload for NullAccountUpdateLog {
+    const EVENT: &'static str = "null_account_update";
+}
+
+/// The owner moves a token into bridge custody to be wrapped for `destination`,
+/// watched by the bridge relayer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeLockLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub destination: String,
+}
+
+impl EventPayload for BridgeLockLog {
+    const EVENT: &'static str = "bridge_lock";
+}
+
+/// The bridge releases a token locked by `owner_id` to `receiver_id`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeUnlockLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub receiver_id: AccountId,
+}
+
+impl EventPayload for BridgeUnlockLog {
+    const EVENT: &'static str = "bridge_unlock";
+}
+
+/// The contract owner closes minting for good
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintingClosedLog {}
+
+impl EventPayload for MintingClosedLog {
+    const EVENT: &'static str = "minting_closed";
+}
+
+/// An FT donation reached the charity account and the donor received a badge
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDonationLog {
+    pub donor_id: AccountId,
+    pub ft_token_id: AccountId,
+    pub amount: U128,
+    pub badge_id: TokenId,
+}
+
+impl EventPayload for FtDonationLog {
+    const EVENT: &'static str = "ft_donation";
+}
+
+/// Forwarding an FT donation failed, the FT is returned to the donor
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDonationFailedLog {
+    pub donor_id: AccountId,
+    pub ft_token_id: AccountId,
+    pub amount: U128,
+}
+
+impl EventPayload for FtDonationFailedLog {
+    const EVENT: &'static str = "ft_donation_failed";
+}
+
+/// The contract owner allows or disallows a market to have FT payouts distributed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutMarketUpdateLog {
+    pub market_id: AccountId,
+    pub allowed: bool,
+}
+
+impl EventPayload for PayoutMarketUpdateLog {
+    const EVENT: &'static str = "payout_market_update";
+}
+
+/// The contract owner accepts or refuses an FT for the price of payout market sales
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutFtTokenUpdateLog {
+    pub ft_contract: AccountId,
+    pub accepted: bool,
+}
+
+impl EventPayload for PayoutFtTokenUpdateLog {
+    const EVENT: &'static str = "payout_ft_token_update";
+}
+
+/// A market has the FT price of a sale paid out by the contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPayoutDistributeLog {
+    pub token_id: TokenId,
+    pub seller_id: AccountId,
+    pub ft_contract: AccountId,
+    pub amount: U128,
+}
+
+impl EventPayload for FtPayoutDistributeLog {
+    const EVENT: &'static str = "ft_payout_distribute";
+}
+
+/// An FT payout share couldn't be transferred and is kept claimable
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPayoutFailedLog {
+    pub account_id: AccountId,
+    pub ft_contract: AccountId,
+    pub amount: U128,
+}
+
+impl EventPayload for FtPayoutFailedLog {
+    const EVENT: &'static str = "ft_payout_failed";
+}
+
+/// A payee claims FT payouts whose transfer failed before
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPayoutClaimLog {
+    pub account_id: AccountId,
+    pub ft_contract: AccountId,
+    pub amount: U128,
+}
+
+impl EventPayload for FtPayoutClaimLog {
+    const EVENT: &'static str = "ft_payout_claim";
+}
+
+/// The contract owner takes a governance snapshot
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GovernanceSnapshotLog {
+    pub snapshot_id: String,
+    pub block_height: U64,
+}
+
+impl EventPayload for GovernanceSnapshotLog {
+    const EVENT: &'static str = "governance_snapshot";
+}
+
+/// The token owner approves an account, `conditions` are set by `nft_set_approval_with_conditions`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftApproveLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub approved_account_id: AccountId,
+    pub approval_id: U64,
+    pub conditions: Option<ApprovalConditions>,
+}
+
+impl EventPayload for NftApproveLog {
+    const EVENT: &'static str = "nft_approve";
+}
+
+/// The token owner revokes an approval
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftRevokeLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub account_id: AccountId,
+}
+
+impl EventPayload for NftRevokeLog {
+    const EVENT: &'static str = "nft_revoke";
+}
+
+/// The token owner revokes all approvals
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftRevokeAllLog {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+}
+
+impl EventPayload for NftRevokeAllLog {
+    const EVENT: &'static str = "nft_revoke_all";
+}
+
+/// The contract owner deploys a new collection
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionCreateLog {
+    pub collection_id: AccountId,
+    pub owner_id: AccountId,
+    pub name: String,
+    pub symbol: String,
+    pub max_supply: U64,
+}
+
+impl EventPayload for CollectionCreateLog {
+    const EVENT: &'static str = "collection_create";
+}
+
+/// The owner stages code to be deployed by `upgrade`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CodeStageLog {
+    #[serde(flatten)]
+    pub staged_code: StagedCode,
+}
+
+impl EventPayload for CodeStageLog {
+    const EVENT: &'static str = "code_stage";
+}
+
+/// The owner replaces the contract icon, logged by its hash as the icon itself may not fit a log
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftContractIconUpdatedLog {
+    pub icon_hash: Base58CryptoHash,
+    /// Length of the data URL in bytes
+    pub icon_len: u32,
+}
+
+impl EventPayload for NftContractIconUpdatedLog {
+    const EVENT: &'static str = "nft_contract_icon_updated";
+}
+
+/// The owner discards the staged code
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CodeDiscardLog {
+    pub hash: Base58CryptoHash,
+}
+
+impl EventPayload for CodeDiscardLog {
+    const EVENT: &'static str = "code_discard";
+}
+
+/// The staged code is deployed to this contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CodeDeployLog {
+    pub hash: Base58CryptoHash,
+}
+
+impl EventPayload for CodeDeployLog {
+    const EVENT: &'static str = "code_deploy";
+}
+
+/// A chunk of tokens is swept by `migrate_tokens`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenExtrasMigrateLog {
+    pub swept: U64,
+    /// Records rewritten in the latest layout
+    pub migrated: U64,
+    pub done: bool,
+}
+
+impl EventPayload for TokenExtrasMigrateLog {
+    const EVENT: &'static str = "token_extras_migrate";
+}
+
+/// A chunk of exported state is imported
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StateImportLog {
+    pub kind: String,
+    pub records: U64,
+}
+
+impl EventPayload for StateImportLog {
+    const EVENT: &'static str = "state_import";
+}
+
+/// The stored state is rewritten in the current layout
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StateMigrateLog {
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+impl EventPayload for StateMigrateLog {
+    const EVENT: &'static str = "state_migrate";
+}
+
+/// A single token is minted, with the details off-chain systems would otherwise query afterwards
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintReceiptLog {
+    pub token_id: TokenId,
+    pub owner: AccountId,
+    pub block_height: U64,
+    /// Block timestamp in seconds
+    pub timestamp: U64,
+    /// yoctoNEAR charged for the storage of the token
+    pub storage_charged: U128,
+}
+
+impl EventPayload for MintReceiptLog {
+    const EVENT: &'static str = "mint_receipt";
+}
+
+/// Events every public mutating method must emit in its main scenario, checked by
+/// `test_no_mutating_method_is_silent`. A new mutating method has to be registered here
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) const METHOD_EVENTS: &[(&str, &[&str])] = &[
+    ("nft_mint_all", &["nft_mint"]),
+    ("nft_mint", &["nft_mint", "mint_receipt"]),
+    ("nft_mint_with_receipt", &["nft_mint", "mint_receipt"]),
+    ("nft_mint_with_collaborators", &["nft_mint", "mint_receipt"]),
+    ("nft_mint_curve", &["nft_mint", "mint_receipt"]),
+    ("nft_migrate_from_paras", &["nft_mint", "mint_receipt"]),
+    ("set_paras_ft_token", &["paras_ft_token_update"]),
+    ("set_paras_marketplace", &["paras_marketplace_update"]),
+    ("nft_airdrop", &["nft_mint"]),
+    ("nft_transfer", &["nft_transfer"]),
+    ("set_null_account", &["null_account_update"]),
+    ("nft_transfer_call", &["nft_transfer"]),
+    ("nft_resolve_transfer", &["nft_transfer"]),
+    ("nft_transfer_payout", &["nft_transfer"]),
+    ("nft_batch_transfer_to_self", &["nft_transfer"]),
+    ("nft_transfer_all", &["nft_transfer", "nft_transfer_all"]),
+    ("set_min_royalty_bps", &["royalty_policy_update"]),
+    ("set_default_royalties", &["royalty_policy_update"]),
+    ("set_payout_market", &["payout_market_update"]),
+    ("set_payout_ft_token", &["payout_ft_token_update"]),
+    ("on_ft_payout", &["ft_payout_failed"]),
+    ("claim_ft_payout", &["ft_payout_claim"]),
+    (
+        "nft_run_integration_diagnostics",
+        &["integration_diagnostics"],
+    ),
+    ("nft_burn", &["nft_burn"]),
+    ("nft_approve", &["nft_approve"]),
+    ("nft_approve_with_refund", &["nft_approve"]),
+    ("nft_set_approval_with_conditions", &["nft_approve"]),
+    ("nft_batch_approve", &["nft_approve"]),
+    ("nft_revoke", &["nft_revoke"]),
+    ("nft_revoke_all", &["nft_revoke_all"]),
+    ("list_for_sale", &["nft_list"]),
+    ("list_for_sale_until", &["nft_list"]),
+    ("prune_listing", &["nft_delist"]),
+    ("nft_list_for_sale_with_offers", &["nft_list"]),
+    ("nft_make_offer", &["nft_offer"]),
+    ("create_lazy_listing", &["nft_lazy_list"]),
+    ("buy_lazy", &["nft_mint", "mint_receipt"]),
+    ("nft_withdraw_offer", &["nft_offer_withdraw"]),
+    (
+        "nft_accept_offer",
+        &["nft_transfer", "nft_delist", "nft_sale"],
+    ),
+    ("delist", &["nft_delist"]),
+    ("nft_bulk_delist", &["nft_bulk_delist"]),
+    ("list_for_sale_ft", &["nft_list"]),
+    ("list_dutch_auction", &["nft_list_dutch"]),
+    ("buy", &["nft_transfer", "nft_delist", "nft_sale"]),
+    (
+        "ft_on_transfer",
+        &["nft_transfer", "nft_delist", "nft_sale"],
+    ),
+    (
+        "nft_dutch_buy_and_list",
+        &["nft_transfer", "nft_delist", "nft_sale", "nft_list"],
+    ),
+    ("nft_bulk_update_sale_prices", &["nft_bulk_price_update"]),
+    ("set_min_sale_price", &["min_sale_price_update"]),
+    (
+        "nft_set_floor_price_and_delist_above",
+        &["nft_delist", "min_sale_price_update"],
+    ),
+    ("set_default_media", &["default_media_update"]),
+    ("set_reveal_config", &["reveal_config_update"]),
+    ("set_ft_mint_price", &["ft_mint_price_update"]),
+    (
+        "ft_mint_storage_pool_top_up",
+        &["ft_mint_storage_pool_top_up"],
+    ),
+    ("audit_storage_pool_top_up", &["audit_storage_pool_top_up"]),
+    ("set_build_info", &["build_info_set"]),
+    #[cfg(feature = "testnet")]
+    ("nft_mint_test", &["nft_mint", "mint_receipt"]),
+    ("pay_to_reveal", &["nft_reveal"]),
+    ("set_default_marketplace", &["default_marketplace_update"]),
+    ("set_platform_fee", &["platform_fee_update"]),
+    (
+        "set_default_max_resale_price",
+        &["default_resale_cap_update"],
+    ),
+    ("nft_mint_with_resale_cap", &["nft_mint", "mint_receipt"]),
+    ("set_stake_points_per_day", &["stake_points_rate_update"]),
+    ("nft_stake", &["nft_stake"]),
+    ("nft_lease", &["nft_lease"]),
+    ("nft_accept_lease", &["nft_lease_accept"]),
+    ("nft_attach", &["nft_attach"]),
+    ("nft_update_contract_icon", &["nft_contract_icon_updated"]),
+    ("nft_detach", &["nft_detach"]),
+    ("nft_unstake", &["nft_unstake"]),
+    (
+        "set_global_transfer_unlock",
+        &["global_transfer_unlock_set"],
+    ),
+    ("set_mint_curve", &["mint_curve_update"]),
+    ("set_mint_payout_accounts", &["mint_payout_accounts_update"]),
+    (
+        "set_max_daily_mints_per_account",
+        &["max_daily_mints_update"],
+    ),
+    ("close_minting", &["minting_closed"]),
+    ("nft_approve_collection", &["series_operator_approve"]),
+    ("nft_revoke_collection", &["series_operator_revoke"]),
+    ("nft_mint_series", &["nft_mint", "mint_receipt"]),
+    ("nft_lock_as_collateral", &["nft_collateral_lock"]),
+    ("nft_release_collateral", &["nft_collateral_release"]),
+    ("set_bridge_account", &["bridge_account_update"]),
+    ("bridge_lock", &["nft_transfer", "bridge_lock"]),
+    ("bridge_unlock", &["nft_transfer", "bridge_unlock"]),
+    (
+        "nft_liquidate_collateral",
+        &["nft_transfer", "nft_collateral_liquidate"],
+    ),
+    (
+        "nft_transfer_ownership_of_contract",
+        &["contract_ownership_transferred"],
+    ),
+    (
+        "set_charity_account",
+        &["charity_account_update", "charity_change_scheduled"],
+    ),
+    ("execute_charity_change", &["charity_change_executed"]),
+    ("set_donation_ft_token", &["donation_ft_token_update"]),
+    ("on_charity_transfer", &["donation_failed"]),
+    ("on_donation", &["donation_failed"]),
+    ("retry_charity_transfer", &["charity_retry"]),
+    ("start_matching_campaign", &["matching_campaign_start"]),
+    ("close_matching_campaign", &["matching_campaign_close"]),
+    ("on_charity_retry", &["charity_retry"]),
+    ("on_ft_donation", &["ft_donation", "nft_mint"]),
+    ("on_refund_complete", &["refund_failed"]),
+    ("create_event_drop", &["nft_transfer", "event_drop_create"]),
+    ("propose_external_swap", &["external_swap_propose"]),
+    ("cancel_external_swap", &["external_swap_cancel"]),
+    (
+        "nft_on_transfer",
+        &["nft_transfer", "external_swap_complete"],
+    ),
+    (
+        "on_external_swap_forward",
+        &["external_swap_forward_failed"],
+    ),
+    (
+        "retry_external_swap_forward",
+        &["external_swap_forward_retry"],
+    ),
+    ("on_event_drop", &["nft_transfer", "event_drop_failed"]),
+    ("set_social_config", &["social_config_update"]),
+    ("social_pool_top_up", &["social_pool_top_up"]),
+    ("on_social_post", &["social_post_failed"]),
+    ("nft_take_governance_snapshot", &["governance_snapshot"]),
+    ("update_token_metadata", &["nft_metadata_update"]),
+    ("set_token_media", &["nft_metadata_update"]),
+    ("nft_create_collection", &["collection_create"]),
+    ("migrate", &["state_migrate"]),
+    ("import_state_chunk", &["state_import"]),
+    ("migrate_tokens", &["token_extras_migrate"]),
+    ("stage_code", &["code_stage"]),
+    ("discard_staged_code", &["code_discard"]),
+    ("upgrade", &["code_deploy"]),
+];
+
+/// Longest log line indexers are guaranteed to accept
+const MAX_EVENT_LOG_LEN: usize = 16 * 1024;
+
+fn as_str_ids(token_ids: &[TokenId]) -> Vec<&str> {
+    token_ids.iter().map(String::as_str).collect()
+}
+
+fn json_len<T: Serialize + ?Sized>(value: &T) -> usize {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| env::panic_str("Cannot serialize event"))
+        .len()
+}
+
+/// Length of an `EVENT_JSON` log line of the NEP-171 `event` with empty data
+fn nep171_envelope_len(event: &str) -> usize {
+    format!(
+        r#"EVENT_JSON:{{"standard":"{}","version":"{}","event":"{}","data":[]}}"#,
+        NEP171_STANDARD, NEP171_VERSION, event
+    )
+    .len()
+}
+
+/// Group token IDs by key (e.g. owner) in the order keys first appear,
+/// then split the groups into chunks each fitting a single log line.
+/// `entry_len` is the serialized length of a data entry of the key with no token IDs
+fn chunk_grouped_token_ids<'a, K: Clone + Eq + std::hash::Hash>(
+    items: impl IntoIterator<Item = (K, &'a TokenId)>,
+    envelope_len: usize,
+    entry_len: impl Fn(&K) -> usize,
+) -> Vec<Vec<(K, Vec<&'a str>)>> {
+    let mut groups: Vec<(K, Vec<&str>)> = vec![];
+    let mut group_index = HashMap::new();
+    for (key, token_id) in items {
+        let index = *group_index.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, vec![]));
+            groups.len() - 1
+        });
+        groups[index].1.push(token_id.as_str());
+    }
+
+    let mut chunks: Vec<Vec<(K, Vec<&str>)>> = vec![];
+    let mut chunk: Vec<(K, Vec<&str>)> = vec![];
+    let mut chunk_len = envelope_len;
+    for (key, token_ids) in groups {
+        let mut entry_open = false;
+        for token_id in token_ids {
+            let token_id_len = json_len(token_id);
+            // `,"<token_id>"` appended to the open entry
+            if entry_open && chunk_len + 1 + token_id_len <= MAX_EVENT_LOG_LEN {
+                chunk_len += 1 + token_id_len;
+            } else {
+                // a new entry, preceded by a comma unless it's the first one of the chunk
+                let new_entry_len = |chunk: &Vec<_>| {
+                    usize::from(!chunk.is_empty()) + entry_len(&key) + token_id_len
+                };
+                if !chunk.is_empty() && chunk_len + new_entry_len(&chunk) > MAX_EVENT_LOG_LEN {
+                    chunks.push(std::mem::take(&mut chunk));
+                    chunk_len = envelope_len;
+                }
+                chunk_len += new_entry_len(&chunk);
+                chunk.push((key.clone(), vec![]));
+                entry_open = true;
+            }
+            if let Some((_, entry_token_ids)) = chunk.last_mut() {
+                entry_token_ids.push(token_id);
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// `authorized_id` is only reported for changes made by an approved account
+fn authorized<'a>(sender_id: Option<&'a AccountId>, owner_id: &AccountId) -> Option<&'a AccountId> {
+    sender_id.filter(|sender_id| *sender_id != owner_id)
+}
+
+/// Log a single `nft_mint` event for tokens minted to `owner_id`
+pub(crate) fn emit_mint(owner_id: &AccountId, token_ids: &[TokenId], memo: Option<&str>) {
+    emit_nep171(
+        "nft_mint",
+        vec![NftMint {
+            owner_id,
+            token_ids: &as_str_ids(token_ids),
+            memo,
+        }],
+    );
+}
+
+/// Log the receipt of `token` minted in the current block taking `storage_used` bytes.
+/// Returns the logged receipt
+pub(crate) fn emit_mint_receipt(token: &Token, storage_used: StorageUsage) -> MintReceiptLog {
+    let receipt = MintReceiptLog {
+        token_id: token.token_id.clone(),
+        owner: token.owner_id.clone(),
+        block_height: U64(env::block_height()),
+        timestamp: U64(env::block_timestamp() / 1_000_000_000),
+        storage_charged: U128(Balance::from(storage_used) * env::storage_byte_cost()),
+    };
+    emit(vec![receipt.clone()]);
+    receipt
+}
+
+/// Log `nft_mint` events for tokens minted to several owners, grouping the tokens per owner.
+/// Entries are split across as few log lines as fit `MAX_EVENT_LOG_LEN`
+pub(crate) fn emit_mint_batch(minted: &[(AccountId, TokenId)]) {
+    let chunks = chunk_grouped_token_ids(
+        minted
+            .iter()
+            .map(|(owner_id, token_id)| (owner_id, token_id)),
+        nep171_envelope_len("nft_mint"),
+        |owner_id| {
+            json_len(&NftMint {
+                owner_id,
+                token_ids: &[],
+                memo: None,
+            })
+        },
+    );
+    for chunk in chunks {
+        let data: Vec<NftMint> = chunk
+            .iter()
+            .map(|(owner_id, token_ids)| NftMint {
+                owner_id,
+                token_ids,
+                memo: None,
+            })
+            .collect();
+        emit_nep171("nft_mint", data);
+    }
+}
+
+/// Log a single `nft_transfer` event, `sender_id` is the account that initiated the transfer
+pub(crate) fn emit_transfer(
+    old_owner_id: &AccountId,
+    new_owner_id: &AccountId,
+    token_ids: &[TokenId],
+    sender_id: Option<&AccountId>,
+    memo: Option<&str>,
+) {
+    emit_nep171(
+        "nft_transfer",
+        vec![NftTransfer {
+            old_owner_id,
+            new_owner_id,
+            token_ids: &as_str_ids(token_ids),
+            authorized_id: authorized(sender_id, old_owner_id),
+            memo,
+        }],
+    );
+}
+
+/// Log `nft_transfer` events for tokens of several owners transferred to `receiver_id` by the
+/// receiver itself, grouping the tokens per previous owner. Entries are split across as few
+/// log lines as fit `MAX_EVENT_LOG_LEN`
+pub(crate) fn emit_transfer_batch(transferred: &[(AccountId, TokenId)], receiver_id: &AccountId) {
+    let chunks = chunk_grouped_token_ids(
+        transferred
+            .iter()
+            .map(|(old_owner_id, token_id)| (old_owner_id, token_id)),
+        nep171_envelope_len("nft_transfer"),
+        |old_owner_id| {
+            json_len(&NftTransfer {
+                old_owner_id,
+                new_owner_id: receiver_id,
+                token_ids: &[],
+                authorized_id: Some(receiver_id),
+                memo: None,
+            })
+        },
+    );
+    for chunk in chunks {
+        let data: Vec<NftTransfer> = chunk
+            .iter()
+            .map(|(old_owner_id, token_ids)| NftTransfer {
+                old_owner_id,
+                new_owner_id: receiver_id,
+                token_ids,
+                authorized_id: Some(receiver_id),
+                memo: None,
+            })
+            .collect();
+        emit_nep171("nft_transfer", data);
+    }
+}
+
+/// Log a single `nft_burn` event for tokens of `owner_id` burned by the owner
+pub(crate) fn emit_burn(owner_id: &AccountId, token_ids: &[TokenId], memo: Option<&str>) {
+    emit_nep171(
+        "nft_burn",
+        vec![NftBurn {
+            owner_id,
+            token_ids: &as_str_ids(token_ids),
+            authorized_id: None,
+            memo,
+        }],
+    );
+}
+
+/// Data entry of the NEP-171 `nft_metadata_update` event
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftMetadataUpdate<'a> {
+    token_ids: &'a [&'a str],
+}
+
+/// Log `nft_metadata_update` for tokens whose metadata changed, all in a single entry
+/// unless they don't fit `MAX_EVENT_LOG_LEN`
+pub(crate) fn emit_metadata_update(token_ids: &[TokenId]) {
+    let chunks = chunk_grouped_token_ids(
+        token_ids.iter().map(|token_id| ((), token_id)),
+        nep171_envelope_len("nft_metadata_update"),
+        |_| json_len(&NftMetadataUpdate { token_ids: &[] }),
+    );
+    for chunk in chunks {
+        let data: Vec<NftMetadataUpdate> = chunk
+            .iter()
+            .map(|(_, token_ids)| NftMetadataUpdate { token_ids })
+            .collect();
+        emit_nep171("nft_metadata_update", data);
+    }
+}
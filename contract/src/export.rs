@@ -0,0 +1,349 @@
+/*!
+Export and import of the contract state in chunks, to rehearse an upgrade on testnet with a
+copy of the mainnet state. Chunks are Borsh-encoded lists of records of one kind:
+
+- `tokens`: owner, metadata and activity of every token
+- `approvals`: approvals of a token along with their conditions
+- `extras`: royalties and collaborators of a token
+- `sales`: listings
+- `config`: contract metadata and settings, a single record
+
+Export is a plain view. Records of the token kinds are paged by token ID, a chunk returns the
+cursor of the next one and may hold fewer records than `limit`. Import rebuilds the indexes
+(enumeration, royalty payees) from the records.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::metadata::{NFTContractMetadata, TokenMetadata};
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance};
+
+use crate::approval::ApprovalConditions;
+use crate::audit::TokenActivity;
+use crate::curve::MintCurve;
+use crate::events::{self, StateImportLog};
+use crate::extras::{self, TokenExtras};
+use crate::sale::Sale;
+use crate::{Contract, ContractExt};
+
+/// Bounds the size of a chunk, keeping it well under the return value and gas limits
+const MAX_EXPORT_CHUNK_LEN: u64 = 50;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StateChunk {
+    /// Borsh-encoded records
+    pub data: Base64VecU8,
+    /// `from_token_id` of the next chunk, `None` after the last one
+    pub next_token_id: Option<TokenId>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct TokenRecord {
+    token_id: TokenId,
+    owner_id: AccountId,
+    metadata: Option<TokenMetadata>,
+    activity: Option<TokenActivity>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ApprovalRecord {
+    token_id: TokenId,
+    approvals: HashMap<AccountId, u64>,
+    next_approval_id: Option<u64>,
+    conditions: Vec<(AccountId, ApprovalConditions)>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ExtrasRecord {
+    token_id: TokenId,
+    extras: TokenExtras,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct SaleRecord {
+    token_id: TokenId,
+    sale: Sale,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ConfigRecord {
+    metadata: NFTContractMetadata,
+    default_media: Option<String>,
+    default_marketplace: Option<AccountId>,
+    mint_curve: Option<MintCurve>,
+    charity_account_id: Option<AccountId>,
+    min_sale_price: Balance,
+}
+
+fn encode<T: BorshSerialize>(records: Vec<T>) -> Base64VecU8 {
+    records
+        .try_to_vec()
+        .unwrap_or_else(|_| env::panic_str("Cannot serialize state chunk"))
+        .into()
+}
+
+fn decode<T: BorshDeserialize>(data: Base64VecU8) -> Vec<T> {
+    Vec::<T>::try_from_slice(&data.0).unwrap_or_else(|_| env::panic_str("Invalid state chunk"))
+}
+
+impl Contract {
+    /// IDs of the tokens after `from_token_id` in ID order
+    fn token_ids_page(&self, from_token_id: &Option<TokenId>, limit: u64) -> Vec<TokenId> {
+        match from_token_id {
+            Some(token_id) => self
+                .tokens
+                .owner_by_id
+                .iter_from(token_id.clone())
+                .take(limit as usize)
+                .map(|(token_id, _)| token_id)
+                .collect(),
+            None => self
+                .tokens
+                .owner_by_id
+                .iter()
+                .take(limit as usize)
+                .map(|(token_id, _)| token_id)
+                .collect(),
+        }
+    }
+
+    fn internal_import_tokens(&mut self, records: Vec<TokenRecord>) -> usize {
+        let mut imported = Vec::with_capacity(records.len());
+        for record in records {
+            self.tokens.internal_mint_with_refund(
+                record.token_id.clone(),
+                record.owner_id.clone(),
+                record.metadata,
+                None,
+            );
+            if let Some(activity) = record.activity {
+                self.token_activity.insert(&record.token_id, &activity);
+            }
+            imported.push((record.owner_id, record.token_id));
+        }
+        events::emit_mint_batch(&imported);
+        imported.len()
+    }
+
+    fn internal_import_approvals(&mut self, records: Vec<ApprovalRecord>) -> usize {
+        let count = records.len();
+        for record in records {
+            require!(
+                self.tokens.owner_by_id.contains_key(&record.token_id),
+                "Token not found"
+            );
+            if let Some(approvals_by_id) = self.tokens.approvals_by_id.as_mut() {
+                approvals_by_id.insert(&record.token_id, &record.approvals);
+            }
+            if let (Some(next_approval_id_by_id), Some(next_approval_id)) = (
+                self.tokens.next_approval_id_by_id.as_mut(),
+                record.next_approval_id,
+            ) {
+                next_approval_id_by_id.insert(&record.token_id, &next_approval_id);
+            }
+            for (account_id, conditions) in record.conditions {
+                self.conditional_approvals
+                    .insert(&(record.token_id.clone(), account_id), &conditions);
+            }
+        }
+        count
+    }
+
+    fn internal_import_extras(&mut self, records: Vec<ExtrasRecord>) -> usize {
+        let count = records.len();
+        for ExtrasRecord { token_id, extras } in records {
+            require!(
+                self.tokens.owner_by_id.contains_key(&token_id),
+                "Token not found"
+            );
+            self.internal_set_royalties(&token_id, &extras.royalties);
+            self.internal_update_token_extras(&token_id, |token_extras| {
+                token_extras.collaborators = extras.collaborators;
+            });
+        }
+        count
+    }
+
+    fn internal_import_sales(&mut self, records: Vec<SaleRecord>) -> usize {
+        let count = records.len();
+        for SaleRecord { token_id, sale } in records {
+            require!(
+                self.tokens.owner_by_id.get(&token_id) == Some(sale.owner_id.clone()),
+                "Sale of a token not owned by the seller"
+            );
+            if let Some(previous_sale) = self.sales.insert(&token_id, &sale) {
+                self.internal_unindex_listing(&token_id, &previous_sale);
+            }
+            self.internal_index_listing(&token_id, &sale);
+        }
+        count
+    }
+
+    fn internal_import_config(&mut self, records: Vec<ConfigRecord>) -> usize {
+        let count = records.len();
+        for record in records {
+            self.metadata.set(&record.metadata);
+            self.default_media = record.default_media;
+            self.default_marketplace = record.default_marketplace;
+            self.mint_curve = record.mint_curve;
+            self.charity_account_id = record.charity_account_id;
+            self.min_sale_price = record.min_sale_price;
+        }
+        count
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Records of `kind` of the tokens after `from_token_id`, at most 50 tokens per call
+    pub fn export_state_chunk(
+        &self,
+        kind: String,
+        from_token_id: Option<TokenId>,
+        limit: u64,
+    ) -> StateChunk {
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let limit = limit.min(MAX_EXPORT_CHUNK_LEN);
+        if kind == "config" {
+            return StateChunk {
+                data: encode(if from_token_id.is_none() {
+                    vec![ConfigRecord {
+                        metadata: self.metadata.get().unwrap(),
+                        default_media: self.default_media.clone(),
+                        default_marketplace: self.default_marketplace.clone(),
+                        mint_curve: self.mint_curve.clone(),
+                        charity_account_id: self.charity_account_id.clone(),
+                        min_sale_price: self.min_sale_price,
+                    }]
+                } else {
+                    vec![]
+                }),
+                next_token_id: None,
+            };
+        }
+        let token_ids = self.token_ids_page(&from_token_id, limit);
+        let next_token_id = token_ids
+            .last()
+            .filter(|token_id| self.tokens.owner_by_id.higher(token_id).is_some())
+            .cloned();
+        let data = match kind.as_str() {
+            "tokens" => encode(
+                token_ids
+                    .into_iter()
+                    .map(|token_id| TokenRecord {
+                        owner_id: self
+                            .tokens
+                            .owner_by_id
+                            .get(&token_id)
+                            .unwrap_or_else(|| env::panic_str("Token not found")),
+                        metadata: self
+                            .tokens
+                            .token_metadata_by_id
+                            .as_ref()
+                            .and_then(|by_id| by_id.get(&token_id)),
+                        activity: self.token_activity.get(&token_id),
+                        token_id,
+                    })
+                    .collect(),
+            ),
+            "approvals" => encode(
+                token_ids
+                    .into_iter()
+                    .filter_map(|token_id| {
+                        let approvals = self
+                            .tokens
+                            .approvals_by_id
+                            .as_ref()
+                            .and_then(|by_id| by_id.get(&token_id))
+                            .unwrap_or_default();
+                        let next_approval_id = self
+                            .tokens
+                            .next_approval_id_by_id
+                            .as_ref()
+                            .and_then(|by_id| by_id.get(&token_id));
+                        if approvals.is_empty() && next_approval_id.is_none() {
+                            return None;
+                        }
+                        let conditions = approvals
+                            .keys()
+                            .filter_map(|account_id| {
+                                let conditions = self
+                                    .conditional_approvals
+                                    .get(&(token_id.clone(), account_id.clone()))?;
+                                Some((account_id.clone(), conditions))
+                            })
+                            .collect();
+                        Some(ApprovalRecord {
+                            token_id,
+                            approvals,
+                            next_approval_id,
+                            conditions,
+                        })
+                    })
+                    .collect(),
+            ),
+            "extras" => encode(
+                token_ids
+                    .into_iter()
+                    .filter(|token_id| {
+                        self.token_extras.contains_key(token_id)
+                            || extras::legacy_royalties().contains_key(token_id)
+                            || self.token_collaborators.contains_key(token_id)
+                    })
+                    .map(|token_id| ExtrasRecord {
+                        extras: self.internal_token_extras(&token_id),
+                        token_id,
+                    })
+                    .collect(),
+            ),
+            "sales" => encode(
+                token_ids
+                    .into_iter()
+                    .filter_map(|token_id| {
+                        let sale = self.sales.get(&token_id)?;
+                        Some(SaleRecord { token_id, sale })
+                    })
+                    .collect(),
+            ),
+            _ => env::panic_str("Unknown state chunk kind"),
+        };
+        StateChunk {
+            data,
+            next_token_id,
+        }
+    }
+
+    /// Import a chunk exported by `export_state_chunk`. Only possible before anything is
+    /// minted on this contract, tokens must be imported before the records referring to them.
+    /// Storage is paid by the owner
+    #[payable]
+    pub fn import_state_chunk(&mut self, kind: String, data: Base64VecU8) {
+        self.assert_owner();
+        require!(
+            !self.has_minted,
+            "State can only be imported before minting"
+        );
+        let initial_storage = env::storage_usage();
+        let records = match kind.as_str() {
+            "tokens" => self.internal_import_tokens(decode(data)),
+            "approvals" => self.internal_import_approvals(decode(data)),
+            "extras" => self.internal_import_extras(decode(data)),
+            "sales" => self.internal_import_sales(decode(data)),
+            "config" => self.internal_import_config(decode(data)),
+            _ => env::panic_str("Unknown state chunk kind"),
+        };
+        refund_deposit_to_account(
+            env::storage_usage().saturating_sub(initial_storage),
+            env::predecessor_account_id(),
+        );
+        self.internal_audit("import_state", None, None);
+        events::emit(vec![StateImportLog {
+            kind,
+            records: U64(records as u64),
+        }]);
+    }
+}
@@ -0,0 +1,182 @@
+/*!
+Per-token records beyond the NEP-171 ones: royalties and collaborators.
+
+Records are versioned. A record in an older layout is read transparently and rewritten in the
+latest layout the first time it is written, so a layout change doesn't need a migration of
+every token in a single call. `migrate_tokens` sweeps the tokens in chunks to finish the job.
+
+Before records were versioned, royalties were stored as bare maps under a prefix of their own.
+Those are read from there and moved to the versioned records the same way.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, require, AccountId};
+
+use crate::collaborators::Collaborator;
+use crate::events::{self, TokenExtrasMigrateLog};
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Bounds gas used by a single `migrate_tokens` call
+const MAX_TOKENS_MIGRATED: u64 = 100;
+
+/// Royalties and collaborators of a token in the latest layout
+#[derive(BorshDeserialize, BorshSerialize, Default, Clone, Debug, PartialEq)]
+pub struct TokenExtras {
+    pub royalties: HashMap<AccountId, u16>,
+    pub collaborators: Vec<Collaborator>,
+}
+
+impl TokenExtras {
+    fn is_empty(&self) -> bool {
+        self.royalties.is_empty() && self.collaborators.is_empty()
+    }
+}
+
+/// Token record in the layout it was written with
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
+pub enum VersionedTokenExtras {
+    /// Royalties only, collaborators are kept in a record of their own
+    V1(HashMap<AccountId, u16>),
+    V2(TokenExtras),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenMigrationProgress {
+    /// Tokens visited by `migrate_tokens` so far
+    pub swept: U64,
+    pub done: bool,
+}
+
+/// Royalties written before records were versioned, collaborators are kept in a record of their own
+pub(crate) fn legacy_royalties() -> LookupMap<TokenId, HashMap<AccountId, u16>> {
+    LookupMap::new(StorageKey::Royalties)
+}
+
+impl Contract {
+    /// Royalties and collaborators of `token_id` in any layout
+    pub(crate) fn internal_token_extras(&self, token_id: &TokenId) -> TokenExtras {
+        match self.token_extras.get(token_id) {
+            Some(VersionedTokenExtras::V1(royalties)) => TokenExtras {
+                royalties,
+                collaborators: self.token_collaborators.get(token_id).unwrap_or_default(),
+            },
+            Some(VersionedTokenExtras::V2(extras)) => extras,
+            None => TokenExtras {
+                royalties: legacy_royalties().get(token_id).unwrap_or_default(),
+                collaborators: self.token_collaborators.get(token_id).unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Whether `token_id` has a record in a layout older than the latest one
+    fn has_outdated_token_extras(&self, token_id: &TokenId) -> bool {
+        match self.token_extras.get(token_id) {
+            Some(VersionedTokenExtras::V1(_)) => true,
+            Some(VersionedTokenExtras::V2(_)) => false,
+            None => {
+                legacy_royalties().contains_key(token_id)
+                    || self.token_collaborators.contains_key(token_id)
+            }
+        }
+    }
+
+    /// Change the royalties and collaborators of `token_id`, the record is written in the
+    /// latest layout. Storage is paid by the caller of the method
+    pub(crate) fn internal_update_token_extras(
+        &mut self,
+        token_id: &TokenId,
+        update: impl FnOnce(&mut TokenExtras),
+    ) {
+        let mut extras = self.internal_token_extras(token_id);
+        update(&mut extras);
+        legacy_royalties().remove(token_id);
+        self.token_collaborators.remove(token_id);
+        if extras.is_empty() {
+            self.token_extras.remove(token_id);
+        } else {
+            self.token_extras
+                .insert(token_id, &VersionedTokenExtras::V2(extras));
+        }
+    }
+
+    /// Remove the royalties and collaborators of `token_id` in any layout
+    pub(crate) fn internal_remove_token_extras(&mut self, token_id: &TokenId) -> TokenExtras {
+        let extras = self.internal_token_extras(token_id);
+        self.token_extras.remove(token_id);
+        legacy_royalties().remove(token_id);
+        self.token_collaborators.remove(token_id);
+        extras
+    }
+
+    fn is_token_migration_done(&self) -> bool {
+        match &self.token_migration_cursor {
+            Some(token_id) => self.tokens.owner_by_id.higher(token_id).is_none(),
+            None => self.tokens.owner_by_id.is_empty(),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Rewrite records of the next `limit` tokens in the latest layout. Anyone can call it,
+    /// storage is paid by the contract. Returns whether all tokens are migrated
+    pub fn migrate_tokens(&mut self, limit: u64) -> bool {
+        require!(
+            limit > 0 && limit <= MAX_TOKENS_MIGRATED,
+            format!("Limit must be between 1 and {}", MAX_TOKENS_MIGRATED)
+        );
+        require!(
+            !self.is_token_migration_done(),
+            "Token migration is complete"
+        );
+        let token_ids: Vec<TokenId> = match self.token_migration_cursor.clone() {
+            Some(cursor) => self
+                .tokens
+                .owner_by_id
+                .iter_from(cursor)
+                .take(limit as usize)
+                .map(|(token_id, _)| token_id)
+                .collect(),
+            None => self
+                .tokens
+                .owner_by_id
+                .iter()
+                .take(limit as usize)
+                .map(|(token_id, _)| token_id)
+                .collect(),
+        };
+
+        let mut migrated: u64 = 0;
+        for token_id in &token_ids {
+            if self.has_outdated_token_extras(token_id) {
+                self.internal_update_token_extras(token_id, |_| {});
+                migrated += 1;
+            }
+        }
+        self.token_migration_swept += token_ids.len() as u64;
+        if let Some(token_id) = token_ids.last() {
+            self.token_migration_cursor = Some(token_id.clone());
+        }
+        let done = self.is_token_migration_done();
+        self.internal_audit("migrate_tokens", None, None);
+        events::emit(vec![TokenExtrasMigrateLog {
+            swept: U64(token_ids.len() as u64),
+            migrated: U64(migrated),
+            done,
+        }]);
+        done
+    }
+
+    pub fn token_migration_progress(&self) -> TokenMigrationProgress {
+        TokenMigrationProgress {
+            swept: U64(self.token_migration_swept),
+            done: self.is_token_migration_done(),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+/*!
+Factory deploying new collection contracts as sub-accounts of this contract.
+
+The deployed WASM is expected to be this contract: it's initialized with `new`, which makes the
+factory the owner, and ownership is then handed to the caller in the same batch. `symbol` and
+`max_supply` are only recorded in the `collection_create` event.
+*/
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde_json::json;
+use near_sdk::{env, near_bindgen, require, AccountId, Gas};
+
+use crate::events::{self, CollectionCreateLog};
+use crate::{Contract, ContractExt};
+
+/// Largest collection contract accepted by the factory
+const MAX_COLLECTION_WASM_LEN: usize = 2 * 1024 * 1024;
+const GAS_FOR_COLLECTION_INIT: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_COLLECTION_OWNERSHIP: Gas = Gas(10_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+    /// Create `{name}.{current_account_id}`, fund it with the attached deposit, deploy `wasm`
+    /// and initialize it owned by the caller. Returns the new collection account
+    #[payable]
+    pub fn nft_create_collection(
+        &mut self,
+        name: String,
+        symbol: String,
+        max_supply: u64,
+        wasm: Base64VecU8,
+    ) -> AccountId {
+        self.assert_owner();
+        let wasm: Vec<u8> = wasm.into();
+        require!(!wasm.is_empty(), "Collection code is empty");
+        require!(
+            wasm.len() <= MAX_COLLECTION_WASM_LEN,
+            "Collection code exceeds 2 MB"
+        );
+        require!(max_supply > 0, "Max supply must be positive");
+        let collection_id: AccountId = format!("{}.{}", name, env::current_account_id())
+            .parse()
+            .unwrap_or_else(|_| env::panic_str("Invalid collection name"));
+
+        let ownership_args = json!({ "new_owner": env::predecessor_account_id() }).to_string();
+        let promise = env::promise_batch_create(&collection_id);
+        env::promise_batch_action_create_account(promise);
+        env::promise_batch_action_transfer(promise, env::attached_deposit());
+        env::promise_batch_action_deploy_contract(promise, &wasm);
+        env::promise_batch_action_function_call(promise, "new", b"", 0, GAS_FOR_COLLECTION_INIT);
+        env::promise_batch_action_function_call(
+            promise,
+            "nft_transfer_ownership_of_contract",
+            ownership_args.as_bytes(),
+            1,
+            GAS_FOR_COLLECTION_OWNERSHIP,
+        );
+        self.internal_audit("create_collection", None, Some(env::attached_deposit()));
+        events::emit(vec![CollectionCreateLog {
+            collection_id: collection_id.clone(),
+            owner_id: env::predecessor_account_id(),
+            name,
+            symbol,
+            max_supply: max_supply.into(),
+        }]);
+        collection_id
+    }
+}
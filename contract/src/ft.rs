@@ -0,0 +1,191 @@
+/*!
+Fungible token (NEP-141) payments. A token listed for an FT is bought by `ft_transfer_call`
+of the FT to this contract with a message naming the purchase, payouts are then made in
+the same FT.
+
+A new token may be minted for an FT in the same way when the owner sets an FT mint price.
+The price is sent on to the contract owner. The storage of such a token is paid from a pool
+of NEAR the owner tops up, a mint the pool can't cover is refunded. Payout markets send
+the FT price of their sales the same way to have it paid out, see `ft_payout`.
+*/
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    env, ext_contract, near_bindgen, require, serde_json, AccountId, Balance, Gas, Promise,
+    PromiseOrValue,
+};
+
+use crate::events::{self, FtMintPriceUpdateLog, FtMintStoragePoolTopUpLog};
+use crate::{Contract, ContractExt};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+
+/// Only the generated `ext_ft` is used
+#[allow(dead_code)]
+#[ext_contract(ext_ft)]
+trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+/// Currency a price is paid in
+#[derive(Clone, Debug, PartialEq)]
+pub enum PaymentToken {
+    Near,
+    /// NEP-141 token of the given contract
+    Ft(AccountId),
+}
+
+impl PaymentToken {
+    /// Send `amount` of the currency to `receiver_id`.
+    /// An FT transfer attaches one yoctoNEAR paid by this contract
+    pub(crate) fn send(&self, receiver_id: AccountId, amount: Balance) -> Promise {
+        match self {
+            Self::Near => Promise::new(receiver_id).transfer(amount),
+            Self::Ft(ft_contract_id) => ext_ft::ext(ft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(receiver_id, U128(amount), None),
+        }
+    }
+}
+
+/// Price of minting a token for an FT
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMintPrice {
+    pub ft_token_id: AccountId,
+    pub price: U128,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMintRequest {
+    pub token_id: TokenId,
+    pub metadata: TokenMetadata,
+}
+
+/// `msg` of an FT transfer to this contract
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum FtTransferMsg {
+    /// Buy the listed token, e.g. `{"buy":{"token_id":"0"}}`
+    Buy { token_id: TokenId },
+    /// Mint a new token to the sender, e.g. `{"mint":{"token_id":"0","metadata":{..}}}`
+    Mint(Box<FtMintRequest>),
+    /// Pay out the price of the token's sale settled by the sending market,
+    /// e.g. `{"distribute_payout":{"token_id":"0"}}`
+    DistributePayout { token_id: TokenId },
+}
+
+impl Contract {
+    /// Mint the requested token to `sender_id` if `amount` of `ft_token_id` is exactly
+    /// the FT mint price, its storage paid from the FT mint storage pool.
+    /// Returns the amount left unspent
+    fn internal_ft_mint(
+        &mut self,
+        ft_token_id: &AccountId,
+        sender_id: AccountId,
+        amount: Balance,
+        request: FtMintRequest,
+    ) -> Balance {
+        let price = match &self.ft_mint_price {
+            Some(FtMintPrice {
+                ft_token_id: price_token_id,
+                price,
+            }) if price_token_id == ft_token_id && price.0 == amount => price.0,
+            _ => return amount,
+        };
+        let initial_storage = env::storage_usage();
+        self.internal_count_daily_mint(&sender_id);
+        let token =
+            self.internal_mint_with_royalties(request.token_id, sender_id, request.metadata, None);
+        let storage_used = env::storage_usage() - initial_storage;
+        let storage_cost = Balance::from(storage_used) * env::storage_byte_cost();
+        require!(
+            storage_cost <= self.ft_mint_storage_pool,
+            "FT mint storage pool is too low"
+        );
+        self.ft_mint_storage_pool -= storage_cost;
+        self.internal_send_ft_payout(self.tokens.owner_id.clone(), ft_token_id.clone(), price);
+        self.internal_audit("mint", Some(&token.token_id), Some(price));
+        events::emit_mint(&token.owner_id, std::slice::from_ref(&token.token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        amount - price
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the price of minting a token for an FT, `None` disables FT mints
+    pub fn set_ft_mint_price(&mut self, ft_mint_price: Option<FtMintPrice>) {
+        self.assert_owner();
+        self.ft_mint_price = ft_mint_price.clone();
+        self.internal_audit("set_ft_mint_price", None, None);
+        events::emit(vec![FtMintPriceUpdateLog { ft_mint_price }]);
+    }
+
+    pub fn ft_mint_price(&self) -> Option<FtMintPrice> {
+        self.ft_mint_price.clone()
+    }
+
+    /// Add the attached deposit to the pool paying for the storage of FT mints
+    #[payable]
+    pub fn ft_mint_storage_pool_top_up(&mut self) {
+        self.assert_owner();
+        let amount = env::attached_deposit();
+        require!(amount > 0, "Requires attached deposit");
+        self.ft_mint_storage_pool += amount;
+        self.internal_audit("ft_mint_storage_pool_top_up", None, Some(amount));
+        events::emit(vec![FtMintStoragePoolTopUpLog {
+            amount: U128(amount),
+            pool: U128(self.ft_mint_storage_pool),
+        }]);
+    }
+
+    /// yoctoNEAR left to pay for the storage of FT mints
+    pub fn ft_mint_storage_pool(&self) -> U128 {
+        U128(self.ft_mint_storage_pool)
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// Handle an FT transfer as described by `msg`, the amount left unspent is returned
+    /// to the sender. A failed call or a mint paid with another amount returns the whole amount.
+    /// The `donate` message forwards the whole amount to the charity account
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        if msg == "donate" {
+            return self.internal_ft_donate(env::predecessor_account_id(), sender_id, amount.0);
+        }
+        let msg: FtTransferMsg =
+            serde_json::from_str(&msg).unwrap_or_else(|_| env::panic_str("Invalid message"));
+        let payment_token = PaymentToken::Ft(env::predecessor_account_id());
+        let unspent = match msg {
+            FtTransferMsg::Buy { token_id } => {
+                self.internal_buy(&token_id, &sender_id, &payment_token, amount.0)
+            }
+            FtTransferMsg::Mint(request) => self.internal_ft_mint(
+                &env::predecessor_account_id(),
+                sender_id,
+                amount.0,
+                *request,
+            ),
+            FtTransferMsg::DistributePayout { token_id } => self.internal_distribute_ft_payout(
+                &env::predecessor_account_id(),
+                &sender_id,
+                token_id,
+                amount.0,
+            ),
+        };
+        PromiseOrValue::Value(U128(unspent))
+    }
+}
@@ -0,0 +1,190 @@
+/*!
+FT payouts distributed by this contract for marketplaces that settle a sale in an FT, e.g. wNEAR,
+but don't pay the royalties themselves. A sale an allowed market settles with `nft_transfer_payout`
+is recorded along with its seller. The market then sends the price with `ft_transfer_call` and
+the message `{"distribute_payout":{"token_id":".."}}`, and the received amount is split as
+`nft_payout` would split it. Only FTs the contract owner accepts for payouts are taken, as any
+contract may call `ft_on_transfer` naming any sender. Each sale is paid out once, an FT transfer
+matching no sale of the market is returned. The record is kept by the contract until the sale
+is paid out.
+
+A share whose transfer fails, e.g. because the payee isn't registered with the FT, is kept
+as a claimable balance, as are the failed FT payouts of sales on this contract. Its storage
+is paid by the contract as callbacks carry no deposit.
+*/
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, is_promise_success, near_bindgen, AccountId, Balance, Gas};
+
+use crate::events::{
+    self, FtPayoutClaimLog, FtPayoutDistributeLog, FtPayoutFailedLog, PayoutFtTokenUpdateLog,
+    PayoutMarketUpdateLog,
+};
+use crate::ft::PaymentToken;
+use crate::{Contract, ContractExt};
+
+const GAS_FOR_FT_PAYOUT_CALLBACK: Gas = Gas(5_000_000_000_000);
+
+/// Sale settled by a payout market whose FT price wasn't paid out yet
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PendingFtPayout {
+    pub market_id: AccountId,
+    pub seller_id: AccountId,
+}
+
+impl Contract {
+    /// Record the sale of `token_id` by `seller_id` if the caller is a payout market,
+    /// replacing a previous sale that was never paid out
+    pub(crate) fn internal_record_ft_payout_sale(
+        &mut self,
+        token_id: &TokenId,
+        seller_id: &AccountId,
+    ) {
+        let market_id = env::predecessor_account_id();
+        if self.payout_markets.contains(&market_id) {
+            self.pending_ft_payouts.insert(
+                token_id,
+                &PendingFtPayout {
+                    market_id,
+                    seller_id: seller_id.clone(),
+                },
+            );
+        }
+    }
+
+    /// Pay out `amount` of `ft_contract` sent by `market_id` for its sale of `token_id` to
+    /// the royalty payees, the platform and the seller. Returns the amount left unspent,
+    /// all of it if the FT isn't accepted for payouts or the market has no sale of the token
+    /// to pay out
+    pub(crate) fn internal_distribute_ft_payout(
+        &mut self,
+        ft_contract: &AccountId,
+        market_id: &AccountId,
+        token_id: TokenId,
+        amount: Balance,
+    ) -> Balance {
+        if !self.payout_ft_tokens.contains(ft_contract) {
+            return amount;
+        }
+        let seller_id = match self.pending_ft_payouts.get(&token_id) {
+            Some(sale) if sale.market_id == *market_id => sale.seller_id,
+            _ => return amount,
+        };
+        self.pending_ft_payouts.remove(&token_id);
+        for (account_id, share) in self.internal_payout(&token_id, &seller_id, amount) {
+            if share > 0 {
+                self.internal_send_ft_payout(account_id, ft_contract.clone(), share);
+            }
+        }
+        self.internal_audit("distribute_ft_payout", Some(&token_id), Some(amount));
+        events::emit(vec![FtPayoutDistributeLog {
+            token_id,
+            seller_id,
+            ft_contract: ft_contract.clone(),
+            amount: U128(amount),
+        }]);
+        0
+    }
+
+    /// Transfer `amount` of `ft_contract` to `account_id`, crediting it back if the transfer fails
+    pub(crate) fn internal_send_ft_payout(
+        &self,
+        account_id: AccountId,
+        ft_contract: AccountId,
+        amount: u128,
+    ) {
+        PaymentToken::Ft(ft_contract.clone())
+            .send(account_id.clone(), amount)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_FT_PAYOUT_CALLBACK)
+                    .on_ft_payout(account_id, ft_contract, U128(amount)),
+            );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allow or disallow `market_id` to have the FT price of its sales paid out
+    pub fn set_payout_market(&mut self, market_id: AccountId, allowed: bool) {
+        self.assert_owner();
+        if allowed {
+            self.payout_markets.insert(&market_id);
+        } else {
+            self.payout_markets.remove(&market_id);
+        }
+        self.internal_audit("set_payout_market", None, None);
+        events::emit(vec![PayoutMarketUpdateLog { market_id, allowed }]);
+    }
+
+    pub fn payout_markets(&self) -> Vec<AccountId> {
+        self.payout_markets.to_vec()
+    }
+
+    /// Accept or refuse `ft_contract` for the FT price of payout market sales
+    pub fn set_payout_ft_token(&mut self, ft_contract: AccountId, accepted: bool) {
+        self.assert_owner();
+        if accepted {
+            self.payout_ft_tokens.insert(&ft_contract);
+        } else {
+            self.payout_ft_tokens.remove(&ft_contract);
+        }
+        self.internal_audit("set_payout_ft_token", None, None);
+        events::emit(vec![PayoutFtTokenUpdateLog {
+            ft_contract,
+            accepted,
+        }]);
+    }
+
+    /// FTs accepted for the FT price of payout market sales
+    pub fn payout_ft_tokens(&self) -> Vec<AccountId> {
+        self.payout_ft_tokens.to_vec()
+    }
+
+    /// Keep a share whose transfer failed claimable by `account_id`
+    #[private]
+    pub fn on_ft_payout(&mut self, account_id: AccountId, ft_contract: AccountId, amount: U128) {
+        if is_promise_success() {
+            return;
+        }
+        let key = (account_id.clone(), ft_contract.clone());
+        let claimable = self.ft_claimable.get(&key).unwrap_or_default();
+        self.ft_claimable.insert(&key, &(claimable + amount.0));
+        self.internal_audit("ft_payout_failed", None, Some(amount.0));
+        events::emit(vec![FtPayoutFailedLog {
+            account_id,
+            ft_contract,
+            amount,
+        }]);
+    }
+
+    /// Amount of `ft_contract` `account_id` may claim
+    pub fn ft_claimable(&self, account_id: AccountId, ft_contract: AccountId) -> U128 {
+        U128(
+            self.ft_claimable
+                .get(&(account_id, ft_contract))
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Transfer the caller's claimable balance of `ft_contract` to it,
+    /// a failed transfer keeps it claimable
+    #[payable]
+    pub fn claim_ft_payout(&mut self, ft_contract: AccountId) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .ft_claimable
+            .remove(&(account_id.clone(), ft_contract.clone()))
+            .unwrap_or_else(|| env::panic_str("Nothing to claim"));
+        self.internal_send_ft_payout(account_id.clone(), ft_contract.clone(), amount);
+        self.internal_audit("claim_ft_payout", None, Some(amount));
+        events::emit(vec![FtPayoutClaimLog {
+            account_id,
+            ft_contract,
+            amount: U128(amount),
+        }]);
+        U128(amount)
+    }
+}
@@ -0,0 +1,57 @@
+/*!
+Everything stored about a token in a single view, saving clients a round-trip per feature.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, AccountId};
+
+use crate::audit::TokenActivity;
+use crate::collaborators::Collaborator;
+use crate::rental::Lease;
+use crate::{Contract, ContractExt};
+
+/// Bounds the history returned along with a token, the rest is paged by `nft_provenance_page`
+const MAX_HISTORY_LEN: u64 = 10;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenFull {
+    /// Token as shown by `nft_token`
+    pub token: Token,
+    /// Mint time and transfer count, unknown for tokens minted before activity was tracked
+    pub activity: Option<TokenActivity>,
+    /// Listing price in the listing currency
+    pub current_price: Option<U128>,
+    pub royalties: HashMap<AccountId, u16>,
+    pub collaborators: Vec<Collaborator>,
+    /// Latest owners oldest first along with the time in seconds they got the token
+    pub history: Vec<(AccountId, U64)>,
+    /// Whether the token can't be transferred now, see `nft_is_transferable`
+    pub is_locked: bool,
+    pub is_staked: bool,
+    /// Lease offered or running, see `nft_lease_of`
+    pub rental: Option<Lease>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn nft_token_full(&self, token_id: TokenId) -> Option<TokenFull> {
+        let token = self.nft_token(token_id.clone())?;
+        let extras = self.internal_token_extras(&token_id);
+        Some(TokenFull {
+            token,
+            activity: self.token_activity.get(&token_id),
+            current_price: self.nft_get_listing_price(token_id.clone()),
+            royalties: extras.royalties,
+            collaborators: extras.collaborators,
+            history: self.internal_recent_owners(&token_id, MAX_HISTORY_LEN),
+            is_locked: self.internal_transfer_lock(&token_id).is_some(),
+            is_staked: self.is_staked(&token_id),
+            rental: self.leases.get(&token_id),
+        })
+    }
+}
@@ -0,0 +1,63 @@
+/*!
+Ownership snapshots for snapshot-based governance voting.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::events::{self, GovernanceSnapshotLog};
+use crate::{Contract, ContractExt};
+
+/// Owners of all tokens at the moment the snapshot was taken
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct GovernanceSnapshot {
+    pub snapshot_id: String,
+    pub block_height: u64,
+    pub token_owners: HashMap<TokenId, AccountId>,
+    /// Block timestamp in seconds
+    pub taken_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Record current owners of all tokens under `snapshot_id`.
+    /// Storage is paid by the caller, the unused deposit is refunded
+    #[payable]
+    pub fn nft_take_governance_snapshot(&mut self, snapshot_id: String) {
+        self.assert_owner();
+        assert!(
+            self.governance_snapshots.get(&snapshot_id).is_none(),
+            "Snapshot already exists"
+        );
+        let initial_storage = env::storage_usage();
+        let snapshot = GovernanceSnapshot {
+            snapshot_id: snapshot_id.clone(),
+            block_height: env::block_height(),
+            token_owners: self.tokens.owner_by_id.iter().collect(),
+            taken_at: env::block_timestamp() / 1_000_000_000u64,
+        };
+        self.governance_snapshots.insert(&snapshot_id, &snapshot);
+        refund_deposit_to_account(
+            env::storage_usage() - initial_storage,
+            env::predecessor_account_id(),
+        );
+        self.internal_audit("governance_snapshot", None, None);
+        events::emit(vec![GovernanceSnapshotLog {
+            snapshot_id,
+            block_height: snapshot.block_height.into(),
+        }]);
+    }
+
+    /// Owner of `token_id` at the moment snapshot `snapshot_id` was taken
+    pub fn nft_snapshot_owner_at(
+        &self,
+        snapshot_id: String,
+        token_id: TokenId,
+    ) -> Option<AccountId> {
+        self.governance_snapshots
+            .get(&snapshot_id)
+            .and_then(|snapshot| snapshot.token_owners.get(&token_id).cloned())
+    }
+}
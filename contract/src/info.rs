@@ -0,0 +1,143 @@
+/*!
+Information about the deployed build of the contract, including the NEP-330 source metadata.
+
+The owner records how the deployed wasm was built once per code version, so anyone can
+rebuild it from the repository and compare the hash. `upgrade` clears the record.
+
+The standards implemented are listed along with their methods for marketplaces to check.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, require};
+
+use crate::events::{self, BuildInfoSetLog};
+use crate::{Contract, ContractExt};
+
+const SOURCE_LINK: &str = "https://github.com/vitalii427/ukrainian-magicals-nft";
+
+/// Standards implemented as (standard, version, methods)
+const SUPPORTED_INTERFACES: &[(&str, &str, &[&str])] = &[
+    (
+        "nep171",
+        "1.2.0",
+        &[
+            "nft_transfer",
+            "nft_transfer_call",
+            "nft_token",
+            "nft_resolve_transfer",
+        ],
+    ),
+    ("nep177", "2.1.0", &["nft_metadata"]),
+    (
+        "nep178",
+        "1.1.0",
+        &[
+            "nft_approve",
+            "nft_revoke",
+            "nft_revoke_all",
+            "nft_is_approved",
+        ],
+    ),
+    (
+        "nep181",
+        "1.0.0",
+        &[
+            "nft_total_supply",
+            "nft_tokens",
+            "nft_supply_for_owner",
+            "nft_tokens_for_owner",
+        ],
+    ),
+    ("nep199", "2.0.0", &["nft_payout", "nft_transfer_payout"]),
+    ("nep330", "1.1.0", &["contract_source_metadata"]),
+];
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuildInfo {
+    pub git_commit: String,
+    pub rustc_version: String,
+    /// SHA-256 of the deployed wasm
+    pub wasm_hash: Base64VecU8,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractInfo {
+    /// Crate version the wasm was built from
+    pub version: String,
+    /// Set by testnet builds, which expose faucet methods
+    pub testnet: bool,
+    pub build_info: Option<BuildInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InterfaceInfo {
+    pub standard: String,
+    pub version: String,
+    pub methods: Vec<String>,
+}
+
+/// NEP-330 source metadata
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractSourceMetadata {
+    pub version: Option<String>,
+    pub link: Option<String>,
+    pub build_info: Option<BuildInfo>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Record how the deployed code was built, once per code version
+    pub fn set_build_info(
+        &mut self,
+        git_commit: String,
+        rustc_version: String,
+        wasm_hash: Base64VecU8,
+    ) {
+        self.assert_owner();
+        require!(
+            self.build_info.is_none(),
+            "Build info is already set for this code version"
+        );
+        require!(wasm_hash.0.len() == 32, "Wasm hash must be 32 bytes");
+        let build_info = BuildInfo {
+            git_commit,
+            rustc_version,
+            wasm_hash,
+        };
+        self.build_info = Some(build_info.clone());
+        self.internal_audit("set_build_info", None, None);
+        events::emit(vec![BuildInfoSetLog { build_info }]);
+    }
+
+    pub fn contract_info(&self) -> ContractInfo {
+        ContractInfo {
+            version: env!("CARGO_PKG_VERSION").into(),
+            testnet: cfg!(feature = "testnet"),
+            build_info: self.build_info.clone(),
+        }
+    }
+
+    pub fn contract_source_metadata(&self) -> ContractSourceMetadata {
+        ContractSourceMetadata {
+            version: Some(env!("CARGO_PKG_VERSION").into()),
+            link: Some(SOURCE_LINK.into()),
+            build_info: self.build_info.clone(),
+        }
+    }
+
+    pub fn nft_supported_interfaces_detail(&self) -> Vec<InterfaceInfo> {
+        SUPPORTED_INTERFACES
+            .iter()
+            .map(|(standard, version, methods)| InterfaceInfo {
+                standard: (*standard).into(),
+                version: (*version).into(),
+                methods: methods.iter().map(|method| (*method).into()).collect(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,174 @@
+/*!
+Consistency checks of the contract state, meant to be run as a view by operators, e.g.
+after a migration. Records kept in lookup maps can't be enumerated, so they are checked
+from the tokens referring to them; records of burned tokens nothing refers to anymore,
+like their approvals, can't be found.
+
+The health check sums up the core NEP-171 records only. Orphan approvals are looked up for
+tokens named by enumerable records (listings, drops, parents and royalty indexes of owned tokens).
+*/
+use std::collections::{BTreeMap, BTreeSet};
+
+use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, AccountId};
+
+use crate::{Contract, ContractExt};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HealthReport {
+    pub total_supply_matches_owner_count: bool,
+    pub all_metadata_present: bool,
+    pub no_orphan_approvals: bool,
+    /// Tokens missing metadata, orphan approvals and a supply mismatch, each counted once
+    pub issues_count: u32,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn nft_contract_health_check(&self) -> HealthReport {
+        let mut issues_count = 0;
+        let mut owner_count: u128 = 0;
+        let mut referenced: BTreeSet<TokenId> = BTreeSet::new();
+        for (token_id, _) in self.tokens.owner_by_id.iter() {
+            owner_count += 1;
+            if let Some(token_metadata_by_id) = &self.tokens.token_metadata_by_id {
+                if !token_metadata_by_id.contains_key(&token_id) {
+                    issues_count += 1;
+                }
+            }
+            for account_id in self.internal_token_extras(&token_id).royalties.into_keys() {
+                if let Some(token_ids) = self.royalty_tokens_per_account.get(&account_id) {
+                    referenced.extend(token_ids.iter());
+                }
+            }
+        }
+        let all_metadata_present = issues_count == 0;
+        let total_supply_matches_owner_count = self.nft_total_supply().0 == owner_count;
+        if !total_supply_matches_owner_count {
+            issues_count += 1;
+        }
+
+        referenced.extend(self.sales.keys());
+        referenced.extend(self.event_drops.keys());
+        referenced.extend(self.token_children.keys());
+        let orphan_approvals = match &self.tokens.approvals_by_id {
+            Some(approvals_by_id) => referenced
+                .iter()
+                .filter(|token_id| {
+                    !self.tokens.owner_by_id.contains_key(token_id)
+                        && approvals_by_id.contains_key(token_id)
+                })
+                .count() as u32,
+            None => 0,
+        };
+        issues_count += orphan_approvals;
+
+        HealthReport {
+            total_supply_matches_owner_count,
+            all_metadata_present,
+            no_orphan_approvals: orphan_approvals == 0,
+            issues_count,
+        }
+    }
+
+    /// Inconsistencies found in the state, empty when it is healthy
+    pub fn check_invariants(&self) -> Vec<String> {
+        let mut issues = vec![];
+        let mut supply_per_owner: BTreeMap<AccountId, u64> = BTreeMap::new();
+        let mut royalty_accounts: BTreeSet<AccountId> = BTreeSet::new();
+
+        for (token_id, owner_id) in self.tokens.owner_by_id.iter() {
+            *supply_per_owner.entry(owner_id.clone()).or_default() += 1;
+            if let Some(token_metadata_by_id) = &self.tokens.token_metadata_by_id {
+                if !token_metadata_by_id.contains_key(&token_id) {
+                    issues.push(format!("Token {} has no metadata", token_id));
+                }
+            }
+            if let Some(tokens_per_owner) = &self.tokens.tokens_per_owner {
+                let listed = tokens_per_owner
+                    .get(&owner_id)
+                    .map(|token_ids| token_ids.contains(&token_id));
+                if listed != Some(true) {
+                    issues.push(format!(
+                        "Token {} is missing from the enumeration of {}",
+                        token_id, owner_id
+                    ));
+                }
+            }
+            for account_id in self.internal_token_extras(&token_id).royalties.into_keys() {
+                let indexed = self
+                    .royalty_tokens_per_account
+                    .get(&account_id)
+                    .map(|token_ids| token_ids.contains(&token_id));
+                if indexed != Some(true) {
+                    issues.push(format!(
+                        "Token {} is missing from the royalty index of {}",
+                        token_id, account_id
+                    ));
+                }
+                royalty_accounts.insert(account_id);
+            }
+            if let (Some(approvals_by_id), Some(next_approval_id_by_id)) = (
+                &self.tokens.approvals_by_id,
+                &self.tokens.next_approval_id_by_id,
+            ) {
+                let next_approval_id = next_approval_id_by_id.get(&token_id).unwrap_or(1);
+                for (account_id, approval_id) in approvals_by_id.get(&token_id).unwrap_or_default()
+                {
+                    if approval_id >= next_approval_id {
+                        issues.push(format!(
+                            "Approval {} of {} for token {} isn't below the next approval ID {}",
+                            approval_id, account_id, token_id, next_approval_id
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(tokens_per_owner) = &self.tokens.tokens_per_owner {
+            for (owner_id, supply) in &supply_per_owner {
+                let listed = tokens_per_owner
+                    .get(owner_id)
+                    .map(|token_ids| token_ids.len())
+                    .unwrap_or_default();
+                if listed != *supply {
+                    issues.push(format!(
+                        "Enumeration of {} lists {} tokens, {} owned",
+                        owner_id, listed, supply
+                    ));
+                }
+            }
+        }
+
+        for (token_id, sale) in self.sales.iter() {
+            match self.tokens.owner_by_id.get(&token_id) {
+                None => issues.push(format!("Sale of missing token {}", token_id)),
+                Some(owner_id) if owner_id != sale.owner_id => issues.push(format!(
+                    "Sale of token {} by {} who doesn't own it",
+                    token_id, sale.owner_id
+                )),
+                Some(_) => {}
+            }
+        }
+
+        for account_id in royalty_accounts {
+            for token_id in self
+                .royalty_tokens_per_account
+                .get(&account_id)
+                .map(|token_ids| token_ids.to_vec())
+                .unwrap_or_default()
+            {
+                if !self.tokens.owner_by_id.contains_key(&token_id) {
+                    issues.push(format!(
+                        "Royalty index of {} lists missing token {}",
+                        account_id, token_id
+                    ));
+                }
+            }
+        }
+        issues
+    }
+}
@@ -0,0 +1,151 @@
+/*!
+Event drops: owner-held tokens handed to a Keypom contract with `nft_transfer_call`,
+to be claimed with QR codes at conferences.
+
+A token is recorded as in a drop when it's sent, the record is removed if Keypom returns
+the token or once Keypom transfers it to the claimer. Record storage is paid by the owner.
+A token with attached children can't be dropped.
+*/
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenResolver;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId, Gas};
+
+use crate::events::{self, EventDropFailedLog, EventDropLog};
+use crate::nft_core::ext_nft_receiver;
+use crate::nft_core::GAS_FOR_RESOLVE_CHILDLESS_TRANSFER;
+use crate::storage::{refund_released_storage, refund_released_storage_uncounted};
+use crate::{Contract, ContractExt};
+
+/// Bounds the cross-contract calls made by a single drop to fit the gas of a transaction
+const MAX_EVENT_DROP_TOKENS: usize = 8;
+const GAS_FOR_KEYPOM_NFT_ON_TRANSFER: Gas = Gas(15_000_000_000_000);
+/// Resolution of the transfer along with the refund of the drop record
+pub(crate) const GAS_FOR_EVENT_DROP_CALLBACK: Gas =
+    Gas(GAS_FOR_RESOLVE_CHILDLESS_TRANSFER.0 + 5_000_000_000_000);
+
+impl Contract {
+    /// Forget `token_id` leaving a drop, refunding the record storage to the contract owner
+    pub(crate) fn internal_remove_event_drop(&mut self, token_id: &TokenId) {
+        let initial_storage = env::storage_usage();
+        if self.event_drops.remove(token_id).is_some() {
+            refund_released_storage(
+                initial_storage - env::storage_usage(),
+                self.tokens.owner_id.clone(),
+            );
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Send owner-held `token_ids` to `keypom_contract` with `drop_config` as the message of
+    /// each `nft_transfer_call`. The deposit must cover the drop records
+    #[payable]
+    pub fn create_event_drop(
+        &mut self,
+        token_ids: Vec<TokenId>,
+        keypom_contract: AccountId,
+        drop_config: String,
+    ) {
+        self.assert_owner();
+        require!(!token_ids.is_empty(), "No tokens to drop");
+        require!(token_ids.len() <= MAX_EVENT_DROP_TOKENS, "Too many tokens");
+        let per_token_gas = GAS_FOR_KEYPOM_NFT_ON_TRANSFER.0 + GAS_FOR_EVENT_DROP_CALLBACK.0;
+        require!(
+            env::prepaid_gas().0 > per_token_gas * token_ids.len() as u64,
+            "More gas is required"
+        );
+        let owner_id = self.tokens.owner_id.clone();
+
+        let mut storage_used = 0;
+        for token_id in &token_ids {
+            require!(
+                self.tokens.owner_by_id.get(token_id).as_ref() == Some(&owner_id),
+                "Token isn't held by the owner"
+            );
+            // children returned along with a token would outgrow the gas of the callback
+            require!(!self.has_children(token_id), "Token has attached children");
+            let (_, approved_account_ids) =
+                self.internal_transfer(&owner_id, &keypom_contract, token_id, None, None, None);
+            self.internal_refund_cleared_approvals(
+                token_id,
+                approved_account_ids,
+                owner_id.clone(),
+            );
+            let initial_storage = env::storage_usage();
+            self.event_drops.insert(token_id, &keypom_contract);
+            storage_used += env::storage_usage() - initial_storage;
+        }
+        refund_deposit_to_account(storage_used, owner_id.clone());
+
+        for token_id in &token_ids {
+            self.internal_audit("event_drop", Some(token_id), None);
+            ext_nft_receiver::ext(keypom_contract.clone())
+                .with_static_gas(GAS_FOR_KEYPOM_NFT_ON_TRANSFER)
+                .nft_on_transfer(
+                    owner_id.clone(),
+                    owner_id.clone(),
+                    token_id.clone(),
+                    drop_config.clone(),
+                )
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_EVENT_DROP_CALLBACK)
+                        .on_event_drop(owner_id.clone(), keypom_contract.clone(), token_id.clone()),
+                );
+        }
+        events::emit(vec![EventDropLog {
+            keypom_contract,
+            token_ids,
+        }]);
+    }
+
+    /// Resolve the transfer of `token_id` to `keypom_contract`, the token leaves the drop
+    /// if it was returned. Returns whether it stays in the drop
+    #[private]
+    pub fn on_event_drop(
+        &mut self,
+        previous_owner_id: AccountId,
+        keypom_contract: AccountId,
+        token_id: TokenId,
+    ) -> bool {
+        let transferred = self.nft_resolve_transfer(
+            previous_owner_id,
+            keypom_contract.clone(),
+            token_id.clone(),
+            None,
+        );
+        if !transferred {
+            // the refund is left without a callback, this one's gas only covers the transfer
+            let initial_storage = env::storage_usage();
+            self.event_drops.remove(&token_id);
+            refund_released_storage_uncounted(
+                initial_storage - env::storage_usage(),
+                self.tokens.owner_id.clone(),
+            );
+            self.internal_audit("event_drop_failed", Some(&token_id), None);
+            events::emit(vec![EventDropFailedLog {
+                keypom_contract,
+                token_id,
+            }]);
+        }
+        transferred
+    }
+
+    /// Tokens held in drops along with their Keypom contract
+    pub fn event_drop_tokens(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<(TokenId, AccountId)> {
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        self.event_drops
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+}
@@ -0,0 +1,99 @@
+/*!
+Lazy minting: the owner lists metadata at a price and the token is minted only when bought,
+so its storage is paid by the buyer. The price goes where mint proceeds go, to the charity
+account if configured, else to the mint payout accounts or the owner.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, Token};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, Balance, Promise};
+
+use crate::events::{self, LazyListLog};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LazyListing {
+    pub metadata: TokenMetadata,
+    pub price: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// List `metadata` of a token to be minted when bought for `price` yoctoNEAR.
+    /// The listing storage is paid by the owner. Returns the listing ID
+    #[payable]
+    pub fn create_lazy_listing(&mut self, metadata: TokenMetadata, price: U128) -> U64 {
+        self.assert_owner();
+        require!(price.0 > 0, "Price must be positive");
+        let listing_id = self.next_lazy_listing_id;
+        let initial_storage = env::storage_usage();
+        self.lazy_listings
+            .insert(&listing_id, &LazyListing { metadata, price });
+        self.next_lazy_listing_id += 1;
+        refund_deposit_to_account(
+            env::storage_usage() - initial_storage,
+            env::predecessor_account_id(),
+        );
+        self.internal_audit("lazy_list", None, Some(price.0));
+        events::emit(vec![LazyListLog {
+            listing_id: U64(listing_id),
+            price,
+        }]);
+        U64(listing_id)
+    }
+
+    /// Mint the lazily listed token to the caller charging its price plus storage.
+    /// The listing storage is refunded to the owner, the excess deposit to the caller
+    #[payable]
+    pub fn buy_lazy(&mut self, listing_id: U64) -> Token {
+        let initial_storage = env::storage_usage();
+        let listing = self
+            .lazy_listings
+            .remove(&listing_id.0)
+            .unwrap_or_else(|| env::panic_str("Lazy listing not found"));
+        refund_released_storage(
+            initial_storage - env::storage_usage(),
+            self.tokens.owner_id.clone(),
+        );
+
+        let buyer_id = env::predecessor_account_id();
+        let token_id = self.next_curve_token_id();
+        let initial_storage = env::storage_usage();
+        self.internal_count_daily_mint(&buyer_id);
+        let token = self.internal_mint_with_royalties(
+            token_id.clone(),
+            buyer_id.clone(),
+            listing.metadata,
+            None,
+        );
+        let storage_used = env::storage_usage() - initial_storage;
+        let storage_cost = Balance::from(storage_used) * env::storage_byte_cost();
+
+        let price = listing.price.0;
+        let required = price + storage_cost;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= required,
+            format!(
+                "Must attach {} yoctoNEAR to cover price and storage",
+                required
+            )
+        );
+        self.internal_pay_mint_price(&buyer_id, price);
+        if deposit > required {
+            Promise::new(buyer_id.clone()).transfer(deposit - required);
+        }
+        self.internal_audit("mint", Some(&token_id), Some(price));
+        events::emit_mint(&buyer_id, std::slice::from_ref(&token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        token
+    }
+
+    pub fn lazy_listing(&self, listing_id: U64) -> Option<LazyListing> {
+        self.lazy_listings.get(&listing_id.0)
+    }
+}
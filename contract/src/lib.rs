@@ -15,9 +15,61 @@ NOTES:
   - To prevent the deployed contract from being modified or deleted, it should not have any access
     keys on its account.
 */
+mod airdrop;
+mod approval;
+mod audit;
+mod bridge;
+mod burn;
+mod certificate;
+mod collaborators;
+mod collateral;
+mod composable;
+mod curve;
+mod diagnostics;
+mod donation;
+mod donors;
+mod enumeration;
+mod events;
+mod export;
+mod extras;
+mod factory;
+mod ft;
+mod ft_payout;
+mod full;
+mod governance;
 mod icon;
+mod info;
+mod invariants;
+mod keypom;
+mod lazy;
+mod lottery;
+mod matching;
+mod media;
+mod metadata;
+mod migrate;
+mod mint_limit;
+mod mint_split;
+mod nft_core;
+mod offer;
+mod ownership;
+mod paras;
+mod price_index;
+mod provenance;
+mod rental;
+mod resale;
+mod reveal;
+mod royalty;
+mod sale;
+mod series;
+mod social;
+mod staking;
+mod storage;
+mod swap;
+mod tags;
+#[cfg(feature = "testnet")]
+mod testnet;
+mod upgrade;
 
-use near_contract_standards::non_fungible_token::events::NftMint;
 use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
 };
@@ -25,18 +77,170 @@ use near_contract_standards::non_fungible_token::{
     refund_deposit_to_account, NonFungibleToken, Token, TokenId,
 };
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LazyOption;
-use near_sdk::{
-    env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault, Promise, PromiseOrValue,
+use near_sdk::collections::{
+    LazyOption, LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet, Vector,
 };
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, BorshStorageKey, PanicOnDefault};
+use std::collections::HashMap;
 
+use crate::approval::ApprovalConditions;
+use crate::audit::{AuditEntry, TokenActivity};
+use crate::bridge::BridgeLock;
+use crate::collaborators::Collaborator;
+use crate::collateral::Collateral;
+use crate::curve::MintCurve;
+use crate::donation::{CharityTransfer, PendingCharityChange};
+use crate::extras::VersionedTokenExtras;
+use crate::ft::FtMintPrice;
+use crate::ft_payout::PendingFtPayout;
+use crate::governance::GovernanceSnapshot;
 use crate::icon::DATA_IMAGE_WEBP_NEAR_ICON;
+use crate::info::BuildInfo;
+use crate::lazy::LazyListing;
+use crate::matching::MatchingCampaign;
+use crate::mint_split::MintPayoutAccount;
+use crate::nft_core::ApprovalSnapshot;
+use crate::rental::Lease;
+use crate::sale::Sale;
+use crate::swap::ExternalSwap;
+use crate::upgrade::StagedCode;
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     tokens: NonFungibleToken,
     metadata: LazyOption<NFTContractMetadata>,
+    governance_snapshots: LookupMap<String, GovernanceSnapshot>,
+    default_media: Option<String>,
+    token_extras: LookupMap<TokenId, VersionedTokenExtras>,
+    royalty_tokens_per_account: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    sales: UnorderedMap<TokenId, Sale>,
+    default_marketplace: Option<AccountId>,
+    mint_curve: Option<MintCurve>,
+    conditional_approvals: LookupMap<(TokenId, AccountId), ApprovalConditions>,
+    charity_account_id: Option<AccountId>,
+    total_donated: Balance,
+    token_activity: LookupMap<TokenId, TokenActivity>,
+    min_sale_price: Balance,
+    /// Collaborators of tokens whose extras are in the `V1` layout
+    token_collaborators: LookupMap<TokenId, Vec<Collaborator>>,
+    audit_log: Vector<AuditEntry>,
+    staged_code: Option<StagedCode>,
+    /// Last token visited by `migrate_tokens`
+    token_migration_cursor: Option<TokenId>,
+    token_migration_swept: u64,
+    /// Whether a token was ever minted, state can't be imported afterwards
+    has_minted: bool,
+    /// Metadata shown for every token not revealed yet, `None` once the collection is revealed
+    unrevealed_metadata: Option<TokenMetadata>,
+    reveal_fee: Option<Balance>,
+    /// Tokens revealed early by `pay_to_reveal`
+    revealed_tokens: LookupSet<TokenId>,
+    ft_mint_price: Option<FtMintPrice>,
+    /// How the deployed code was built, cleared by `upgrade`
+    build_info: Option<BuildInfo>,
+    /// Amounts in yoctoNEAR offered by buyers for tokens
+    offers: LookupMap<(TokenId, AccountId), Balance>,
+    lazy_listings: UnorderedMap<u64, LazyListing>,
+    next_lazy_listing_id: u64,
+    /// Failed refunds kept by the contract
+    total_revenue: Balance,
+    /// Approvals cleared by pending `nft_transfer_call`s, by token and previous owner
+    transfer_approval_snapshots: LookupMap<(TokenId, AccountId), ApprovalSnapshot>,
+    /// Timestamp in seconds transfers of every token are locked until
+    global_transfer_unlock: u64,
+    /// Basis points of every sale price paid to the contract owner
+    platform_fee_bps: u16,
+    /// Day bucket of the last mint each account received and the mints received that day
+    daily_mint_count: LookupMap<AccountId, (u64, u32)>,
+    max_daily_mints_per_account: u32,
+    /// Timestamp in seconds each staked token was staked at
+    staked_at: LookupMap<TokenId, u64>,
+    staked_tokens_per_account: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    stake_points: LookupMap<AccountId, u64>,
+    stake_points_per_day: u64,
+    leases: LookupMap<TokenId, Lease>,
+    /// Highest resale price in yoctoNEAR of capped tokens
+    max_resale_prices: LookupMap<TokenId, Balance>,
+    default_max_resale_price: Option<Balance>,
+    /// Tokens attached to each parent token
+    token_children: UnorderedMap<TokenId, UnorderedSet<TokenId>>,
+    token_parents: LookupMap<TokenId, TokenId>,
+    /// Amounts forwarded to the charity account per FT contract
+    ft_donations: LookupMap<AccountId, Balance>,
+    next_donation_badge_id: u64,
+    /// Tokens that can't be transferred or listed, e.g. donation badges
+    soulbound_tokens: LookupSet<TokenId>,
+    /// Owners of each token oldest first along with the time in seconds they got it
+    provenance: LookupMap<TokenId, Vector<(AccountId, u64)>>,
+    minting_closed: bool,
+    /// Tokens locked as loan collateral
+    collateral: LookupMap<TokenId, Collateral>,
+    /// Operators allowed to mint tokens of a series
+    series_approvals: LookupMap<(String, AccountId), bool>,
+    token_series: LookupMap<TokenId, String>,
+    /// Social DB posts go to, `social.near` if `None`
+    social_db_id: Option<AccountId>,
+    social_posts_enabled: bool,
+    /// yoctoNEAR paying for social DB posts
+    social_pool: Balance,
+    /// Tokens handed to Keypom drops along with the Keypom contract
+    event_drops: UnorderedMap<TokenId, AccountId>,
+    /// Proposed swaps of our tokens for tokens of other NFT contracts
+    external_swaps: LookupMap<u64, ExternalSwap>,
+    next_external_swap_id: u64,
+    /// Fixed NEAR price listings sorted by price and token
+    price_index: TreeMap<(Balance, TokenId), ()>,
+    /// NEAR confirmed sent to the charity account
+    charity_sent: Balance,
+    /// NEAR that failed to reach the charity account and wasn't retried successfully
+    charity_failed: Balance,
+    /// FTs besides NEAR approve msgs may list tokens in
+    paras_ft_tokens: UnorderedSet<AccountId>,
+    /// Team accounts the price of each paid mint is split between, empty to pay the owner
+    mint_payout_accounts: Vec<MintPayoutAccount>,
+    /// Markets allowed to have FT payouts distributed by the contract
+    payout_markets: UnorderedSet<AccountId>,
+    /// FT payouts whose transfer failed, by payee and FT contract
+    ft_claimable: LookupMap<(AccountId, AccountId), Balance>,
+    /// Lowest total royalty in basis points a minted token may carry
+    min_royalty_bps: u16,
+    /// Royalties of tokens minted without royalties of their own
+    default_royalties: HashMap<AccountId, u16>,
+    /// Releases tokens in bridge custody
+    bridge_account_id: Option<AccountId>,
+    bridge_locks: UnorderedMap<TokenId, BridgeLock>,
+    /// Tags of each token from its metadata `extra` at mint
+    token_tags: LookupMap<TokenId, Vec<String>>,
+    tag_index: LookupMap<String, UnorderedSet<TokenId>>,
+    /// yoctoNEAR each donor donated that reached the charity account
+    donations_by_account: UnorderedMap<AccountId, Balance>,
+    /// FT amounts donated by donor and FT contract
+    ft_donations_by_account: LookupMap<(AccountId, AccountId), Balance>,
+    /// Top donors by NEAR donated, highest first
+    top_donors: LazyOption<Vec<(Balance, AccountId)>>,
+    /// Token `nft_set_floor_price_and_delist_above` continues after
+    floor_update_cursor: Option<TokenId>,
+    matching_campaign: Option<MatchingCampaign>,
+    /// Account transfers to which burn the token
+    null_account: Option<AccountId>,
+    pending_charity_change: Option<PendingCharityChange>,
+    /// Paras marketplace account whose approve msgs are checked
+    paras_marketplace: Option<AccountId>,
+    /// Completed swaps whose counterparty's token is held until forwarded to the proposer
+    external_swap_forwards: LookupMap<u64, ExternalSwap>,
+    /// Sales settled by payout markets whose FT price wasn't paid out yet
+    pending_ft_payouts: LookupMap<TokenId, PendingFtPayout>,
+    /// FTs accepted by `ft_on_transfer` for donations
+    donation_ft_tokens: UnorderedSet<AccountId>,
+    /// Outcomes of every NEAR transfer to the charity account
+    charity_ledger: Vector<CharityTransfer>,
+    /// yoctoNEAR paying for the storage of audit entries
+    audit_storage_pool: Balance,
+    /// yoctoNEAR paying for the storage of tokens minted for an FT
+    ft_mint_storage_pool: Balance,
+    /// FTs payout markets may pay the price of their sales in
+    payout_ft_tokens: UnorderedSet<AccountId>,
 }
 
 #[derive(BorshSerialize, BorshStorageKey)]
@@ -46,6 +250,55 @@ enum StorageKey {
     TokenMetadata,
     Enumeration,
     Approval,
+    GovernanceSnapshots,
+    Royalties,
+    RoyaltyTokensPerAccount { account_hash: Vec<u8> },
+    RoyaltyAccounts,
+    Sales,
+    ConditionalApprovals,
+    TokenActivity,
+    TokenCollaborators,
+    AuditLog,
+    RevealedTokens,
+    Offers,
+    LazyListings,
+    TransferApprovalSnapshots,
+    StakedAt,
+    StakedTokens,
+    StakedTokensPerAccount { account_hash: Vec<u8> },
+    StakePoints,
+    Leases,
+    MaxResalePrices,
+    TokenChildren,
+    TokenChildrenPerParent { token_hash: Vec<u8> },
+    TokenParents,
+    FtDonations,
+    SoulboundTokens,
+    Provenance,
+    ProvenancePerToken { token_hash: Vec<u8> },
+    Collateral,
+    SeriesApprovals,
+    TokenSeries,
+    EventDrops,
+    ExternalSwaps,
+    ParasFtTokens,
+    PayoutMarkets,
+    FtClaimable,
+    BridgeLocks,
+    TokenTags,
+    TagIndex,
+    TagIndexPerTag { tag_hash: Vec<u8> },
+    DonationsByAccount,
+    FtDonationsByAccount,
+    TopDonors,
+    TokenExtras,
+    ExternalSwapForwards,
+    PriceIndex,
+    PendingFtPayouts,
+    DonationFtTokens,
+    CharityLedger,
+    DailyMintCounts,
+    PayoutFtTokens,
 }
 
 const ARWEAVE_GATEWAY_BASE_URL: &str = "https://arweave.net/";
@@ -53,8 +306,6 @@ const NFT_NAME: &str = "Ukrainian Magicals";
 const NFT_SYMBOL: &str = "UAMAG";
 const NFT_DESCRIPTION: &str = "Ukrainian Magicals - unique NFT collection created by Ukrainian augmented reality team called Magicals within the framework of Hackathon «For Ukraine» by NEAR UA";
 
-// TODO: add sale & royalties
-
 #[near_bindgen]
 impl Contract {
     /// Initializes the contract owned by the caller with predefined metadata
@@ -69,17 +320,17 @@ impl Contract {
             reference: None,
             reference_hash: None,
         };
-        let contract = Self {
-            tokens: NonFungibleToken::new(
+        migrate::write_state_version();
+        Self::from_token_state(
+            NonFungibleToken::new(
                 StorageKey::NonFungibleToken,
                 env::predecessor_account_id(),
                 Some(StorageKey::TokenMetadata),
                 Some(StorageKey::Enumeration),
                 Some(StorageKey::Approval),
             ),
-            metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
-        };
-        contract
+            LazyOption::new(StorageKey::Metadata, Some(&metadata)),
+        )
     }
 
     /// Mint 3 predefined tokens for contract owner as an initial tokens owner
@@ -87,11 +338,11 @@ impl Contract {
     pub fn nft_mint_all(&mut self) {
         let initial_storage = env::storage_usage();
         let issued_at = format!("{}", env::block_timestamp() / 1_000_000_000u64);
-        let token_ids = &["0", "1", "2"];
-        self.tokens.internal_mint_with_refund(
-            token_ids[0].into(),
+        let token_ids = ["0".to_string(), "1".to_string(), "2".to_string()];
+        self.internal_mint_with_royalties(
+            token_ids[0].clone(),
             self.tokens.owner_id.clone(),
-            Some(TokenMetadata {
+            TokenMetadata {
                 title: Some("#0 Mariupol".into()),
                 description: Some(NFT_DESCRIPTION.into()),
                 media: Some("Cqe2tJCF-yygmxci0RsESa62zQNqPV9oZVDeallYI7o".into()),
@@ -104,13 +355,13 @@ impl Contract {
                 extra: None,
                 reference: Some("Akb7UGDwSbcYka0-frMk5T-YTJQurXzdD0ZBnSqyBRQ".into()),
                 reference_hash: None,
-            }),
+            },
             None,
         );
-        self.tokens.internal_mint_with_refund(
-            token_ids[1].into(),
+        self.internal_mint_with_royalties(
+            token_ids[1].clone(),
             self.tokens.owner_id.clone(),
-            Some(TokenMetadata {
+            TokenMetadata {
                 title: Some("#1 Kharkiv".into()),
                 description: Some(NFT_DESCRIPTION.into()),
                 media: Some("g2kMZ1OhktT0X8R1OzAbdpIk81Dr28uLdyJPlO5YvlM".into()),
@@ -123,13 +374,13 @@ impl Contract {
                 extra: None,
                 reference: Some("65nN_FOLcxCmm5dEPDQi_pQBTu6hxSslvFiepNE02F4".into()),
                 reference_hash: None,
-            }),
+            },
             None,
         );
-        self.tokens.internal_mint_with_refund(
-            token_ids[2].into(),
+        self.internal_mint_with_royalties(
+            token_ids[2].clone(),
             self.tokens.owner_id.clone(),
-            Some(TokenMetadata {
+            TokenMetadata {
                 title: Some("#2 Mykolaiv".into()),
                 description: Some(NFT_DESCRIPTION.into()),
                 media: Some("Cqe2tJCF-yygmxci0RsESa62zQNqPV9oZVDeallYI7o".into()),
@@ -142,25 +393,238 @@ impl Contract {
                 extra: None,
                 reference: Some("U8zVK7opopOesv9trJihrwIcZl7tAQcil0sbetfSJ4U".into()),
                 reference_hash: None,
-            }),
+            },
             None,
         );
         refund_deposit_to_account(
             env::storage_usage() - initial_storage,
             env::predecessor_account_id(),
         );
-        NftMint {
-            owner_id: &self.tokens.owner_id,
-            token_ids,
-            memo: None,
+        for token_id in &token_ids {
+            self.internal_audit("mint", Some(token_id), None);
         }
-        .emit();
+        events::emit_mint(&self.tokens.owner_id, &token_ids, None);
+    }
+
+    /// Mint a new token with ID=`token_id` belonging to `token_owner_id`
+    /// with optional perpetual royalties in basis points. Returns the token as NEP-171 clients
+    /// expect, the mint receipt is logged and returned by `nft_mint_with_receipt`
+    #[payable]
+    pub fn nft_mint(
+        &mut self,
+        token_id: TokenId,
+        token_owner_id: AccountId,
+        token_metadata: TokenMetadata,
+        perpetual_royalties: Option<HashMap<AccountId, u16>>,
+    ) -> Token {
+        self.internal_owner_mint(
+            token_id,
+            token_owner_id,
+            token_metadata,
+            perpetual_royalties,
+        )
+        .0
+    }
+
+    /// Mint like `nft_mint`, returning the receipt of the mint
+    #[payable]
+    pub fn nft_mint_with_receipt(
+        &mut self,
+        token_id: TokenId,
+        token_owner_id: AccountId,
+        token_metadata: TokenMetadata,
+        perpetual_royalties: Option<HashMap<AccountId, u16>>,
+    ) -> events::MintReceiptLog {
+        self.internal_owner_mint(
+            token_id,
+            token_owner_id,
+            token_metadata,
+            perpetual_royalties,
+        )
+        .1
     }
 }
 
-near_contract_standards::impl_non_fungible_token_core!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_approval!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_enumeration!(Contract, tokens);
+impl Contract {
+    fn internal_owner_mint(
+        &mut self,
+        token_id: TokenId,
+        token_owner_id: AccountId,
+        token_metadata: TokenMetadata,
+        perpetual_royalties: Option<HashMap<AccountId, u16>>,
+    ) -> (Token, events::MintReceiptLog) {
+        self.assert_owner();
+        let initial_storage = env::storage_usage();
+        self.internal_count_daily_mint(&token_owner_id);
+        let token = self.internal_mint_with_royalties(
+            token_id,
+            token_owner_id,
+            token_metadata,
+            perpetual_royalties,
+        );
+        let storage_used = env::storage_usage() - initial_storage;
+        refund_deposit_to_account(storage_used, env::predecessor_account_id());
+        self.internal_audit("mint", Some(&token.token_id), None);
+        events::emit_mint(&token.owner_id, std::slice::from_ref(&token.token_id), None);
+        self.internal_post_social(
+            &token.token_id,
+            format!(
+                "Ukrainian Magical #{} was minted to @{}",
+                token.token_id, token.owner_id
+            ),
+        );
+        let receipt = events::emit_mint_receipt(&token, storage_used);
+        (token, receipt)
+    }
+
+    /// Contract holding `tokens` and `metadata` with every other record empty
+    /// and every setting at its default
+    pub(crate) fn from_token_state(
+        tokens: NonFungibleToken,
+        metadata: LazyOption<NFTContractMetadata>,
+    ) -> Self {
+        Self {
+            metadata,
+            governance_snapshots: LookupMap::new(StorageKey::GovernanceSnapshots),
+            default_media: None,
+            token_extras: LookupMap::new(StorageKey::TokenExtras),
+            royalty_tokens_per_account: LookupMap::new(StorageKey::RoyaltyAccounts),
+            sales: UnorderedMap::new(StorageKey::Sales),
+            default_marketplace: None,
+            mint_curve: None,
+            conditional_approvals: LookupMap::new(StorageKey::ConditionalApprovals),
+            charity_account_id: None,
+            total_donated: 0,
+            token_activity: LookupMap::new(StorageKey::TokenActivity),
+            min_sale_price: 0,
+            token_collaborators: LookupMap::new(StorageKey::TokenCollaborators),
+            audit_log: Vector::new(StorageKey::AuditLog),
+            staged_code: None,
+            token_migration_cursor: None,
+            token_migration_swept: 0,
+            has_minted: !tokens.owner_by_id.is_empty(),
+            unrevealed_metadata: None,
+            reveal_fee: None,
+            revealed_tokens: LookupSet::new(StorageKey::RevealedTokens),
+            ft_mint_price: None,
+            build_info: None,
+            offers: LookupMap::new(StorageKey::Offers),
+            lazy_listings: UnorderedMap::new(StorageKey::LazyListings),
+            next_lazy_listing_id: 0,
+            total_revenue: 0,
+            transfer_approval_snapshots: LookupMap::new(StorageKey::TransferApprovalSnapshots),
+            global_transfer_unlock: 0,
+            platform_fee_bps: 0,
+            daily_mint_count: LookupMap::new(StorageKey::DailyMintCounts),
+            max_daily_mints_per_account: mint_limit::MAX_DAILY_MINTS_PER_ACCOUNT,
+            staked_at: LookupMap::new(StorageKey::StakedAt),
+            staked_tokens_per_account: LookupMap::new(StorageKey::StakedTokens),
+            stake_points: LookupMap::new(StorageKey::StakePoints),
+            stake_points_per_day: staking::DEFAULT_STAKE_POINTS_PER_DAY,
+            leases: LookupMap::new(StorageKey::Leases),
+            max_resale_prices: LookupMap::new(StorageKey::MaxResalePrices),
+            default_max_resale_price: None,
+            token_children: UnorderedMap::new(StorageKey::TokenChildren),
+            token_parents: LookupMap::new(StorageKey::TokenParents),
+            ft_donations: LookupMap::new(StorageKey::FtDonations),
+            next_donation_badge_id: 0,
+            soulbound_tokens: LookupSet::new(StorageKey::SoulboundTokens),
+            provenance: LookupMap::new(StorageKey::Provenance),
+            minting_closed: false,
+            collateral: LookupMap::new(StorageKey::Collateral),
+            series_approvals: LookupMap::new(StorageKey::SeriesApprovals),
+            token_series: LookupMap::new(StorageKey::TokenSeries),
+            social_db_id: None,
+            social_posts_enabled: false,
+            social_pool: 0,
+            event_drops: UnorderedMap::new(StorageKey::EventDrops),
+            external_swaps: LookupMap::new(StorageKey::ExternalSwaps),
+            next_external_swap_id: 0,
+            price_index: TreeMap::new(StorageKey::PriceIndex),
+            charity_sent: 0,
+            charity_failed: 0,
+            paras_ft_tokens: UnorderedSet::new(StorageKey::ParasFtTokens),
+            mint_payout_accounts: vec![],
+            payout_markets: UnorderedSet::new(StorageKey::PayoutMarkets),
+            ft_claimable: LookupMap::new(StorageKey::FtClaimable),
+            min_royalty_bps: 0,
+            default_royalties: HashMap::new(),
+            bridge_account_id: None,
+            bridge_locks: UnorderedMap::new(StorageKey::BridgeLocks),
+            token_tags: LookupMap::new(StorageKey::TokenTags),
+            tag_index: LookupMap::new(StorageKey::TagIndex),
+            donations_by_account: UnorderedMap::new(StorageKey::DonationsByAccount),
+            ft_donations_by_account: LookupMap::new(StorageKey::FtDonationsByAccount),
+            top_donors: LazyOption::new(StorageKey::TopDonors, None),
+            floor_update_cursor: None,
+            matching_campaign: None,
+            null_account: None,
+            pending_charity_change: None,
+            paras_marketplace: None,
+            external_swap_forwards: LookupMap::new(StorageKey::ExternalSwapForwards),
+            pending_ft_payouts: LookupMap::new(StorageKey::PendingFtPayouts),
+            donation_ft_tokens: UnorderedSet::new(StorageKey::DonationFtTokens),
+            charity_ledger: Vector::new(StorageKey::CharityLedger),
+            audit_storage_pool: 0,
+            ft_mint_storage_pool: 0,
+            payout_ft_tokens: UnorderedSet::new(StorageKey::PayoutFtTokens),
+            tokens,
+        }
+    }
+
+    /// Mint `token_id` to `owner_id` recording its activity and approving the default marketplace.
+    /// Storage is paid by the caller, panics once minting is closed. No royalties are set,
+    /// see `internal_mint_with_royalties`
+    pub(crate) fn internal_mint(
+        &mut self,
+        token_id: TokenId,
+        owner_id: AccountId,
+        token_metadata: TokenMetadata,
+    ) -> Token {
+        let mut token = self.internal_mint_without_defaults(token_id, owner_id, token_metadata);
+        if let Some(max_resale_price) = self.default_max_resale_price {
+            self.max_resale_prices
+                .insert(&token.token_id, &max_resale_price);
+        }
+        if let (Some((marketplace_id, approval_id)), Some(approved_account_ids)) = (
+            self.internal_approve_default_marketplace(&token.token_id),
+            token.approved_account_ids.as_mut(),
+        ) {
+            approved_account_ids.insert(marketplace_id, approval_id);
+        }
+        token
+    }
+
+    /// `internal_mint` without the default resale cap and marketplace approval,
+    /// for tokens that are never sold
+    pub(crate) fn internal_mint_without_defaults(
+        &mut self,
+        token_id: TokenId,
+        owner_id: AccountId,
+        token_metadata: TokenMetadata,
+    ) -> Token {
+        self.assert_minting_open();
+        require!(
+            !self.is_null_account(&owner_id),
+            "Cannot mint to the null account"
+        );
+        self.internal_record_tags(&token_id, &token_metadata);
+        let token =
+            self.tokens
+                .internal_mint_with_refund(token_id, owner_id, Some(token_metadata), None);
+        self.internal_record_mint(&token.token_id);
+        self.has_minted = true;
+        token
+    }
+
+    pub(crate) fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Unauthorized"
+        );
+    }
+}
 
 #[near_bindgen]
 impl NonFungibleTokenMetadataProvider for Contract {
@@ -171,31 +635,30 @@ impl NonFungibleTokenMetadataProvider for Contract {
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::testing_env;
-    use std::collections::HashMap;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+    use near_contract_standards::non_fungible_token::core::{
+        NonFungibleTokenCore, NonFungibleTokenReceiver, NonFungibleTokenResolver,
+    };
+    use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
+    use near_sdk::json_types::{U128, U64};
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseOrValue, StorageUsage};
 
     use super::*;
 
-    const MINT_STORAGE_COST: u128 = 5870000000000000000000;
-    const MINT_ALL_STORAGE_COST: u128 = 21310000000000000000000;
-
-    impl Contract {
-        /// Mint a new token with ID=`token_id` belonging to `token_owner_id`.
-        pub fn nft_mint(
-            &mut self,
-            token_id: TokenId,
-            token_owner_id: AccountId,
-            token_metadata: TokenMetadata,
-        ) -> Token {
-            assert_eq!(
-                env::predecessor_account_id(),
-                self.tokens.owner_id,
-                "Unauthorized"
-            );
-            self.tokens
-                .internal_mint(token_id, token_owner_id, Some(token_metadata))
-        }
+    const MINT_STORAGE_BYTES: StorageUsage = 900;
+    const MINT_ALL_STORAGE_BYTES: StorageUsage = 2908;
+    const SNAPSHOT_STORAGE_BYTES: StorageUsage = 200;
+    const LISTING_STORAGE_BYTES: StorageUsage = 400;
+    const CURVE_MINT_STORAGE_BYTES: StorageUsage = 1029;
+    const CONDITIONAL_APPROVAL_STORAGE_BYTES: StorageUsage = 250;
+    const MARKETPLACE_APPROVAL_STORAGE_BYTES: StorageUsage = 120;
+
+    /// Cost of `bytes` of storage at the current price per byte
+    fn storage_cost(bytes: StorageUsage) -> Balance {
+        Balance::from(bytes) * env::storage_byte_cost()
     }
 
     fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
@@ -224,6 +687,114 @@ mod tests {
         }
     }
 
+    /// Mint token "0" to alice who approves charlie under `conditions`
+    fn setup_conditional_approval(
+        context: &mut VMContextBuilder,
+        conditions: approval::ApprovalConditions,
+    ) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint("0".to_string(), accounts(0), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_set_approval_with_conditions("0".to_string(), accounts(2), conditions);
+        contract
+    }
+
+    fn assert_event_round_trip<T>(event_log: events::EventLog<T>, json: &str)
+    where
+        T: near_sdk::serde::Serialize
+            + near_sdk::serde::de::DeserializeOwned
+            + std::fmt::Debug
+            + PartialEq,
+    {
+        assert_eq!(near_sdk::serde_json::to_string(&event_log).unwrap(), json);
+        assert_eq!(
+            near_sdk::serde_json::from_str::<events::EventLog<T>>(json).unwrap(),
+            event_log
+        );
+    }
+
+    #[test]
+    fn test_emit_event_format() {
+        testing_env!(get_context(accounts(0)).build());
+        events::emit_event(
+            "sold_out",
+            vec![near_sdk::serde_json::json!({ "supply": 3 })],
+        );
+        events::emit(vec![events::DefaultMediaUpdateLog {
+            default_media: None,
+        }]);
+        assert_eq!(
+            get_logs(),
+            vec![
+                format!(
+                    r#"EVENT_JSON:{{"standard":"{}","version":"{}","event":"sold_out","data":[{{"supply":3}}]}}"#,
+                    events::EVENT_STANDARD,
+                    events::EVENT_VERSION
+                ),
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"default_media_update","data":[{"default_media":null}]}"#.to_string(),
+            ]
+        );
+    }
+
+    fn event_log<T: events::EventPayload>(data: Vec<T>) -> events::EventLog<T> {
+        events::EventLog {
+            standard: events::EVENT_STANDARD.into(),
+            version: events::EVENT_VERSION.into(),
+            event: T::EVENT.into(),
+            data,
+        }
+    }
+
+    /// Receipt logged by the last call
+    fn logged_mint_receipt() -> events::MintReceiptLog {
+        let log = get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|json| {
+                near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(json).unwrap()
+            })
+            .find(|event| event["event"] == "mint_receipt")
+            .unwrap();
+        near_sdk::serde_json::from_value(log["data"][0].clone()).unwrap()
+    }
+
+    /// Bytes of the newest audit entry, paid by the contract rather than the caller
+    fn last_audit_entry_bytes(contract: &Contract) -> u64 {
+        last_audit_entries_bytes(contract, 1)
+    }
+
+    /// Bytes of the `count` newest audit entries
+    fn last_audit_entries_bytes(contract: &Contract, count: u64) -> u64 {
+        use crate::storage::StorageSize;
+
+        let len = contract.audit_log.len();
+        (len - count..len)
+            .map(|index| {
+                let entry = contract.audit_log.get(index).unwrap();
+                40 + StorageKey::AuditLog.storage_size() + 8 + entry.storage_size()
+            })
+            .sum()
+    }
+
+    /// Bytes of the provenance record of `owner_id` appended by a transfer
+    fn provenance_record_bytes(owner_id: &AccountId) -> u64 {
+        use crate::storage::StorageSize;
+
+        40 + 37 + 8 + owner_id.storage_size() + 8
+    }
+
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
@@ -249,176 +820,9150 @@ mod tests {
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
             .predecessor_account_id(accounts(0))
             .build());
 
         let token_id = "0".to_string();
-        let token = contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        let token = contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
         assert_eq!(token.token_id, token_id);
         assert_eq!(token.owner_id, accounts(0));
         assert_eq!(token.metadata.unwrap(), sample_token_metadata());
         assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
     }
 
-    #[test]
-    fn test_mint_all() {
-        let mut context = get_context(accounts(0));
-        testing_env!(context.build());
+    /// Bytes charged to the caller for minting token "0" to alice on a new contract, measured
+    /// on a throwaway contract whose storage is cleared afterwards
+    fn measured_mint_storage_bytes(context: &mut VMContextBuilder) -> StorageUsage {
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
         let mut contract = Contract::new();
-
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_ALL_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
             .build());
+        let initial_storage = env::storage_usage();
+        contract.nft_mint("0".to_string(), accounts(0), sample_token_metadata(), None);
+        let bytes = env::storage_usage() - initial_storage - last_audit_entry_bytes(&contract);
+        near_sdk::mock::with_mocked_blockchain(|b| b.take_storage());
+        bytes
+    }
 
-        contract.nft_mint_all();
+    #[test]
+    fn test_mint_with_computed_storage_deposit() {
+        let mut context = get_context(accounts(0));
+        let deposit = storage_cost(measured_mint_storage_bytes(&mut context));
+        assert!(deposit <= storage_cost(MINT_STORAGE_BYTES));
 
-        // TODO: check nft_token() results
+        testing_env!(context.attached_deposit(0).build());
+        let mut contract = Contract::new();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+        let token = contract.nft_mint("0".to_string(), accounts(0), sample_token_metadata(), None);
+        assert_eq!(token.owner_id, accounts(0));
     }
 
     #[test]
-    fn test_transfer() {
+    fn test_mint_receipt() {
         let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let mut contract = Contract::new();
+        let bytes = measured_mint_storage_bytes(&mut context);
 
+        testing_env!(context.attached_deposit(0).build());
+        let mut contract = Contract::new();
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .block_index(42)
+            .block_timestamp(1_700_000_000_000_000_000)
             .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        let token = contract.nft_mint("0".to_string(), accounts(0), sample_token_metadata(), None);
+        assert_eq!(
+            logged_mint_receipt(),
+            events::MintReceiptLog {
+                token_id: token.token_id,
+                owner: token.owner_id,
+                block_height: U64(42),
+                timestamp: U64(1_700_000_000),
+                storage_charged: U128(storage_cost(bytes)),
+            }
+        );
+    }
 
+    #[test]
+    fn test_mint_with_receipt() {
+        let mut context = get_context(accounts(0));
+        let bytes = measured_mint_storage_bytes(&mut context);
+
+        testing_env!(context.attached_deposit(0).build());
+        let mut contract = Contract::new();
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(1)
-            .predecessor_account_id(accounts(0))
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .block_index(42)
+            .block_timestamp(1_700_000_000_000_000_000)
             .build());
-        contract.nft_transfer(accounts(1), token_id.clone(), None, None);
+        let receipt = contract.nft_mint_with_receipt(
+            "0".to_string(),
+            accounts(0),
+            sample_token_metadata(),
+            None,
+        );
+        assert_eq!(
+            receipt,
+            events::MintReceiptLog {
+                token_id: "0".to_string(),
+                owner: accounts(0),
+                block_height: U64(42),
+                timestamp: U64(1_700_000_000),
+                storage_charged: U128(storage_cost(bytes)),
+            }
+        );
+        assert_eq!(logged_mint_receipt(), receipt);
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(0)
+        );
+    }
 
+    #[test]
+    #[should_panic(expected = "yoctoNEAR to cover storage")]
+    fn test_mint_with_computed_storage_deposit_minus_one() {
+        let mut context = get_context(accounts(0));
+        let deposit = storage_cost(measured_mint_storage_bytes(&mut context));
+
+        testing_env!(context.attached_deposit(0).build());
+        let mut contract = Contract::new();
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
+            .attached_deposit(deposit - 1)
             .build());
-        if let Some(token) = contract.nft_token(token_id.clone()) {
-            assert_eq!(token.token_id, token_id);
-            assert_eq!(token.owner_id, accounts(1));
-            assert_eq!(token.metadata.unwrap(), sample_token_metadata());
-            assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
-        } else {
-            panic!("token not correctly created, or not found by nft_token");
-        }
+        contract.nft_mint("0".to_string(), accounts(0), sample_token_metadata(), None);
     }
 
     #[test]
-    fn test_approve() {
+    fn test_mint_with_estimated_mint_cost() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
         let mut contract = Contract::new();
+        contract.set_default_marketplace(Some(accounts(2)));
+        contract.set_default_royalties(HashMap::from([(accounts(3), 500)]));
+        contract.set_default_max_resale_price(Some(U128(1000)));
 
+        let deposit = contract.nft_estimated_mint_cost().0;
+        let holder_id: AccountId = "magical-holder.near".parse().unwrap();
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
+            .attached_deposit(deposit)
             .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        contract.nft_mint(
+            "0".to_string(),
+            holder_id.clone(),
+            TokenMetadata {
+                title: Some("#0 Mariupol".into()),
+                description: Some(NFT_DESCRIPTION.into()),
+                media: Some("Cqe2tJCF-yygmxci0RsESa62zQNqPV9oZVDeallYI7o".into()),
+                copies: Some(1u64),
+                issued_at: Some("1648000000".into()),
+                reference: Some("Akb7UGDwSbcYka0-frMk5T-YTJQurXzdD0ZBnSqyBRQ".into()),
+                ..sample_token_metadata()
+            },
+            None,
+        );
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            holder_id
+        );
+    }
+
+    #[test]
+    fn test_mint_all() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
 
-        // alice approves bob
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
+            .attached_deposit(storage_cost(MINT_ALL_STORAGE_BYTES))
             .predecessor_account_id(accounts(0))
             .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
-            .build());
-        assert!(contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        contract.nft_mint_all();
+
+        // TODO: check nft_token() results
     }
 
     #[test]
-    fn test_revoke() {
+    fn test_mint_at_min_royalty() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
         let mut contract = Contract::new();
+        contract.set_default_royalties(HashMap::from([(accounts(2), 500)]));
+        contract.set_min_royalty_bps(500);
+        assert_eq!(contract.min_royalty_bps(), 500);
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 300), (accounts(3), 200)])),
+        );
+        assert_eq!(
+            contract.internal_token_extras(&"0".to_string()).royalties,
+            HashMap::from([(accounts(2), 300), (accounts(3), 200)])
+        );
+    }
 
-        // alice approves bob
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+    #[test]
+    #[should_panic(expected = "Royalties must total at least 500 basis points")]
+    fn test_mint_below_min_royalty() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_royalties(HashMap::from([(accounts(2), 500)]));
+        contract.set_min_royalty_bps(500);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 499)])),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Default royalties are below the minimum royalty")]
+    fn test_min_royalty_without_default_royalties() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_min_royalty_bps(500);
+    }
+
+    #[test]
+    fn test_mint_all_with_default_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_royalties(HashMap::from([(accounts(2), 500)]));
+        contract.set_min_royalty_bps(500);
+        call_env(
+            &mut context,
+            storage_cost(MINT_ALL_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint_all();
+        for token_id in ["0", "1", "2"] {
+            assert_eq!(
+                contract
+                    .internal_token_extras(&token_id.to_string())
+                    .royalties,
+                HashMap::from([(accounts(2), 500)])
+            );
+        }
+        assert_eq!(
+            contract
+                .royalties_for_account(accounts(2), None, None)
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Default royalties are below the minimum royalty")]
+    fn test_default_royalties_below_min_royalty() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_royalties(HashMap::from([(accounts(2), 500)]));
+        contract.set_min_royalty_bps(500);
+        contract.set_default_royalties(HashMap::from([(accounts(2), 250)]));
+    }
+
+    #[test]
+    fn test_royalty_policy_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::RoyaltyPolicyUpdateLog {
+                min_royalty_bps: 500,
+                default_royalties: HashMap::from([(accounts(2), 500)]),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"royalty_policy_update","data":[{"min_royalty_bps":500,"default_royalties":{"charlie":500}}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_token_metadata_hash() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
 
-        // alice revokes bob
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(1)
+            .attached_deposit(storage_cost(MINT_ALL_STORAGE_BYTES))
             .predecessor_account_id(accounts(0))
             .build());
-        contract.nft_revoke(token_id.clone(), accounts(1));
+        contract.nft_mint_all();
+
         testing_env!(context
             .storage_usage(env::storage_usage())
             .account_balance(env::account_balance())
             .is_view(true)
             .attached_deposit(0)
             .build());
-        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+        assert_eq!(
+            contract.nft_token_metadata_hash("1".to_string()),
+            "717efe44a823595f86a36874ac2da2e438a94a42818ed30426f4aa54a9ef96a9"
+        );
     }
 
     #[test]
-    fn test_revoke_all() {
+    fn test_metadata_diff() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        let variant = TokenMetadata {
+            title: Some("Olive Tree Variant".into()),
+            media: Some("variant.webp".into()),
+            copies: Some(2),
+            ..sample_token_metadata()
+        };
+        for (token_id, metadata) in [
+            ("0", sample_token_metadata()),
+            ("1", sample_token_metadata()),
+            ("2", variant.clone()),
+        ] {
+            call_env(
+                &mut context,
+                storage_cost(MINT_STORAGE_BYTES) * 2,
+                accounts(0),
+            );
+            contract.nft_mint(token_id.into(), accounts(1), metadata, None);
+        }
+
+        assert_eq!(
+            contract.nft_metadata_diff("0".into(), "1".into()),
+            metadata::MetadataDiff {
+                different_fields: vec![],
+                token_a_values: HashMap::new(),
+                token_b_values: HashMap::new(),
+            }
+        );
+        let sample = sample_token_metadata();
+        assert_eq!(
+            contract.nft_metadata_diff("0".into(), "2".into()),
+            metadata::MetadataDiff {
+                different_fields: vec!["copies".into(), "media".into(), "title".into()],
+                token_a_values: HashMap::from([
+                    (
+                        "copies".into(),
+                        sample.copies.map(|copies| copies.to_string())
+                    ),
+                    ("media".into(), sample.media),
+                    ("title".into(), sample.title),
+                ]),
+                token_b_values: HashMap::from([
+                    ("copies".into(), Some("2".into())),
+                    ("media".into(), variant.media),
+                    ("title".into(), variant.title),
+                ]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_provenance_pages() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        let owners = [accounts(2), accounts(3), accounts(4), accounts(1)];
+        let mut previous_owner_id = accounts(1);
+        for (i, owner_id) in owners.iter().enumerate() {
+            call_env(&mut context, 1, previous_owner_id.clone());
+            testing_env!(context
+                .block_timestamp((i as u64 + 1) * 1_000_000_000)
+                .build());
+            contract.nft_transfer(owner_id.clone(), "0".to_string(), None, None);
+            previous_owner_id = owner_id.clone();
+        }
+
+        let expected: Vec<(AccountId, U64)> = std::iter::once(accounts(1))
+            .chain(owners)
+            .enumerate()
+            .map(|(i, owner_id)| (owner_id, U64(i as u64)))
+            .collect();
+        let token_id = || "0".to_string();
+        assert_eq!(
+            contract.nft_provenance_page(token_id(), 0, 2),
+            expected[0..2]
+        );
+        assert_eq!(
+            contract.nft_provenance_page(token_id(), 2, 2),
+            expected[2..4]
+        );
+        assert_eq!(
+            contract.nft_provenance_page(token_id(), 4, 2),
+            expected[4..]
+        );
+        assert_eq!(contract.nft_provenance_page(token_id(), 0, 100), expected);
+        assert!(contract.nft_provenance_page(token_id(), 5, 2).is_empty());
+        assert!(contract
+            .nft_provenance_page("unknown".to_string(), 0, 2)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_provenance_keeps_every_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        let mut owner_id = accounts(1);
+        for i in 0..25 {
+            let receiver_id = if i % 2 == 0 { accounts(2) } else { accounts(1) };
+            call_env(&mut context, 1, owner_id);
+            contract.nft_transfer(receiver_id.clone(), "0".to_string(), None, None);
+            owner_id = receiver_id;
+        }
+
+        let history = contract.nft_provenance_page("0".to_string(), 0, 100);
+        assert_eq!(history.len(), 26);
+        assert_eq!(history[0].0, accounts(1));
+        assert_eq!(history.last().unwrap().0, owner_id);
+        assert_eq!(
+            contract.nft_provenance_page("0".to_string(), 20, 10),
+            history[20..]
+        );
+    }
+
+    #[test]
+    fn test_burn_clears_provenance() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+        call_env(&mut context, 1, accounts(2));
+        contract.nft_burn("0".to_string(), None);
+        assert!(contract
+            .nft_provenance_page("0".to_string(), 0, 100)
+            .is_empty());
+        assert!(contract.provenance.get(&"0".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_token_storage_bytes() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
         let mut contract = Contract::new();
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
             .predecessor_account_id(accounts(0))
             .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        contract.nft_mint("0".to_string(), accounts(0), sample_token_metadata(), None);
+
+        // the second token of the same owner doesn't create per-owner records
+        let initial_storage = env::storage_usage();
+        let token_id = "1".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+        let mint_bytes = env::storage_usage() - initial_storage - last_audit_entry_bytes(&contract);
+        let estimate = contract.token_storage_bytes(token_id.clone());
+        assert!(
+            estimate.abs_diff(mint_bytes) <= 16,
+            "{} vs {}",
+            estimate,
+            mint_bytes
+        );
 
-        // alice approves bob
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
+            .attached_deposit(storage_cost(15))
             .predecessor_account_id(accounts(0))
             .build());
+        let initial_storage = env::storage_usage();
         contract.nft_approve(token_id.clone(), accounts(1), None);
+        let approve_bytes =
+            env::storage_usage() - initial_storage - last_audit_entry_bytes(&contract);
+        let estimate_with_approval = contract.token_storage_bytes(token_id.clone());
+        assert!(
+            (estimate_with_approval - estimate).abs_diff(approve_bytes) <= 16,
+            "{} vs {}",
+            estimate_with_approval - estimate,
+            approve_bytes
+        );
+
+        assert_eq!(
+            contract.token_storage_cost(token_id).0,
+            storage_cost(estimate_with_approval)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token not found")]
+    fn test_token_storage_bytes_unknown_token() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new();
+        contract.token_storage_bytes("0".to_string());
+    }
+
+    #[test]
+    fn test_governance_snapshot() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(SNAPSHOT_STORAGE_BYTES))
+            .block_index(42)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let initial_storage = env::storage_usage();
+        contract.nft_take_governance_snapshot("before".to_string());
+        // the unused deposit is refunded to the caller who attached it
+        let storage_used =
+            env::storage_usage() - initial_storage - last_audit_entry_bytes(&contract);
+        assert_eq!(
+            payments(),
+            vec![(
+                accounts(0),
+                accounts(0),
+                storage_cost(SNAPSHOT_STORAGE_BYTES) - storage_cost(storage_used)
+            )]
+        );
 
-        // alice revokes bob
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(1)
             .predecessor_account_id(accounts(0))
             .build());
-        contract.nft_revoke_all(token_id.clone());
+        contract.nft_transfer(accounts(1), token_id.clone(), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(SNAPSHOT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_take_governance_snapshot("after".to_string());
+
         testing_env!(context
             .storage_usage(env::storage_usage())
             .account_balance(env::account_balance())
             .is_view(true)
             .attached_deposit(0)
             .build());
-        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        assert_eq!(
+            contract.nft_snapshot_owner_at("before".to_string(), token_id.clone()),
+            Some(accounts(0))
+        );
+        assert_eq!(
+            contract.nft_snapshot_owner_at("after".to_string(), token_id.clone()),
+            Some(accounts(1))
+        );
+        assert_eq!(
+            contract.nft_snapshot_owner_at("before".to_string(), "1".to_string()),
+            None
+        );
+        assert_eq!(
+            contract.nft_snapshot_owner_at("unknown".to_string(), token_id),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Snapshot already exists")]
+    fn test_governance_snapshot_duplicate() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(SNAPSHOT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_take_governance_snapshot("0".to_string());
+        contract.nft_take_governance_snapshot("0".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_governance_snapshot_unauthorized() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .attached_deposit(storage_cost(SNAPSHOT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_take_governance_snapshot("0".to_string());
+    }
+
+    #[test]
+    fn test_default_media() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+        let mut metadata_with_media = sample_token_metadata();
+        metadata_with_media.media = Some("own-media".into());
+        contract.nft_mint("1".to_string(), accounts(0), metadata_with_media, None);
+
+        assert_eq!(
+            contract
+                .nft_token(token_id.clone())
+                .unwrap()
+                .metadata
+                .unwrap()
+                .media,
+            None
+        );
+
+        contract.set_default_media(Some("default-media".into()));
+        assert_eq!(contract.default_media(), Some("default-media".into()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        let fallback = Some(format!("{}default-media", ARWEAVE_GATEWAY_BASE_URL));
+        let token = contract.nft_token(token_id.clone()).unwrap();
+        assert_eq!(token.metadata.unwrap().media, fallback);
+        let medias: Vec<Option<String>> = contract
+            .nft_tokens_for_owner(accounts(0), None, None)
+            .into_iter()
+            .map(|token| token.metadata.unwrap().media)
+            .collect();
+        assert_eq!(medias, vec![fallback, Some("own-media".into())]);
+    }
+
+    #[test]
+    fn test_token_ids_for_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        for (token_id, owner_id) in [
+            ("0", accounts(1)),
+            ("1", accounts(3)),
+            ("2", accounts(1)),
+            ("3", accounts(1)),
+        ] {
+            call_env(
+                &mut context,
+                storage_cost(MINT_STORAGE_BYTES) * 2,
+                accounts(0),
+            );
+            contract.nft_mint(token_id.into(), owner_id, sample_token_metadata(), None);
+        }
+
+        let token_ids = contract.nft_token_ids_for_owner(accounts(1), None, None);
+        assert_eq!(token_ids, vec!["0", "2", "3"]);
+        let full_token_ids: Vec<TokenId> = contract
+            .nft_tokens_for_owner(accounts(1), None, None)
+            .into_iter()
+            .map(|token| token.token_id)
+            .collect();
+        assert_eq!(token_ids, full_token_ids);
+        assert_eq!(
+            contract.nft_token_ids_for_owner(accounts(1), Some(U128(1)), Some(1)),
+            vec!["2"]
+        );
+        assert!(contract
+            .nft_token_ids_for_owner(accounts(2), None, None)
+            .is_empty());
+    }
+
+    #[cfg(feature = "testnet")]
+    #[test]
+    fn test_mint_test() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        assert!(contract.contract_info().testnet);
+
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(2),
+        );
+        let token = contract.nft_mint_test(accounts(3));
+        assert_eq!(token.token_id, "0");
+        assert_eq!(token.owner_id, accounts(3));
+        assert_eq!(
+            token.metadata.unwrap().title,
+            Some(format!("{} test #0", NFT_NAME))
+        );
+    }
+
+    #[cfg(not(feature = "testnet"))]
+    #[test]
+    fn test_default_build_has_no_testnet_methods() {
+        // an inherent `nft_mint_test` would shadow the probe and fail to compile
+        trait MintTestProbe {
+            fn nft_mint_test(&mut self) -> bool {
+                false
+            }
+        }
+        impl MintTestProbe for Contract {}
+
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        assert!(!contract.nft_mint_test());
+        assert!(!contract.contract_info().testnet);
+        assert!(events::METHOD_EVENTS
+            .iter()
+            .all(|(method, _)| *method != "nft_mint_test"));
+    }
+
+    #[test]
+    fn test_token_raw() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .build());
+        let mut with_media = sample_token_metadata();
+        with_media.media = Some("media-0".into());
+        contract.nft_mint("0".to_string(), accounts(1), with_media.clone(), None);
+        contract.nft_mint("1".to_string(), accounts(1), sample_token_metadata(), None);
+        contract.set_default_media(Some("default-media".into()));
+        contract.set_reveal_config(
+            Some(TokenMetadata {
+                title: Some("Hidden".into()),
+                ..sample_token_metadata()
+            }),
+            None,
+        );
+
+        assert_eq!(
+            contract.nft_token_raw("0".to_string()).unwrap().metadata,
+            Some(with_media)
+        );
+        assert_eq!(
+            contract.nft_token_raw("1".to_string()).unwrap().metadata,
+            Some(sample_token_metadata())
+        );
+        assert!(contract.nft_token_raw("2".to_string()).is_none());
+
+        // the media isn't resolved against base_uri
+        let base_uri = "https://gateway.example/".to_string();
+        let mut metadata = contract.metadata.get().unwrap();
+        metadata.base_uri = Some(base_uri.clone());
+        contract.metadata.set(&metadata);
+        let media = contract
+            .nft_token_raw("0".to_string())
+            .unwrap()
+            .metadata
+            .unwrap()
+            .media
+            .unwrap();
+        assert_eq!(media, "media-0");
+        assert!(!media.starts_with(&base_uri));
+        // the public views keep hiding the token
+        assert_eq!(
+            contract
+                .nft_token_rendered("0".to_string(), None)
+                .unwrap()
+                .metadata
+                .unwrap()
+                .title,
+            Some("Hidden".into())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_token_raw_by_non_owner() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_token(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.nft_token_raw("0".to_string());
+    }
+
+    #[test]
+    fn test_token_rendered() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 3)
+            .build());
+        let mut without_media = sample_token_metadata();
+        without_media.reference = Some("reference-0".into());
+        contract.nft_mint("0".to_string(), accounts(1), without_media, None);
+        let mut with_media = sample_token_metadata();
+        with_media.media = Some("media-1".into());
+        contract.nft_mint("1".to_string(), accounts(1), with_media, None);
+        let mut with_url = sample_token_metadata();
+        with_url.media = Some("https://example.com/media-2".into());
+        contract.nft_mint("2".to_string(), accounts(1), with_url, None);
+        contract.set_default_media(Some("default-media".into()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        let rendered = |token_id: &str, locale: Option<&str>| {
+            let metadata = contract
+                .nft_token_rendered(token_id.to_string(), locale.map(String::from))
+                .unwrap()
+                .metadata
+                .unwrap();
+            (metadata.media.unwrap(), metadata.reference)
+        };
+        let url = |path: &str| format!("{}{}", ARWEAVE_GATEWAY_BASE_URL, path);
+        assert_eq!(
+            rendered("0", Some("uk")),
+            (url("default-media"), Some(url("reference-0")))
+        );
+        assert_eq!(rendered("0", None), rendered("0", Some("uk")));
+        assert_eq!(rendered("1", Some("uk")), (url("media-1"), None));
+        assert_eq!(
+            rendered("2", None),
+            ("https://example.com/media-2".to_string(), None)
+        );
+        assert!(contract.nft_token_rendered("3".to_string(), None).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_default_media_unauthorized() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_default_media(Some("default-media".into()));
+    }
+
+    #[test]
+    fn test_royalties_for_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 4)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(0),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(1), 500), (accounts(2), 1000)])),
+        );
+        contract.nft_mint("1".to_string(), accounts(0), sample_token_metadata(), None);
+        contract.nft_mint(
+            "2".to_string(),
+            accounts(0),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(1), 250)])),
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.royalties_for_account(accounts(1), None, None),
+            vec![("0".to_string(), 500), ("2".to_string(), 250)]
+        );
+        assert_eq!(
+            contract.royalties_for_account(accounts(1), Some(U128(1)), Some(1)),
+            vec![("2".to_string(), 250)]
+        );
+        assert_eq!(
+            contract.royalties_for_account(accounts(2), None, None),
+            vec![("0".to_string(), 1000)]
+        );
+        assert!(contract
+            .royalties_for_account(accounts(3), None, None)
+            .is_empty());
+    }
+
+    /// Token "0" of bob that can't be resold above 1000
+    fn contract_with_capped_token(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(context, storage_cost(MINT_STORAGE_BYTES) * 2, accounts(0));
+        contract.nft_mint_with_resale_cap(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            U128(1000),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_list_within_resale_cap() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_capped_token(&mut context);
+        assert_eq!(
+            contract.nft_max_resale_price("0".to_string()),
+            Some(U128(1000))
+        );
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_for_sale("0".to_string(), U128(1000));
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_for_sale("0".to_string(), U128(900));
+        assert_eq!(
+            contract.nft_get_listing_price("0".to_string()),
+            Some(U128(900))
+        );
+
+        call_env(&mut context, 900, accounts(2));
+        contract.buy("0".to_string());
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(2)
+        );
+    }
+
+    #[test]
+    fn test_default_resale_cap() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_max_resale_price(Some(U128(500)));
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 3,
+            accounts(0),
+        );
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata(), None);
+        contract.nft_mint_with_resale_cap(
+            "1".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            U128(2000),
+        );
+        call_env(&mut context, 0, accounts(0));
+        contract.set_default_max_resale_price(None);
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_mint("2".to_string(), accounts(1), sample_token_metadata(), None);
+
+        assert_eq!(
+            contract.nft_max_resale_price("0".to_string()),
+            Some(U128(500))
+        );
+        assert_eq!(
+            contract.nft_max_resale_price("1".to_string()),
+            Some(U128(2000))
+        );
+        assert_eq!(contract.nft_max_resale_price("2".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Price exceeds the resale cap of 1000 yoctoNEAR")]
+    fn test_list_above_resale_cap() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_capped_token(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_for_sale("0".to_string(), U128(1001));
+    }
+
+    #[test]
+    #[should_panic(expected = "Price exceeds the resale cap of 1000 yoctoNEAR")]
+    fn test_list_capped_token_for_ft() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_capped_token(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_for_sale_ft("0".to_string(), U128(10), accounts(4));
+    }
+
+    #[test]
+    fn test_default_resale_cap_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::DefaultResaleCapUpdateLog {
+                default_max_resale_price: Some(U128(1000)),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"default_resale_cap_update","data":[{"default_max_resale_price":"1000"}]}"#,
+        );
+    }
+
+    const LEASE_STORAGE_BYTES: StorageUsage = 300;
+
+    /// Token "0" of bob leased to charlie for a fee of 1000 until 100 seconds
+    fn contract_with_lease(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        call_env(context, storage_cost(LEASE_STORAGE_BYTES), accounts(1));
+        contract.nft_lease("0".to_string(), accounts(2), U64(100), U128(1000));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1500)
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(10_000_000_000)
+            .build());
+        contract.nft_accept_lease("0".to_string());
+        contract
+    }
+
+    #[test]
+    fn test_lease_lifecycle() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, storage_cost(LEASE_STORAGE_BYTES), accounts(1));
+        contract.nft_lease("0".to_string(), accounts(2), U64(100), U128(1000));
+        // an offered lease doesn't hold the token yet
+        assert_eq!(contract.nft_current_user("0".to_string()), accounts(1));
+        assert!(contract.nft_is_transferable("0".to_string()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1500)
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(10_000_000_000)
+            .build());
+        contract.nft_accept_lease("0".to_string());
+        // the fee goes to the owner, the excess back to the lessee
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(
+            paid,
+            vec![
+                (accounts(0), accounts(1), 1000),
+                (accounts(0), accounts(2), 500)
+            ]
+        );
+        assert_eq!(contract.nft_current_user("0".to_string()), accounts(2));
+        assert!(!contract.nft_is_transferable("0".to_string()));
+        assert!(contract.nft_lease_of("0".to_string()).unwrap().accepted);
+
+        // the lease expires by itself
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(100_000_000_000)
+            .build());
+        assert_eq!(contract.nft_current_user("0".to_string()), accounts(1));
+        assert!(contract.nft_is_transferable("0".to_string()));
+        contract.nft_transfer(accounts(3), "0".to_string(), None, None);
+        assert_eq!(contract.nft_lease_of("0".to_string()), None);
+        assert_eq!(contract.nft_current_user("0".to_string()), accounts(3));
+    }
+
+    #[test]
+    fn test_lease_fee_pays_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(3), 1000)])),
+        );
+        call_env(&mut context, storage_cost(LEASE_STORAGE_BYTES), accounts(1));
+        contract.nft_lease("0".to_string(), accounts(2), U64(100), U128(1000));
+        call_env(&mut context, 1000, accounts(2));
+        contract.nft_accept_lease("0".to_string());
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(
+            paid,
+            vec![
+                (accounts(0), accounts(1), 900),
+                (accounts(0), accounts(3), 100)
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is leased")]
+    fn test_owner_transfer_during_lease() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_lease(&mut context);
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(50_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(3), "0".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is leased")]
+    fn test_lessee_transfer_during_lease() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_lease(&mut context);
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(50_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(3), "0".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is leased")]
+    fn test_list_during_lease() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_lease(&mut context);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(50_000_000_000)
+            .build());
+        contract.list_for_sale("0".to_string(), U128(1000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is staked")]
+    fn test_accept_lease_of_token_staked_since_offered() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, storage_cost(LEASE_STORAGE_BYTES), accounts(1));
+        contract.nft_lease("0".to_string(), accounts(2), U64(100), U128(1000));
+        call_env(&mut context, storage_cost(STAKE_STORAGE_BYTES), accounts(1));
+        contract.nft_stake("0".to_string());
+        call_env(&mut context, 1000, accounts(2));
+        contract.nft_accept_lease("0".to_string());
+    }
+
+    #[test]
+    fn test_accept_lease_removes_listing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_listing(&mut context);
+        call_env(&mut context, storage_cost(LEASE_STORAGE_BYTES), accounts(1));
+        contract.nft_lease("0".to_string(), accounts(2), U64(100), U128(1000));
+        call_env(&mut context, 1000, accounts(2));
+        contract.nft_accept_lease("0".to_string());
+        assert_eq!(contract.nft_get_listing_price("0".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is leased")]
+    fn test_burn_during_lease() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_lease(&mut context);
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(50_000_000_000)
+            .build());
+        contract.nft_burn("0".to_string(), None);
+    }
+
+    #[test]
+    fn test_lease_logs_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftLeaseLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                lessee_id: accounts(2),
+                until: U64(100),
+                fee: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_lease","data":[{"token_id":"0","owner_id":"bob","lessee_id":"charlie","until":"100","fee":"1000"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::NftLeaseAcceptLog {
+                token_id: "0".into(),
+                lessee_id: accounts(2),
+                until: U64(100),
+                fee: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_lease_accept","data":[{"token_id":"0","lessee_id":"charlie","until":"100","fee":"1000"}]}"#,
+        );
+    }
+
+    const ATTACH_STORAGE_BYTES: StorageUsage = 500;
+
+    /// Tokens "1" and "2" of bob attached to his token "0"
+    fn contract_with_children(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        for token_id in ["0", "1", "2"] {
+            call_env(context, storage_cost(MINT_STORAGE_BYTES) * 2, accounts(0));
+            contract.nft_mint(token_id.into(), accounts(1), sample_token_metadata(), None);
+        }
+        for child_id in ["1", "2"] {
+            call_env(context, storage_cost(ATTACH_STORAGE_BYTES), accounts(1));
+            contract.nft_attach(child_id.into(), "0".to_string());
+        }
+        contract
+    }
+
+    #[test]
+    fn test_transfer_parent_with_children() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_children(&mut context);
+        assert_eq!(
+            contract.nft_children("0".to_string(), None, None),
+            vec!["1".to_string(), "2".to_string()]
+        );
+        assert_eq!(contract.nft_parent("1".to_string()), Some("0".to_string()));
+        assert!(!contract.nft_is_transferable("1".to_string()));
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+        for token_id in ["0", "1", "2"] {
+            assert_eq!(
+                contract.nft_token(token_id.into()).unwrap().owner_id,
+                accounts(2)
+            );
+        }
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(0));
+        assert_eq!(contract.nft_supply_for_owner(accounts(2)), U128(3));
+        assert_eq!(contract.nft_children("0".to_string(), None, None).len(), 2);
+    }
+
+    #[test]
+    fn test_detach_child() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_children(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_detach("1".to_string());
+        assert_eq!(contract.nft_parent("1".to_string()), None);
+        assert_eq!(
+            contract.nft_children("0".to_string(), None, None),
+            vec!["2".to_string()]
+        );
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "1".to_string(), None, None);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(3), "0".to_string(), None, None);
+        assert_eq!(
+            contract.nft_token("1".to_string()).unwrap().owner_id,
+            accounts(2)
+        );
+        assert_eq!(
+            contract.nft_token("2".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+    }
+
+    #[test]
+    fn test_children_return_with_refused_parent() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_children(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        let _ = contract.nft_transfer_call(accounts(3), "0".to_string(), None, None, "".into());
+        assert_eq!(
+            contract.nft_token("2".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert!(!resolve_pending_transfer_call(
+            &mut context,
+            &mut contract,
+            true
+        ));
+        for token_id in ["0", "1", "2"] {
+            assert_eq!(
+                contract.nft_token(token_id.into()).unwrap().owner_id,
+                accounts(1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_children_return_within_gas_budget() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_children(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_max_daily_mints_per_account(20);
+        for index in 3..=composable::MAX_CHILDREN_PER_PARENT {
+            let child_id = index.to_string();
+            call_env(
+                &mut context,
+                storage_cost(MINT_STORAGE_BYTES) * 2,
+                accounts(0),
+            );
+            contract.nft_mint(child_id.clone(), accounts(1), sample_token_metadata(), None);
+            call_env(
+                &mut context,
+                storage_cost(ATTACH_STORAGE_BYTES),
+                accounts(1),
+            );
+            contract.nft_attach(child_id, "0".to_string());
+        }
+        call_env(&mut context, 1, accounts(1));
+        let _ = contract.nft_transfer_call(accounts(3), "0".to_string(), None, None, "".into());
+        // the receiver approves a child before returning the parent
+        call_env(
+            &mut context,
+            storage_cost(MARKETPLACE_APPROVAL_STORAGE_BYTES),
+            accounts(3),
+        );
+        contract.nft_approve("1".to_string(), accounts(2), None);
+        assert!(!resolve_pending_transfer_call(
+            &mut context,
+            &mut contract,
+            true
+        ));
+        assert_eq!(
+            contract.nft_token("10".to_string()).unwrap().owner_id,
+            accounts(1)
+        );
+        assert!(payments()
+            .iter()
+            .any(|(_, account_id, _)| *account_id == accounts(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is attached")]
+    fn test_transfer_attached_child() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_children(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "1".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Parent token is attached")]
+    fn test_attach_to_attached_parent() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_children(&mut context);
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_mint("3".to_string(), accounts(1), sample_token_metadata(), None);
+        call_env(
+            &mut context,
+            storage_cost(ATTACH_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_attach("3".to_string(), "1".to_string());
+    }
+
+    #[test]
+    fn test_nft_attach_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftAttachLog {
+                child_id: "1".into(),
+                parent_id: "0".into(),
+                owner_id: accounts(1),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_attach","data":[{"child_id":"1","parent_id":"0","owner_id":"bob"}]}"#,
+        );
+    }
+
+    const STAKE_STORAGE_BYTES: StorageUsage = 400;
+
+    /// Token "0" of bob staked at `block_timestamp`
+    fn contract_with_staked_token(
+        context: &mut VMContextBuilder,
+        block_timestamp: u64,
+    ) -> Contract {
+        let mut contract = contract_with_token(context);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(STAKE_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(block_timestamp)
+            .build());
+        contract.nft_stake("0".to_string());
+        contract
+    }
+
+    #[test]
+    fn test_staking_accrues_points() {
+        let mut context = get_context(accounts(0));
+        let day = 86_400_000_000_000;
+        let mut contract = contract_with_staked_token(&mut context, 0);
+        assert_eq!(
+            contract.staked_tokens(accounts(1), None, None),
+            vec!["0".to_string()]
+        );
+        assert!(!contract.nft_is_transferable("0".to_string()));
+
+        // only whole days count
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .block_timestamp(day * 7 / 2)
+            .build());
+        assert_eq!(contract.nft_unstake("0".to_string()), U64(3));
+        assert_eq!(contract.points_of(accounts(1)), U64(3));
+        assert!(contract.staked_tokens(accounts(1), None, None).is_empty());
+        assert!(contract.nft_is_transferable("0".to_string()));
+        // the stake storage is refunded
+        assert_eq!(payments().len(), 1);
+
+        call_env(&mut context, 0, accounts(0));
+        contract.set_stake_points_per_day(2);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(STAKE_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(day * 4)
+            .build());
+        contract.nft_stake("0".to_string());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .block_timestamp(day * 5)
+            .build());
+        assert_eq!(contract.nft_unstake("0".to_string()), U64(2));
+        assert_eq!(contract.points_of(accounts(1)), U64(5));
+    }
+
+    #[test]
+    fn test_staking_removes_listing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_listing(&mut context);
+        call_env(&mut context, storage_cost(STAKE_STORAGE_BYTES), accounts(1));
+        contract.nft_stake("0".to_string());
+        assert_eq!(contract.nft_get_listing_price("0".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is staked")]
+    fn test_transfer_staked_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staked_token(&mut context, 0);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is staked")]
+    fn test_list_staked_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staked_token(&mut context, 0);
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_for_sale("0".to_string(), U128(1000));
+    }
+
+    #[test]
+    fn test_stake_logs_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftStakeLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_stake","data":[{"token_id":"0","owner_id":"bob"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::NftUnstakeLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                points: U64(3),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_unstake","data":[{"token_id":"0","owner_id":"bob","points":"3"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::StakePointsRateUpdateLog {
+                points_per_day: U64(2),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"stake_points_rate_update","data":[{"points_per_day":"2"}]}"#,
+        );
+    }
+
+    /// Contract minting on a free curve
+    fn contract_with_free_curve(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(0),
+            increment: U128(0),
+            step_size: None,
+        }));
+        contract
+    }
+
+    /// Have bob mint `count` tokens on the curve at `block_timestamp`
+    fn curve_mint_by_bob_at(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        count: u32,
+        block_timestamp: u64,
+    ) {
+        for _ in 0..count {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(storage_cost(CURVE_MINT_STORAGE_BYTES) * 2)
+                .predecessor_account_id(accounts(1))
+                .block_timestamp(block_timestamp)
+                .build());
+            contract.nft_mint_curve();
+        }
+    }
+
+    #[test]
+    fn test_daily_mint_limit_resets() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_free_curve(&mut context);
+        curve_mint_by_bob_at(&mut context, &mut contract, 5, 0);
+        assert_eq!(contract.nft_daily_mint_limit(accounts(1)), 0);
+        assert_eq!(contract.nft_daily_mint_limit(accounts(2)), 5);
+
+        // the next day starts a new bucket, replacing the counter of the previous one
+        let day = 86_400_000_000_000;
+        testing_env!(context.block_timestamp(day).build());
+        assert_eq!(contract.nft_daily_mint_limit(accounts(1)), 5);
+        curve_mint_by_bob_at(&mut context, &mut contract, 2, day);
+        assert_eq!(contract.nft_daily_mint_limit(accounts(1)), 3);
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(7));
+        assert_eq!(contract.daily_mint_count.get(&accounts(1)), Some((1, 2)));
+
+        call_env(&mut context, 0, accounts(0));
+        contract.set_max_daily_mints_per_account(10);
+        assert_eq!(contract.nft_daily_mint_limit(accounts(1)), 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "Daily mint limit reached")]
+    fn test_daily_mint_limit_exceeded() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_free_curve(&mut context);
+        curve_mint_by_bob_at(&mut context, &mut contract, 5, 0);
+        curve_mint_by_bob_at(&mut context, &mut contract, 1, 86_399_000_000_000);
+    }
+
+    #[test]
+    fn test_owner_mints_are_not_limited() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 6,
+            accounts(0),
+        );
+        for token_id in 0..6 {
+            contract.nft_mint(
+                token_id.to_string(),
+                accounts(1),
+                sample_token_metadata(),
+                None,
+            );
+        }
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(6));
+        assert_eq!(contract.nft_daily_mint_limit(accounts(1)), 5);
+    }
+
+    #[test]
+    fn test_transfer_ownership_of_contract() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, 1, accounts(0));
+        contract.nft_transfer_ownership_of_contract(accounts(1));
+        assert_eq!(contract.contract_owner(), accounts(1));
+        assert_eq!(logged_events(), vec!["contract_ownership_transferred"]);
+
+        call_env(&mut context, 0, accounts(1));
+        contract.set_platform_fee(250);
+        assert_eq!(contract.platform_fee_bps(), 250);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_old_owner_after_ownership_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, 1, accounts(0));
+        contract.nft_transfer_ownership_of_contract(accounts(1));
+        call_env(&mut context, 0, accounts(0));
+        contract.set_platform_fee(250);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_transfer_ownership_of_contract_without_yocto() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.nft_transfer_ownership_of_contract(accounts(1));
+    }
+
+    #[test]
+    fn test_contract_ownership_transferred_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::ContractOwnershipTransferredLog {
+                old_owner_id: accounts(0),
+                new_owner_id: accounts(1),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"contract_ownership_transferred","data":[{"old_owner_id":"alice","new_owner_id":"bob"}]}"#,
+        );
+    }
+
+    const SERIES_APPROVAL_STORAGE_BYTES: StorageUsage = 100;
+
+    const SWAP_STORAGE_BYTES: StorageUsage = 300;
+
+    /// Token "0" of bob proposed to be swapped for token "7" of other.near owned by danny
+    fn contract_with_external_swap(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        call_env(context, storage_cost(SWAP_STORAGE_BYTES), accounts(1));
+        let swap_id = contract.propose_external_swap(
+            "0".to_string(),
+            "other.near".parse().unwrap(),
+            "7".to_string(),
+            accounts(3),
+        );
+        assert_eq!(swap_id, U64(0));
+        contract
+    }
+
+    /// `nft_on_transfer` of token `token_id` sent by danny through `nft_contract`
+    fn receive_external_token(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        nft_contract: &str,
+        token_id: &str,
+        msg: &str,
+    ) -> bool {
+        call_env(context, 0, nft_contract.parse().unwrap());
+        match contract.nft_on_transfer(accounts(3), accounts(3), token_id.into(), msg.into()) {
+            PromiseOrValue::Value(return_token) => return_token,
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+    }
+
+    #[test]
+    fn test_external_swap_completed_by_matching_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_external_swap(&mut context);
+        assert!(!receive_external_token(
+            &mut context,
+            &mut contract,
+            "other.near",
+            "7",
+            "0"
+        ));
+
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(contract.external_swap(U64(0)), None);
+        let forwards: Vec<_> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id.as_str() == "other.near")
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                VmAction::FunctionCall {
+                    function_name,
+                    args,
+                    deposit,
+                    ..
+                } if function_name == "nft_transfer" => Some((args, deposit)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(forwards.len(), 1);
+        let args: near_sdk::serde_json::Value =
+            near_sdk::serde_json::from_slice(&forwards[0].0).unwrap();
+        assert_eq!(args["receiver_id"], "bob");
+        assert_eq!(args["token_id"], "7");
+        assert_eq!(forwards[0].1, 1);
+    }
+
+    /// Resolve the forward of the counterparty's token of swap 0 with `result`
+    fn resolve_external_swap_forward(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        result: near_sdk::PromiseResult,
+    ) {
+        let swap = contract.external_swap_forward(U64(0)).unwrap();
+        testing_env!(
+            context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(0)
+                .predecessor_account_id(accounts(0))
+                .prepaid_gas(swap::GAS_FOR_FORWARD_CALLBACK)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+        contract.on_external_swap_forward(U64(0), swap);
+    }
+
+    #[test]
+    fn test_external_swap_forward_retry() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_external_swap(&mut context);
+        receive_external_token(&mut context, &mut contract, "other.near", "7", "0");
+        assert!(contract.external_swap_forward(U64(0)).is_some());
+
+        // a failed forward keeps the swap for a retry
+        resolve_external_swap_forward(&mut context, &mut contract, near_sdk::PromiseResult::Failed);
+        assert!(contract.external_swap_forward(U64(0)).is_some());
+        assert!(payments().is_empty());
+
+        context.prepaid_gas(near_sdk::Gas(300_000_000_000_000));
+        call_env(&mut context, 1, accounts(1));
+        contract.retry_external_swap_forward(U64(0));
+        assert!(near_sdk::test_utils::get_created_receipts()
+            .iter()
+            .any(|receipt| receipt.receiver_id.as_str() == "other.near"));
+
+        // the proposal storage is refunded once forwarded
+        resolve_external_swap_forward(
+            &mut context,
+            &mut contract,
+            near_sdk::PromiseResult::Successful(vec![]),
+        );
+        assert_eq!(contract.external_swap_forward(U64(0)), None);
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be the proposer")]
+    fn test_external_swap_forward_retry_by_other() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_external_swap(&mut context);
+        receive_external_token(&mut context, &mut contract, "other.near", "7", "0");
+        call_env(&mut context, 1, accounts(3));
+        contract.retry_external_swap_forward(U64(0));
+    }
+
+    #[test]
+    fn test_external_swap_mismatch_returns_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_external_swap(&mut context);
+        for (nft_contract, token_id, msg) in [
+            ("other.near", "8", "0"),
+            ("fake.near", "7", "0"),
+            ("other.near", "7", "1"),
+            ("other.near", "7", "swap"),
+        ] {
+            assert!(receive_external_token(
+                &mut context,
+                &mut contract,
+                nft_contract,
+                token_id,
+                msg
+            ));
+        }
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(1)
+        );
+        assert!(contract.external_swap(U64(0)).is_some());
+
+        // the proposer no longer holds the token
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+        assert!(receive_external_token(
+            &mut context,
+            &mut contract,
+            "other.near",
+            "7",
+            "0"
+        ));
+    }
+
+    #[test]
+    fn test_external_swap_propose_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::ExternalSwapProposeLog {
+                swap_id: U64(0),
+                swap: swap::ExternalSwap {
+                    proposer_id: accounts(1),
+                    my_token_id: "0".into(),
+                    their_contract: "other.near".parse().unwrap(),
+                    their_token_id: "7".into(),
+                    counterparty: accounts(3),
+                },
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"external_swap_propose","data":[{"swap_id":"0","proposer_id":"bob","my_token_id":"0","their_contract":"other.near","their_token_id":"7","counterparty":"danny"}]}"#,
+        );
+    }
+
+    const EVENT_DROP_STORAGE_BYTES: StorageUsage = 600;
+
+    /// Tokens "0", "1" and "2" of the owner sent to the keypom.near drop
+    fn contract_with_event_drop(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        for token_id in ["0", "1", "2"] {
+            call_env(context, storage_cost(MINT_STORAGE_BYTES) * 2, accounts(0));
+            contract.nft_mint(token_id.into(), accounts(0), sample_token_metadata(), None);
+        }
+        call_env(context, storage_cost(EVENT_DROP_STORAGE_BYTES), accounts(0));
+        contract.create_event_drop(
+            vec!["0".to_string(), "1".to_string(), "2".to_string()],
+            "keypom.near".parse().unwrap(),
+            r#"{"drop_id":"conference"}"#.to_string(),
+        );
+        contract
+    }
+
+    /// Resolve the drop of `token_id` with the outcome of `nft_on_transfer`
+    fn resolve_event_drop(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        token_id: &str,
+        outcome: near_sdk::PromiseResult,
+    ) -> bool {
+        testing_env!(
+            context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(0)
+                .predecessor_account_id(accounts(0))
+                .prepaid_gas(keypom::GAS_FOR_EVENT_DROP_CALLBACK)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![outcome],
+        );
+        contract.on_event_drop(
+            accounts(0),
+            "keypom.near".parse().unwrap(),
+            token_id.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_event_drop_mixed_outcomes() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_event_drop(&mut context);
+        let keypom: AccountId = "keypom.near".parse().unwrap();
+        let calls: Vec<_> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == keypom)
+            .collect();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(contract.event_drop_tokens(None, None).len(), 3);
+
+        // kept by keypom, failed and returned by keypom
+        assert!(resolve_event_drop(
+            &mut context,
+            &mut contract,
+            "0",
+            near_sdk::PromiseResult::Successful(b"false".to_vec()),
+        ));
+        assert!(!resolve_event_drop(
+            &mut context,
+            &mut contract,
+            "1",
+            near_sdk::PromiseResult::Failed,
+        ));
+        assert!(!resolve_event_drop(
+            &mut context,
+            &mut contract,
+            "2",
+            near_sdk::PromiseResult::Successful(b"true".to_vec()),
+        ));
+
+        assert_eq!(
+            contract.event_drop_tokens(None, None),
+            vec![("0".to_string(), keypom.clone())]
+        );
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            keypom
+        );
+        for token_id in ["1", "2"] {
+            assert_eq!(
+                contract.nft_token(token_id.to_string()).unwrap().owner_id,
+                accounts(0)
+            );
+        }
+        // the record of a returned token is refunded within the gas of the callback
+        assert_eq!(payments().len(), 1);
+
+        // claiming the token takes it out of the drop
+        context.prepaid_gas(near_sdk::Gas(300_000_000_000_000));
+        call_env(&mut context, 1, keypom);
+        contract.nft_transfer(accounts(3), "0".to_string(), None, None);
+        assert!(contract.event_drop_tokens(None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Token isn't held by the owner")]
+    fn test_event_drop_of_other_owner_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(EVENT_DROP_STORAGE_BYTES),
+            accounts(0),
+        );
+        contract.create_event_drop(
+            vec!["0".to_string()],
+            "keypom.near".parse().unwrap(),
+            String::new(),
+        );
+    }
+
+    #[test]
+    fn test_event_drop_failed_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::EventDropFailedLog {
+                keypom_contract: "keypom.near".parse().unwrap(),
+                token_id: "0".into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"event_drop_failed","data":[{"keypom_contract":"keypom.near","token_id":"0"}]}"#,
+        );
+    }
+
+    /// Receivers and deposits of the social DB posts made
+    fn social_posts() -> Vec<(AccountId, Balance)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        VmAction::FunctionCall {
+                            function_name,
+                            deposit,
+                            ..
+                        } if function_name == "set" => Some((receiver_id.clone(), deposit)),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    /// Contract posting to the social DB with a pool paying for two posts
+    fn contract_with_social_posts(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_social_config(None, true);
+        call_env(context, social::SOCIAL_POST_DEPOSIT * 2, accounts(0));
+        contract.social_pool_top_up();
+        contract
+    }
+
+    #[test]
+    fn test_mint_posts_to_social_db_only_when_enabled() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, social::SOCIAL_POST_DEPOSIT, accounts(0));
+        contract.social_pool_top_up();
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata(), None);
+        assert!(social_posts().is_empty());
+
+        call_env(&mut context, 0, accounts(0));
+        contract.set_social_config(None, true);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint("1".to_string(), accounts(1), sample_token_metadata(), None);
+        assert_eq!(
+            social_posts(),
+            vec![("social.near".parse().unwrap(), social::SOCIAL_POST_DEPOSIT)]
+        );
+        assert_eq!(contract.social_config().pool, U128(0));
+    }
+
+    #[test]
+    fn test_mint_without_social_pool_skips_post() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_social_config(Some("social.testnet".parse().unwrap()), true);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata(), None);
+        assert!(social_posts().is_empty());
+    }
+
+    #[test]
+    fn test_failed_social_post_is_tolerated() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_social_posts(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata(), None);
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        contract.on_social_post("0".to_string(), U128(social::SOCIAL_POST_DEPOSIT));
+        assert_eq!(
+            contract.social_config().pool,
+            U128(social::SOCIAL_POST_DEPOSIT * 2)
+        );
+        assert_eq!(logged_events(), vec!["social_post_failed"]);
+        assert!(contract.nft_token("0".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_social_post_failed_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::SocialPostFailedLog {
+                token_id: "0".into(),
+                deposit: U128(10),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"social_post_failed","data":[{"token_id":"0","deposit":"10"}]}"#,
+        );
+    }
+
+    /// Contract with charlie approved as an operator of the "kyiv" series
+    fn contract_with_series_operator(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(
+            context,
+            storage_cost(SERIES_APPROVAL_STORAGE_BYTES),
+            accounts(0),
+        );
+        contract.nft_approve_collection(accounts(2), "kyiv".to_string());
+        contract
+    }
+
+    #[test]
+    fn test_series_operator_mints_in_series() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_series_operator(&mut context);
+        assert!(contract.nft_is_series_operator("kyiv".to_string(), accounts(2)));
+        assert!(!contract.nft_is_series_operator("lviv".to_string(), accounts(2)));
+
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(2),
+        );
+        let token = contract.nft_mint_series(
+            "kyiv".to_string(),
+            "kyiv-0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+        );
+        assert_eq!(token.owner_id, accounts(1));
+        assert_eq!(
+            contract.nft_token_series("kyiv-0".to_string()),
+            Some("kyiv".to_string())
+        );
+
+        call_env(&mut context, 0, accounts(0));
+        contract.nft_revoke_collection(accounts(2), "kyiv".to_string());
+        assert!(!contract.nft_is_series_operator("kyiv".to_string(), accounts(2)));
+        assert_eq!(payments().len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_series_operator_mints_in_other_series() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_series_operator(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(2),
+        );
+        contract.nft_mint_series(
+            "lviv".to_string(),
+            "lviv-0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+        );
+    }
+
+    #[test]
+    fn test_series_operator_approve_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::SeriesOperatorApproveLog {
+                series_id: "kyiv".into(),
+                operator_id: accounts(2),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"series_operator_approve","data":[{"series_id":"kyiv","operator_id":"charlie"}]}"#,
+        );
+    }
+
+    /// Token "0" of bob paying charlie 10% royalties, locked as collateral of a loan from lender.near
+    const COLLATERAL_STORAGE_BYTES: StorageUsage = 300;
+
+    fn contract_with_collateral(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(context, storage_cost(MINT_STORAGE_BYTES) * 2, accounts(0));
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 1000)])),
+        );
+        call_env(context, storage_cost(COLLATERAL_STORAGE_BYTES), accounts(1));
+        contract.nft_lock_as_collateral(
+            "0".to_string(),
+            "lender.near".parse().unwrap(),
+            "loan-1".to_string(),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_release_collateral() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_collateral(&mut context);
+        assert_eq!(
+            contract.nft_collateral("0".to_string()),
+            Some(collateral::Collateral {
+                lender_contract: "lender.near".parse().unwrap(),
+                loan_ref: "loan-1".to_string(),
+            })
+        );
+        assert!(!contract.nft_is_transferable("0".to_string()));
+
+        // the lock storage goes back to bob
+        call_env(&mut context, 1, "lender.near".parse().unwrap());
+        let initial_storage = env::storage_usage();
+        contract.nft_release_collateral("0".to_string());
+        let storage_released =
+            initial_storage + last_audit_entry_bytes(&contract) - env::storage_usage();
+        assert_eq!(
+            payments(),
+            vec![(accounts(0), accounts(1), storage_cost(storage_released))]
+        );
+        assert_eq!(logged_events(), vec!["nft_collateral_release"]);
+        assert!(contract.nft_collateral("0".to_string()).is_none());
+        assert!(contract.nft_is_transferable("0".to_string()));
+    }
+
+    /// Token "0" of bob locked for 0xabc on the bridge of bridge.near
+    fn contract_with_bridged_token(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        call_env(context, 0, accounts(0));
+        contract.set_bridge_account(Some("bridge.near".parse().unwrap()));
+        call_env(context, 1, accounts(1));
+        contract.bridge_lock("0".to_string(), "0xabc".to_string());
+        contract
+    }
+
+    #[test]
+    fn test_bridge_lock_and_unlock() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_bridged_token(&mut context);
+        assert_eq!(logged_events(), vec!["nft_transfer", "bridge_lock"]);
+        assert!(contract
+            .nft_tokens_for_owner(accounts(1), None, None)
+            .is_empty());
+        assert_eq!(
+            contract.bridged_tokens(None, None),
+            vec![(
+                "0".to_string(),
+                bridge::BridgeLock {
+                    owner_id: accounts(1),
+                    destination: "0xabc".to_string(),
+                }
+            )]
+        );
+        assert!(!contract.nft_is_transferable("0".to_string()));
+
+        call_env(&mut context, 1, "bridge.near".parse().unwrap());
+        contract.bridge_unlock("0".to_string(), accounts(3));
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert!(contract.bridged_tokens(None, None).is_empty());
+        assert_eq!(
+            contract.nft_tokens_for_owner(accounts(3), None, None).len(),
+            1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be the bridge")]
+    fn test_bridge_unlock_by_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_bridged_token(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.bridge_unlock("0".to_string(), accounts(1));
+    }
+
+    #[test]
+    fn test_bridge_logs_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::BridgeLockLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                destination: "0xabc".into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"bridge_lock","data":[{"token_id":"0","owner_id":"bob","destination":"0xabc"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::BridgeUnlockLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                receiver_id: accounts(3),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"bridge_unlock","data":[{"token_id":"0","owner_id":"bob","receiver_id":"danny"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::BridgeAccountUpdateLog {
+                bridge_account_id: Some("bridge.near".parse().unwrap()),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"bridge_account_update","data":[{"bridge_account_id":"bridge.near"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_liquidate_collateral() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_collateral(&mut context);
+        call_env(&mut context, 1, "lender.near".parse().unwrap());
+        let payout =
+            contract.nft_liquidate_collateral("0".to_string(), accounts(3), U128(10_000), None);
+
+        assert_eq!(
+            payout.payout,
+            HashMap::from([(accounts(1), U128(9_000)), (accounts(2), U128(1_000))])
+        );
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert!(contract.nft_collateral("0".to_string()).is_none());
+        assert_eq!(
+            logged_events(),
+            vec!["nft_transfer", "nft_collateral_liquidate"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_lock_collateral_without_storage_deposit() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_lock_as_collateral(
+            "0".to_string(),
+            "lender.near".parse().unwrap(),
+            "loan-1".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be the lender")]
+    fn test_owner_release_collateral() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_collateral(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_release_collateral("0".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be the lender")]
+    fn test_other_lender_liquidate_collateral() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_collateral(&mut context);
+        call_env(&mut context, 1, accounts(3));
+        contract.nft_liquidate_collateral("0".to_string(), accounts(3), U128(10_000), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is collateralized")]
+    fn test_transfer_collateral() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_collateral(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(3), "0".to_string(), None, None);
+    }
+
+    #[test]
+    fn test_nft_collateral_lock_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftCollateralLockLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                lender_contract: "lender.near".parse().unwrap(),
+                loan_ref: "loan-1".into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_collateral_lock","data":[{"token_id":"0","owner_id":"bob","lender_contract":"lender.near","loan_ref":"loan-1"}]}"#,
+        );
+    }
+
+    /// Token "0" of bob with minting closed afterwards
+    fn contract_with_minting_closed(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        call_env(context, 0, accounts(0));
+        contract.close_minting();
+        contract
+    }
+
+    #[test]
+    fn test_close_minting_keeps_transfers_and_edits() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_minting_closed(&mut context);
+        assert!(contract.minting_closed());
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        let token_metadata = TokenMetadata {
+            title: Some("Olive Tree Revealed".into()),
+            ..sample_token_metadata()
+        };
+        contract.update_token_metadata("0".to_string(), token_metadata.clone());
+        call_env(&mut context, 0, accounts(0));
+        contract.set_platform_fee(250);
+
+        let token = contract.nft_token("0".to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(token.metadata, Some(token_metadata));
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting closed")]
+    fn test_mint_after_close() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_minting_closed(&mut context);
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_mint("1".to_string(), accounts(1), sample_token_metadata(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting closed")]
+    fn test_mint_all_after_close() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_minting_closed(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(MINT_ALL_STORAGE_BYTES),
+            accounts(0),
+        );
+        contract.nft_mint_all();
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting closed")]
+    fn test_airdrop_after_close() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_minting_closed(&mut context);
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_airdrop(vec![airdrop::Airdrop {
+            token_id: "1".to_string(),
+            receiver_id: accounts(1),
+            token_metadata: sample_token_metadata(),
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting closed")]
+    fn test_mint_curve_after_close() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_minting_closed(&mut context);
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(1000),
+            increment: U128(0),
+            step_size: None,
+        }));
+        call_env(
+            &mut context,
+            1000 + storage_cost(CURVE_MINT_STORAGE_BYTES),
+            accounts(2),
+        );
+        contract.nft_mint_curve();
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting closed")]
+    fn test_ft_donation_after_close() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_minting_closed(&mut context);
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_donation_ft_token("usdc.near".parse().unwrap(), true);
+        call_env(&mut context, 0, "usdc.near".parse().unwrap());
+        let _ = contract.ft_on_transfer(accounts(2), U128(1000), "donate".to_string());
+    }
+
+    #[test]
+    fn test_max_daily_mints_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::MaxDailyMintsUpdateLog {
+                max_daily_mints_per_account: 10,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"max_daily_mints_update","data":[{"max_daily_mints_per_account":10}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_fee_breakdown() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 500)])),
+        );
+        contract.set_platform_fee(200);
+
+        // the royalty is paid from the price less the platform fee
+        let breakdown = contract.fee_breakdown("0".to_string(), U128(10_000));
+        assert_eq!(
+            breakdown,
+            royalty::FeeBreakdown {
+                platform_fee: U128(200),
+                royalties: HashMap::from([(accounts(2), U128(490))]),
+                seller_net: U128(9310),
+            }
+        );
+        let royalties: Balance = breakdown.royalties.values().map(|amount| amount.0).sum();
+        assert_eq!(
+            breakdown.platform_fee.0 + royalties + breakdown.seller_net.0,
+            10_000
+        );
+        assert_eq!(
+            contract.internal_payout(&"0".to_string(), &accounts(1), 10_000),
+            HashMap::from([(accounts(0), 200), (accounts(1), 9310), (accounts(2), 490)])
+        );
+    }
+
+    #[test]
+    fn test_fee_summary() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        assert_eq!(contract.nft_fee_summary().mint_price, U128(0));
+
+        contract.set_platform_fee(250);
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(1000),
+            increment: U128(100),
+            step_size: None,
+        }));
+        assert_eq!(
+            contract.nft_fee_summary(),
+            royalty::FeeSummary {
+                platform_fee_bps: 250,
+                referral_fee_bps: 0,
+                charity_fee_bps: 0,
+                mint_price: U128(1000),
+                presale_price: U128(0),
+                reward_rate_per_second: U128(0),
+                min_royalty_claim: U128(0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_simulate_sale() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 6)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata(), None);
+        contract.nft_mint(
+            "1".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(3), 333), (accounts(2), 1250)])),
+        );
+        contract.nft_mint_with_collaborators(
+            "2".to_string(),
+            sample_token_metadata(),
+            vec![collaborators::Collaborator {
+                account_id: accounts(4),
+                royalty_bps: 700,
+                role: "artist".into(),
+            }],
+        );
+
+        let assert_sums_to_price = |simulation: &royalty::SaleSimulation| {
+            let royalties: Balance = simulation
+                .royalty_payouts
+                .iter()
+                .map(|(_, amount)| amount.0)
+                .sum();
+            assert_eq!(
+                simulation.seller_receives.0 + royalties + simulation.platform_fee.0,
+                simulation.total_price.0
+            );
+        };
+        for platform_fee_bps in [0, 250] {
+            contract.set_platform_fee(platform_fee_bps);
+            for token_id in ["0", "1", "2"] {
+                for price in [0, 1, 999, 10_000, 123_456_789] {
+                    assert_sums_to_price(
+                        &contract.nft_simulate_sale(token_id.to_string(), U128(price)),
+                    );
+                }
+            }
+        }
+
+        assert_eq!(
+            contract.nft_simulate_sale("1".to_string(), U128(10_000)),
+            royalty::SaleSimulation {
+                seller_receives: U128(8208),
+                royalty_payouts: vec![(accounts(2), U128(1218)), (accounts(3), U128(324))],
+                platform_fee: U128(250),
+                total_price: U128(10_000),
+            }
+        );
+        assert!(contract
+            .nft_simulate_sale("0".to_string(), U128(10_000))
+            .royalty_payouts
+            .is_empty());
+    }
+
+    #[test]
+    fn test_platform_fee_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::PlatformFeeUpdateLog {
+                platform_fee_bps: 200,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"platform_fee_update","data":[{"platform_fee_bps":200}]}"#,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties can't exceed 10000 basis points")]
+    fn test_mint_royalties_over_total() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 4)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(0),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(1), 5000), (accounts(2), 5001)])),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties and the platform fee can't exceed 10000 basis points")]
+    fn test_mint_royalties_over_platform_fee() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_platform_fee(2000);
+
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(0),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(1), 8001)])),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties and the platform fee can't exceed 10000 basis points")]
+    fn test_mint_with_collaborators_over_platform_fee() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_platform_fee(2000);
+
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_mint_with_collaborators(
+            "0".to_string(),
+            sample_token_metadata(),
+            vec![collaborators::Collaborator {
+                account_id: accounts(1),
+                royalty_bps: 8001,
+                role: "artist".into(),
+            }],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties can't exceed 10000 basis points")]
+    fn test_mint_with_collaborators_over_default_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_royalties(HashMap::from([(accounts(2), 9000)]));
+
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_mint_with_collaborators(
+            "0".to_string(),
+            sample_token_metadata(),
+            vec![collaborators::Collaborator {
+                account_id: accounts(1),
+                royalty_bps: 1001,
+                role: "artist".into(),
+            }],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties and the platform fee can't exceed 10000 basis points")]
+    fn test_platform_fee_over_default_royalties() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_royalties(HashMap::from([(accounts(1), 9000)]));
+        contract.set_platform_fee(1001);
+    }
+
+    #[test]
+    fn test_mint_with_collaborators() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .build());
+        let collaborators = vec![
+            collaborators::Collaborator {
+                account_id: accounts(1),
+                royalty_bps: 1000,
+                role: "artist".into(),
+            },
+            collaborators::Collaborator {
+                account_id: accounts(2),
+                royalty_bps: 250,
+                role: "animator".into(),
+            },
+        ];
+        let token = contract.nft_mint_with_collaborators(
+            "0".to_string(),
+            sample_token_metadata(),
+            collaborators.clone(),
+        );
+        assert_eq!(token.owner_id, accounts(0));
+        assert_eq!(
+            contract.nft_token_collaborators("0".to_string()),
+            collaborators
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract
+                .nft_payout("0".to_string(), U128(1000), None)
+                .payout,
+            HashMap::from([
+                (accounts(0), U128(875)),
+                (accounts(1), U128(100)),
+                (accounts(2), U128(25)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_token_full() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        let collaborators = vec![collaborators::Collaborator {
+            account_id: accounts(1),
+            royalty_bps: 1000,
+            role: "artist".into(),
+        }];
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint_with_collaborators(
+            "0".to_string(),
+            sample_token_metadata(),
+            collaborators.clone(),
+        );
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(0),
+        );
+        contract.list_for_sale("0".to_string(), U128(1000));
+
+        let full = contract.nft_token_full("0".to_string()).unwrap();
+        assert_eq!(full.token, contract.nft_token("0".to_string()).unwrap());
+        assert_eq!(full.activity.unwrap().transfer_count, 0);
+        assert_eq!(full.current_price, Some(U128(1000)));
+        assert!(full.royalties.is_empty());
+        assert_eq!(full.collaborators, collaborators);
+        assert_eq!(full.history, vec![(accounts(0), U64(0))]);
+        assert!(!full.is_locked);
+        assert!(!full.is_staked);
+        assert_eq!(full.rental, None);
+        assert_eq!(contract.nft_token_full("1".to_string()), None);
+
+        call_env(&mut context, storage_cost(LEASE_STORAGE_BYTES), accounts(0));
+        contract.nft_lease("0".to_string(), accounts(2), U64(100), U128(1000));
+        call_env(&mut context, 1000, accounts(2));
+        contract.nft_accept_lease("0".to_string());
+        let full = contract.nft_token_full("0".to_string()).unwrap();
+        assert!(full.is_locked);
+        assert_eq!(full.rental.unwrap().lessee_id, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Collaborator royalties can't exceed 10000 basis points")]
+    fn test_mint_with_collaborators_over_total() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .build());
+        contract.nft_mint_with_collaborators(
+            "0".to_string(),
+            sample_token_metadata(),
+            vec![
+                collaborators::Collaborator {
+                    account_id: accounts(1),
+                    royalty_bps: 10_000,
+                    role: "artist".into(),
+                },
+                collaborators::Collaborator {
+                    account_id: accounts(2),
+                    royalty_bps: 1,
+                    role: "animator".into(),
+                },
+            ],
+        );
+    }
+
+    /// Mint `token_id` to bob with `extra` metadata
+    fn mint_with_extra(
+        contract: &mut Contract,
+        context: &mut VMContextBuilder,
+        token_id: &str,
+        extra: &str,
+    ) {
+        call_env(context, storage_cost(MINT_STORAGE_BYTES) * 3, accounts(0));
+        contract.nft_mint(
+            token_id.to_string(),
+            accounts(1),
+            TokenMetadata {
+                extra: Some(extra.to_string()),
+                ..sample_token_metadata()
+            },
+            None,
+        );
+    }
+
+    #[test]
+    fn test_tokens_by_tag() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        mint_with_extra(
+            &mut contract,
+            &mut context,
+            "0",
+            r#"{"tags":["kyiv","sunflower"]}"#,
+        );
+        mint_with_extra(&mut contract, &mut context, "1", r#"{"tags":["kyiv"]}"#);
+        mint_with_extra(&mut contract, &mut context, "2", "not json");
+
+        let tagged = |contract: &Contract, tag: &str| -> Vec<TokenId> {
+            contract
+                .nft_tokens_by_tag(tag.to_string(), None, None)
+                .into_iter()
+                .map(|token| token.token_id)
+                .collect()
+        };
+        assert_eq!(
+            contract.nft_token_tags("0".to_string()),
+            vec!["kyiv".to_string(), "sunflower".to_string()]
+        );
+        assert!(contract.nft_token_tags("2".to_string()).is_empty());
+        assert_eq!(tagged(&contract, "kyiv"), vec!["0", "1"]);
+        assert_eq!(tagged(&contract, "sunflower"), vec!["0"]);
+        assert_eq!(
+            contract
+                .nft_tokens_by_tag("kyiv".to_string(), Some(U128(1)), Some(1))
+                .len(),
+            1
+        );
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_burn("1".to_string(), None);
+        assert_eq!(tagged(&contract, "kyiv"), vec!["0"]);
+        assert!(contract.nft_token_tags("1".to_string()).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many tags")]
+    fn test_mint_with_too_many_tags() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        let tags: Vec<String> = (0..11).map(|i| format!("tag{}", i)).collect();
+        mint_with_extra(
+            &mut contract,
+            &mut context,
+            "0",
+            &near_sdk::serde_json::json!({ "tags": tags }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "must be 1 to 32 lowercase letters or digits")]
+    fn test_mint_with_invalid_tag() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        mint_with_extra(&mut contract, &mut context, "0", r#"{"tags":["Kyiv"]}"#);
+    }
+
+    #[test]
+    fn test_mint_events() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint("3".to_string(), accounts(1), sample_token_metadata(), None);
+        assert_eq!(
+            get_logs()[0],
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_mint","data":[{"owner_id":"bob","token_ids":["3"]}]}"#
+        );
+        assert_eq!(logged_events(), vec!["nft_mint", "mint_receipt"]);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                storage_cost(MINT_ALL_STORAGE_BYTES) + storage_cost(MINT_STORAGE_BYTES)
+            )
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint_all();
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_mint","data":[{"owner_id":"alice","token_ids":["0","1","2"]}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfer_events() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        // owner's transfer has no authorized_id
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_transfer(accounts(1), token_id.clone(), None, Some("gift".into()));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_transfer","data":[{"old_owner_id":"alice","new_owner_id":"bob","token_ids":["0"],"memo":"gift"}]}"#
+            ]
+        );
+
+        // bob approves charlie who transfers the token to danny
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(20))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(2), None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_transfer(accounts(3), token_id, None, None);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"danny","token_ids":["0"],"authorized_id":"charlie"}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metadata_update_event() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .build());
+        let token_metadata = TokenMetadata {
+            title: Some("Olive Tree Revealed".into()),
+            ..sample_token_metadata()
+        };
+        contract.update_token_metadata(token_id.clone(), token_metadata.clone());
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_metadata_update","data":[{"token_ids":["0"]}]}"#
+            ]
+        );
+        assert_eq!(
+            contract.nft_token(token_id).unwrap().metadata,
+            Some(token_metadata)
+        );
+    }
+
+    #[test]
+    fn test_set_token_media() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let media_hash = near_sdk::json_types::Base64VecU8(env::sha256(b"bafy-new"));
+        contract.set_token_media("0".to_string(), "bafy-new".into(), Some(media_hash.clone()));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_metadata_update","data":[{"token_ids":["0"]}]}"#
+            ]
+        );
+        // only the media fields change
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().metadata,
+            Some(TokenMetadata {
+                media: Some("bafy-new".into()),
+                media_hash: Some(media_hash),
+                ..sample_token_metadata()
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_set_token_media_by_token_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(1));
+        contract.set_token_media("0".to_string(), "bafy-new".into(), None);
+    }
+
+    const SAMPLE_ICON: &str =
+        "data:image/webp;base64,UklGRhoAAABXRUJQVlA4TA0AAAAvAAAAEAcQERGIiP4HAA==";
+
+    #[test]
+    fn test_update_contract_icon() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, 0, accounts(0));
+        contract.nft_update_contract_icon(SAMPLE_ICON.to_string());
+
+        assert_eq!(contract.nft_metadata().icon.as_deref(), Some(SAMPLE_ICON));
+        // the default icon is larger, its storage is refunded
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(0));
+        assert_eq!(logged_events(), vec!["nft_contract_icon_updated"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Icon exceeds 100 KB")]
+    fn test_update_contract_icon_oversized() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, 0, accounts(0));
+        let icon = format!("data:image/webp;base64,{}", "A".repeat(100 * 1024));
+        contract.nft_update_contract_icon(icon);
+    }
+
+    #[test]
+    #[should_panic(expected = "Icon must be an image data URL")]
+    fn test_update_contract_icon_invalid_format() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, 0, accounts(0));
+        contract.nft_update_contract_icon("https://arweave.net/icon.webp".to_string());
+    }
+
+    #[test]
+    fn test_nft_contract_icon_updated_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftContractIconUpdatedLog {
+                icon_hash: [0; 32].into(),
+                icon_len: 70,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_contract_icon_updated","data":[{"icon_hash":"11111111111111111111111111111111","icon_len":70}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_metadata_update_event_batched() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        events::emit_metadata_update(&["0".to_string(), "1".to_string(), "2".to_string()]);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_metadata_update","data":[{"token_ids":["0","1","2"]}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_transfer(accounts(1), token_id.clone(), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        if let Some(token) = contract.nft_token(token_id.clone()) {
+            assert_eq!(token.token_id, token_id);
+            assert_eq!(token.owner_id, accounts(1));
+            assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+            assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
+        } else {
+            panic!("token not correctly created, or not found by nft_token");
+        }
+    }
+
+    #[test]
+    fn test_approve() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(15))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert!(contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+    }
+
+    #[test]
+    fn test_approved_tokens_for() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        for token_id in ["0", "1", "2"] {
+            call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+            contract.nft_mint(
+                token_id.to_string(),
+                accounts(1),
+                sample_token_metadata(),
+                None,
+            );
+        }
+        for token_id in ["0", "2"] {
+            call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(1));
+            contract.nft_approve(token_id.to_string(), accounts(2), None);
+        }
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(1));
+        contract.nft_approve("1".to_string(), accounts(3), None);
+
+        assert_eq!(
+            contract.nft_approved_tokens_for(accounts(1), accounts(2), None, None),
+            vec!["0".to_string(), "2".to_string()]
+        );
+        assert_eq!(
+            contract.nft_approved_tokens_for(accounts(1), accounts(2), Some(U128(1)), Some(1)),
+            Vec::<TokenId>::new()
+        );
+        assert_eq!(
+            contract.nft_approved_tokens_for(accounts(1), accounts(2), Some(U128(1)), Some(2)),
+            vec!["2".to_string()]
+        );
+        assert!(contract
+            .nft_approved_tokens_for(accounts(3), accounts(2), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_revoke() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(15))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        // alice revokes bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_revoke(token_id.clone(), accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+    }
+
+    #[test]
+    fn test_revoke_all() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(15))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        // alice approves charlie and danny
+        for account_id in [accounts(2), accounts(3)] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(storage_cost(20))
+                .predecessor_account_id(accounts(0))
+                .build());
+            contract.nft_approve(token_id.clone(), account_id, None);
+        }
+
+        // alice revokes all, released storage is refunded to her
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let initial_storage = env::storage_usage();
+        let initial_balance = env::account_balance();
+        contract.nft_revoke_all(token_id.clone());
+        let storage_released =
+            initial_storage - env::storage_usage() + last_audit_entry_bytes(&contract);
+        let approvals_deposit = [accounts(1), accounts(2), accounts(3)]
+            .iter()
+            .map(|account_id| account_id.as_str().len() as u64 + 4 + 8)
+            .sum::<u64>();
+        assert!(storage_released >= approvals_deposit);
+        assert_eq!(
+            initial_balance - env::account_balance(),
+            storage_cost(storage_released)
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(2), Some(2)));
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(3), Some(3)));
+    }
+
+    #[test]
+    fn test_transfer_to_null_account_burns() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        let null_account: AccountId = "null.near".parse().unwrap();
+        call_env(&mut context, 0, accounts(0));
+        contract.set_null_account(Some(null_account.clone()));
+        assert_eq!(contract.null_account(), Some(null_account.clone()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let initial_storage = env::storage_usage();
+        contract.nft_transfer(null_account.clone(), "0".to_string(), None, None);
+        let storage_released =
+            initial_storage - env::storage_usage() + last_audit_entry_bytes(&contract);
+        assert_eq!(logged_events(), vec!["nft_burn"]);
+        assert_eq!(contract.nft_token("0".to_string()), None);
+        assert_eq!(contract.nft_total_supply(), U128(0));
+        assert_eq!(contract.nft_supply_for_owner(null_account), U128(0));
+        assert_eq!(
+            payments(),
+            vec![(accounts(0), accounts(1), storage_cost(storage_released))]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be token owner.")]
+    fn test_approved_transfer_to_null_account() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_null_account(Some("null.near".parse().unwrap()));
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_approve("0".to_string(), accounts(2), None);
+
+        call_env(&mut context, 1, accounts(2));
+        contract.nft_transfer("null.near".parse().unwrap(), "0".to_string(), Some(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot transfer to the null account")]
+    fn test_transfer_call_to_null_account() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_null_account(Some("null.near".parse().unwrap()));
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer_call(
+            "null.near".parse().unwrap(),
+            "0".to_string(),
+            None,
+            None,
+            "".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Null account holds tokens")]
+    fn test_set_null_account_holding_tokens() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_null_account(Some(accounts(1)));
+    }
+
+    #[test]
+    fn test_null_account_logs_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NullAccountUpdateLog {
+                null_account: Some("null.near".parse().unwrap()),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"null_account_update","data":[{"null_account":"null.near"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_burn_clears_approvals() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(
+            token_id.clone(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(3), 500)])),
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_set_approval_with_conditions(
+            token_id.clone(),
+            accounts(2),
+            approval::ApprovalConditions {
+                min_price: Some(U128(1000)),
+                expires_at: None,
+                max_uses: None,
+            },
+        );
+        let token_bytes = contract.token_storage_bytes(token_id.clone());
+
+        // bob burns the token, the approval is dropped and its storage refunded to him
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let initial_storage = env::storage_usage();
+        let initial_balance = env::account_balance();
+        contract.nft_burn(token_id.clone(), None);
+        let storage_released =
+            initial_storage - env::storage_usage() + last_audit_entry_bytes(&contract);
+        assert_eq!(
+            initial_balance - env::account_balance(),
+            storage_cost(storage_released)
+        );
+        assert!(
+            storage_released + 16 >= token_bytes,
+            "{} vs {}",
+            storage_released,
+            token_bytes
+        );
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_burn","data":[{"owner_id":"bob","token_ids":["0"]}]}"#
+        );
+
+        assert!(contract.nft_token(token_id.clone()).is_none());
+        assert!(contract
+            .tokens
+            .approvals_by_id
+            .as_ref()
+            .unwrap()
+            .get(&token_id)
+            .is_none());
+        assert!(contract
+            .tokens
+            .next_approval_id_by_id
+            .as_ref()
+            .unwrap()
+            .get(&token_id)
+            .is_none());
+        assert_eq!(
+            contract.nft_approval_conditions(token_id.clone(), accounts(2)),
+            None
+        );
+        assert!(contract
+            .royalties_for_account(accounts(3), None, None)
+            .is_empty());
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(0));
+    }
+
+    #[test]
+    fn test_transfer_refunds_approvals() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(15))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+        let approvals_bytes = crate::storage::approvals_record_bytes(
+            &token_id,
+            &HashMap::from([(accounts(1), 1u64)]),
+        );
+
+        // bob transfers the token to charlie, the cleared approval is refunded to alice
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let initial_balance = env::account_balance();
+        contract.nft_transfer(accounts(2), token_id.clone(), Some(1), None);
+        assert_eq!(
+            initial_balance - env::account_balance(),
+            storage_cost(approvals_bytes)
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        let token = contract.nft_token(token_id).unwrap();
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
+    }
+
+    #[test]
+    fn test_buy() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(
+            token_id.clone(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 1000)])),
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        let initial_storage = env::storage_usage();
+        contract.list_for_sale(token_id.clone(), U128(1000));
+        let listing_bytes =
+            env::storage_usage() - initial_storage - last_audit_entry_bytes(&contract);
+        assert_eq!(
+            contract.nft_get_listing_price(token_id.clone()),
+            Some(U128(1000))
+        );
+        assert_eq!(
+            contract.internal_payout(&token_id, &accounts(1), 1000),
+            HashMap::from([(accounts(1), 900), (accounts(2), 100)])
+        );
+
+        // danny overpays, the excess and the listing storage less danny's provenance record
+        // are refunded
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1500)
+            .predecessor_account_id(accounts(3))
+            .build());
+        let initial_balance = env::account_balance();
+        contract.buy(token_id.clone());
+        assert_eq!(
+            initial_balance - env::account_balance(),
+            1500 + storage_cost(listing_bytes - provenance_record_bytes(&accounts(3)))
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.nft_token(token_id.clone()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(contract.nft_get_listing_price(token_id), None);
+    }
+
+    #[test]
+    fn test_dutch_auction_price_falls() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_dutch_auction(&mut context);
+        let token_id = "0".to_string();
+
+        for (now, price) in [
+            (1_000, 2000),
+            (1_050, 1500),
+            (1_099, 1010),
+            (1_100, 1000),
+            (5_000, 1000),
+        ] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .block_timestamp(now * 1_000_000_000)
+                .is_view(true)
+                .attached_deposit(0)
+                .build());
+            assert_eq!(
+                contract.nft_get_listing_price(token_id.clone()),
+                Some(U128(price))
+            );
+        }
+    }
+
+    #[test]
+    fn test_dutch_auction_price_near_u128_max() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        let token_id = "0".to_string();
+        context.block_timestamp(1_000 * 1_000_000_000);
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_dutch_auction(token_id.clone(), U128(u128::MAX), U128(1), 1_000);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(1_250 * 1_000_000_000)
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.nft_get_listing_price(token_id),
+            Some(U128(u128::MAX - (u128::MAX - 1) / 4))
+        );
+    }
+
+    #[test]
+    fn test_dutch_buy_and_list() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_dutch_auction(&mut context);
+        let token_id = "0".to_string();
+
+        // halfway through the auction danny buys at 1500 and relists at 3000
+        context.block_timestamp(1_050 * 1_000_000_000);
+        call_env(
+            &mut context,
+            1500 + storage_cost(LISTING_STORAGE_BYTES),
+            accounts(3),
+        );
+        contract.nft_dutch_buy_and_list(token_id.clone(), U128(3000));
+        assert_eq!(
+            logged_events(),
+            vec!["nft_delist", "nft_transfer", "nft_sale", "nft_list"]
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.nft_token(token_id.clone()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(
+            contract.sales.get(&token_id),
+            Some(Sale {
+                owner_id: accounts(3),
+                price: U128(3000),
+                dutch_auction: None,
+                ft_token_id: None,
+                expires_at: None,
+                accept_counter_offers: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_dutch_buy_and_list_keeps_purchase_when_listing_fails() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_dutch_auction(&mut context);
+        let token_id = "0".to_string();
+
+        // nothing is left to pay for the listing storage
+        context.block_timestamp(1_100 * 1_000_000_000);
+        call_env(&mut context, 1000, accounts(3));
+        contract.nft_dutch_buy_and_list(token_id.clone(), U128(3000));
+        assert_eq!(
+            contract.nft_token(token_id.clone()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(contract.nft_get_listing_price(token_id.clone()), None);
+
+        // the relist price is below the minimum
+        call_env(&mut context, 0, accounts(0));
+        contract.set_min_sale_price(U128(500));
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(3),
+        );
+        contract.list_dutch_auction(token_id.clone(), U128(1000), U128(1000), 1);
+        call_env(
+            &mut context,
+            1000 + storage_cost(LISTING_STORAGE_BYTES),
+            accounts(4),
+        );
+        contract.nft_dutch_buy_and_list(token_id.clone(), U128(100));
+        assert_eq!(
+            contract.nft_token(token_id.clone()).unwrap().owner_id,
+            accounts(4)
+        );
+        assert_eq!(contract.nft_get_listing_price(token_id), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is not on a Dutch auction")]
+    fn test_dutch_buy_and_list_fixed_price_listing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_listing(&mut context);
+        call_env(
+            &mut context,
+            1000 + storage_cost(LISTING_STORAGE_BYTES),
+            accounts(3),
+        );
+        contract.nft_dutch_buy_and_list("0".to_string(), U128(3000));
+    }
+
+    /// Token "0" of bob paying charlie 10% royalty, listed for 1000 of `ft_token_id`
+    /// or NEAR if `None`
+    fn contract_with_royalty_listing(
+        context: &mut VMContextBuilder,
+        ft_token_id: Option<AccountId>,
+    ) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(context, storage_cost(MINT_STORAGE_BYTES) * 2, accounts(0));
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 1000)])),
+        );
+        call_env(context, storage_cost(LISTING_STORAGE_BYTES), accounts(1));
+        match ft_token_id {
+            Some(ft_token_id) => {
+                contract.list_for_sale_ft("0".to_string(), U128(1000), ft_token_id)
+            }
+            None => contract.list_for_sale("0".to_string(), U128(1000)),
+        }
+        contract
+    }
+
+    /// Payout in the shape the Mintbase market reads back from `nft_transfer_payout`
+    const MINTBASE_PAYOUT_FIXTURE: &str = r#"{"payout":{"seller.near":"9750000000000000000000000","royalty.near":"250000000000000000000000"}}"#;
+
+    #[test]
+    fn test_payout_json() {
+        let payout: royalty::Payout =
+            near_sdk::serde_json::from_str(MINTBASE_PAYOUT_FIXTURE).unwrap();
+        assert_eq!(
+            payout.payout,
+            HashMap::from([
+                (
+                    "seller.near".parse().unwrap(),
+                    U128(9_750_000_000_000_000_000_000_000)
+                ),
+                (
+                    "royalty.near".parse().unwrap(),
+                    U128(250_000_000_000_000_000_000_000)
+                ),
+            ])
+        );
+        assert_eq!(
+            near_sdk::serde_json::to_value(&payout).unwrap(),
+            near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(MINTBASE_PAYOUT_FIXTURE)
+                .unwrap()
+        );
+        assert_eq!(
+            near_sdk::serde_json::to_string(&royalty::Payout {
+                payout: HashMap::from([(accounts(1), U128(1000))]),
+            })
+            .unwrap(),
+            r#"{"payout":{"bob":"1000"}}"#
+        );
+    }
+
+    #[test]
+    fn test_nft_payout_mintbase() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_royalty_listing(&mut context, None);
+        let payout = contract.nft_payout_mintbase("0".to_string(), U128(1000), 10);
+        assert_eq!(
+            payout,
+            contract.nft_payout("0".to_string(), U128(1000), Some(10))
+        );
+        assert_eq!(
+            near_sdk::serde_json::to_value(&payout).unwrap(),
+            near_sdk::serde_json::json!({"payout": {"bob": "900", "charlie": "100"}})
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Payout exceeds max_len_payout")]
+    fn test_nft_payout_mintbase_max_len() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_royalty_listing(&mut context, None);
+        contract.nft_payout_mintbase("0".to_string(), U128(1000), 1);
+    }
+
+    /// Payments made by the call as (contract called, receiver, amount). A NEAR transfer
+    /// is made by this contract, an FT transfer by the FT contract
+    fn payments() -> Vec<(AccountId, AccountId, Balance)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        VmAction::Transfer { deposit } => {
+                            Some((accounts(0), receiver_id.clone(), deposit))
+                        }
+                        VmAction::FunctionCall {
+                            function_name,
+                            args,
+                            deposit,
+                            ..
+                        } if function_name == "ft_transfer" => {
+                            assert_eq!(deposit, 1);
+                            let args: near_sdk::serde_json::Value =
+                                near_sdk::serde_json::from_slice(&args).unwrap();
+                            Some((
+                                receiver_id.clone(),
+                                args["receiver_id"].as_str().unwrap().parse().unwrap(),
+                                args["amount"].as_str().unwrap().parse().unwrap(),
+                            ))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_buy_pays_payees_in_near() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_royalty_listing(&mut context, None);
+
+        call_env(&mut context, 1000, accounts(3));
+        contract.buy("0".to_string());
+        let payments = payments();
+        assert!(payments.contains(&(accounts(0), accounts(1), 900)));
+        assert!(payments.contains(&(accounts(0), accounts(2), 100)));
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+    }
+
+    #[test]
+    fn test_ft_buy_pays_payees_in_ft() {
+        let mut context = get_context(accounts(0));
+        let ft_token_id: AccountId = "usdc.near".parse().unwrap();
+        let mut contract = contract_with_royalty_listing(&mut context, Some(ft_token_id.clone()));
+
+        // danny sends 1500 usdc, 500 are returned
+        call_env(&mut context, 0, ft_token_id.clone());
+        let unspent = contract.ft_on_transfer(
+            accounts(3),
+            U128(1500),
+            r#"{"buy":{"token_id":"0"}}"#.to_string(),
+        );
+        assert!(matches!(unspent, PromiseOrValue::Value(U128(500))));
+        let payments = payments();
+        assert!(payments.contains(&(ft_token_id.clone(), accounts(1), 900)));
+        assert!(payments.contains(&(ft_token_id.clone(), accounts(2), 100)));
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+
+        // every share is resolved, a failed one becomes claimable
+        let resolved = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter(|action| {
+                matches!(action, VmAction::FunctionCall { function_name, .. } if function_name == "on_ft_payout")
+            })
+            .count();
+        let ft_payments = payments
+            .iter()
+            .filter(|(token, _, _)| *token == ft_token_id)
+            .count();
+        assert_eq!(resolved, ft_payments);
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        contract.on_ft_payout(accounts(2), ft_token_id.clone(), U128(100));
+        assert_eq!(contract.ft_claimable(accounts(2), ft_token_id), U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is priced in another currency")]
+    fn test_buy_ft_listing_with_near() {
+        let mut context = get_context(accounts(0));
+        let mut contract =
+            contract_with_royalty_listing(&mut context, Some("usdc.near".parse().unwrap()));
+        call_env(&mut context, 1000, accounts(3));
+        contract.buy("0".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is priced in another currency")]
+    fn test_ft_buy_with_other_ft() {
+        let mut context = get_context(accounts(0));
+        let mut contract =
+            contract_with_royalty_listing(&mut context, Some("usdc.near".parse().unwrap()));
+        call_env(&mut context, 0, "usdt.near".parse().unwrap());
+        let _ = contract.ft_on_transfer(
+            accounts(3),
+            U128(1000),
+            r#"{"buy":{"token_id":"0"}}"#.to_string(),
+        );
+    }
+
+    /// Contract minting for 1000 USDC with a storage pool of `MINT_STORAGE_BYTES * 2`
+    fn contract_with_ft_mint_price(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(context, 0, accounts(0));
+        contract.set_ft_mint_price(Some(ft::FtMintPrice {
+            ft_token_id: "usdc.near".parse().unwrap(),
+            price: U128(1000),
+        }));
+        call_env(context, storage_cost(MINT_STORAGE_BYTES * 2), accounts(0));
+        contract.ft_mint_storage_pool_top_up();
+        contract
+    }
+
+    fn ft_mint_msg() -> String {
+        near_sdk::serde_json::json!({
+            "mint": { "token_id": "0", "metadata": sample_token_metadata() }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_ft_mint() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_ft_mint_price(&mut context);
+        call_env(&mut context, 0, "usdc.near".parse().unwrap());
+        let initial_storage = env::storage_usage();
+        let unspent = contract.ft_on_transfer(accounts(3), U128(1000), ft_mint_msg());
+        assert!(matches!(unspent, PromiseOrValue::Value(U128(0))));
+        let token = contract.nft_token("0".to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(3));
+        assert_eq!(token.metadata, Some(sample_token_metadata()));
+        assert_eq!(logged_events(), vec!["nft_mint", "mint_receipt"]);
+
+        // the storage is paid from the pool and the price goes to the owner
+        let storage_used =
+            env::storage_usage() - initial_storage - last_audit_entry_bytes(&contract);
+        assert_eq!(
+            logged_mint_receipt().storage_charged,
+            U128(storage_cost(storage_used))
+        );
+        assert_eq!(
+            contract.ft_mint_storage_pool(),
+            U128(storage_cost(MINT_STORAGE_BYTES * 2 - storage_used))
+        );
+        assert_eq!(
+            payments(),
+            vec![("usdc.near".parse().unwrap(), accounts(0), 1000)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FT mint storage pool is too low")]
+    fn test_ft_mint_without_storage_pool() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_ft_mint_price(Some(ft::FtMintPrice {
+            ft_token_id: "usdc.near".parse().unwrap(),
+            price: U128(1000),
+        }));
+        call_env(&mut context, 0, "usdc.near".parse().unwrap());
+        let _ = contract.ft_on_transfer(accounts(3), U128(1000), ft_mint_msg());
+    }
+
+    #[test]
+    fn test_ft_mint_storage_pool_top_up_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::FtMintStoragePoolTopUpLog {
+                amount: U128(1000),
+                pool: U128(3000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"ft_mint_storage_pool_top_up","data":[{"amount":"1000","pool":"3000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_ft_mint_rejected() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_ft_mint_price(&mut context);
+        for (ft_token_id, amount) in [("usdc.near", 999), ("usdc.near", 1001), ("usdt.near", 1000)]
+        {
+            call_env(&mut context, 0, ft_token_id.parse().unwrap());
+            let unspent = contract.ft_on_transfer(accounts(3), U128(amount), ft_mint_msg());
+            assert!(matches!(unspent, PromiseOrValue::Value(U128(refund)) if refund == amount));
+        }
+        call_env(&mut context, 0, accounts(0));
+        contract.set_ft_mint_price(None);
+        call_env(&mut context, 0, "usdc.near".parse().unwrap());
+        let unspent = contract.ft_on_transfer(accounts(3), U128(1000), ft_mint_msg());
+        assert!(matches!(unspent, PromiseOrValue::Value(U128(1000))));
+        assert_eq!(contract.nft_token("0".to_string()), None);
+    }
+
+    #[test]
+    fn test_ft_mint_price_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::FtMintPriceUpdateLog {
+                ft_mint_price: Some(ft::FtMintPrice {
+                    ft_token_id: "usdc.near".parse().unwrap(),
+                    price: U128(1000),
+                }),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"ft_mint_price_update","data":[{"ft_mint_price":{"ft_token_id":"usdc.near","price":"1000"}}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_check_invariants_healthy() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_royalty_listing(&mut context, None);
+        assert_eq!(contract.check_invariants(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_check_invariants_reports_desync() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_royalty_listing(&mut context, None);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint(
+            "1".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 500)])),
+        );
+
+        // drop token "0" from the enumeration of bob
+        let tokens_per_owner = contract.tokens.tokens_per_owner.as_mut().unwrap();
+        let mut token_ids = tokens_per_owner.get(&accounts(1)).unwrap();
+        token_ids.remove(&"0".to_string());
+        tokens_per_owner.insert(&accounts(1), &token_ids);
+        // hand token "0" over without removing its listing
+        contract
+            .tokens
+            .owner_by_id
+            .insert(&"0".to_string(), &accounts(3));
+        // remove token "1" leaving its royalty index entry behind
+        contract.tokens.owner_by_id.remove(&"1".to_string());
+
+        assert_eq!(
+            contract.check_invariants(),
+            vec![
+                "Token 0 is missing from the enumeration of danny",
+                "Enumeration of danny lists 0 tokens, 1 owned",
+                "Sale of token 0 by bob who doesn't own it",
+                "Royalty index of charlie lists missing token 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_health_check() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_royalty_listing(&mut context, None);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint("1".to_string(), accounts(1), sample_token_metadata(), None);
+        call_env(&mut context, storage_cost(20), accounts(1));
+        contract.nft_approve("0".to_string(), accounts(3), None);
+        assert_eq!(
+            contract.nft_contract_health_check(),
+            invariants::HealthReport {
+                total_supply_matches_owner_count: true,
+                all_metadata_present: true,
+                no_orphan_approvals: true,
+                issues_count: 0,
+            }
+        );
+
+        // drop the metadata of token "1"
+        contract
+            .tokens
+            .token_metadata_by_id
+            .as_mut()
+            .unwrap()
+            .remove(&"1".to_string());
+        // remove the listed token "0" leaving its approvals behind
+        contract.tokens.owner_by_id.remove(&"0".to_string());
+
+        assert_eq!(
+            contract.nft_contract_health_check(),
+            invariants::HealthReport {
+                total_supply_matches_owner_count: true,
+                all_metadata_present: false,
+                no_orphan_approvals: false,
+                issues_count: 2,
+            }
+        );
+    }
+
+    /// Tokens "0" and "1" of bob and "2" of danny, all approved for charlie
+    fn contract_with_operator_approvals(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        for (token_id, owner_id) in [("0", accounts(1)), ("1", accounts(1)), ("2", accounts(3))] {
+            call_env(context, storage_cost(MINT_STORAGE_BYTES) * 2, accounts(0));
+            contract.nft_mint(
+                token_id.into(),
+                owner_id.clone(),
+                sample_token_metadata(),
+                None,
+            );
+            call_env(context, storage_cost(20), owner_id);
+            contract.nft_approve(token_id.into(), accounts(2), None);
+        }
+        contract
+    }
+
+    /// Tokens "0", "1" and "2" of bob, "1" and "2" already approved for danny
+    fn contract_with_approved_tokens(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        for token_id in ["0", "1", "2"] {
+            call_env(context, storage_cost(MINT_STORAGE_BYTES) * 2, accounts(0));
+            contract.nft_mint(token_id.into(), accounts(1), sample_token_metadata(), None);
+        }
+        for token_id in ["1", "2", "2"] {
+            call_env(context, storage_cost(20), accounts(1));
+            contract.nft_approve(token_id.into(), accounts(3), None);
+        }
+        contract
+    }
+
+    #[test]
+    fn test_batch_approve() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_approved_tokens(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        let token_ids = vec!["0".to_string(), "1".to_string(), "2".to_string()];
+        let approval_ids = contract.nft_batch_approve(token_ids.clone(), accounts(2));
+
+        assert_eq!(approval_ids, vec![1, 2, 3]);
+        for (token_id, approval_id) in token_ids.into_iter().zip(approval_ids) {
+            assert!(contract.nft_is_approved(token_id, accounts(2), Some(approval_id)));
+        }
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(1));
+        assert!(
+            refunds[0].2 > 0 && refunds[0].2 < storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be token owner.")]
+    fn test_batch_approve_not_owned_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_approved_tokens(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint("3".to_string(), accounts(3), sample_token_metadata(), None);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_batch_approve(vec!["0".to_string(), "3".to_string()], accounts(2));
+    }
+
+    #[test]
+    fn test_approve_with_refund_to_relayed_account() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        let promise =
+            contract.nft_approve_with_refund("0".to_string(), accounts(2), None, Some(accounts(4)));
+
+        assert!(promise.is_none());
+        assert!(contract.nft_is_approved("0".to_string(), accounts(2), Some(1)));
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(4));
+        assert!(
+            refunds[0].2 > 0 && refunds[0].2 < storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES)
+        );
+    }
+
+    #[test]
+    fn test_approve_with_refund_defaults_to_caller() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_approve_with_refund("0".to_string(), accounts(2), None, None);
+
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(1));
+    }
+
+    #[test]
+    fn test_approve_with_refund_returns_conditions_to_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = setup_conditional_approval(
+            &mut context,
+            approval::ApprovalConditions {
+                min_price: None,
+                expires_at: None,
+                max_uses: Some(1),
+            },
+        );
+
+        // the conditions alice paid for are refunded to her, the excess deposit to danny
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(0),
+        );
+        let initial_storage = env::storage_usage();
+        contract.nft_approve_with_refund("0".to_string(), accounts(2), None, Some(accounts(3)));
+        let storage_released =
+            initial_storage + last_audit_entry_bytes(&contract) - env::storage_usage();
+
+        let refunds = payments();
+        assert_eq!(refunds.len(), 2);
+        assert_eq!(refunds[0].1, accounts(0));
+        assert_eq!(refunds[0].2, storage_cost(storage_released));
+        assert_eq!(refunds[1].1, accounts(3));
+        assert_eq!(
+            contract.nft_approval_conditions("0".to_string(), accounts(2)),
+            None
+        );
+    }
+
+    /// Gas attached to `nft_on_approve` calls made so far
+    fn on_approve_gas() -> Vec<near_sdk::Gas> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                VmAction::FunctionCall {
+                    function_name, gas, ..
+                } if function_name == "nft_on_approve" => Some(gas),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Token "0" of bob, charlie is the Paras marketplace
+    fn contract_with_paras_marketplace(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        contract.set_paras_marketplace(Some(accounts(2)));
+        contract
+    }
+
+    #[test]
+    fn test_approve_with_paras_sale_msg() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_paras_marketplace(&mut context);
+        assert_eq!(contract.paras_marketplace(), Some(accounts(2)));
+        contract.set_paras_ft_token("usdc.near".parse().unwrap(), true);
+        assert_eq!(
+            contract.paras_ft_tokens(),
+            vec!["usdc.near".parse::<AccountId>().unwrap()]
+        );
+
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        assert!(contract
+            .nft_approve(
+                "0".to_string(),
+                accounts(2),
+                Some(r#"{"market_type":"sale","price":"1000","ft_token_id":"near"}"#.into()),
+            )
+            .is_some());
+        contract.nft_approve(
+            "0".to_string(),
+            accounts(2),
+            Some(r#"{"market_type":"sale","price":"5","ft_token_id":"usdc.near"}"#.into()),
+        );
+        assert!(contract.nft_is_approved("0".to_string(), accounts(2), None));
+        assert_eq!(on_approve_gas(), vec![near_sdk::Gas(30_000_000_000_000); 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Approve msg must be a JSON object")]
+    fn test_approve_with_malformed_msg() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_paras_marketplace(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_approve(
+            "0".to_string(),
+            accounts(2),
+            Some(r#"{"market_type":"sale","price":"#.into()),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Sale price must be positive")]
+    fn test_approve_with_zero_price_msg() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_paras_marketplace(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_approve_with_refund(
+            "0".to_string(),
+            accounts(2),
+            Some(r#"{"market_type":"sale","price":"0","ft_token_id":"near"}"#.into()),
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FT usdc.near is not accepted")]
+    fn test_approve_with_unaccepted_ft_msg() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_paras_marketplace(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_approve(
+            "0".to_string(),
+            accounts(2),
+            Some(r#"{"market_type":"sale","price":"5","ft_token_id":"usdc.near"}"#.into()),
+        );
+    }
+
+    #[test]
+    fn test_approve_other_marketplace_with_any_msg() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_paras_marketplace(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        // only msgs sent to Paras are checked
+        contract.nft_approve("0".to_string(), accounts(3), Some("list at 5".into()));
+        assert!(contract.nft_is_approved("0".to_string(), accounts(3), None));
+    }
+
+    #[test]
+    fn test_paras_marketplace_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::ParasMarketplaceUpdateLog {
+                paras_marketplace: Some(accounts(2)),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"paras_marketplace_update","data":[{"paras_marketplace":"charlie"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_paras_ft_token_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::ParasFtTokenUpdateLog {
+                ft_token_id: "usdc.near".parse().unwrap(),
+                accepted: true,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"paras_ft_token_update","data":[{"ft_token_id":"usdc.near","accepted":true}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_integration_diagnostics() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(MARKETPLACE_APPROVAL_STORAGE_BYTES),
+            accounts(0),
+        );
+        let initial_storage = env::storage_usage();
+        assert!(contract.nft_run_integration_diagnostics());
+        assert_eq!(
+            logged_events(),
+            vec!["nft_approve", "nft_revoke", "integration_diagnostics"]
+        );
+        // only the audit entries of the approval, the revoke and the run are left
+        assert_eq!(
+            env::storage_usage() - initial_storage,
+            last_audit_entries_bytes(&contract, 3)
+        );
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert!(contract
+            .nft_tokens_for_owner(accounts(0), None, None)
+            .is_empty());
+        assert_eq!(contract.nft_contract_health_check().issues_count, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_integration_diagnostics_by_non_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_run_integration_diagnostics();
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_integration_diagnostics_without_approval_deposit() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 1, accounts(0));
+        contract.nft_run_integration_diagnostics();
+    }
+
+    #[test]
+    fn test_integration_diagnostics_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::IntegrationDiagnosticsLog { success: true }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"integration_diagnostics","data":[{"success":true}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_transfer_all() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 5)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_ids: Vec<TokenId> = (0..5).map(|i| i.to_string()).collect();
+        for token_id in &token_ids {
+            contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+        }
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer_all(accounts(3), Some("moving wallets".into()));
+        let logs = get_logs();
+        assert_eq!(logs.len(), 2);
+        let transfer: near_sdk::serde_json::Value =
+            near_sdk::serde_json::from_str(logs[0].trim_start_matches("EVENT_JSON:")).unwrap();
+        assert_eq!(transfer["event"], "nft_transfer");
+        assert_eq!(transfer["data"][0]["old_owner_id"], "bob");
+        assert_eq!(transfer["data"][0]["new_owner_id"], "danny");
+        assert_eq!(transfer["data"][0]["memo"], "moving wallets");
+        assert_eq!(
+            transfer["data"][0]["token_ids"].as_array().unwrap().len(),
+            5
+        );
+        assert_eq!(
+            logs[1],
+            r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"nft_transfer_all","data":[{"owner_id":"bob","receiver_id":"danny","count":5}]}"#
+        );
+
+        let mut owned: Vec<TokenId> = contract
+            .nft_tokens_for_owner(accounts(3), None, None)
+            .into_iter()
+            .map(|token| token.token_id)
+            .collect();
+        owned.sort();
+        assert_eq!(owned, token_ids);
+        assert!(contract
+            .nft_tokens_for_owner(accounts(1), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_transfer_all_refunds_once() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_approved_tokens(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_for_sale("0".to_string(), U128(1000));
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer_all(accounts(3), None);
+        assert_eq!(
+            contract.nft_tokens_for_owner(accounts(3), None, None).len(),
+            3
+        );
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Caller has no tokens")]
+    fn test_transfer_all_without_tokens() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 1, accounts(2));
+        contract.nft_transfer_all(accounts(3), None);
+    }
+
+    #[test]
+    fn test_nft_transfer_all_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftTransferAllLog {
+                owner_id: accounts(1),
+                receiver_id: accounts(3),
+                count: 5,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_transfer_all","data":[{"owner_id":"bob","receiver_id":"danny","count":5}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_batch_transfer_to_self() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_operator_approvals(&mut context);
+
+        call_env(&mut context, 1, accounts(2));
+        contract.nft_batch_transfer_to_self(vec!["0".into(), "1".into(), "2".into()]);
+        let transfer_logs: Vec<String> = get_logs()
+            .into_iter()
+            .filter(|log| log.contains(r#""event":"nft_transfer""#))
+            .collect();
+        assert_eq!(
+            transfer_logs,
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","token_ids":["0","1"],"authorized_id":"charlie"},{"old_owner_id":"danny","new_owner_id":"charlie","token_ids":["2"],"authorized_id":"charlie"}]}"#
+            ]
+        );
+        for token_id in ["0", "1", "2"] {
+            let token = contract.nft_token(token_id.into()).unwrap();
+            assert_eq!(token.owner_id, accounts(2));
+            assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
+        }
+        assert_eq!(contract.check_invariants(), Vec::<String>::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "Sender not approved")]
+    fn test_batch_transfer_to_self_not_approved() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_operator_approvals(&mut context);
+        call_env(&mut context, 1, accounts(4));
+        contract.nft_batch_transfer_to_self(vec!["0".into()]);
+    }
+
+    /// Store extras of `token_id` in the `V1` layout: charlie's royalty and danny as a collaborator
+    fn write_v1_token_extras(contract: &mut Contract, token_id: &str) {
+        let token_id = token_id.to_string();
+        contract.token_extras.insert(
+            &token_id,
+            &extras::VersionedTokenExtras::V1(HashMap::from([(accounts(2), 1000)])),
+        );
+        contract.token_collaborators.insert(
+            &token_id,
+            &vec![collaborators::Collaborator {
+                account_id: accounts(3),
+                royalty_bps: 500,
+                role: "artist".into(),
+            }],
+        );
+    }
+
+    fn is_v2_token_extras(contract: &Contract, token_id: &str) -> bool {
+        matches!(
+            contract.token_extras.get(&token_id.to_string()),
+            Some(extras::VersionedTokenExtras::V2(_))
+        ) && contract
+            .token_collaborators
+            .get(&token_id.to_string())
+            .is_none()
+    }
+
+    #[test]
+    fn test_v1_token_extras_read_transparently() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        write_v1_token_extras(&mut contract, "0");
+
+        assert_eq!(
+            contract.internal_payout(&"0".to_string(), &accounts(1), 1000),
+            HashMap::from([(accounts(1), 850), (accounts(2), 100), (accounts(3), 50)])
+        );
+        assert_eq!(
+            contract.nft_token_collaborators("0".to_string())[0].account_id,
+            accounts(3)
+        );
+        assert!(!is_v2_token_extras(&contract, "0"));
+    }
+
+    #[test]
+    fn test_v1_token_extras_upgraded_on_write() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        write_v1_token_extras(&mut contract, "0");
+
+        contract.internal_update_token_extras(&"0".to_string(), |extras| {
+            extras.royalties.insert(accounts(4), 200);
+        });
+        assert!(is_v2_token_extras(&contract, "0"));
+        assert_eq!(
+            contract.internal_payout(&"0".to_string(), &accounts(1), 1000),
+            HashMap::from([
+                (accounts(1), 830),
+                (accounts(2), 100),
+                (accounts(3), 50),
+                (accounts(4), 20)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_migrate_tokens_in_chunks() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_operator_approvals(&mut context);
+        write_v1_token_extras(&mut contract, "0");
+        write_v1_token_extras(&mut contract, "2");
+        assert_eq!(
+            contract.token_migration_progress(),
+            extras::TokenMigrationProgress {
+                swept: U64(0),
+                done: false
+            }
+        );
+
+        call_env(&mut context, 0, accounts(4));
+        assert!(!contract.migrate_tokens(2));
+        assert!(is_v2_token_extras(&contract, "0"));
+        assert!(!is_v2_token_extras(&contract, "2"));
+        assert_eq!(
+            contract.token_migration_progress(),
+            extras::TokenMigrationProgress {
+                swept: U64(2),
+                done: false
+            }
+        );
+
+        assert!(contract.migrate_tokens(2));
+        assert!(is_v2_token_extras(&contract, "2"));
+        assert_eq!(
+            contract.token_migration_progress(),
+            extras::TokenMigrationProgress {
+                swept: U64(3),
+                done: true
+            }
+        );
+        assert_eq!(
+            contract.internal_payout(&"2".to_string(), &accounts(3), 1000),
+            HashMap::from([(accounts(3), 900), (accounts(2), 100)])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token migration is complete")]
+    fn test_migrate_tokens_after_completion() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 0, accounts(4));
+        contract.migrate_tokens(10);
+        contract.migrate_tokens(10);
+    }
+
+    #[test]
+    fn test_random_winner_is_token_owner() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_operator_approvals(&mut context);
+
+        let winners: std::collections::HashSet<AccountId> = (0..20)
+            .map(|seed| contract.nft_random_winner(Some(seed.to_string())))
+            .collect();
+        assert_eq!(
+            winners,
+            std::collections::HashSet::from([accounts(1), accounts(3)])
+        );
+        assert_eq!(
+            contract.nft_random_winner(Some("kyiv".into())),
+            contract.nft_random_winner(Some("kyiv".into()))
+        );
+
+        testing_env!(context.random_seed([7; 32]).build());
+        assert!([accounts(1), accounts(3)].contains(&contract.nft_random_winner(None)));
+    }
+
+    #[test]
+    #[should_panic(expected = "No tokens to draw from")]
+    fn test_random_winner_without_tokens() {
+        testing_env!(get_context(accounts(0)).build());
+        Contract::new().nft_random_winner(None);
+    }
+
+    #[test]
+    fn test_audit_bundle() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .block_timestamp(1_000 * 1_000_000_000)
+            .build());
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 1000)])),
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .block_timestamp(2_000 * 1_000_000_000)
+            .build());
+        contract.nft_mint("1".to_string(), accounts(2), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.list_for_sale("0".to_string(), U128(1000));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_transfer(accounts(3), "1".to_string(), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.audit_bundle(U128(0), 10),
+            vec![
+                audit::TokenAudit {
+                    token_id: "0".to_string(),
+                    owner_id: accounts(1),
+                    royalties: HashMap::from([(accounts(2), 1000)]),
+                    sale: Some(Sale {
+                        owner_id: accounts(1),
+                        price: U128(1000),
+                        dutch_auction: None,
+                        ft_token_id: None,
+                        expires_at: None,
+                        accept_counter_offers: false,
+                    }),
+                    transfer_count: 0,
+                    minted_at: Some(1_000),
+                },
+                audit::TokenAudit {
+                    token_id: "1".to_string(),
+                    owner_id: accounts(3),
+                    royalties: HashMap::new(),
+                    sale: None,
+                    transfer_count: 1,
+                    minted_at: Some(2_000),
+                },
+            ]
+        );
+        let page = contract.audit_bundle(U128(1), 1);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].token_id, "1");
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .block_timestamp(1_000 * 1_000_000_000)
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(2_000 * 1_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(2), token_id.clone(), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(3_000 * 1_000_000_000)
+            .build());
+        contract.list_for_sale(token_id.clone(), U128(1000));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1000)
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(4_000 * 1_000_000_000)
+            .build());
+        contract.buy(token_id.clone());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        let entry = |action: &str, actor: AccountId, amount: Option<u128>, timestamp: u64| {
+            audit::AuditEntry {
+                action: action.into(),
+                actor,
+                token_id: Some(token_id.clone()),
+                amount: amount.map(U128),
+                timestamp,
+            }
+        };
+        assert_eq!(
+            contract.nft_audit_log(None, None),
+            vec![
+                entry("mint", accounts(0), None, 1_000),
+                entry("transfer", accounts(1), None, 2_000),
+                entry("list", accounts(2), Some(1000), 3_000),
+                entry("sale", accounts(3), Some(1000), 4_000),
+            ]
+        );
+        assert_eq!(
+            contract.nft_audit_log(Some(3.into()), Some(10)),
+            vec![entry("sale", accounts(3), Some(1000), 4_000)]
+        );
+    }
+
+    #[test]
+    fn test_audit_storage_pool_pays_entries() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, storage_cost(1000), accounts(0));
+        contract.audit_storage_pool_top_up();
+        let top_up_bytes = last_audit_entry_bytes(&contract);
+        assert_eq!(
+            contract.audit_storage_pool(),
+            U128(storage_cost(1000 - top_up_bytes))
+        );
+        assert_eq!(logged_events(), vec!["audit_storage_pool_top_up"]);
+
+        for i in 0..1_000 {
+            contract.internal_audit(&i.to_string(), None, None);
+        }
+        // every entry is kept, the ones the pool doesn't cover are paid by the contract
+        assert_eq!(contract.audit_log.len(), 1_001);
+        assert_eq!(
+            contract.nft_audit_log(None, Some(1))[0].action,
+            "audit_storage_pool_top_up"
+        );
+        assert_eq!(
+            contract.nft_audit_log(Some(U64(1_000)), None)[0].action,
+            "999"
+        );
+        assert_eq!(contract.audit_storage_pool(), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_audit_storage_pool_top_up_by_other() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, 1000, accounts(1));
+        contract.audit_storage_pool_top_up();
+    }
+
+    #[test]
+    fn test_audit_storage_pool_top_up_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::AuditStoragePoolTopUpLog {
+                amount: U128(1000),
+                pool: U128(3000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"audit_storage_pool_top_up","data":[{"amount":"1000","pool":"3000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_transfer_removes_listing() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.list_for_sale(token_id.clone(), U128(1000));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_transfer(accounts(1), token_id.clone(), None, None);
+        assert_eq!(contract.nft_get_listing_price(token_id), None);
+    }
+
+    #[test]
+    fn test_bulk_update_sale_prices() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 5)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_ids: Vec<TokenId> = (0..5).map(|i| i.to_string()).collect();
+        for token_id in &token_ids {
+            contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+        }
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES) * 5)
+            .predecessor_account_id(accounts(1))
+            .build());
+        for token_id in &token_ids {
+            contract.list_for_sale(token_id.clone(), U128(1000));
+        }
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let updates: Vec<sale::PriceUpdate> = token_ids
+            .iter()
+            .enumerate()
+            .map(|(i, token_id)| sale::PriceUpdate {
+                token_id: token_id.clone(),
+                new_price: U128(2000 + i as u128),
+            })
+            .collect();
+        contract.nft_bulk_update_sale_prices(updates);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"nft_bulk_price_update","data":[{"token_id":"0","new_price":"2000"},{"token_id":"1","new_price":"2001"},{"token_id":"2","new_price":"2002"},{"token_id":"3","new_price":"2003"},{"token_id":"4","new_price":"2004"}]}"#
+            ]
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .build());
+        for (i, token_id) in token_ids.into_iter().enumerate() {
+            assert_eq!(
+                contract.nft_get_listing_price(token_id),
+                Some(U128(2000 + i as u128))
+            );
+        }
+    }
+
+    #[test]
+    fn test_bulk_delist() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 5)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_ids: Vec<TokenId> = (0..5).map(|i| i.to_string()).collect();
+        for token_id in &token_ids {
+            contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+        }
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES) * 5)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let listing_storage = env::storage_usage();
+        for token_id in &token_ids {
+            contract.list_for_sale(token_id.clone(), U128(1000));
+        }
+        let listing_bytes = env::storage_usage() - listing_storage;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let initial_balance = env::account_balance();
+        contract.nft_bulk_delist(token_ids.clone());
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"nft_bulk_delist","data":[{"owner_id":"bob","token_ids":["0","1","2","3","4"]}]}"#
+            ]
+        );
+        // the listings are refunded in one transfer
+        let refunded = initial_balance - env::account_balance();
+        assert!(refunded > 0 && refunded < storage_cost(listing_bytes));
+        for token_id in token_ids {
+            assert_eq!(contract.nft_get_listing_price(token_id), None);
+        }
+        assert!(contract
+            .nft_tokens_by_price_range(U128(0), U128(u128::MAX), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be token owner.")]
+    fn test_bulk_delist_other_owners_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_listing(&mut context);
+        call_env(&mut context, 1, accounts(2));
+        contract.nft_bulk_delist(vec!["0".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_bulk_update_sale_prices_unauthorized() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.list_for_sale(token_id.clone(), U128(1000));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_bulk_update_sale_prices(vec![sale::PriceUpdate {
+            token_id,
+            new_price: U128(1),
+        }]);
+    }
+
+    /// Tokens "0", "1" and "2" of bob listed for 500, 1500 and 800
+    fn contract_with_three_listings(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        for (token_id, price) in [("0", 500), ("1", 1500), ("2", 800)] {
+            call_env(context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+            contract.nft_mint(
+                token_id.to_string(),
+                accounts(1),
+                sample_token_metadata(),
+                None,
+            );
+            call_env(context, storage_cost(LISTING_STORAGE_BYTES), accounts(1));
+            contract.list_for_sale(token_id.to_string(), U128(price));
+        }
+        contract
+    }
+
+    #[test]
+    fn test_set_floor_price_in_batches() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_three_listings(&mut context);
+
+        // "0" is delisted and "1" kept, "2" is left for the next call
+        call_env(&mut context, 0, accounts(0));
+        assert_eq!(
+            contract.nft_set_floor_price_and_delist_above(U128(1000), 2),
+            Some("1".to_string())
+        );
+        assert_eq!(contract.min_sale_price(), U128(1000));
+        assert_eq!(logged_events(), vec!["nft_delist", "min_sale_price_update"]);
+        assert_eq!(contract.nft_get_listing_price("0".to_string()), None);
+        assert_eq!(
+            contract.nft_get_listing_price("2".to_string()),
+            Some(U128(800))
+        );
+        assert_eq!(payments().len(), 1);
+
+        // a listing removed between the batches doesn't move the rest
+        call_env(&mut context, 1, accounts(1));
+        contract.delist("1".to_string());
+
+        call_env(&mut context, 0, accounts(0));
+        assert_eq!(
+            contract.nft_set_floor_price_and_delist_above(U128(1000), 2),
+            None
+        );
+        assert_eq!(contract.nft_get_listing_price("2".to_string()), None);
+        assert_eq!(
+            payments()
+                .into_iter()
+                .map(|(_, receiver_id, _)| receiver_id)
+                .collect::<Vec<_>>(),
+            vec![accounts(1)]
+        );
+    }
+
+    #[test]
+    fn test_set_floor_price_at_once() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_three_listings(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        assert_eq!(
+            contract.nft_set_floor_price_and_delist_above(U128(600), 10),
+            None
+        );
+        assert_eq!(contract.nft_get_listing_price("0".to_string()), None);
+        assert_eq!(
+            contract.nft_get_listing_price("1".to_string()),
+            Some(U128(1500))
+        );
+        assert_eq!(
+            contract.nft_get_listing_price("2".to_string()),
+            Some(U128(800))
+        );
+        assert_eq!(
+            contract
+                .nft_tokens_by_price_range(U128(0), U128(10_000), None, None)
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_list_at_min_sale_price() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_min_sale_price(U128(1000));
+        assert_eq!(contract.min_sale_price(), U128(1000));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.list_for_sale(token_id.clone(), U128(1000));
+        assert_eq!(contract.nft_get_listing_price(token_id), Some(U128(1000)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Price must be at least 1000")]
+    fn test_list_below_min_sale_price() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_min_sale_price(U128(1000));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(1), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.list_for_sale(token_id, U128(999));
+    }
+
+    #[test]
+    fn test_event_envelope() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        events::emit(vec![events::DefaultMediaUpdateLog {
+            default_media: None,
+        }]);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"default_media_update","data":[{"default_media":null}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nft_list_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftListLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                price: U128(1000),
+                ft_token_id: None,
+                expires_at: None,
+                accept_counter_offers: false,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_list","data":[{"token_id":"0","owner_id":"bob","price":"1000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_delist_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftDelistLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_delist","data":[{"token_id":"0","owner_id":"bob"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_bulk_delist_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftBulkDelistLog {
+                owner_id: accounts(1),
+                token_ids: vec!["0".into(), "1".into()],
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_bulk_delist","data":[{"owner_id":"bob","token_ids":["0","1"]}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_sale_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftSaleLog {
+                token_id: "0".into(),
+                seller_id: accounts(1),
+                buyer_id: accounts(2),
+                price: U128(1000),
+                ft_token_id: None,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_sale","data":[{"token_id":"0","seller_id":"bob","buyer_id":"charlie","price":"1000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_ft_sale_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftSaleLog {
+                token_id: "0".into(),
+                seller_id: accounts(1),
+                buyer_id: accounts(2),
+                price: U128(1000),
+                ft_token_id: Some("usdc.near".parse().unwrap()),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_sale","data":[{"token_id":"0","seller_id":"bob","buyer_id":"charlie","price":"1000","ft_token_id":"usdc.near"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_dutch_list_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftDutchListLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                auction: sale::DutchAuction {
+                    start_price: U128(2000),
+                    end_price: U128(1000),
+                    starts_at: 1_000,
+                    ends_at: 1_100,
+                },
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_list_dutch","data":[{"token_id":"0","owner_id":"bob","auction":{"start_price":"2000","end_price":"1000","starts_at":1000,"ends_at":1100}}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_price_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![
+                sale::PriceUpdate {
+                    token_id: "0".into(),
+                    new_price: U128(1000),
+                },
+                sale::PriceUpdate {
+                    token_id: "1".into(),
+                    new_price: U128(2000),
+                },
+            ]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_bulk_price_update","data":[{"token_id":"0","new_price":"1000"},{"token_id":"1","new_price":"2000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_default_media_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::DefaultMediaUpdateLog {
+                default_media: Some("default-media".into()),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"default_media_update","data":[{"default_media":"default-media"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_governance_snapshot_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::GovernanceSnapshotLog {
+                snapshot_id: "before".into(),
+                block_height: 42.into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"governance_snapshot","data":[{"snapshot_id":"before","block_height":"42"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_min_sale_price_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::MinSalePriceUpdateLog {
+                min_sale_price: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"min_sale_price_update","data":[{"min_sale_price":"1000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_approve_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftApproveLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                approved_account_id: accounts(2),
+                approval_id: 3.into(),
+                conditions: Some(approval::ApprovalConditions {
+                    min_price: Some(U128(1000)),
+                    expires_at: None,
+                    max_uses: Some(2),
+                }),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_approve","data":[{"token_id":"0","owner_id":"bob","approved_account_id":"charlie","approval_id":"3","conditions":{"min_price":"1000","expires_at":null,"max_uses":2}}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_revoke_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftRevokeLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                account_id: accounts(2),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_revoke","data":[{"token_id":"0","owner_id":"bob","account_id":"charlie"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_revoke_all_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftRevokeAllLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_revoke_all","data":[{"token_id":"0","owner_id":"bob"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_collection_create_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::CollectionCreateLog {
+                collection_id: "kyiv.alice".parse().unwrap(),
+                owner_id: accounts(0),
+                name: "kyiv".into(),
+                symbol: "KYIV".into(),
+                max_supply: 100.into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"collection_create","data":[{"collection_id":"kyiv.alice","owner_id":"alice","name":"kyiv","symbol":"KYIV","max_supply":"100"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_default_marketplace_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::DefaultMarketplaceUpdateLog {
+                default_marketplace: Some(accounts(2)),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"default_marketplace_update","data":[{"default_marketplace":"charlie"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_mint_approves_default_marketplace() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_marketplace(Some(accounts(2)));
+        assert_eq!(contract.default_marketplace(), Some(accounts(2)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                storage_cost(MINT_STORAGE_BYTES)
+                    + storage_cost(MINT_ALL_STORAGE_BYTES)
+                    + storage_cost(MARKETPLACE_APPROVAL_STORAGE_BYTES) * 4
+            )
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint("3".to_string(), accounts(1), sample_token_metadata(), None);
+        assert_eq!(
+            contract
+                .nft_token("3".to_string())
+                .unwrap()
+                .approved_account_ids
+                .unwrap(),
+            HashMap::from([(accounts(2), 1)])
+        );
+        contract.nft_mint_all();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        for token_id in ["0", "1", "2", "3"] {
+            assert!(contract.nft_is_approved(token_id.to_string(), accounts(2), Some(1)));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach 9640000000000000000000 yoctoNEAR to cover storage")]
+    fn test_mint_charges_default_marketplace_approval() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_default_marketplace(Some(accounts(2)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata(), None);
+    }
+
+    #[test]
+    fn test_mint_curve_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::MintCurveUpdateLog {
+                mint_curve: Some(MintCurve {
+                    base_price: U128(1000),
+                    increment: U128(10),
+                    step_size: None,
+                }),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"mint_curve_update","data":[{"mint_curve":{"base_price":"1000","increment":"10","step_size":null}}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_mint_price_split_between_payout_accounts() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(1001),
+            increment: U128(0),
+            step_size: None,
+        }));
+        let payout_accounts = vec![
+            mint_split::MintPayoutAccount {
+                account_id: accounts(1),
+                bps: 5000,
+            },
+            mint_split::MintPayoutAccount {
+                account_id: accounts(2),
+                bps: 3000,
+            },
+            mint_split::MintPayoutAccount {
+                account_id: accounts(3),
+                bps: 2000,
+            },
+        ];
+        contract.set_mint_payout_accounts(payout_accounts.clone());
+        assert_eq!(contract.mint_payout_accounts(), payout_accounts);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1001 + storage_cost(CURVE_MINT_STORAGE_BYTES) * 2)
+            .predecessor_account_id(accounts(4))
+            .build());
+        contract.nft_mint_curve();
+        // besides the shares only the excess deposit is paid, back to the minter
+        let mut shares: Vec<(AccountId, Balance)> = payments()
+            .into_iter()
+            .map(|(_, receiver_id, amount)| (receiver_id, amount))
+            .filter(|(receiver_id, _)| receiver_id != &accounts(4))
+            .collect();
+        shares.sort();
+        // the first account takes the rounding dust
+        assert_eq!(
+            shares,
+            vec![(accounts(1), 501), (accounts(2), 300), (accounts(3), 200)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Payout shares must add up to 10000 basis points")]
+    fn test_mint_payout_accounts_must_cover_the_price() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_mint_payout_accounts(vec![mint_split::MintPayoutAccount {
+            account_id: accounts(1),
+            bps: 9000,
+        }]);
+    }
+
+    #[test]
+    fn test_mint_payout_accounts_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::MintPayoutAccountsUpdateLog {
+                payout_accounts: vec![mint_split::MintPayoutAccount {
+                    account_id: accounts(1),
+                    bps: 10000,
+                }],
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"mint_payout_accounts_update","data":[{"payout_accounts":[{"account_id":"bob","bps":10000}]}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_mint_curve() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        let base_price = 1_000_000_000_000_000_000_000_000;
+        let increment = 100_000_000_000_000_000_000_000;
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(base_price),
+            increment: U128(increment),
+            step_size: None,
+        }));
+
+        for (i, account_id) in [accounts(1), accounts(2), accounts(3)]
+            .into_iter()
+            .enumerate()
+        {
+            let price = base_price + i as u128 * increment;
+            assert_eq!(contract.current_curve_price(), U128(price));
+
+            // the excess deposit is refunded along with the price paid to the owner
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .account_balance(env::account_balance())
+                .attached_deposit(price + storage_cost(CURVE_MINT_STORAGE_BYTES) * 2)
+                .predecessor_account_id(account_id.clone())
+                .build());
+            let initial_storage = env::storage_usage();
+            let initial_balance = env::account_balance();
+            let token = contract.nft_mint_curve();
+            let used_storage_cost = storage_cost(
+                env::storage_usage() - initial_storage - last_audit_entry_bytes(&contract),
+            );
+            assert_eq!(token.token_id, i.to_string());
+            assert_eq!(token.owner_id, account_id);
+            assert_eq!(
+                logged_mint_receipt().storage_charged,
+                U128(used_storage_cost)
+            );
+            assert_eq!(
+                initial_balance - env::account_balance(),
+                price + storage_cost(CURVE_MINT_STORAGE_BYTES) * 2 - used_storage_cost
+            );
+        }
+        assert_eq!(
+            contract.current_curve_price(),
+            U128(base_price + 3 * increment)
+        );
+    }
+
+    #[test]
+    fn test_peek_next_ids() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(0),
+            increment: U128(0),
+            step_size: None,
+        }));
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint("1".to_string(), accounts(1), sample_token_metadata(), None);
+
+        // "1" is taken out of sequence and skipped
+        let peeked = contract.peek_next_ids(3);
+        assert_eq!(peeked, vec!["2", "3", "4"]);
+        for token_id in peeked {
+            call_env(
+                &mut context,
+                storage_cost(CURVE_MINT_STORAGE_BYTES) * 2,
+                accounts(2),
+            );
+            assert_eq!(contract.nft_mint_curve().token_id, token_id);
+        }
+        assert!(contract.peek_next_ids(0).is_empty());
+    }
+
+    #[test]
+    fn test_mint_curve_stepwise() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(1000),
+            increment: U128(500),
+            step_size: Some(2),
+        }));
+
+        let mut prices = vec![];
+        for _ in 0..5 {
+            prices.push(contract.current_curve_price().0);
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(2000 + storage_cost(CURVE_MINT_STORAGE_BYTES))
+                .predecessor_account_id(accounts(1))
+                .build());
+            contract.nft_mint_curve();
+        }
+        assert_eq!(prices, vec![1000, 1000, 1500, 1500, 2000]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Must attach 10290000000000000000010 yoctoNEAR to cover price and storage"
+    )]
+    fn test_mint_curve_underpaid() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(10),
+            increment: U128(1),
+            step_size: None,
+        }));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(CURVE_MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_mint_curve();
+    }
+
+    #[test]
+    fn test_airdrop_events_grouped_per_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 3)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let drops = [(accounts(1), "0"), (accounts(2), "1"), (accounts(1), "2")]
+            .into_iter()
+            .map(|(receiver_id, token_id)| airdrop::Airdrop {
+                token_id: token_id.into(),
+                receiver_id,
+                token_metadata: sample_token_metadata(),
+            })
+            .collect();
+        contract.nft_airdrop(drops);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.2.0","event":"nft_mint","data":[{"owner_id":"bob","token_ids":["0","2"]},{"owner_id":"charlie","token_ids":["1"]}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_airdrop_events_chunked() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 400)
+            .predecessor_account_id(accounts(0))
+            .build());
+        // long token IDs make the 30 mints exceed a single log line,
+        // the total length of logs is capped by the runtime separately
+        let mut config = near_sdk::VMConfig::test();
+        config.limit_config.max_total_log_length = 64 * 1024;
+        testing_env!(context.build(), config);
+        let drops: Vec<airdrop::Airdrop> = (0..30)
+            .map(|i| airdrop::Airdrop {
+                token_id: format!("{:0>1000}", i),
+                receiver_id: format!("recipient-{}.near", i).parse().unwrap(),
+                token_metadata: sample_token_metadata(),
+            })
+            .collect();
+        contract.nft_airdrop(drops.clone());
+
+        let logs = get_logs();
+        assert_eq!(logs.len(), 2);
+        let mut logged = vec![];
+        for log in logs {
+            assert!(log.len() <= 16 * 1024, "{}", log.len());
+            let event: near_sdk::serde_json::Value =
+                near_sdk::serde_json::from_str(log.strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+            assert_eq!(event["event"], "nft_mint");
+            for entry in event["data"].as_array().unwrap() {
+                for token_id in entry["token_ids"].as_array().unwrap() {
+                    logged.push((
+                        entry["owner_id"].as_str().unwrap().to_string(),
+                        token_id.as_str().unwrap().to_string(),
+                    ));
+                }
+            }
+        }
+        let expected: Vec<(String, String)> = drops
+            .into_iter()
+            .map(|drop| (drop.receiver_id.to_string(), drop.token_id))
+            .collect();
+        assert_eq!(logged, expected);
+    }
+
+    #[test]
+    fn test_create_collection() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        let deposit = 5_000_000_000_000_000_000_000_000;
+        testing_env!(context
+            .attached_deposit(deposit)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let wasm = b"\0asm mock collection".to_vec();
+        let collection_id =
+            contract.nft_create_collection("kyiv".into(), "KYIV".into(), 100, wasm.clone().into());
+        assert_eq!(collection_id.as_str(), "kyiv.alice");
+
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, collection_id);
+        let actions = &receipts[0].actions;
+        assert_eq!(actions.len(), 5);
+        assert_eq!(actions[0], VmAction::CreateAccount);
+        assert_eq!(actions[1], VmAction::Transfer { deposit });
+        assert_eq!(actions[2], VmAction::DeployContract { code: wasm });
+        // the collection is initialized by the factory, which then hands it over to alice
+        match &actions[3] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                deposit,
+                ..
+            } => {
+                assert_eq!(function_name, "new");
+                assert!(args.is_empty());
+                assert_eq!(*deposit, 0);
+            }
+            action => panic!("unexpected action {:?}", action),
+        }
+        match &actions[4] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                deposit,
+                ..
+            } => {
+                assert_eq!(function_name, "nft_transfer_ownership_of_contract");
+                assert_eq!(*deposit, 1);
+                assert_eq!(
+                    near_sdk::serde_json::from_slice::<near_sdk::serde_json::Value>(args).unwrap(),
+                    near_sdk::serde_json::json!({ "new_owner": "alice" })
+                );
+            }
+            action => panic!("unexpected action {:?}", action),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Collection code exceeds 2 MB")]
+    fn test_create_collection_wasm_too_large() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.nft_create_collection(
+            "kyiv".into(),
+            "KYIV".into(),
+            100,
+            vec![0; 2 * 1024 * 1024 + 1].into(),
+        );
+    }
+
+    #[test]
+    fn test_conditional_approval_min_price() {
+        let mut context = get_context(accounts(0));
+        let mut contract = setup_conditional_approval(
+            &mut context,
+            approval::ApprovalConditions {
+                min_price: Some(U128(1000)),
+                expires_at: None,
+                max_uses: None,
+            },
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let payout = contract.nft_transfer_payout(
+            accounts(3),
+            "0".to_string(),
+            Some(1),
+            None,
+            U128(1000),
+            Some(1),
+        );
+        assert_eq!(payout.payout, HashMap::from([(accounts(0), U128(1000))]));
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(
+            contract.nft_approval_conditions("0".to_string(), accounts(2)),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Approval requires a price of at least 1000")]
+    fn test_conditional_approval_below_min_price() {
+        let mut context = get_context(accounts(0));
+        let mut contract = setup_conditional_approval(
+            &mut context,
+            approval::ApprovalConditions {
+                min_price: Some(U128(1000)),
+                expires_at: None,
+                max_uses: None,
+            },
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_transfer_payout(accounts(3), "0".to_string(), None, None, U128(999), None);
+    }
+
+    #[test]
+    fn test_conditional_approval_expires_at() {
+        let mut context = get_context(accounts(0));
+        context.block_timestamp(50_000_000_000);
+        let contract = setup_conditional_approval(
+            &mut context,
+            approval::ApprovalConditions {
+                min_price: None,
+                expires_at: Some(100),
+                max_uses: None,
+            },
+        );
+        assert!(contract.nft_is_approved("0".to_string(), accounts(2), Some(1)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(100_000_000_000)
+            .is_view(true)
+            .build());
+        assert!(!contract.nft_is_approved("0".to_string(), accounts(2), Some(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Approval expired")]
+    fn test_conditional_approval_expired() {
+        let mut context = get_context(accounts(0));
+        let mut contract = setup_conditional_approval(
+            &mut context,
+            approval::ApprovalConditions {
+                min_price: None,
+                expires_at: Some(100),
+                max_uses: None,
+            },
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(100_000_000_000)
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_transfer(accounts(3), "0".to_string(), None, None);
+    }
+
+    #[test]
+    fn test_conditional_approval_max_uses() {
+        let mut context = get_context(accounts(0));
+        let mut contract = setup_conditional_approval(
+            &mut context,
+            approval::ApprovalConditions {
+                min_price: None,
+                expires_at: None,
+                max_uses: Some(2),
+            },
+        );
+
+        // charlie sends the token to danny who returns it, each attempt is a use
+        for uses_left in [1, 0] {
+            let approval_id = contract
+                .nft_token("0".to_string())
+                .unwrap()
+                .approved_account_ids
+                .unwrap()[&accounts(2)];
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(1)
+                .predecessor_account_id(accounts(2))
+                .build());
+            let _ = contract.nft_transfer_call(
+                accounts(3),
+                "0".to_string(),
+                None,
+                None,
+                "".to_string(),
+            );
+            testing_env!(
+                context
+                    .storage_usage(env::storage_usage())
+                    .attached_deposit(0)
+                    .predecessor_account_id(accounts(0))
+                    .build(),
+                near_sdk::VMConfig::test(),
+                near_sdk::RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![near_sdk::PromiseResult::Successful(b"true".to_vec())],
+            );
+            assert!(!contract.nft_resolve_transfer(
+                accounts(0),
+                accounts(3),
+                "0".to_string(),
+                Some(HashMap::from([(accounts(2), approval_id)])),
+            ));
+
+            assert_eq!(
+                contract.nft_token("0".to_string()).unwrap().owner_id,
+                accounts(0)
+            );
+            assert_eq!(
+                contract.nft_is_approved("0".to_string(), accounts(2), None),
+                uses_left > 0
+            );
+            assert_eq!(
+                contract
+                    .nft_approval_conditions("0".to_string(), accounts(2))
+                    .map(|conditions| conditions.max_uses),
+                Some(Some(uses_left)).filter(|_| uses_left > 0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_conditional_approval_combined() {
+        let mut context = get_context(accounts(0));
+        context.block_timestamp(50_000_000_000);
+        let mut contract = setup_conditional_approval(
+            &mut context,
+            approval::ApprovalConditions {
+                min_price: Some(U128(1000)),
+                expires_at: Some(100),
+                max_uses: Some(1),
+            },
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let initial_balance = env::account_balance();
+        contract.nft_transfer_payout(
+            accounts(3),
+            "0".to_string(),
+            Some(1),
+            None,
+            U128(1500),
+            None,
+        );
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(
+            contract.nft_approval_conditions("0".to_string(), accounts(2)),
+            None
+        );
+        // the cleared approval and its conditions are refunded to alice
+        assert!(
+            initial_balance - env::account_balance()
+                > storage_cost(crate::storage::approvals_record_bytes(
+                    &"0".to_string(),
+                    &HashMap::from([(accounts(2), 1u64)]),
+                ))
+        );
+    }
+
+    #[test]
+    fn test_charity_account_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::CharityAccountUpdateLog {
+                charity_account_id: Some(accounts(4)),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"charity_account_update","data":[{"charity_account_id":"eugene"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_donation_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::DonationLog {
+                donor_id: accounts(1),
+                amount: U128(1000),
+                context: donation::DonationContext::Mint,
+                matched: None,
+                total_donated: U128(3000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"donation","data":[{"donor_id":"bob","amount":"1000","context":"mint","total_donated":"3000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_donation_failed_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::DonationFailedLog {
+                index: U64(3),
+                donor_id: accounts(1),
+                amount: U128(1000),
+                context: donation::DonationContext::Royalty,
+                total_donated: U128(2000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"donation_failed","data":[{"index":"3","donor_id":"bob","amount":"1000","context":"royalty","total_donated":"2000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_donations() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(1000),
+            increment: U128(0),
+            step_size: None,
+        }));
+
+        // the curve mint price is donated
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1000 + storage_cost(CURVE_MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_mint_curve();
+        assert_eq!(contract.total_donated(), U128(1000));
+        assert!(get_logs().contains(
+            &r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"donation","data":[{"donor_id":"bob","amount":"1000","context":"mint","total_donated":"1000"}]}"#.to_string()
+        ));
+
+        // so is the charity's royalty share of a sale
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES) * 2)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            "royalty".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(4), 1000)])),
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.list_for_sale("royalty".to_string(), U128(5000));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(5000)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.buy("royalty".to_string());
+        assert_eq!(contract.total_donated(), U128(1500));
+        assert!(get_logs().contains(
+            &r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"donation","data":[{"donor_id":"charlie","amount":"500","context":"royalty","total_donated":"1500"}]}"#.to_string()
+        ));
+    }
+
+    #[test]
+    fn test_charity_change_grace_period() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(1_000_000_000_000).build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_mint_curve(Some(MintCurve {
+            base_price: U128(1000),
+            increment: U128(0),
+            step_size: None,
+        }));
+
+        // the change is announced, not made
+        contract.set_charity_account(Some(accounts(3)));
+        let effective_at = U64(1000 + donation::CHARITY_CHANGE_DELAY);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            &format!(
+                r#"EVENT_JSON:{{"standard":"uamag","version":"1.0.0","event":"charity_change_scheduled","data":[{{"charity_account_id":"danny","effective_at":"{}"}}]}}"#,
+                effective_at.0
+            )
+        );
+        assert_eq!(
+            contract.charity_account(),
+            donation::CharityAccount {
+                charity_account_id: Some(accounts(4)),
+                pending_change: Some(donation::PendingCharityChange {
+                    charity_account_id: Some(accounts(3)),
+                    effective_at,
+                }),
+            }
+        );
+
+        // donations keep going to the old account during the grace period
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1000 + storage_cost(CURVE_MINT_STORAGE_BYTES) * 2)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp((effective_at.0 - 1) * 1_000_000_000)
+            .build());
+        contract.nft_mint_curve();
+        assert!(payments().contains(&(accounts(0), accounts(4), 1000)));
+        assert!(!logged_events().contains(&"charity_change_executed".to_string()));
+
+        // and to the new one once it's over, the first donation executes the change
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(effective_at.0 * 1_000_000_000)
+            .build());
+        assert_eq!(
+            contract.charity_account(),
+            donation::CharityAccount {
+                charity_account_id: Some(accounts(3)),
+                pending_change: None,
+            }
+        );
+        contract.nft_mint_curve();
+        assert!(payments().contains(&(accounts(0), accounts(3), 1000)));
+        assert!(!payments().contains(&(accounts(0), accounts(4), 1000)));
+        assert_eq!(
+            logged_events(),
+            vec![
+                "charity_change_executed",
+                "donation",
+                "nft_mint",
+                "mint_receipt"
+            ]
+        );
+        assert_eq!(contract.total_donated(), U128(2000));
+    }
+
+    #[test]
+    fn test_execute_charity_change() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_charity_account(None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(donation::CHARITY_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.execute_charity_change();
+        assert_eq!(logged_events(), vec!["charity_change_executed"]);
+        assert_eq!(
+            contract.charity_account(),
+            donation::CharityAccount {
+                charity_account_id: None,
+                pending_change: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cancel_charity_change() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_charity_account(Some(accounts(3)));
+        contract.set_charity_account(Some(accounts(4)));
+        assert_eq!(contract.charity_account().pending_change, None);
+
+        testing_env!(context
+            .block_timestamp(donation::CHARITY_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        assert_eq!(
+            contract.charity_account().charity_account_id,
+            Some(accounts(4))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Charity change is not due yet")]
+    fn test_execute_charity_change_early() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_charity_account(Some(accounts(3)));
+
+        testing_env!(context
+            .block_timestamp((donation::CHARITY_CHANGE_DELAY - 1) * 1_000_000_000)
+            .build());
+        contract.execute_charity_change();
+    }
+
+    #[test]
+    fn test_charity_change_logs_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::CharityChangeScheduledLog {
+                charity_account_id: Some(accounts(3)),
+                effective_at: U64(172800),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"charity_change_scheduled","data":[{"charity_account_id":"danny","effective_at":"172800"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::CharityChangeExecutedLog {
+                previous_account_id: Some(accounts(4)),
+                charity_account_id: None,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"charity_change_executed","data":[{"previous_account_id":"eugene","charity_account_id":null}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_donation_callback() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.internal_donate(accounts(1), 1000, donation::DonationContext::Mint);
+        contract.internal_donate(accounts(2), 500, donation::DonationContext::Royalty);
+        assert_eq!(contract.total_donated(), U128(1500));
+
+        // a successful transfer keeps the total
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        contract.on_charity_transfer(accounts(1), U128(1000), donation::DonationContext::Mint);
+        assert_eq!(contract.total_donated(), U128(1500));
+        assert!(get_logs().is_empty());
+
+        // a failed one is taken out of it
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        contract.on_charity_transfer(accounts(2), U128(500), donation::DonationContext::Royalty);
+        assert_eq!(contract.total_donated(), U128(1000));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"donation_failed","data":[{"index":"1","donor_id":"charlie","amount":"500","context":"royalty","total_donated":"1000"}]}"#
+            ]
+        );
+
+        // both outcomes are in the ledger
+        let ledger = contract.charity_ledger(None, None);
+        assert_eq!(ledger.len(), 2);
+        assert!(ledger[0].success);
+        assert_eq!(ledger[0].amount, U128(1000));
+        assert_eq!(ledger[0].context, donation::DonationContext::Mint);
+        assert!(!ledger[1].success);
+        assert_eq!(ledger[1].donor_id, accounts(2));
+        assert_eq!(ledger[1].amount, U128(500));
+        assert_eq!(ledger[1].context, donation::DonationContext::Royalty);
+        assert_eq!(
+            contract.charity_ledger(Some(U64(1)), Some(1)),
+            ledger[1..].to_vec()
+        );
+        assert_eq!(
+            contract.charity_totals(),
+            donation::CharityTotals {
+                sent: U128(1000),
+                failed: U128(500),
+            }
+        );
+    }
+
+    #[test]
+    fn test_top_donors_boundary() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        let donor = |i: usize| -> AccountId { format!("donor{}.near", i).parse().unwrap() };
+        let arrived = |context: &mut VMContextBuilder, result| {
+            testing_env!(
+                context.predecessor_account_id(accounts(0)).build(),
+                near_sdk::VMConfig::test(),
+                near_sdk::RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![result],
+            );
+        };
+
+        // 100 donors of 1000 to 1099 fill the leaderboard
+        arrived(&mut context, near_sdk::PromiseResult::Successful(vec![]));
+        for i in 0..donors::MAX_TOP_DONORS {
+            contract.on_charity_transfer(
+                donor(i),
+                U128(1000 + i as u128),
+                donation::DonationContext::Mint,
+            );
+        }
+        assert_eq!(contract.top_donors(None).len(), 100);
+        assert_eq!(
+            contract.top_donors(Some(2)),
+            vec![(donor(99), U128(1099)), (donor(98), U128(1098))]
+        );
+        assert_eq!(contract.donor_rank(donor(99)), Some(1));
+        assert_eq!(contract.donor_rank(donor(0)), Some(100));
+
+        // a donor below the last place stays out
+        let late: AccountId = "late.near".parse().unwrap();
+        contract.on_charity_transfer(late.clone(), U128(500), donation::DonationContext::Mint);
+        assert_eq!(contract.donated_by(late.clone()), U128(500));
+        assert_eq!(contract.donor_rank(late.clone()), None);
+
+        // and gets in by donating more, after donor50 who donated as much earlier,
+        // pushing the last place out
+        contract.on_charity_transfer(late.clone(), U128(550), donation::DonationContext::Royalty);
+        assert_eq!(contract.donor_rank(late.clone()), Some(51));
+        assert_eq!(contract.donor_rank(donor(50)), Some(50));
+        assert_eq!(contract.donor_rank(donor(0)), None);
+        assert_eq!(contract.donated_by(donor(0)), U128(1000));
+        assert_eq!(contract.top_donors(None).len(), 100);
+
+        // a donation that didn't arrive isn't credited
+        contract.internal_donate(donor(0), 1000, donation::DonationContext::Mint);
+        arrived(&mut context, near_sdk::PromiseResult::Failed);
+        contract.on_charity_transfer(donor(0), U128(1000), donation::DonationContext::Mint);
+        assert_eq!(contract.donated_by(donor(0)), U128(1000));
+        assert_eq!(contract.donor_rank(donor(0)), None);
+
+        // the one it climbed back in with is
+        arrived(&mut context, near_sdk::PromiseResult::Successful(vec![]));
+        contract.on_charity_transfer(donor(0), U128(1000), donation::DonationContext::Mint);
+        assert_eq!(contract.donor_rank(donor(0)), Some(1));
+        assert_eq!(contract.donor_rank(donor(1)), None);
+    }
+
+    /// Contract with charity dave and a campaign of sponsor.near matching up to 1500
+    /// until second 100
+    fn contract_with_matching_campaign(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        call_env(context, 1500, "sponsor.near".parse().unwrap());
+        contract.start_matching_campaign("sponsor.near".parse().unwrap(), U128(1500), U64(100));
+        contract
+    }
+
+    #[test]
+    fn test_matching_campaign_at_cap() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_matching_campaign(&mut context);
+        let sponsor: AccountId = "sponsor.near".parse().unwrap();
+
+        call_env(&mut context, 0, accounts(0));
+        contract.internal_donate(accounts(1), 1000, donation::DonationContext::Mint);
+        assert_eq!(
+            payments(),
+            vec![
+                (accounts(0), accounts(4), 1000),
+                (accounts(0), accounts(4), 1000)
+            ]
+        );
+
+        // only what is left of the cap is matched
+        call_env(&mut context, 0, accounts(0));
+        contract.internal_donate(accounts(2), 800, donation::DonationContext::Royalty);
+        assert_eq!(
+            payments(),
+            vec![
+                (accounts(0), accounts(4), 800),
+                (accounts(0), accounts(4), 500)
+            ]
+        );
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"donation","data":[{"donor_id":"charlie","amount":"800","context":"royalty","matched":"500","total_donated":"3300"}]}"#
+            ]
+        );
+        assert_eq!(contract.matching_campaign().unwrap().matched, U128(1500));
+
+        call_env(&mut context, 0, accounts(0));
+        contract.internal_donate(accounts(1), 100, donation::DonationContext::Mint);
+        assert_eq!(payments(), vec![(accounts(0), accounts(4), 100)]);
+        assert_eq!(contract.total_donated(), U128(3400));
+
+        // an exhausted campaign can be closed by anyone, nothing is left to refund
+        call_env(&mut context, 0, accounts(3));
+        contract.close_matching_campaign();
+        assert_eq!(payments(), vec![]);
+        assert_eq!(contract.matching_campaign(), None);
+
+        // the sponsor's matches count as its donations once they arrive
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        contract.on_charity_transfer(
+            sponsor.clone(),
+            U128(500),
+            donation::DonationContext::Matching,
+        );
+        assert_eq!(contract.donated_by(sponsor), U128(500));
+    }
+
+    #[test]
+    fn test_matching_campaign_refund() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_matching_campaign(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.internal_donate(accounts(1), 1000, donation::DonationContext::Mint);
+
+        testing_env!(context
+            .block_timestamp(100 * 1_000_000_000)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.internal_donate(accounts(1), 1000, donation::DonationContext::Mint);
+        assert_eq!(payments(), vec![(accounts(0), accounts(4), 1000)]);
+
+        call_env(&mut context, 0, accounts(3));
+        contract.close_matching_campaign();
+        assert_eq!(
+            payments(),
+            vec![(accounts(0), "sponsor.near".parse().unwrap(), 500)]
+        );
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"matching_campaign_close","data":[{"sponsor":"sponsor.near","matched":"1000","refunded":"500"}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Matching campaign is still running")]
+    fn test_close_running_matching_campaign() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_matching_campaign(&mut context);
+        call_env(&mut context, 0, accounts(3));
+        contract.close_matching_campaign();
+    }
+
+    #[test]
+    fn test_matching_campaign_start_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::MatchingCampaignStartLog {
+                sponsor: "sponsor.near".parse().unwrap(),
+                cap: U128(1500),
+                ends_at: U64(100),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"matching_campaign_start","data":[{"sponsor":"sponsor.near","cap":"1500","ends_at":"100"}]}"#,
+        );
+    }
+
+    /// Contract with charity dave and a failed transfer of 500 donated by charlie at index 0
+    fn contract_with_failed_charity_transfer(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.internal_donate(accounts(2), 500, donation::DonationContext::Royalty);
+        testing_env!(
+            context.build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        contract.on_charity_transfer(accounts(2), U128(500), donation::DonationContext::Royalty);
+        contract
+    }
+
+    #[test]
+    fn test_retry_charity_transfer() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_failed_charity_transfer(&mut context);
+        assert_eq!(contract.total_donated(), U128(0));
+
+        // anyone may retry
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.retry_charity_transfer(U64(0));
+        assert!(contract.charity_ledger(None, None)[0].retry_pending);
+
+        // a failed retry can be retried again
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        contract.on_charity_retry(U64(0));
+        let transfer = &contract.charity_ledger(None, None)[0];
+        assert!(!transfer.success && !transfer.retry_pending);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.retry_charity_transfer(U64(0));
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        assert_eq!(contract.donated_by(accounts(2)), U128(0));
+        contract.on_charity_retry(U64(0));
+        let transfer = &contract.charity_ledger(None, None)[0];
+        assert!(transfer.success && !transfer.retry_pending);
+        assert_eq!(contract.donated_by(accounts(2)), U128(500));
+        assert_eq!(contract.donor_rank(accounts(2)), Some(1));
+        assert_eq!(
+            contract.charity_totals(),
+            donation::CharityTotals {
+                sent: U128(500),
+                failed: U128(0),
+            }
+        );
+        assert_eq!(contract.total_donated(), U128(500));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"charity_retry","data":[{"index":"0","amount":"500","success":true}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Charity transfer succeeded")]
+    fn test_retry_succeeded_charity_transfer() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.internal_donate(accounts(1), 1000, donation::DonationContext::Mint);
+        testing_env!(
+            context.build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        contract.on_charity_transfer(accounts(1), U128(1000), donation::DonationContext::Mint);
+        testing_env!(context.build());
+        contract.retry_charity_transfer(U64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Retry is already pending")]
+    fn test_retry_pending_charity_transfer() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_failed_charity_transfer(&mut context);
+        testing_env!(context.build());
+        contract.retry_charity_transfer(U64(0));
+        contract.retry_charity_transfer(U64(0));
+    }
+
+    #[test]
+    fn test_charity_retry_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::CharityRetryLog {
+                index: U64(3),
+                amount: U128(500),
+                success: None,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"charity_retry","data":[{"index":"3","amount":"500","success":null}]}"#,
+        );
+    }
+
+    /// Token "0" of bob paying charlie 10% royalties sold to danny by market.near
+    fn contract_with_ft_payout_sale(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_royalty_listing(context, None);
+        call_env(context, 0, accounts(0));
+        contract.set_payout_market("market.near".parse().unwrap(), true);
+        contract.set_payout_ft_token("wnear.near".parse().unwrap(), true);
+        call_env(
+            context,
+            storage_cost(MARKETPLACE_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        let _ = contract.nft_approve("0".to_string(), "market.near".parse().unwrap(), None);
+        call_env(context, 1, "market.near".parse().unwrap());
+        contract.nft_transfer_payout(
+            accounts(3),
+            "0".to_string(),
+            None,
+            None,
+            U128(1000),
+            Some(10),
+        );
+        contract
+    }
+
+    /// `ft_on_transfer` of `amount` wnear.near sent by market.near to pay out the sale of "0"
+    fn receive_ft_payout(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        amount: Balance,
+    ) -> U128 {
+        receive_ft_payout_of(context, contract, "wnear.near".parse().unwrap(), amount)
+    }
+
+    /// `ft_on_transfer` of `amount` of `ft_contract` claiming to be sent by market.near
+    /// to pay out the sale of "0"
+    fn receive_ft_payout_of(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        ft_contract: AccountId,
+        amount: Balance,
+    ) -> U128 {
+        call_env(context, 0, ft_contract);
+        match contract.ft_on_transfer(
+            "market.near".parse().unwrap(),
+            U128(amount),
+            r#"{"distribute_payout":{"token_id":"0"}}"#.to_string(),
+        ) {
+            PromiseOrValue::Value(unspent) => unspent,
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+    }
+
+    /// The sale of `contract_with_ft_payout_sale` paid out with 1000 wnear.near
+    fn contract_with_ft_payout(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_ft_payout_sale(context);
+        assert_eq!(receive_ft_payout(context, &mut contract, 1000), U128(0));
+        contract
+    }
+
+    #[test]
+    fn test_distribute_ft_payout() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_ft_payout(&mut context);
+        assert_eq!(
+            contract.payout_markets(),
+            vec!["market.near".parse::<AccountId>().unwrap()]
+        );
+        let mut paid = payments();
+        paid.sort();
+        let wnear: AccountId = "wnear.near".parse().unwrap();
+        assert_eq!(
+            paid,
+            vec![
+                (wnear.clone(), accounts(1), 900),
+                (wnear.clone(), accounts(2), 100)
+            ]
+        );
+
+        // the share of charlie fails and becomes claimable, the one of bob goes through
+        for (account_id, amount, result) in [
+            (
+                accounts(1),
+                900,
+                near_sdk::PromiseResult::Successful(vec![]),
+            ),
+            (accounts(2), 100, near_sdk::PromiseResult::Failed),
+        ] {
+            testing_env!(
+                context.predecessor_account_id(accounts(0)).build(),
+                near_sdk::VMConfig::test(),
+                near_sdk::RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![result],
+            );
+            contract.on_ft_payout(account_id, wnear.clone(), U128(amount));
+        }
+        assert_eq!(contract.ft_claimable(accounts(1), wnear.clone()), U128(0));
+        assert_eq!(contract.ft_claimable(accounts(2), wnear.clone()), U128(100));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"ft_payout_failed","data":[{"account_id":"charlie","ft_contract":"wnear.near","amount":"100"}]}"#
+            ]
+        );
+
+        call_env(&mut context, 1, accounts(2));
+        assert_eq!(contract.claim_ft_payout(wnear.clone()), U128(100));
+        assert_eq!(payments(), vec![(wnear.clone(), accounts(2), 100)]);
+        assert_eq!(contract.ft_claimable(accounts(2), wnear.clone()), U128(0));
+
+        // a failed claim is claimable again
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        contract.on_ft_payout(accounts(2), wnear.clone(), U128(100));
+        assert_eq!(contract.ft_claimable(accounts(2), wnear), U128(100));
+    }
+
+    #[test]
+    fn test_ft_payout_paid_once() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_ft_payout(&mut context);
+        // the sale is paid out already, the FT goes back to the market
+        assert_eq!(
+            receive_ft_payout(&mut context, &mut contract, 1000),
+            U128(1000)
+        );
+        assert!(payments().is_empty());
+    }
+
+    #[test]
+    fn test_ft_payout_from_other_ft() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_ft_payout_sale(&mut context);
+        assert_eq!(
+            contract.payout_ft_tokens(),
+            vec!["wnear.near".parse::<AccountId>().unwrap()]
+        );
+        // any contract may forge the market as sender, its FT is returned and the sale kept
+        assert_eq!(
+            receive_ft_payout_of(
+                &mut context,
+                &mut contract,
+                "fake.near".parse().unwrap(),
+                1000
+            ),
+            U128(1000)
+        );
+        assert!(payments().is_empty());
+        assert!(logged_events().is_empty());
+
+        assert_eq!(
+            receive_ft_payout(&mut context, &mut contract, 1000),
+            U128(0)
+        );
+        assert_eq!(payments().len(), 2);
+    }
+
+    #[test]
+    fn test_payout_ft_token_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::PayoutFtTokenUpdateLog {
+                ft_contract: "wnear.near".parse().unwrap(),
+                accepted: true,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"payout_ft_token_update","data":[{"ft_contract":"wnear.near","accepted":true}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_ft_payout_without_sale() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_royalty_listing(&mut context, None);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_payout_market("market.near".parse().unwrap(), true);
+        contract.set_payout_ft_token("wnear.near".parse().unwrap(), true);
+        // the token was never sold by the market, nor has it a previous owner
+        assert_eq!(
+            receive_ft_payout(&mut context, &mut contract, 1000),
+            U128(1000)
+        );
+        assert!(payments().is_empty());
+    }
+
+    #[test]
+    fn test_ft_payout_logs_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::FtPayoutDistributeLog {
+                token_id: "0".into(),
+                seller_id: accounts(1),
+                ft_contract: "wnear.near".parse().unwrap(),
+                amount: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"ft_payout_distribute","data":[{"token_id":"0","seller_id":"bob","ft_contract":"wnear.near","amount":"1000"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::FtPayoutClaimLog {
+                account_id: accounts(2),
+                ft_contract: "wnear.near".parse().unwrap(),
+                amount: U128(100),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"ft_payout_claim","data":[{"account_id":"charlie","ft_contract":"wnear.near","amount":"100"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::PayoutMarketUpdateLog {
+                market_id: "market.near".parse().unwrap(),
+                allowed: true,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"payout_market_update","data":[{"market_id":"market.near","allowed":true}]}"#,
+        );
+    }
+
+    /// Contract with charity dave that received a donation of 1000 usdc.near by charlie,
+    /// resolved with the outcome of forwarding it. Minted tokens are approved for danny
+    /// and capped at 5000 yoctoNEAR by default
+    fn contract_with_ft_donation(
+        context: &mut VMContextBuilder,
+        forwarded: near_sdk::PromiseResult,
+    ) -> (Contract, U128) {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_donation_ft_token("usdc.near".parse().unwrap(), true);
+        contract.set_default_marketplace(Some(accounts(3)));
+        contract.set_default_max_resale_price(Some(U128(5000)));
+        call_env(context, 0, "usdc.near".parse().unwrap());
+        let donation = contract.ft_on_transfer(accounts(2), U128(1000), "donate".to_string());
+        assert!(matches!(donation, PromiseOrValue::Promise(_)));
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![forwarded],
+        );
+        let refunded =
+            contract.on_ft_donation(accounts(2), "usdc.near".parse().unwrap(), U128(1000));
+        (contract, refunded)
+    }
+
+    #[test]
+    fn test_ft_donation_mints_badge() {
+        let mut context = get_context(accounts(0));
+        let (contract, refunded) =
+            contract_with_ft_donation(&mut context, near_sdk::PromiseResult::Successful(vec![]));
+        assert_eq!(refunded, U128(0));
+        assert_eq!(
+            contract.total_donated_ft("usdc.near".parse().unwrap()),
+            U128(1000)
+        );
+        assert_eq!(
+            contract.donated_ft_by(accounts(2), "usdc.near".parse().unwrap()),
+            U128(1000)
+        );
+        assert!(contract.top_donors(None).is_empty());
+
+        let badge = contract.nft_token("donation-0".to_string()).unwrap();
+        assert_eq!(badge.owner_id, accounts(2));
+        assert_eq!(
+            badge.metadata.unwrap().extra.as_deref(),
+            Some(r#"{"amount":"1000","ft_token_id":"usdc.near"}"#)
+        );
+        assert!(!contract.nft_is_transferable("donation-0".to_string()));
+        // badges are never sold
+        assert_eq!(badge.approved_account_ids, Some(HashMap::new()));
+        assert_eq!(
+            contract.nft_max_resale_price("donation-0".to_string()),
+            None
+        );
+        assert_eq!(logged_events(), vec!["nft_mint", "ft_donation"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "FT fake.near is not accepted for donations")]
+    fn test_ft_donation_of_unaccepted_ft() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_charity_account(Some(accounts(4)));
+        contract.set_donation_ft_token("usdc.near".parse().unwrap(), true);
+        assert_eq!(
+            contract.donation_ft_tokens(),
+            vec!["usdc.near".parse::<AccountId>().unwrap()]
+        );
+        call_env(&mut context, 0, "fake.near".parse().unwrap());
+        let _ = contract.ft_on_transfer(accounts(2), U128(1000), "donate".to_string());
+    }
+
+    #[test]
+    fn test_ft_donation_forwarding_failed() {
+        let mut context = get_context(accounts(0));
+        let (contract, refunded) =
+            contract_with_ft_donation(&mut context, near_sdk::PromiseResult::Failed);
+        // the FT contract returns the whole amount to the donor
+        assert_eq!(refunded, U128(1000));
+        assert_eq!(
+            contract.total_donated_ft("usdc.near".parse().unwrap()),
+            U128(0)
+        );
+        assert!(contract.nft_token("donation-0".to_string()).is_none());
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"ft_donation_failed","data":[{"donor_id":"charlie","ft_token_id":"usdc.near","amount":"1000"}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is soulbound")]
+    fn test_transfer_donation_badge() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, _) =
+            contract_with_ft_donation(&mut context, near_sdk::PromiseResult::Successful(vec![]));
+        call_env(&mut context, 1, accounts(2));
+        contract.nft_transfer(accounts(3), "donation-0".to_string(), None, None);
+    }
+
+    #[test]
+    fn test_ft_donation_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::FtDonationLog {
+                donor_id: accounts(2),
+                ft_token_id: "usdc.near".parse().unwrap(),
+                amount: U128(1000),
+                badge_id: "donation-0".into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"ft_donation","data":[{"donor_id":"charlie","ft_token_id":"usdc.near","amount":"1000","badge_id":"donation-0"}]}"#,
+        );
+        assert_event_round_trip(
+            event_log(vec![events::DonationFtTokenUpdateLog {
+                ft_token_id: "usdc.near".parse().unwrap(),
+                accepted: true,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"donation_ft_token_update","data":[{"ft_token_id":"usdc.near","accepted":true}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_refund_callback() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        // a successful refund isn't revenue
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        contract.on_refund_complete(accounts(1), U128(1000));
+        assert_eq!(contract.total_revenue(), U128(0));
+        assert!(get_logs().is_empty());
+
+        // a failed one stays on the contract
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        contract.on_refund_complete(accounts(1), U128(1000));
+        contract.on_refund_complete(accounts(2), U128(500));
+        assert_eq!(contract.total_revenue(), U128(1500));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"refund_failed","data":[{"account_id":"charlie","amount":"500"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_refund_failed_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::RefundFailedLog {
+                account_id: accounts(1),
+                amount: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"refund_failed","data":[{"account_id":"bob","amount":"1000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_token_certificate() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_token(&mut context);
+        testing_env!(context.block_timestamp(1_000_000_000_000).build());
+        let cert = contract.nft_token_certificate("0".to_string());
+        assert_eq!(cert.owner, accounts(1));
+        assert_eq!(cert.contract_id, env::current_account_id());
+        assert_eq!(cert.issued_at, 1000);
+        assert_eq!(cert.digest.len(), 32);
+        assert!(contract.nft_verify_certificate(cert.clone()));
+
+        let mut tampered = cert.clone();
+        tampered.issued_at += 1;
+        assert!(!contract.nft_verify_certificate(tampered));
+        let mut tampered = cert.clone();
+        tampered.digest[0] ^= 1;
+        assert!(!contract.nft_verify_certificate(tampered));
+
+        // a certificate for another owner, even with a matching digest
+        let mut tampered = cert.clone();
+        tampered.owner = accounts(2);
+        tampered.digest = tampered.compute_digest();
+        assert!(!contract.nft_verify_certificate(tampered));
+
+        // a certificate issued by another contract
+        let mut tampered = cert;
+        tampered.contract_id = accounts(5);
+        assert!(!contract.nft_verify_certificate(tampered));
+    }
+
+    #[test]
+    fn test_token_certificate_outdated_by_transfer() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        let cert = contract.nft_token_certificate("0".to_string());
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+        assert!(!contract.nft_verify_certificate(cert));
+        let cert = contract.nft_token_certificate("0".to_string());
+        assert!(contract.nft_verify_certificate(cert));
+    }
+
+    #[test]
+    fn test_is_transferable() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_token(&mut context);
+        assert!(contract.nft_is_transferable("0".to_string()));
+        assert!(!contract.nft_is_transferable("1".to_string()));
+    }
+
+    #[test]
+    fn test_global_transfer_unlock() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_global_transfer_unlock(100);
+        assert_eq!(contract.global_transfer_unlock(), 100);
+        assert!(!contract.nft_is_transferable("0".to_string()));
+
+        // minting isn't locked
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.nft_mint("1".to_string(), accounts(1), sample_token_metadata(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(100_000_000_000)
+            .build());
+        assert!(contract.nft_is_transferable("0".to_string()));
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(2)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers locked until 100")]
+    fn test_transfer_before_global_unlock() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_global_transfer_unlock(100);
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(99_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers locked until 100")]
+    fn test_burn_before_global_unlock() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_global_transfer_unlock(100);
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(99_000_000_000)
+            .build());
+        contract.nft_burn("0".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Global transfer unlock is already set")]
+    fn test_global_transfer_unlock_set_twice() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_global_transfer_unlock(100);
+        contract.set_global_transfer_unlock(200);
+    }
+
+    #[test]
+    fn test_global_transfer_unlock_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::GlobalTransferUnlockLog { unlock_at: 100 }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"global_transfer_unlock_set","data":[{"unlock_at":100}]}"#,
+        );
+    }
+
+    /// Token "0" of bob approved for charlie, sent to danny by `nft_transfer_call`
+    fn contract_with_pending_transfer_call(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        call_env(
+            context,
+            storage_cost(MARKETPLACE_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        let _ = contract.nft_approve("0".to_string(), accounts(2), None);
+        call_env(context, 1, accounts(1));
+        let _ =
+            contract.nft_transfer_call(accounts(3), "0".to_string(), None, None, "".to_string());
+        contract
+    }
+
+    /// Call `nft_resolve_transfer` of token "0" with danny's answer to `nft_on_transfer`
+    fn resolve_pending_transfer_call(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        return_token: bool,
+    ) -> bool {
+        testing_env!(
+            context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(0)
+                .predecessor_account_id(accounts(0))
+                .prepaid_gas(nft_core::GAS_FOR_RESOLVE_TRANSFER)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(
+                return_token.to_string().into_bytes()
+            )],
+        );
+        contract.nft_resolve_transfer(accounts(1), accounts(3), "0".to_string(), None)
+    }
+
+    #[test]
+    fn test_resolve_transfer_within_gas_budget() {
+        for return_token in [false, true] {
+            near_sdk::mock::with_mocked_blockchain(|b| b.take_storage());
+            let mut context = get_context(accounts(0));
+            let mut contract = contract_with_pending_transfer_call(&mut context);
+            // host calls and the refunds scheduled are charged against the prepaid gas
+            assert_eq!(
+                resolve_pending_transfer_call(&mut context, &mut contract, return_token),
+                !return_token
+            );
+            let refunds = payments();
+            assert_eq!(refunds.len(), usize::from(!return_token));
+            assert!(refunds
+                .iter()
+                .all(|(_, account_id, _)| *account_id == accounts(1)));
+        }
+    }
+
+    #[test]
+    fn test_transfer_call_snapshots_approvals() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_pending_transfer_call(&mut context);
+        let snapshot = contract
+            .transfer_approval_snapshots
+            .get(&("0".to_string(), accounts(1)))
+            .unwrap();
+        assert!(snapshot.approved_account_ids.contains_key(&accounts(2)));
+        assert!(!contract.nft_is_approved("0".to_string(), accounts(2), None));
+
+        // the approvals deposit is held until resolution
+        assert!(payments().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_refused_transfer_restores_snapshot() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_pending_transfer_call(&mut context);
+        assert!(!resolve_pending_transfer_call(
+            &mut context,
+            &mut contract,
+            true
+        ));
+
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(1)
+        );
+        assert!(contract.nft_is_approved("0".to_string(), accounts(2), None));
+        assert!(contract
+            .transfer_approval_snapshots
+            .get(&("0".to_string(), accounts(1)))
+            .is_none());
+        // the restored approvals keep their deposit
+        assert!(payments().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_accepted_transfer_drops_snapshot() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_pending_transfer_call(&mut context);
+        assert!(resolve_pending_transfer_call(
+            &mut context,
+            &mut contract,
+            false
+        ));
+
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert!(!contract.nft_is_approved("0".to_string(), accounts(2), None));
+        assert!(contract
+            .transfer_approval_snapshots
+            .get(&("0".to_string(), accounts(1)))
+            .is_none());
+        // the cleared approvals are refunded to bob
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(1));
+    }
+
+    #[test]
+    fn test_resolve_burned_transfer_refunds_approvals() {
+        for return_token in [false, true] {
+            let mut context = get_context(accounts(0));
+            let mut contract = contract_with_pending_transfer_call(&mut context);
+            // danny burns the token in `nft_on_transfer`
+            contract.internal_burn(&"0".to_string(), &accounts(3));
+            assert!(resolve_pending_transfer_call(
+                &mut context,
+                &mut contract,
+                return_token
+            ));
+
+            // the whole approvals record is refunded, partly by the standard implementation
+            // if danny asked to return the token
+            let approvals_bytes = crate::storage::approvals_record_bytes(
+                &"0".to_string(),
+                &HashMap::from([(accounts(2), 1u64)]),
+            );
+            let refunds = payments();
+            assert!(refunds
+                .iter()
+                .all(|(_, receiver_id, _)| receiver_id == &accounts(1)));
+            assert_eq!(
+                refunds.iter().map(|(_, _, amount)| amount).sum::<Balance>(),
+                storage_cost(approvals_bytes)
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_transfer_call_more_than_one_yocto() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        call_env(
+            &mut context,
+            storage_cost(MARKETPLACE_APPROVAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        let _ = contract.nft_approve("0".to_string(), accounts(2), None);
+        call_env(&mut context, 2, accounts(1));
+        let _ =
+            contract.nft_transfer_call(accounts(3), "0".to_string(), None, None, "".to_string());
+    }
+
+    /// Names of the events logged by the last call
+    fn logged_events() -> Vec<String> {
+        get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|json| {
+                near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(json).unwrap()
+                    ["event"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// Contract owned by alice with token "0" minted to bob
+    fn contract_with_token(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(MINT_STORAGE_BYTES))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata(), None);
+        contract
+    }
+
+    /// Token "0" of bob listed for 1000 yoctoNEAR
+    fn contract_with_listing(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(LISTING_STORAGE_BYTES))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.list_for_sale("0".to_string(), U128(1000));
+        contract
+    }
+
+    fn contract_with_lazy_listing(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        let listing_id = contract.create_lazy_listing(sample_token_metadata(), U128(1000));
+        assert_eq!(listing_id, U64(0));
+        contract
+    }
+
+    #[test]
+    fn test_buy_lazy_listing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_lazy_listing(&mut context);
+        assert_eq!(contract.nft_total_supply(), U128(0));
+        assert_eq!(
+            contract.lazy_listing(U64(0)).unwrap().metadata,
+            sample_token_metadata()
+        );
+
+        call_env(
+            &mut context,
+            1000 + storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(3),
+        );
+        let token = contract.buy_lazy(U64(0));
+        assert_eq!(token.token_id, "0");
+        assert_eq!(token.owner_id, accounts(3));
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert_eq!(contract.lazy_listing(U64(0)), None);
+        assert!(payments().contains(&(accounts(0), accounts(0), 1000)));
+        assert_eq!(logged_events(), vec!["nft_mint", "mint_receipt"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Lazy listing not found")]
+    fn test_buy_lazy_listing_twice() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_lazy_listing(&mut context);
+        for _ in 0..2 {
+            call_env(
+                &mut context,
+                1000 + storage_cost(MINT_STORAGE_BYTES) * 2,
+                accounts(3),
+            );
+            contract.buy_lazy(U64(0));
+        }
+    }
+
+    #[test]
+    fn test_lazy_list_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::LazyListLog {
+                listing_id: U64(0),
+                price: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_lazy_list","data":[{"listing_id":"0","price":"1000"}]}"#,
+        );
+    }
+
+    const OFFER_STORAGE_BYTES: StorageUsage = 71;
+
+    /// Token "0" of bob listed for 1000, open to offers below it if `accept_counter_offers`
+    fn contract_with_offers_listing(
+        context: &mut VMContextBuilder,
+        accept_counter_offers: bool,
+    ) -> Contract {
+        let mut contract = contract_with_token(context);
+        call_env(context, storage_cost(LISTING_STORAGE_BYTES), accounts(1));
+        contract.nft_list_for_sale_with_offers("0".to_string(), U128(1000), accept_counter_offers);
+        contract
+    }
+
+    fn make_offer(context: &mut VMContextBuilder, contract: &mut Contract, amount: Balance) {
+        call_env(
+            context,
+            amount + storage_cost(OFFER_STORAGE_BYTES),
+            accounts(3),
+        );
+        contract.nft_make_offer("0".to_string(), U128(amount));
+    }
+
+    #[test]
+    fn test_accept_counter_offer() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_offers_listing(&mut context, true);
+        make_offer(&mut context, &mut contract, 600);
+        assert_eq!(
+            contract.nft_offer("0".to_string(), accounts(3)),
+            Some(U128(600))
+        );
+        assert!(payments().is_empty());
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_accept_offer("0".to_string(), accounts(3));
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(contract.nft_offer("0".to_string(), accounts(3)), None);
+        assert!(payments().contains(&(accounts(0), accounts(1), 600)));
+        assert_eq!(
+            logged_events(),
+            vec!["nft_delist", "nft_transfer", "nft_sale"]
+        );
+    }
+
+    #[test]
+    fn test_tokens_by_price_range() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_max_daily_mints_per_account(20);
+        let price = |i: u128| (i * 7 % 20 + 1) * 1000;
+        for i in 0..20 {
+            call_env(
+                &mut context,
+                storage_cost(MINT_STORAGE_BYTES) * 2,
+                accounts(0),
+            );
+            contract.nft_mint(i.to_string(), accounts(1), sample_token_metadata(), None);
+            call_env(
+                &mut context,
+                storage_cost(LISTING_STORAGE_BYTES),
+                accounts(1),
+            );
+            contract.list_for_sale(i.to_string(), U128(price(i)));
+        }
+        // delisted and FT priced tokens aren't in range
+        call_env(&mut context, 1, accounts(1));
+        contract.delist("3".to_string());
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.list_for_sale_ft("4".to_string(), U128(6000), "usdc.near".parse().unwrap());
+
+        let in_range = contract.nft_tokens_by_price_range(U128(5000), U128(12000), None, None);
+        let prices: Vec<u128> = in_range.iter().map(|(_, price)| price.0).collect();
+        let mut expected: Vec<u128> = (0..20)
+            .filter(|i| *i != 3 && *i != 4)
+            .map(price)
+            .filter(|price| (5000..=12000).contains(price))
+            .collect();
+        expected.sort();
+        assert_eq!(prices, expected);
+        for (token, listed_price) in &in_range {
+            let i: u128 = token.token_id.parse().unwrap();
+            assert_eq!(price(i), listed_price.0);
+        }
+
+        let page =
+            contract.nft_tokens_by_price_range(U128(5000), U128(12000), Some(U128(2)), Some(3));
+        assert_eq!(
+            page.iter().map(|(_, price)| price.0).collect::<Vec<_>>(),
+            expected[2..5]
+        );
+    }
+
+    #[test]
+    fn test_accept_offer_distributes_royalty_escrow() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        contract.set_platform_fee(250);
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_mint(
+            "0".to_string(),
+            accounts(1),
+            sample_token_metadata(),
+            Some(HashMap::from([(accounts(2), 1000)])),
+        );
+        call_env(
+            &mut context,
+            storage_cost(LISTING_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.nft_list_for_sale_with_offers("0".to_string(), U128(1000), false);
+        make_offer(&mut context, &mut contract, 2000);
+
+        let preview = contract.nft_royalty_escrow_preview("0".to_string(), U128(2000));
+        assert_eq!(
+            preview,
+            HashMap::from([
+                (accounts(0), U128(50)),
+                (accounts(2), U128(195)),
+                (accounts(1), U128(1755)),
+            ])
+        );
+
+        call_env(&mut context, 1, accounts(1));
+        contract.nft_accept_offer("0".to_string(), accounts(3));
+        let payments = payments();
+        for (account_id, amount) in preview {
+            assert!(payments.contains(&(accounts(0), account_id, amount.0)));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Offer is below the ask price")]
+    fn test_offer_below_ask_price_rejected() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_offers_listing(&mut context, false);
+        make_offer(&mut context, &mut contract, 999);
+    }
+
+    #[test]
+    fn test_withdraw_offer() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_offers_listing(&mut context, false);
+        make_offer(&mut context, &mut contract, 1000);
+
+        call_env(&mut context, 1, accounts(3));
+        contract.nft_withdraw_offer("0".to_string());
+        assert_eq!(contract.nft_offer("0".to_string(), accounts(3)), None);
+        let refunded: Balance = payments()
+            .into_iter()
+            .filter(|(_, receiver_id, _)| *receiver_id == accounts(3))
+            .map(|(_, _, amount)| amount)
+            .sum();
+        assert_eq!(refunded, 1000 + storage_cost(OFFER_STORAGE_BYTES));
+    }
+
+    #[test]
+    fn test_nft_offer_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftOfferLog {
+                token_id: "0".into(),
+                buyer_id: accounts(3),
+                amount: U128(600),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_offer","data":[{"token_id":"0","buyer_id":"danny","amount":"600"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_nft_offer_withdraw_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::NftOfferWithdrawLog {
+                token_id: "0".into(),
+                buyer_id: accounts(3),
+                amount: U128(600),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_offer_withdraw","data":[{"token_id":"0","buyer_id":"danny","amount":"600"}]}"#,
+        );
+    }
+
+    /// Token "0" of bob listed for 1000 at 1000 seconds until 2000 seconds
+    fn contract_with_expiring_listing(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        context.block_timestamp(1_000 * 1_000_000_000);
+        call_env(context, storage_cost(LISTING_STORAGE_BYTES), accounts(1));
+        contract.list_for_sale_until("0".to_string(), U128(1000), 2_000);
+        contract
+    }
+
+    #[test]
+    fn test_prune_expired_listing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_expiring_listing(&mut context);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"nft_list","data":[{"token_id":"0","owner_id":"bob","price":"1000","expires_at":2000}]}"#
+        );
+        context.block_timestamp(1_999 * 1_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(
+            contract.nft_get_listing_price("0".to_string()),
+            Some(U128(1000))
+        );
+
+        context.block_timestamp(2_000 * 1_000_000_000);
+        call_env(&mut context, 0, accounts(3));
+        assert_eq!(contract.nft_get_listing_price("0".to_string()), None);
+        contract.prune_listing("0".to_string());
+        assert_eq!(contract.sales.get(&"0".to_string()), None);
+        assert_eq!(logged_events(), vec!["nft_delist"]);
+        let refunds = payments();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].1, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Listing expired")]
+    fn test_buy_expired_listing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_expiring_listing(&mut context);
+        context.block_timestamp(2_000 * 1_000_000_000);
+        call_env(&mut context, 1000, accounts(3));
+        contract.buy("0".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Listing hasn't expired")]
+    fn test_prune_unexpired_listing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_expiring_listing(&mut context);
+        context.block_timestamp(1_999 * 1_000_000_000);
+        call_env(&mut context, 0, accounts(3));
+        contract.prune_listing("0".to_string());
+    }
+
+    /// Token "0" of bob on a Dutch auction falling from 2000 to 1000 over 100 seconds
+    /// starting at 1000 seconds
+    fn contract_with_dutch_auction(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        context.block_timestamp(1_000 * 1_000_000_000);
+        call_env(context, storage_cost(LISTING_STORAGE_BYTES), accounts(1));
+        contract.list_dutch_auction("0".to_string(), U128(2000), U128(1000), 100);
+        contract
+    }
+
+    /// Token "0" of bob approved for charlie
+    fn contract_with_approval(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost(20))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_approve("0".to_string(), accounts(2), None);
+        contract
+    }
+
+    /// Environment of the call under test
+    fn call_env(
+        context: &mut VMContextBuilder,
+        deposit: Balance,
+        predecessor_account_id: AccountId,
+    ) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .predecessor_account_id(predecessor_account_id)
+            .build());
+    }
+
+    /// Run `method` in a minimal scenario and return the names of the events it logged
+    fn method_scenario_events(method: &str) -> Vec<String> {
+        // every scenario starts from empty storage
+        near_sdk::mock::with_mocked_blockchain(|b| b.take_storage());
+        let mut context = get_context(accounts(0));
+        let token_id = || "0".to_string();
+        match method {
+            "nft_mint_all" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(
+                    &mut context,
+                    storage_cost(MINT_ALL_STORAGE_BYTES),
+                    accounts(0),
+                );
+                contract.nft_mint_all();
+            }
+            "nft_mint" => {
+                contract_with_token(&mut context);
+            }
+            "nft_mint_with_receipt" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+                contract.nft_mint_with_receipt(
+                    token_id(),
+                    accounts(1),
+                    sample_token_metadata(),
+                    None,
+                );
+            }
+            "nft_mint_with_collaborators" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(
+                    &mut context,
+                    storage_cost(MINT_STORAGE_BYTES) * 2,
+                    accounts(0),
+                );
+                contract.nft_mint_with_collaborators(
+                    token_id(),
+                    sample_token_metadata(),
+                    vec![collaborators::Collaborator {
+                        account_id: accounts(1),
+                        royalty_bps: 1000,
+                        role: "artist".into(),
+                    }],
+                );
+            }
+            "nft_migrate_from_paras" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(
+                    &mut context,
+                    storage_cost(MINT_STORAGE_BYTES) * 2,
+                    accounts(0),
+                );
+                contract.nft_migrate_from_paras(
+                    "1204".into(),
+                    "1204:7".into(),
+                    sample_token_metadata(),
+                );
+            }
+            "nft_mint_curve" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                contract.set_mint_curve(Some(MintCurve {
+                    base_price: U128(1000),
+                    increment: U128(0),
+                    step_size: None,
+                }));
+                call_env(
+                    &mut context,
+                    1000 + storage_cost(CURVE_MINT_STORAGE_BYTES),
+                    accounts(1),
+                );
+                contract.nft_mint_curve();
+            }
+            "nft_airdrop" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+                contract.nft_airdrop(vec![airdrop::Airdrop {
+                    token_id: token_id(),
+                    receiver_id: accounts(1),
+                    token_metadata: sample_token_metadata(),
+                }]);
+            }
+            "nft_transfer" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_transfer(accounts(2), token_id(), None, None);
+            }
+            "nft_transfer_call" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                let _ =
+                    contract.nft_transfer_call(accounts(2), token_id(), None, None, "".to_string());
+            }
+            "nft_resolve_transfer" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                let _ =
+                    contract.nft_transfer_call(accounts(2), token_id(), None, None, "".to_string());
+                // the receiver returns the token
+                testing_env!(
+                    context
+                        .storage_usage(env::storage_usage())
+                        .attached_deposit(0)
+                        .predecessor_account_id(accounts(0))
+                        .build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Successful(b"true".to_vec())],
+                );
+                contract.nft_resolve_transfer(accounts(1), accounts(2), token_id(), None);
+            }
+            "nft_transfer_payout" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_transfer_payout(accounts(2), token_id(), None, None, U128(1000), None);
+            }
+            "nft_batch_transfer_to_self" => {
+                let mut contract = contract_with_operator_approvals(&mut context);
+                call_env(&mut context, 1, accounts(2));
+                contract.nft_batch_transfer_to_self(vec![token_id()]);
+            }
+            "nft_run_integration_diagnostics" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(
+                    &mut context,
+                    storage_cost(MARKETPLACE_APPROVAL_STORAGE_BYTES),
+                    accounts(0),
+                );
+                contract.nft_run_integration_diagnostics();
+            }
+            "set_min_royalty_bps" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                contract.set_default_royalties(HashMap::from([(accounts(2), 500)]));
+                contract.set_min_royalty_bps(500);
+            }
+            "set_default_royalties" => {
+                testing_env!(context.build());
+                Contract::new().set_default_royalties(HashMap::from([(accounts(2), 500)]));
+            }
+            "set_null_account" => {
+                testing_env!(context.build());
+                Contract::new().set_null_account(Some("null.near".parse().unwrap()));
+            }
+            "set_bridge_account" => {
+                testing_env!(context.build());
+                Contract::new().set_bridge_account(Some("bridge.near".parse().unwrap()));
+            }
+            "bridge_lock" => {
+                contract_with_bridged_token(&mut context);
+            }
+            "bridge_unlock" => {
+                let mut contract = contract_with_bridged_token(&mut context);
+                call_env(&mut context, 1, "bridge.near".parse().unwrap());
+                contract.bridge_unlock("0".to_string(), accounts(1));
+            }
+            "set_payout_ft_token" => {
+                testing_env!(context.build());
+                Contract::new().set_payout_ft_token("wnear.near".parse().unwrap(), true);
+            }
+            "set_payout_market" => {
+                testing_env!(context.build());
+                Contract::new().set_payout_market("market.near".parse().unwrap(), true);
+            }
+            "on_ft_payout" => {
+                let mut contract = contract_with_ft_payout(&mut context);
+                testing_env!(
+                    context.predecessor_account_id(accounts(0)).build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Failed],
+                );
+                contract.on_ft_payout(accounts(2), "wnear.near".parse().unwrap(), U128(100));
+            }
+            "claim_ft_payout" => {
+                let mut contract = contract_with_ft_payout(&mut context);
+                testing_env!(
+                    context.predecessor_account_id(accounts(0)).build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Failed],
+                );
+                contract.on_ft_payout(accounts(2), "wnear.near".parse().unwrap(), U128(100));
+                call_env(&mut context, 1, accounts(2));
+                contract.claim_ft_payout("wnear.near".parse().unwrap());
+            }
+            "nft_transfer_all" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_transfer_all(accounts(3), None);
+            }
+            "nft_burn" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_burn(token_id(), None);
+            }
+            "nft_approve" => {
+                contract_with_approval(&mut context);
+            }
+            "nft_approve_with_refund" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(
+                    &mut context,
+                    storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+                    accounts(1),
+                );
+                contract.nft_approve_with_refund(token_id(), accounts(2), None, Some(accounts(4)));
+            }
+            "nft_batch_approve" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(
+                    &mut context,
+                    storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+                    accounts(1),
+                );
+                contract.nft_batch_approve(vec![token_id()], accounts(2));
+            }
+            "nft_set_approval_with_conditions" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(
+                    &mut context,
+                    storage_cost(CONDITIONAL_APPROVAL_STORAGE_BYTES),
+                    accounts(1),
+                );
+                contract.nft_set_approval_with_conditions(
+                    token_id(),
+                    accounts(2),
+                    approval::ApprovalConditions {
+                        min_price: Some(U128(1000)),
+                        expires_at: None,
+                        max_uses: None,
+                    },
+                );
+            }
+            "nft_revoke" => {
+                let mut contract = contract_with_approval(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_revoke(token_id(), accounts(2));
+            }
+            "nft_revoke_all" => {
+                let mut contract = contract_with_approval(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_revoke_all(token_id());
+            }
+            "list_for_sale" => {
+                contract_with_listing(&mut context);
+            }
+            "create_lazy_listing" => {
+                contract_with_lazy_listing(&mut context);
+            }
+            "buy_lazy" => {
+                let mut contract = contract_with_lazy_listing(&mut context);
+                call_env(
+                    &mut context,
+                    1000 + storage_cost(MINT_STORAGE_BYTES) * 2,
+                    accounts(3),
+                );
+                contract.buy_lazy(U64(0));
+            }
+            "nft_list_for_sale_with_offers" => {
+                contract_with_offers_listing(&mut context, true);
+            }
+            "nft_make_offer" => {
+                let mut contract = contract_with_offers_listing(&mut context, true);
+                make_offer(&mut context, &mut contract, 600);
+            }
+            "nft_withdraw_offer" => {
+                let mut contract = contract_with_offers_listing(&mut context, true);
+                make_offer(&mut context, &mut contract, 600);
+                call_env(&mut context, 1, accounts(3));
+                contract.nft_withdraw_offer(token_id());
+            }
+            "nft_accept_offer" => {
+                let mut contract = contract_with_offers_listing(&mut context, true);
+                make_offer(&mut context, &mut contract, 600);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_accept_offer(token_id(), accounts(3));
+            }
+            "list_for_sale_until" => {
+                contract_with_expiring_listing(&mut context);
+            }
+            "prune_listing" => {
+                let mut contract = contract_with_expiring_listing(&mut context);
+                context.block_timestamp(2_000 * 1_000_000_000);
+                call_env(&mut context, 0, accounts(3));
+                contract.prune_listing(token_id());
+            }
+            "delist" => {
+                let mut contract = contract_with_listing(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.delist(token_id());
+            }
+            "nft_bulk_delist" => {
+                let mut contract = contract_with_listing(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_bulk_delist(vec![token_id()]);
+            }
+            "buy" => {
+                let mut contract = contract_with_listing(&mut context);
+                call_env(&mut context, 1000, accounts(3));
+                contract.buy(token_id());
+            }
+            "list_for_sale_ft" => {
+                contract_with_royalty_listing(&mut context, Some("usdc.near".parse().unwrap()));
+            }
+            "ft_on_transfer" => {
+                let mut contract =
+                    contract_with_royalty_listing(&mut context, Some("usdc.near".parse().unwrap()));
+                call_env(&mut context, 0, "usdc.near".parse().unwrap());
+                let _ = contract.ft_on_transfer(
+                    accounts(3),
+                    U128(1000),
+                    r#"{"buy":{"token_id":"0"}}"#.to_string(),
+                );
+            }
+            "list_dutch_auction" => {
+                contract_with_dutch_auction(&mut context);
+            }
+            "nft_dutch_buy_and_list" => {
+                let mut contract = contract_with_dutch_auction(&mut context);
+                call_env(
+                    &mut context,
+                    2000 + storage_cost(LISTING_STORAGE_BYTES),
+                    accounts(3),
+                );
+                contract.nft_dutch_buy_and_list(token_id(), U128(3000));
+            }
+            "nft_bulk_update_sale_prices" => {
+                let mut contract = contract_with_listing(&mut context);
+                call_env(&mut context, 0, accounts(1));
+                contract.nft_bulk_update_sale_prices(vec![sale::PriceUpdate {
+                    token_id: token_id(),
+                    new_price: U128(2000),
+                }]);
+            }
+            "nft_set_floor_price_and_delist_above" => {
+                let mut contract = contract_with_listing(&mut context);
+                call_env(&mut context, 0, accounts(0));
+                contract.nft_set_floor_price_and_delist_above(U128(2000), 10);
+            }
+            "set_min_sale_price" => {
+                testing_env!(context.build());
+                Contract::new().set_min_sale_price(U128(1000));
+            }
+            #[cfg(feature = "testnet")]
+            "nft_mint_test" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(
+                    &mut context,
+                    storage_cost(MINT_STORAGE_BYTES) * 2,
+                    accounts(2),
+                );
+                contract.nft_mint_test(accounts(3));
+            }
+            "set_build_info" => {
+                testing_env!(context.build());
+                set_sample_build_info(&mut context, &mut Contract::new());
+            }
+            "set_ft_mint_price" => {
+                testing_env!(context.build());
+                Contract::new().set_ft_mint_price(None);
+            }
+            "set_reveal_config" => {
+                contract_with_hidden_token(&mut context);
+            }
+            "pay_to_reveal" => {
+                let mut contract = contract_with_hidden_token(&mut context);
+                call_env(
+                    &mut context,
+                    REVEAL_FEE + storage_cost(REVEAL_STORAGE_BYTES),
+                    accounts(1),
+                );
+                contract.pay_to_reveal(token_id());
+            }
+            "set_default_media" => {
+                testing_env!(context.build());
+                Contract::new().set_default_media(Some("default-media".into()));
+            }
+            "set_default_marketplace" => {
+                testing_env!(context.build());
+                Contract::new().set_default_marketplace(Some(accounts(2)));
+            }
+            "set_paras_ft_token" => {
+                testing_env!(context.build());
+                Contract::new().set_paras_ft_token("usdc.near".parse().unwrap(), true);
+            }
+            "set_paras_marketplace" => {
+                testing_env!(context.build());
+                Contract::new().set_paras_marketplace(Some(accounts(2)));
+            }
+            "set_stake_points_per_day" => {
+                testing_env!(context.build());
+                Contract::new().set_stake_points_per_day(2);
+            }
+            "nft_lease" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, storage_cost(LEASE_STORAGE_BYTES), accounts(1));
+                contract.nft_lease(token_id(), accounts(2), U64(100), U128(1000));
+            }
+            "nft_accept_lease" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, storage_cost(LEASE_STORAGE_BYTES), accounts(1));
+                contract.nft_lease(token_id(), accounts(2), U64(100), U128(1000));
+                call_env(&mut context, 1000, accounts(2));
+                contract.nft_accept_lease(token_id());
+            }
+            "nft_attach" => {
+                contract_with_children(&mut context);
+            }
+            "nft_detach" => {
+                let mut contract = contract_with_children(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_detach("1".to_string());
+            }
+            "nft_stake" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, storage_cost(STAKE_STORAGE_BYTES), accounts(1));
+                contract.nft_stake(token_id());
+            }
+            "nft_unstake" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, storage_cost(STAKE_STORAGE_BYTES), accounts(1));
+                contract.nft_stake(token_id());
+                call_env(&mut context, 1, accounts(1));
+                contract.nft_unstake(token_id());
+            }
+            "set_default_max_resale_price" => {
+                testing_env!(context.build());
+                Contract::new().set_default_max_resale_price(Some(U128(1000)));
+            }
+            "nft_mint_with_resale_cap" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(
+                    &mut context,
+                    storage_cost(MINT_STORAGE_BYTES) * 2,
+                    accounts(0),
+                );
+                contract.nft_mint_with_resale_cap(
+                    token_id(),
+                    accounts(1),
+                    sample_token_metadata(),
+                    U128(1000),
+                );
+            }
+            "set_platform_fee" => {
+                testing_env!(context.build());
+                Contract::new().set_platform_fee(200);
+            }
+            "set_global_transfer_unlock" => {
+                testing_env!(context.build());
+                Contract::new().set_global_transfer_unlock(100);
+            }
+            "set_max_daily_mints_per_account" => {
+                testing_env!(context.build());
+                Contract::new().set_max_daily_mints_per_account(10);
+            }
+            "set_mint_curve" => {
+                testing_env!(context.build());
+                Contract::new().set_mint_curve(None);
+            }
+            "set_mint_payout_accounts" => {
+                testing_env!(context.build());
+                Contract::new().set_mint_payout_accounts(vec![]);
+            }
+            "propose_external_swap" => {
+                contract_with_external_swap(&mut context);
+            }
+            "cancel_external_swap" => {
+                let mut contract = contract_with_external_swap(&mut context);
+                call_env(&mut context, 1, accounts(1));
+                contract.cancel_external_swap(U64(0));
+            }
+            "nft_on_transfer" => {
+                let mut contract = contract_with_external_swap(&mut context);
+                receive_external_token(&mut context, &mut contract, "other.near", "7", "0");
+            }
+            "retry_external_swap_forward" => {
+                let mut contract = contract_with_external_swap(&mut context);
+                receive_external_token(&mut context, &mut contract, "other.near", "7", "0");
+                call_env(&mut context, 1, accounts(1));
+                contract.retry_external_swap_forward(U64(0));
+            }
+            "on_external_swap_forward" => {
+                let mut contract = contract_with_external_swap(&mut context);
+                let swap = contract.external_swap(U64(0)).unwrap();
+                testing_env!(
+                    context.build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Failed],
+                );
+                contract.on_external_swap_forward(U64(0), swap);
+            }
+            "create_event_drop" => {
+                contract_with_event_drop(&mut context);
+            }
+            "on_event_drop" => {
+                let mut contract = contract_with_event_drop(&mut context);
+                resolve_event_drop(
+                    &mut context,
+                    &mut contract,
+                    "0",
+                    near_sdk::PromiseResult::Failed,
+                );
+            }
+            "set_social_config" => {
+                testing_env!(context.build());
+                Contract::new().set_social_config(None, true);
+            }
+            "social_pool_top_up" => {
+                contract_with_social_posts(&mut context);
+            }
+            "ft_mint_storage_pool_top_up" => {
+                contract_with_ft_mint_price(&mut context);
+            }
+            "audit_storage_pool_top_up" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(&mut context, 1000, accounts(0));
+                contract.audit_storage_pool_top_up();
+            }
+            "on_social_post" => {
+                let mut contract = contract_with_social_posts(&mut context);
+                testing_env!(
+                    context.build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Failed],
+                );
+                contract.on_social_post(token_id(), U128(social::SOCIAL_POST_DEPOSIT));
+            }
+            "nft_approve_collection" => {
+                contract_with_series_operator(&mut context);
+            }
+            "nft_revoke_collection" => {
+                let mut contract = contract_with_series_operator(&mut context);
+                call_env(&mut context, 0, accounts(0));
+                contract.nft_revoke_collection(accounts(2), "kyiv".to_string());
+            }
+            "nft_mint_series" => {
+                let mut contract = contract_with_series_operator(&mut context);
+                call_env(
+                    &mut context,
+                    storage_cost(MINT_STORAGE_BYTES) * 2,
+                    accounts(2),
+                );
+                contract.nft_mint_series(
+                    "kyiv".to_string(),
+                    token_id(),
+                    accounts(1),
+                    sample_token_metadata(),
+                );
+            }
+            "nft_lock_as_collateral" => {
+                contract_with_collateral(&mut context);
+            }
+            "nft_release_collateral" => {
+                let mut contract = contract_with_collateral(&mut context);
+                call_env(&mut context, 1, "lender.near".parse().unwrap());
+                contract.nft_release_collateral(token_id());
+            }
+            "nft_liquidate_collateral" => {
+                let mut contract = contract_with_collateral(&mut context);
+                call_env(&mut context, 1, "lender.near".parse().unwrap());
+                contract.nft_liquidate_collateral(token_id(), accounts(3), U128(1000), None);
+            }
+            "close_minting" => {
+                contract_with_minting_closed(&mut context);
+            }
+            "nft_transfer_ownership_of_contract" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(&mut context, 1, accounts(0));
+                contract.nft_transfer_ownership_of_contract(accounts(1));
+            }
+            "set_donation_ft_token" => {
+                testing_env!(context.build());
+                Contract::new().set_donation_ft_token("usdc.near".parse().unwrap(), true);
+            }
+            "set_charity_account" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                contract.set_charity_account(Some(accounts(4)));
+                contract.set_charity_account(Some(accounts(3)));
+            }
+            "execute_charity_change" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                contract.set_charity_account(Some(accounts(4)));
+                contract.set_charity_account(Some(accounts(3)));
+                testing_env!(context
+                    .block_timestamp(donation::CHARITY_CHANGE_DELAY * 1_000_000_000)
+                    .build());
+                contract.execute_charity_change();
+            }
+            "on_charity_transfer" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                contract.set_charity_account(Some(accounts(4)));
+                contract.internal_donate(accounts(1), 1000, donation::DonationContext::Mint);
+                testing_env!(
+                    context.build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Failed],
+                );
+                contract.on_charity_transfer(
+                    accounts(1),
+                    U128(1000),
+                    donation::DonationContext::Mint,
+                );
+            }
+            "on_donation" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                contract.set_charity_account(Some(accounts(4)));
+                contract.internal_donate(accounts(1), 1000, donation::DonationContext::Mint);
+                testing_env!(
+                    context.build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Failed],
+                );
+                contract.on_donation(accounts(1), U128(1000), donation::DonationContext::Mint);
+            }
+            "start_matching_campaign" => {
+                contract_with_matching_campaign(&mut context);
+            }
+            "close_matching_campaign" => {
+                let mut contract = contract_with_matching_campaign(&mut context);
+                call_env(&mut context, 0, accounts(0));
+                contract.close_matching_campaign();
+            }
+            "retry_charity_transfer" => {
+                let mut contract = contract_with_failed_charity_transfer(&mut context);
+                testing_env!(context.build());
+                contract.retry_charity_transfer(U64(0));
+            }
+            "on_charity_retry" => {
+                let mut contract = contract_with_failed_charity_transfer(&mut context);
+                testing_env!(context.build());
+                contract.retry_charity_transfer(U64(0));
+                testing_env!(
+                    context.build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Successful(vec![])],
+                );
+                contract.on_charity_retry(U64(0));
+            }
+            "on_ft_donation" => {
+                contract_with_ft_donation(
+                    &mut context,
+                    near_sdk::PromiseResult::Successful(vec![]),
+                );
+            }
+            "on_refund_complete" => {
+                testing_env!(
+                    context.build(),
+                    near_sdk::VMConfig::test(),
+                    near_sdk::RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![near_sdk::PromiseResult::Failed],
+                );
+                Contract::new().on_refund_complete(accounts(1), U128(1000));
+            }
+            "nft_take_governance_snapshot" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(
+                    &mut context,
+                    storage_cost(SNAPSHOT_STORAGE_BYTES),
+                    accounts(0),
+                );
+                contract.nft_take_governance_snapshot("before".into());
+            }
+            "nft_update_contract_icon" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(&mut context, 0, accounts(0));
+                contract.nft_update_contract_icon(SAMPLE_ICON.to_string());
+            }
+            "update_token_metadata" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+                contract.update_token_metadata(token_id(), sample_token_metadata());
+            }
+            "set_token_media" => {
+                let mut contract = contract_with_token(&mut context);
+                call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+                contract.set_token_media(token_id(), "bafy-new".into(), None);
+            }
+            "import_state_chunk" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(&mut context, 0, accounts(0));
+                contract.import_state_chunk(
+                    "tokens".into(),
+                    near_sdk::json_types::Base64VecU8(vec![0, 0, 0, 0]),
+                );
+            }
+            "migrate" => {
+                let contract = contract_with_token(&mut context);
+                write_v1_state(&contract);
+                call_env(&mut context, 0, accounts(0));
+                Contract::migrate();
+            }
+            "stage_code" => {
+                contract_with_staged_code(&mut context, b"\0asm v2");
+            }
+            "discard_staged_code" => {
+                let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+                call_env(&mut context, 0, accounts(0));
+                contract.discard_staged_code();
+            }
+            "upgrade" => {
+                let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+                context.block_timestamp((1_000 + upgrade::UPGRADE_DELAY) * 1_000_000_000);
+                call_env(&mut context, 0, accounts(0));
+                let _ = contract.upgrade(code_hash(b"\0asm v2"));
+            }
+            "migrate_tokens" => {
+                let mut contract = contract_with_token(&mut context);
+                write_v1_token_extras(&mut contract, "0");
+                call_env(&mut context, 0, accounts(4));
+                contract.migrate_tokens(10);
+            }
+            "nft_create_collection" => {
+                testing_env!(context.build());
+                let mut contract = Contract::new();
+                call_env(&mut context, 5_000_000_000_000_000_000_000_000, accounts(0));
+                contract.nft_create_collection(
+                    "kyiv".into(),
+                    "KYIV".into(),
+                    100,
+                    b"\0asm mock collection".to_vec().into(),
+                );
+            }
+            _ => panic!("No event scenario for {}", method),
+        }
+        logged_events()
+    }
+
+    #[test]
+    fn test_migrate_from_paras() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        let token = contract.nft_migrate_from_paras(
+            "1204".into(),
+            "1204:7".into(),
+            TokenMetadata {
+                extra: Some(r#"{"attributes":[]}"#.into()),
+                ..sample_token_metadata()
+            },
+        );
+        assert_eq!(token.token_id, "1204:7");
+        assert_eq!(token.owner_id, accounts(0));
+        let extra = contract
+            .nft_token("1204:7".into())
+            .unwrap()
+            .metadata
+            .unwrap()
+            .extra
+            .unwrap();
+        assert_eq!(
+            near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(&extra).unwrap(),
+            near_sdk::serde_json::json!({
+                "attributes": [],
+                "paras_origin": {"token_series_id": "1204", "token_id": "1204:7"},
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid Paras token ID")]
+    fn test_migrate_from_paras_invalid_token_id() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_migrate_from_paras("1204".into(), "1204-7".into(), sample_token_metadata());
+    }
+
+    #[test]
+    #[should_panic(expected = "Token ID doesn't belong to the Paras series")]
+    fn test_migrate_from_paras_other_series() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(
+            &mut context,
+            storage_cost(MINT_STORAGE_BYTES) * 2,
+            accounts(0),
+        );
+        contract.nft_migrate_from_paras("1204".into(), "1205:7".into(), sample_token_metadata());
+    }
+
+    /// Contract with `code` staged by alice at 1000 seconds
+    fn code_hash(code: &[u8]) -> near_sdk::json_types::Base58CryptoHash {
+        let hash: near_sdk::CryptoHash = env::sha256(code).try_into().unwrap();
+        hash.into()
+    }
+
+    fn contract_with_staged_code(context: &mut VMContextBuilder, code: &[u8]) -> Contract {
+        testing_env!(context.block_timestamp(1_000 * 1_000_000_000).build());
+        let mut contract = Contract::new();
+        call_env(context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.stage_code(code.to_vec().into());
+        contract
+    }
+
+    #[test]
+    fn test_stage_code_exposes_hash() {
+        let mut context = get_context(accounts(0));
+        let code = b"\0asm v2";
+        let contract = contract_with_staged_code(&mut context, code);
+        let hash: near_sdk::CryptoHash = env::sha256(code).try_into().unwrap();
+        assert_eq!(
+            contract.staged_code(),
+            Some(upgrade::StagedCode {
+                hash: hash.into(),
+                staged_at: 1_000,
+                deployable_at: 1_000 + upgrade::UPGRADE_DELAY,
+            })
+        );
+        assert_eq!(env::storage_read(b"STAGED_CODE"), Some(code.to_vec()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Upgrade delay hasn't elapsed")]
+    fn test_upgrade_before_delay() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+        context.block_timestamp((1_000 + upgrade::UPGRADE_DELAY - 1) * 1_000_000_000);
+        call_env(&mut context, 0, accounts(0));
+        contract.upgrade(code_hash(b"\0asm v2"));
+    }
+
+    fn set_sample_build_info(context: &mut VMContextBuilder, contract: &mut Contract) {
+        call_env(context, 0, accounts(0));
+        contract.set_build_info("3585728".into(), "1.69.0".into(), vec![7; 32].into());
+    }
+
+    #[test]
+    fn test_set_build_info() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        assert_eq!(contract.contract_info().build_info, None);
+        set_sample_build_info(&mut context, &mut contract);
+
+        let build_info = info::BuildInfo {
+            git_commit: "3585728".into(),
+            rustc_version: "1.69.0".into(),
+            wasm_hash: vec![7; 32].into(),
+        };
+        assert_eq!(
+            contract.contract_info().build_info,
+            Some(build_info.clone())
+        );
+        let source_metadata = contract.contract_source_metadata();
+        assert_eq!(source_metadata.build_info, Some(build_info));
+        assert_eq!(
+            source_metadata.version,
+            Some(env!("CARGO_PKG_VERSION").to_string())
+        );
+        assert_eq!(logged_events(), vec!["build_info_set"]);
+    }
+
+    #[test]
+    fn test_supported_interfaces_detail() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new();
+        let interfaces = contract.nft_supported_interfaces_detail();
+        for (standard, method) in [
+            ("nep171", "nft_transfer_call"),
+            ("nep178", "nft_approve"),
+            ("nep181", "nft_tokens_for_owner"),
+            ("nep199", "nft_payout"),
+        ] {
+            let interface = interfaces
+                .iter()
+                .find(|interface| interface.standard == standard)
+                .unwrap();
+            assert!(interface.methods.iter().any(|listed| listed == method));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Build info is already set for this code version")]
+    fn test_set_build_info_twice() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        set_sample_build_info(&mut context, &mut contract);
+        set_sample_build_info(&mut context, &mut contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Wasm hash must be 32 bytes")]
+    fn test_set_build_info_short_hash() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, 0, accounts(0));
+        contract.set_build_info("3585728".into(), "1.69.0".into(), vec![7; 31].into());
+    }
+
+    #[test]
+    fn test_upgrade_clears_build_info() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+        set_sample_build_info(&mut context, &mut contract);
+        context.block_timestamp((1_000 + upgrade::UPGRADE_DELAY) * 1_000_000_000);
+        call_env(&mut context, 0, accounts(0));
+        let _ = contract.upgrade(code_hash(b"\0asm v2"));
+        assert_eq!(contract.contract_info().build_info, None);
+        set_sample_build_info(&mut context, &mut contract);
+        assert!(contract.contract_info().build_info.is_some());
+    }
+
+    #[test]
+    fn test_build_info_set_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::BuildInfoSetLog {
+                build_info: info::BuildInfo {
+                    git_commit: "3585728".into(),
+                    rustc_version: "1.69.0".into(),
+                    wasm_hash: vec![0; 32].into(),
+                },
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"build_info_set","data":[{"git_commit":"3585728","rustc_version":"1.69.0","wasm_hash":"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_upgrade_after_delay() {
+        let mut context = get_context(accounts(0));
+        let code = b"\0asm v2".to_vec();
+        let mut contract = contract_with_staged_code(&mut context, &code);
+        context.block_timestamp((1_000 + upgrade::UPGRADE_DELAY) * 1_000_000_000);
+        call_env(&mut context, 0, accounts(0));
+        let _ = contract.upgrade(code_hash(b"\0asm v2"));
+
+        assert_eq!(contract.staged_code(), None);
+        assert_eq!(env::storage_read(b"STAGED_CODE"), None);
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        // the storage refund comes first, the owner is the contract account itself
+        let deploy = receipts.last().unwrap();
+        assert_eq!(deploy.receiver_id, accounts(0));
+        assert_eq!(deploy.actions[0], VmAction::DeployContract { code });
+        assert!(matches!(
+            &deploy.actions[1],
+            VmAction::FunctionCall { function_name, .. } if function_name == "migrate"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Staged code hash mismatch")]
+    fn test_upgrade_restaged_code() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.stage_code(b"\0asm v3".to_vec().into());
+        context.block_timestamp((1_000 + 2 * upgrade::UPGRADE_DELAY) * 1_000_000_000);
+        call_env(&mut context, 0, accounts(0));
+        let _ = contract.upgrade(code_hash(b"\0asm v2"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_stage_code_unauthorized() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new();
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(1));
+        contract.stage_code(b"\0asm v2".to_vec().into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_discard_staged_code_unauthorized() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+        call_env(&mut context, 0, accounts(1));
+        contract.discard_staged_code();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_upgrade_unauthorized() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+        context.block_timestamp((1_000 + upgrade::UPGRADE_DELAY) * 1_000_000_000);
+        call_env(&mut context, 0, accounts(1));
+        let _ = contract.upgrade(code_hash(b"\0asm v2"));
+    }
+
+    #[test]
+    fn test_restaging_code_restarts_delay() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+        context.block_timestamp(2_000 * 1_000_000_000);
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.stage_code(b"\0asm v3".to_vec().into());
+        let staged_code = contract.staged_code().unwrap();
+        assert_eq!(staged_code.deployable_at, 2_000 + upgrade::UPGRADE_DELAY);
+        assert_eq!(
+            env::storage_read(b"STAGED_CODE"),
+            Some(b"\0asm v3".to_vec())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "No code staged")]
+    fn test_upgrade_discarded_code() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_staged_code(&mut context, b"\0asm v2");
+        call_env(&mut context, 0, accounts(0));
+        contract.discard_staged_code();
+        assert_eq!(contract.staged_code(), None);
+        context.block_timestamp((1_000 + upgrade::UPGRADE_DELAY) * 1_000_000_000);
+        call_env(&mut context, 0, accounts(0));
+        contract.upgrade(code_hash(b"\0asm v2"));
+    }
+
+    #[test]
+    fn test_code_stage_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::CodeStageLog {
+                staged_code: upgrade::StagedCode {
+                    hash: [0; 32].into(),
+                    staged_at: 1_000,
+                    deployable_at: 260_200,
+                },
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"code_stage","data":[{"hash":"11111111111111111111111111111111","staged_at":1000,"deployable_at":260200}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_code_discard_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::CodeDiscardLog {
+                hash: [0; 32].into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"code_discard","data":[{"hash":"11111111111111111111111111111111"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_code_deploy_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::CodeDeployLog {
+                hash: [0; 32].into(),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"code_deploy","data":[{"hash":"11111111111111111111111111111111"}]}"#,
+        );
+    }
+
+    /// Overwrite the stored state with the first release layout of `contract`
+    fn write_v1_state(contract: &Contract) {
+        env::storage_write(
+            b"STATE",
+            &(&contract.tokens, &contract.metadata).try_to_vec().unwrap(),
+        );
+        // the first release didn't record its state version
+        env::storage_remove(b"STATE_VERSION");
+    }
+
+    #[test]
+    fn test_migrate_v1_state() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_token(&mut context);
+        write_v1_state(&contract);
+
+        call_env(&mut context, 0, accounts(0));
+        let contract = Contract::migrate();
+        assert_eq!(contract.state_version(), 2);
+        assert_eq!(
+            contract.nft_token("0".to_string()).unwrap().owner_id,
+            accounts(1)
+        );
+        assert_eq!(contract.nft_metadata().name, NFT_NAME);
+        assert_eq!(contract.sales.len(), 0);
+        assert_eq!(contract.min_sale_price, 0);
+        assert_eq!(contract.default_media, None);
+        assert_eq!(contract.default_marketplace, None);
+        assert_eq!(contract.total_donated, 0);
+        assert_eq!(contract.audit_log.len(), 1);
+        assert_eq!(contract.audit_log.get(0).unwrap().action, "migrate");
+        assert_eq!(env::storage_read(b"STATE_VERSION"), Some(vec![2]));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"state_migrate","data":[{"from_version":1,"to_version":2}]}"#
+        );
+    }
+
+    #[test]
+    fn test_migrate_current_state_keeps_it() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_listing(&mut context);
+        call_env(&mut context, 0, accounts(0));
+        contract.set_min_sale_price(U128(500));
+        env::state_write(&contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.min_sale_price, 500);
+        assert_eq!(
+            contract.nft_get_listing_price("0".to_string()),
+            Some(U128(1000))
+        );
+        assert!(get_logs()
+            .last()
+            .unwrap()
+            .contains(r#"{"from_version":2,"to_version":2}"#));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot downgrade state from v3 to v2")]
+    fn test_migrate_refuses_downgrade() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_token(&mut context);
+        env::state_write(&contract);
+        env::storage_write(b"STATE_VERSION", &[3]);
+        call_env(&mut context, 0, accounts(0));
+        Contract::migrate();
+    }
+
+    const REVEAL_FEE: u128 = 1000;
+    const REVEAL_STORAGE_BYTES: StorageUsage = 46;
+
+    fn unrevealed_metadata() -> TokenMetadata {
+        TokenMetadata {
+            title: Some("Unrevealed".into()),
+            ..sample_token_metadata()
+        }
+    }
+
+    /// Contract with token "0" minted to bob and hidden until revealed
+    fn contract_with_hidden_token(context: &mut VMContextBuilder) -> Contract {
+        let mut contract = contract_with_token(context);
+        call_env(context, 0, accounts(0));
+        contract.set_reveal_config(Some(unrevealed_metadata()), Some(U128(REVEAL_FEE)));
+        contract
+    }
+
+    #[test]
+    fn test_pay_to_reveal() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_hidden_token(&mut context);
+        assert_eq!(
+            contract.nft_token("0".into()).unwrap().metadata,
+            Some(unrevealed_metadata())
+        );
+
+        call_env(
+            &mut context,
+            REVEAL_FEE + storage_cost(REVEAL_STORAGE_BYTES),
+            accounts(1),
+        );
+        contract.pay_to_reveal("0".into());
+        assert!(contract.nft_is_revealed("0".into()));
+        assert_eq!(
+            contract.nft_token("0".into()).unwrap().metadata,
+            Some(sample_token_metadata())
+        );
+        assert_eq!(payments(), vec![(accounts(0), accounts(0), REVEAL_FEE)]);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"uamag","version":"1.0.0","event":"nft_reveal","data":[{"token_id":"0","owner_id":"bob","fee":"1000"}]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Must attach 460000000000000001000 yoctoNEAR to cover reveal fee and storage"
+    )]
+    fn test_pay_to_reveal_underpaid() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_hidden_token(&mut context);
+        call_env(
+            &mut context,
+            REVEAL_FEE + storage_cost(REVEAL_STORAGE_BYTES) - 1,
+            accounts(1),
+        );
+        contract.pay_to_reveal("0".into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be token owner.")]
+    fn test_pay_to_reveal_by_non_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_hidden_token(&mut context);
+        call_env(
+            &mut context,
+            REVEAL_FEE + storage_cost(REVEAL_STORAGE_BYTES),
+            accounts(2),
+        );
+        contract.pay_to_reveal("0".into());
+    }
+
+    #[test]
+    fn test_token_reveal_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::TokenRevealLog {
+                token_id: "0".into(),
+                owner_id: accounts(1),
+                fee: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"nft_reveal","data":[{"token_id":"0","owner_id":"bob","fee":"1000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_reveal_config_update_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::RevealConfigUpdateLog {
+                hidden: true,
+                reveal_fee: Some(U128(1000)),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"reveal_config_update","data":[{"hidden":true,"reveal_fee":"1000"}]}"#,
+        );
+    }
+
+    const STATE_CHUNK_KINDS: [&str; 5] = ["config", "tokens", "approvals", "extras", "sales"];
+
+    #[test]
+    fn test_export_import_state_round_trip() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_royalty_listing(&mut context, None);
+        for token_id in ["1", "2"] {
+            call_env(
+                &mut context,
+                storage_cost(MINT_STORAGE_BYTES) * 2,
+                accounts(0),
+            );
+            contract.nft_mint(token_id.into(), accounts(3), sample_token_metadata(), None);
+        }
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(3));
+        contract.nft_set_approval_with_conditions(
+            "2".into(),
+            accounts(2),
+            approval::ApprovalConditions {
+                min_price: Some(U128(500)),
+                expires_at: None,
+                max_uses: Some(1),
+            },
+        );
+        call_env(&mut context, 0, accounts(0));
+        contract.set_min_sale_price(U128(100));
+        contract.set_default_media(Some("default.webp".into()));
+
+        // anyone may export, following the cursor of each chunk
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(0)
+            .is_view(true)
+            .build());
+        let mut chunks = vec![];
+        for kind in STATE_CHUNK_KINDS {
+            let mut from_token_id = None;
+            loop {
+                let chunk = contract.export_state_chunk(kind.into(), from_token_id, 2);
+                chunks.push((kind, chunk.data));
+                from_token_id = chunk.next_token_id;
+                if from_token_id.is_none() {
+                    break;
+                }
+            }
+        }
+        assert_eq!(chunks.len(), 1 + 4 * 2);
+        let tokens = contract.nft_tokens(None, None);
+        let payout = contract.nft_payout("0".into(), U128(1000), None);
+
+        near_sdk::mock::with_mocked_blockchain(|b| b.take_storage());
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .is_view(false)
+            .build());
+        let mut imported = Contract::new();
+        for (kind, data) in chunks {
+            call_env(
+                &mut context,
+                storage_cost(MINT_STORAGE_BYTES) * 10,
+                accounts(0),
+            );
+            imported.import_state_chunk(kind.into(), data);
+        }
+        assert_eq!(imported.nft_tokens(None, None), tokens);
+        assert_eq!(imported.nft_payout("0".into(), U128(1000), None), payout);
+        assert_eq!(imported.nft_get_listing_price("0".into()), Some(U128(1000)));
+        assert_eq!(
+            imported
+                .nft_approval_conditions("2".into(), accounts(2))
+                .unwrap()
+                .max_uses,
+            Some(1)
+        );
+        assert_eq!(
+            imported.royalties_for_account(accounts(2), None, None),
+            vec![("0".to_string(), 1000)]
+        );
+        assert_eq!(imported.min_sale_price, 100);
+        assert_eq!(imported.default_media, Some("default.webp".into()));
+        assert!(imported.token_activity("0".into()).is_some());
+        assert!(imported.check_invariants().is_empty());
+        assert!(!imported.has_minted);
+    }
+
+    #[test]
+    #[should_panic(expected = "State can only be imported before minting")]
+    fn test_import_state_after_mint() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_token(&mut context);
+        let data = contract.export_state_chunk("tokens".into(), None, 10).data;
+        call_env(&mut context, storage_cost(MINT_STORAGE_BYTES), accounts(0));
+        contract.import_state_chunk("tokens".into(), data);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown state chunk kind")]
+    fn test_export_unknown_state_chunk_kind() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_token(&mut context);
+        contract.export_state_chunk("sessions".into(), None, 10);
+    }
+
+    #[test]
+    fn test_state_import_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::StateImportLog {
+                kind: "tokens".into(),
+                records: U64(2),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"state_import","data":[{"kind":"tokens","records":"2"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_state_migrate_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::StateMigrateLog {
+                from_version: 1,
+                to_version: 2,
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"state_migrate","data":[{"from_version":1,"to_version":2}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_mint_receipt_log_round_trip() {
+        assert_event_round_trip(
+            event_log(vec![events::MintReceiptLog {
+                token_id: "0".into(),
+                owner: accounts(1),
+                block_height: U64(42),
+                timestamp: U64(1_700_000_000),
+                storage_charged: U128(1000),
+            }]),
+            r#"{"standard":"uamag","version":"1.0.0","event":"mint_receipt","data":[{"token_id":"0","owner":"bob","block_height":"42","timestamp":"1700000000","storage_charged":"1000"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_no_mutating_method_is_silent() {
+        for (method, expected_events) in events::METHOD_EVENTS {
+            assert!(!expected_events.is_empty(), "{} is silent", method);
+            let logged = method_scenario_events(method);
+            for event in *expected_events {
+                assert!(
+                    logged.iter().any(|logged| logged == event),
+                    "{} didn't log {}, logged {:?}",
+                    method,
+                    event,
+                    logged
+                );
+            }
+        }
     }
 }
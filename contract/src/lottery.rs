@@ -0,0 +1,29 @@
+/*!
+Lottery draws using tokens as tickets, an owner's chances grow with the number of tokens owned.
+*/
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    /// Owner of a token drawn at random. The draw uses the block random seed, which
+    /// validators can predict, or `seed` to make it reproducible
+    pub fn nft_random_winner(&self, seed: Option<String>) -> AccountId {
+        let total_supply = self.tokens.owner_by_id.len();
+        require!(total_supply > 0, "No tokens to draw from");
+        let seed = match seed {
+            Some(seed) => env::sha256(seed.as_bytes()),
+            None => env::random_seed(),
+        };
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&seed[..8]);
+        let index = u64::from_le_bytes(bytes) % total_supply;
+        self.tokens
+            .owner_by_id
+            .iter()
+            .nth(index as usize)
+            .map(|(_, owner_id)| owner_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"))
+    }
+}
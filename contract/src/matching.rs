@@ -0,0 +1,125 @@
+/*!
+Matching campaigns: a sponsor escrows NEAR on the contract to match every NEAR donation 1:1
+until the campaign ends or the escrow is used up.
+
+A match is sent to the charity account alongside the donation it matches and counts as a
+donation of the sponsor. Once the campaign is over anyone may close it, returning what is left
+of the escrow to the sponsor. The contract owner may close it early. One campaign runs at a time,
+its record is paid by the contract.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::events::{self, MatchingCampaignCloseLog, MatchingCampaignStartLog};
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchingCampaign {
+    pub sponsor: AccountId,
+    /// yoctoNEAR escrowed by the sponsor, the most that is matched
+    pub cap: U128,
+    /// yoctoNEAR matched so far
+    pub matched: U128,
+    /// Block timestamp in seconds the campaign ends at
+    pub ends_at: U64,
+}
+
+impl MatchingCampaign {
+    fn remaining(&self) -> Balance {
+        self.cap.0 - self.matched.0
+    }
+
+    fn is_over(&self) -> bool {
+        env::block_timestamp() / 1_000_000_000 >= self.ends_at.0 || self.remaining() == 0
+    }
+}
+
+impl Contract {
+    /// Take the match of a donation of `amount` out of the escrow of the running campaign.
+    /// Returns the sponsor and the amount matched, if any
+    pub(crate) fn internal_match_donation(
+        &mut self,
+        amount: Balance,
+    ) -> Option<(AccountId, Balance)> {
+        let campaign = self.matching_campaign.as_mut()?;
+        if campaign.is_over() {
+            return None;
+        }
+        let matched = amount.min(campaign.remaining());
+        campaign.matched = U128(campaign.matched.0 + matched);
+        (matched > 0).then(|| (campaign.sponsor.clone(), matched))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Match NEAR donations until `ends_at` in seconds with the attached deposit of `cap`,
+    /// called by the `sponsor`
+    #[payable]
+    pub fn start_matching_campaign(&mut self, sponsor: AccountId, cap: U128, ends_at: U64) {
+        require!(
+            env::predecessor_account_id() == sponsor,
+            "Predecessor must be the sponsor"
+        );
+        require!(
+            self.internal_charity_account().is_some(),
+            "Charity account is not configured"
+        );
+        require!(
+            self.matching_campaign.is_none(),
+            "A matching campaign is still open"
+        );
+        require!(cap.0 > 0, "Cap must be positive");
+        require!(
+            env::attached_deposit() == cap.0,
+            "Attached deposit must equal the cap"
+        );
+        require!(
+            ends_at.0 > env::block_timestamp() / 1_000_000_000,
+            "Campaign must end in the future"
+        );
+
+        self.matching_campaign = Some(MatchingCampaign {
+            sponsor: sponsor.clone(),
+            cap,
+            matched: U128(0),
+            ends_at,
+        });
+        self.internal_audit("start_matching_campaign", None, Some(cap.0));
+        events::emit(vec![MatchingCampaignStartLog {
+            sponsor,
+            cap,
+            ends_at,
+        }]);
+    }
+
+    /// Close the campaign once it ended or used up its escrow, refunding the rest of
+    /// the escrow to the sponsor. The contract owner may close it any time
+    pub fn close_matching_campaign(&mut self) {
+        let campaign = self
+            .matching_campaign
+            .take()
+            .unwrap_or_else(|| env::panic_str("No matching campaign is open"));
+        require!(
+            campaign.is_over() || env::predecessor_account_id() == self.tokens.owner_id,
+            "Matching campaign is still running"
+        );
+        let refunded = campaign.remaining();
+        if refunded > 0 {
+            Promise::new(campaign.sponsor.clone()).transfer(refunded);
+        }
+        self.internal_audit("close_matching_campaign", None, Some(refunded));
+        events::emit(vec![MatchingCampaignCloseLog {
+            sponsor: campaign.sponsor,
+            matched: campaign.matched,
+            refunded: U128(refunded),
+        }]);
+    }
+
+    pub fn matching_campaign(&self) -> Option<MatchingCampaign> {
+        self.matching_campaign.clone()
+    }
+}
@@ -0,0 +1,80 @@
+/*!
+Contract-level fallback media for tokens minted without their own `media`
+and the token view as clients should display it.
+
+The owner may read a token exactly as stored for support and audits. Gating that view keeps
+it out of client listings but keeps nothing secret: contract state is public through the
+`view_state` RPC, so the metadata of hidden tokens can be read there by anyone. Art that has to
+stay hidden until the reveal must be kept off-chain, with only its hash stored in the metadata.
+*/
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::near_bindgen;
+
+use crate::events::{self, DefaultMediaUpdateLog};
+use crate::{Contract, ContractExt};
+
+impl Contract {
+    /// Resolve `media` against the contract `base_uri` unless it's already an absolute URL
+    pub(crate) fn resolve_media_url(&self, media: &str) -> String {
+        if media.contains("://") || media.starts_with("data:") {
+            return media.to_string();
+        }
+        match self.metadata.get().and_then(|metadata| metadata.base_uri) {
+            Some(base_uri) => format!("{}{}", base_uri, media),
+            None => media.to_string(),
+        }
+    }
+
+    /// Fill in the default media of a token that has no media of its own
+    pub(crate) fn with_default_media(&self, mut token: Token) -> Token {
+        if let (Some(default_media), Some(metadata)) = (&self.default_media, &mut token.metadata) {
+            if metadata.media.is_none() {
+                metadata.media = Some(self.resolve_media_url(default_media));
+            }
+        }
+        token
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the media shown for tokens without their own media, `None` disables the fallback
+    pub fn set_default_media(&mut self, default_media: Option<String>) {
+        self.assert_owner();
+        self.default_media = default_media.clone();
+        self.internal_audit("set_default_media", None, None);
+        events::emit(vec![DefaultMediaUpdateLog { default_media }]);
+    }
+
+    pub fn default_media(&self) -> Option<String> {
+        self.default_media.clone()
+    }
+
+    /// Token as stored, without the unrevealed metadata, `base_uri` resolution or default media.
+    /// Only the owner may call it, views have no caller. It hides nothing from `view_state`
+    pub fn nft_token_raw(&self, token_id: TokenId) -> Option<Token> {
+        self.assert_owner();
+        self.tokens.nft_token(token_id)
+    }
+
+    /// Token with every display transformation applied: unrevealed metadata shown for hidden
+    /// tokens, `media` and `reference` resolved
+    /// against `base_uri` and the default media filled in.
+    /// `locale` is accepted for localized metadata, which isn't stored yet, so it has no effect
+    pub fn nft_token_rendered(&self, token_id: TokenId, locale: Option<String>) -> Option<Token> {
+        let _ = locale;
+        let mut token = self.with_reveal(self.tokens.nft_token(token_id)?);
+        if let Some(metadata) = token.metadata.as_mut() {
+            metadata.media = metadata
+                .media
+                .as_deref()
+                .map(|media| self.resolve_media_url(media));
+            metadata.reference = metadata
+                .reference
+                .as_deref()
+                .map(|reference| self.resolve_media_url(reference));
+        }
+        Some(self.with_default_media(token))
+    }
+}
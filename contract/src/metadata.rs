@@ -0,0 +1,196 @@
+/*!
+Token metadata updates, either of the whole metadata or just of the media of a token, an integrity hash of token metadata for off-chain verification
+and a field by field comparison of two tokens.
+
+The owner may replace the contract icon with an image data URL of at most `MAX_ICON_LEN` bytes.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, serde_json, CryptoHash};
+
+use crate::events::{self, NftContractIconUpdatedLog};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+/// Recommended limit of the contract icon in bytes
+const MAX_ICON_LEN: usize = 100 * 1024;
+
+/// Metadata fields whose values differ between two tokens, named as in the JSON metadata
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MetadataDiff {
+    pub different_fields: Vec<String>,
+    pub token_a_values: HashMap<String, Option<String>>,
+    pub token_b_values: HashMap<String, Option<String>>,
+}
+
+impl Contract {
+    pub(crate) fn internal_token_metadata(&self, token_id: &TokenId) -> TokenMetadata {
+        self.tokens
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(token_id))
+            .unwrap_or_else(|| env::panic_str("Token not found"))
+    }
+
+    /// Store `token_metadata` of the existing `token_id`. Extra storage is paid by the caller
+    /// and released storage is refunded to it
+    fn internal_replace_token_metadata(
+        &mut self,
+        token_id: &TokenId,
+        token_metadata: &TokenMetadata,
+    ) {
+        let token_metadata_by_id = self
+            .tokens
+            .token_metadata_by_id
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("Token metadata is not supported"));
+        let initial_storage = env::storage_usage();
+        token_metadata_by_id
+            .insert(token_id, token_metadata)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        let final_storage = env::storage_usage();
+        if final_storage > initial_storage {
+            refund_deposit_to_account(
+                final_storage - initial_storage,
+                env::predecessor_account_id(),
+            );
+        } else {
+            refund_released_storage(
+                initial_storage - final_storage,
+                env::predecessor_account_id(),
+            );
+        }
+    }
+}
+
+/// Fields of `metadata` as named in its JSON
+fn metadata_fields(metadata: &TokenMetadata) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(metadata) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => env::panic_str("Cannot serialize metadata"),
+    }
+}
+
+/// Value of a field as a string, `None` for an unset field
+fn field_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replace the metadata of `token_id`. Extra storage is paid by the caller
+    /// and released storage is refunded to it
+    #[payable]
+    pub fn update_token_metadata(&mut self, token_id: TokenId, token_metadata: TokenMetadata) {
+        self.assert_owner();
+        self.internal_replace_token_metadata(&token_id, &token_metadata);
+        self.internal_audit("update_token_metadata", Some(&token_id), None);
+        events::emit_metadata_update(&[token_id]);
+    }
+
+    /// Point `token_id` to new `media`, e.g. after a corrupted upload, keeping the rest
+    /// of its metadata. Storage is settled as in `update_token_metadata`
+    #[payable]
+    pub fn set_token_media(
+        &mut self,
+        token_id: TokenId,
+        media: String,
+        media_hash: Option<Base64VecU8>,
+    ) {
+        self.assert_owner();
+        require!(!media.is_empty(), "Media must not be empty");
+        let mut token_metadata = self.internal_token_metadata(&token_id);
+        token_metadata.media = Some(media);
+        token_metadata.media_hash = media_hash;
+        self.internal_replace_token_metadata(&token_id, &token_metadata);
+        self.internal_audit("set_token_media", Some(&token_id), None);
+        events::emit_metadata_update(&[token_id]);
+    }
+
+    /// Replace the contract icon with `new_icon_data_url`, an image data URL.
+    /// Extra storage is paid by the caller and released storage is refunded to it
+    #[payable]
+    pub fn nft_update_contract_icon(&mut self, new_icon_data_url: String) {
+        self.assert_owner();
+        require!(
+            new_icon_data_url.starts_with("data:image/"),
+            "Icon must be an image data URL"
+        );
+        require!(
+            new_icon_data_url.len() <= MAX_ICON_LEN,
+            "Icon exceeds 100 KB"
+        );
+        let mut metadata = self.metadata.get().unwrap();
+        let hash: CryptoHash = env::sha256(new_icon_data_url.as_bytes())
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("Invalid icon hash"));
+        let icon_len = new_icon_data_url.len() as u32;
+        metadata.icon = Some(new_icon_data_url);
+
+        let initial_storage = env::storage_usage();
+        self.metadata.set(&metadata);
+        let final_storage = env::storage_usage();
+        if final_storage > initial_storage {
+            refund_deposit_to_account(
+                final_storage - initial_storage,
+                env::predecessor_account_id(),
+            );
+        } else {
+            refund_released_storage(
+                initial_storage - final_storage,
+                env::predecessor_account_id(),
+            );
+        }
+        self.internal_audit("update_contract_icon", None, None);
+        events::emit(vec![NftContractIconUpdatedLog {
+            icon_hash: hash.into(),
+            icon_len,
+        }]);
+    }
+
+    /// Hex-encoded SHA-256 of the token metadata serialized as canonical JSON
+    /// (keys sorted, no whitespace)
+    pub fn nft_token_metadata_hash(&self, token_id: TokenId) -> String {
+        let metadata = self.internal_token_metadata(&token_id);
+        // objects of `serde_json::Value` keep their keys sorted
+        let canonical = serde_json::to_value(&metadata)
+            .and_then(|value| serde_json::to_string(&value))
+            .unwrap_or_else(|_| env::panic_str("Cannot serialize metadata"));
+        env::sha256(canonical.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Metadata fields of `token_id_a` and `token_id_b` with different values, sorted by name
+    pub fn nft_metadata_diff(&self, token_id_a: TokenId, token_id_b: TokenId) -> MetadataDiff {
+        let fields_a = metadata_fields(&self.internal_token_metadata(&token_id_a));
+        let fields_b = metadata_fields(&self.internal_token_metadata(&token_id_b));
+        let mut diff = MetadataDiff {
+            different_fields: vec![],
+            token_a_values: HashMap::new(),
+            token_b_values: HashMap::new(),
+        };
+        for (field, value_a) in &fields_a {
+            let value_b = fields_b.get(field).unwrap_or(&serde_json::Value::Null);
+            if value_a == value_b {
+                continue;
+            }
+            diff.different_fields.push(field.clone());
+            diff.token_a_values
+                .insert(field.clone(), field_value(value_a));
+            diff.token_b_values
+                .insert(field.clone(), field_value(value_b));
+        }
+        diff
+    }
+}
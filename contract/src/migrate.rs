@@ -0,0 +1,108 @@
+/*!
+State migration. A new release may change the layout of the contract state, `migrate` reads
+the state in any layout the contract was released with and rewrites it in the current one.
+
+The state version is stored under its own key, readable whatever the layout, so older code
+deployed over newer state refuses to migrate it instead of misreading it. Any change of the
+layout after a release bumps `STATE_VERSION` and freezes the released layout here.
+*/
+use near_contract_standards::non_fungible_token::metadata::NFTContractMetadata;
+use near_contract_standards::non_fungible_token::NonFungibleToken;
+use near_sdk::borsh::{self, BorshDeserialize};
+use near_sdk::collections::LazyOption;
+use near_sdk::{env, near_bindgen};
+
+use crate::events::{self, StateMigrateLog};
+use crate::{Contract, ContractExt};
+
+/// Version of the current state layout
+pub(crate) const STATE_VERSION: u8 = 2;
+
+/// Key the SDK stores the contract state under
+const STATE_KEY: &[u8] = b"STATE";
+/// Raw storage key of the state version, missing for states of the first release
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+/// Record the state is in the current layout
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+}
+
+fn deserialize<T: BorshDeserialize>(state: &[u8]) -> T {
+    T::try_from_slice(state)
+        .unwrap_or_else(|_| env::panic_str("Cannot deserialize the contract state"))
+}
+
+/// State layout of the first release holding tokens and contract metadata only
+#[derive(BorshDeserialize)]
+pub(crate) struct ContractV1 {
+    tokens: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+}
+
+/// Contract state in any of the released layouts
+// read once per migration, boxing the variants buys nothing
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum VersionedContract {
+    V1(ContractV1),
+    V2(Contract),
+}
+
+impl VersionedContract {
+    /// Read the stored state. A state of a newer version than this code isn't read at all,
+    /// a state without a stored version is in the layout of the first release
+    fn read() -> Self {
+        let state = env::storage_read(STATE_KEY)
+            .unwrap_or_else(|| env::panic_str("Contract is not initialized"));
+        match env::storage_read(STATE_VERSION_KEY).as_deref() {
+            Some(&[stored_version]) if stored_version > STATE_VERSION => env::panic_str(&format!(
+                "cannot downgrade state from v{} to v{}",
+                stored_version, STATE_VERSION
+            )),
+            Some(&[STATE_VERSION]) => Self::V2(deserialize(&state)),
+            _ => Self::V1(deserialize(&state)),
+        }
+    }
+
+    fn version(&self) -> u8 {
+        match self {
+            Self::V1(_) => 1,
+            Self::V2(_) => 2,
+        }
+    }
+
+    /// State in the current layout, records missing from older layouts start empty
+    fn into_current(self) -> Contract {
+        match self {
+            Self::V1(ContractV1 { tokens, metadata }) => {
+                Contract::from_token_state(tokens, metadata)
+            }
+            Self::V2(contract) => contract,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Rewrite the stored state in the current layout, called by the contract itself
+    /// along with deploying a new release. A state already in the current layout is kept
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = VersionedContract::read();
+        let from_version = state.version();
+        let mut contract = state.into_current();
+        write_state_version();
+        contract.internal_audit("migrate", None, None);
+        events::emit(vec![StateMigrateLog {
+            from_version,
+            to_version: STATE_VERSION,
+        }]);
+        contract
+    }
+
+    /// Version of the state layout
+    pub fn state_version(&self) -> u8 {
+        STATE_VERSION
+    }
+}
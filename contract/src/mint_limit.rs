@@ -0,0 +1,86 @@
+/*!
+Daily limit of tokens an account may receive from mints, so a single account can't sweep
+a public mint. The contract owner isn't limited, neither when receiving tokens nor when
+minting them to others. Each account keeps a single counter, reset by its first mint of a day.
+
+Once a drop is over the owner may close minting for good. Every mint path is stopped,
+including the owner's own mints, while settings stay editable.
+*/
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::events::{self, MaxDailyMintsUpdateLog, MintingClosedLog};
+use crate::{Contract, ContractExt};
+
+/// Default number of mints an account may receive per day
+pub(crate) const MAX_DAILY_MINTS_PER_ACCOUNT: u32 = 5;
+const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+
+fn today() -> u64 {
+    env::block_timestamp() / NANOS_PER_DAY
+}
+
+impl Contract {
+    /// Mints `account_id` received today
+    fn daily_mint_count(&self, account_id: &AccountId) -> u32 {
+        match self.daily_mint_count.get(account_id) {
+            Some((day, count)) if day == today() => count,
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn assert_minting_open(&self) {
+        require!(!self.minting_closed, "Minting closed");
+    }
+
+    /// Count a mint to `account_id` today, panics once the daily limit is reached.
+    /// Mints by the contract owner aren't counted
+    pub(crate) fn internal_count_daily_mint(&mut self, account_id: &AccountId) {
+        if account_id == &self.tokens.owner_id
+            || env::predecessor_account_id() == self.tokens.owner_id
+        {
+            return;
+        }
+        let count = self.daily_mint_count(account_id);
+        require!(
+            count < self.max_daily_mints_per_account,
+            "Daily mint limit reached"
+        );
+        // replaces the counter of a past day
+        self.daily_mint_count
+            .insert(account_id, &(today(), count + 1));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_max_daily_mints_per_account(&mut self, max_daily_mints_per_account: u32) {
+        self.assert_owner();
+        self.max_daily_mints_per_account = max_daily_mints_per_account;
+        self.internal_audit("set_max_daily_mints_per_account", None, None);
+        events::emit(vec![MaxDailyMintsUpdateLog {
+            max_daily_mints_per_account,
+        }]);
+    }
+
+    /// Stop every mint for good
+    pub fn close_minting(&mut self) {
+        self.assert_owner();
+        require!(!self.minting_closed, "Minting closed");
+        self.minting_closed = true;
+        self.internal_audit("close_minting", None, None);
+        events::emit(vec![MintingClosedLog {}]);
+    }
+
+    pub fn minting_closed(&self) -> bool {
+        self.minting_closed
+    }
+
+    /// Mints `account_id` may still receive today
+    pub fn nft_daily_mint_limit(&self, account_id: AccountId) -> u32 {
+        if account_id == self.tokens.owner_id {
+            return u32::MAX;
+        }
+        self.max_daily_mints_per_account
+            .saturating_sub(self.daily_mint_count(&account_id))
+    }
+}
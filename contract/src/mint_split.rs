@@ -0,0 +1,74 @@
+/*!
+Collaborative mint: the price of every paid mint is split between team accounts in basis points
+and sent to them in the same call, nothing accumulates on the contract. A donation to the charity
+account, if configured, takes precedence over the split.
+
+Shares are rounded down, the rounding dust goes to the first account.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::events::{self, MintPayoutAccountsUpdateLog};
+use crate::royalty::{FULL_BASIS_POINTS, MAX_ROYALTY_PAYEES};
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintPayoutAccount {
+    pub account_id: AccountId,
+    /// Share of each mint price in basis points
+    pub bps: u32,
+}
+
+impl Contract {
+    /// Send `price` to the mint payout accounts, returns whether any are configured
+    pub(crate) fn internal_split_mint_price(&self, price: Balance) -> bool {
+        let Some((first, rest)) = self.mint_payout_accounts.split_first() else {
+            return false;
+        };
+        let mut paid = 0;
+        for payout_account in rest {
+            let share =
+                price * Balance::from(payout_account.bps) / Balance::from(FULL_BASIS_POINTS);
+            if share > 0 {
+                Promise::new(payout_account.account_id.clone()).transfer(share);
+            }
+            paid += share;
+        }
+        // the first share takes the rounding dust
+        Promise::new(first.account_id.clone()).transfer(price - paid);
+        true
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Split the price of every paid mint between `payout_accounts`, whose shares must add up
+    /// to 10000 basis points. An empty list sends the price to the contract owner again
+    pub fn set_mint_payout_accounts(&mut self, payout_accounts: Vec<MintPayoutAccount>) {
+        self.assert_owner();
+        require!(
+            payout_accounts.len() <= MAX_ROYALTY_PAYEES,
+            "Too many payout accounts"
+        );
+        if !payout_accounts.is_empty() {
+            let total_bps = payout_accounts
+                .iter()
+                .try_fold(0u32, |total, payout_account| {
+                    total.checked_add(payout_account.bps)
+                });
+            require!(
+                total_bps == Some(FULL_BASIS_POINTS),
+                "Payout shares must add up to 10000 basis points"
+            );
+        }
+        self.mint_payout_accounts = payout_accounts.clone();
+        self.internal_audit("set_mint_payout_accounts", None, None);
+        events::emit(vec![MintPayoutAccountsUpdateLog { payout_accounts }]);
+    }
+
+    pub fn mint_payout_accounts(&self) -> Vec<MintPayoutAccount> {
+        self.mint_payout_accounts.clone()
+    }
+}
@@ -0,0 +1,452 @@
+/*!
+Core NEP-171 methods. Approvals cleared by a transfer are refunded to the previous owner.
+
+The owner may lock transfers of the whole collection until a timestamp, e.g. for a vesting
+drop. Minting isn't affected.
+
+`nft_transfer_call` snapshots the cleared approvals so they are restored if the receiver
+returns the token. The approvals storage the previous owner paid for is held until resolution
+and covers the snapshot, so 1 yoctoNEAR is enough as the standard requires.
+Refunds of the resolution aren't followed by a callback, its gas only covers the transfers.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::core::{
+    NonFungibleTokenCore, NonFungibleTokenResolver,
+};
+use near_contract_standards::non_fungible_token::{bytes_for_approved_account_id, Token, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, near_bindgen, require, AccountId, Balance, Gas,
+    PromiseOrValue, PromiseResult, StorageUsage,
+};
+
+use crate::composable::MAX_CHILDREN_PER_PARENT;
+use crate::events::{self, GlobalTransferUnlockLog, NftTransferAllLog};
+use crate::storage::{
+    approvals_record_bytes, refund_released_storage, refund_released_storage_uncounted,
+};
+use crate::{Contract, ContractExt};
+
+/// Moving an attached child along with its parent, about twice what its host calls take
+const GAS_FOR_MOVED_CHILD: Gas = Gas(4_000_000_000_000);
+/// Resolution of a token returned without children
+pub(crate) const GAS_FOR_RESOLVE_CHILDLESS_TRANSFER: Gas = Gas(10_000_000_000_000);
+/// A parent returned along with the most children it may have takes the most
+pub(crate) const GAS_FOR_RESOLVE_TRANSFER: Gas =
+    Gas(GAS_FOR_RESOLVE_CHILDLESS_TRANSFER.0 + MAX_CHILDREN_PER_PARENT * GAS_FOR_MOVED_CHILD.0);
+const GAS_FOR_NFT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000
+    + MAX_CHILDREN_PER_PARENT * GAS_FOR_MOVED_CHILD.0
+    + GAS_FOR_RESOLVE_TRANSFER.0);
+/// Bounds gas used by a single batch transfer
+const MAX_BATCH_TRANSFER_TOKENS: usize = 50;
+
+/// Approvals of a token cleared by a pending `nft_transfer_call`
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ApprovalSnapshot {
+    pub approved_account_ids: HashMap<AccountId, u64>,
+}
+
+/// Only the generated `ext_nft_receiver` is used
+#[allow(dead_code)]
+#[ext_contract(ext_nft_receiver)]
+trait NonFungibleTokenReceiver {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool>;
+}
+
+impl Contract {
+    /// Why `token_id` can't change hands at the moment, if it can't.
+    /// Every mechanism holding a token in place is checked here
+    pub(crate) fn internal_transfer_lock(&self, token_id: &TokenId) -> Option<String> {
+        if self.is_staked(token_id) {
+            return Some("Token is staked".into());
+        }
+        if self.is_leased(token_id) {
+            return Some("Token is leased".into());
+        }
+        if self.is_attached(token_id) {
+            return Some("Token is attached".into());
+        }
+        if self.is_soulbound(token_id) {
+            return Some("Token is soulbound".into());
+        }
+        if self.is_collateralized(token_id) {
+            return Some("Token is collateralized".into());
+        }
+        if self.is_bridged(token_id) {
+            return Some("Token is bridged".into());
+        }
+        let now = env::block_timestamp() / 1_000_000_000;
+        if now < self.global_transfer_unlock {
+            return Some(format!(
+                "Transfers locked until {}",
+                self.global_transfer_unlock
+            ));
+        }
+        None
+    }
+
+    /// Transfer `token_id` from its owner to `receiver_id` checking that `sender_id` is allowed to,
+    /// `price` is the sale price if the transfer settles a sale.
+    /// Approvals are cleared and returned along with the previous owner, a listing is removed
+    pub(crate) fn internal_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: &TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        price: Option<Balance>,
+    ) -> (AccountId, Option<HashMap<AccountId, u64>>) {
+        let (owner_id, approved_account_ids, storage_released) =
+            self.internal_transfer_unlogged(sender_id, receiver_id, token_id, approval_id, price);
+        refund_released_storage(storage_released, owner_id.clone());
+        events::emit_transfer(
+            &owner_id,
+            receiver_id,
+            std::slice::from_ref(token_id),
+            Some(sender_id),
+            memo.as_deref(),
+        );
+        (owner_id, approved_account_ids)
+    }
+
+    /// `internal_transfer` leaving the `nft_transfer` event of `token_id` to the caller.
+    /// Attached children are moved along and logged. The storage released by the approvals of
+    /// the children, the listing and the lease is returned as well, for the caller to refund
+    /// to the previous owner who paid for it, less the provenance record of the new owner
+    fn internal_transfer_unlogged(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: &TokenId,
+        approval_id: Option<u64>,
+        price: Option<Balance>,
+    ) -> (AccountId, Option<HashMap<AccountId, u64>>, StorageUsage) {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        if let Some(reason) = self.internal_transfer_lock(token_id) {
+            env::panic_str(&reason);
+        }
+
+        // rolled back by a panic below
+        let approved_account_ids = self
+            .tokens
+            .approvals_by_id
+            .as_mut()
+            .and_then(|by_id| by_id.remove(token_id));
+
+        if sender_id != &owner_id {
+            let actual_approval_id = approved_account_ids
+                .as_ref()
+                .unwrap_or_else(|| env::panic_str("Unauthorized"))
+                .get(sender_id)
+                .unwrap_or_else(|| env::panic_str("Sender not approved"));
+            require!(
+                approval_id.is_none() || approval_id.as_ref() == Some(actual_approval_id),
+                format!(
+                    "The actual approval_id {} is different from the given approval_id {:?}",
+                    actual_approval_id, approval_id
+                )
+            );
+            self.internal_use_approval_conditions(token_id, sender_id, price);
+        }
+        require!(
+            &owner_id != receiver_id,
+            "Current and next owner must differ"
+        );
+        require!(
+            !self.is_null_account(receiver_id),
+            "Cannot transfer to the null account"
+        );
+
+        self.tokens
+            .internal_transfer_unguarded(token_id, &owner_id, receiver_id);
+        let provenance_storage = self.internal_record_transfer(token_id);
+        let mut storage_released =
+            self.internal_move_children(token_id, &owner_id, receiver_id, Some(sender_id));
+        if let Some((_, sale_storage_released)) = self.internal_take_sale(token_id) {
+            storage_released += sale_storage_released;
+        }
+        if let Some((_, lease_storage_released)) = self.internal_take_lease(token_id) {
+            storage_released += lease_storage_released;
+        }
+        self.internal_remove_event_drop(token_id);
+        (
+            owner_id,
+            approved_account_ids,
+            storage_released.saturating_sub(provenance_storage),
+        )
+    }
+
+    /// Refund approvals cleared by a completed transfer along with their conditions
+    pub(crate) fn internal_refund_cleared_approvals(
+        &mut self,
+        token_id: &TokenId,
+        approved_account_ids: Option<HashMap<AccountId, u64>>,
+        previous_owner_id: AccountId,
+    ) {
+        let storage_released = self.internal_clear_approvals(token_id, approved_account_ids);
+        refund_released_storage(storage_released, previous_owner_id);
+    }
+
+    /// Remove the conditions of approvals cleared by a completed transfer.
+    /// Returns the storage released by the approvals and their conditions
+    pub(crate) fn internal_clear_approvals(
+        &mut self,
+        token_id: &TokenId,
+        approved_account_ids: Option<HashMap<AccountId, u64>>,
+    ) -> StorageUsage {
+        match approved_account_ids {
+            Some(approved_account_ids) => {
+                approvals_record_bytes(token_id, &approved_account_ids)
+                    + self
+                        .internal_remove_approval_conditions(token_id, approved_account_ids.keys())
+            }
+            None => 0,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Lock transfers of every token until `unlock_at` in seconds, can be set once
+    pub fn set_global_transfer_unlock(&mut self, unlock_at: u64) {
+        self.assert_owner();
+        require!(
+            self.global_transfer_unlock == 0,
+            "Global transfer unlock is already set"
+        );
+        self.global_transfer_unlock = unlock_at;
+        self.internal_audit("set_global_transfer_unlock", None, None);
+        events::emit(vec![GlobalTransferUnlockLog { unlock_at }]);
+    }
+
+    /// Timestamp in seconds transfers are locked until, 0 if they never were
+    pub fn global_transfer_unlock(&self) -> u64 {
+        self.global_transfer_unlock
+    }
+
+    /// Whether `token_id` exists and nothing holds it in place
+    pub fn nft_is_transferable(&self, token_id: TokenId) -> bool {
+        self.tokens.owner_by_id.contains_key(&token_id)
+            && self.internal_transfer_lock(&token_id).is_none()
+    }
+
+    /// Transfer `token_ids` the caller is approved for to the caller, e.g. an aggregator
+    /// gathering tokens. The transfers are logged in a single `nft_transfer` event
+    #[payable]
+    pub fn nft_batch_transfer_to_self(&mut self, token_ids: Vec<TokenId>) {
+        assert_one_yocto();
+        require!(
+            token_ids.len() <= MAX_BATCH_TRANSFER_TOKENS,
+            "Too many tokens"
+        );
+        let receiver_id = env::predecessor_account_id();
+        let mut transferred = Vec::with_capacity(token_ids.len());
+        // refunded once per previous owner
+        let mut storage_released: HashMap<AccountId, StorageUsage> = HashMap::new();
+        for token_id in token_ids {
+            let (previous_owner_id, approved_account_ids, released) =
+                self.internal_transfer_unlogged(&receiver_id, &receiver_id, &token_id, None, None);
+            *storage_released
+                .entry(previous_owner_id.clone())
+                .or_default() +=
+                released + self.internal_clear_approvals(&token_id, approved_account_ids);
+            self.internal_audit("transfer", Some(&token_id), None);
+            transferred.push((previous_owner_id, token_id));
+        }
+        for (previous_owner_id, released) in storage_released {
+            refund_released_storage(released, previous_owner_id);
+        }
+        events::emit_transfer_batch(&transferred, &receiver_id);
+    }
+
+    /// Transfer up to 50 tokens of the caller to `receiver_id`, call again while more remain.
+    /// Tokens held in place, e.g. staked or attached to a parent, are skipped.
+    /// The storage released by the tokens is refunded in a single transfer
+    #[payable]
+    pub fn nft_transfer_all(&mut self, receiver_id: AccountId, memo: Option<String>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let token_ids: Vec<TokenId> = self
+            .tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|by_owner| by_owner.get(&owner_id))
+            .unwrap_or_else(|| env::panic_str("Caller has no tokens"))
+            .iter()
+            .filter(|token_id| self.internal_transfer_lock(token_id).is_none())
+            .take(MAX_BATCH_TRANSFER_TOKENS)
+            .collect();
+        require!(!token_ids.is_empty(), "Caller has no transferable tokens");
+        let mut storage_released = 0;
+        for token_id in &token_ids {
+            let (_, approved_account_ids, released) =
+                self.internal_transfer_unlogged(&owner_id, &receiver_id, token_id, None, None);
+            storage_released +=
+                released + self.internal_clear_approvals(token_id, approved_account_ids);
+            self.internal_audit("transfer", Some(token_id), None);
+        }
+        // a single refund however many tokens carried approvals, listings or leases
+        refund_released_storage(storage_released, owner_id.clone());
+        events::emit_transfer(&owner_id, &receiver_id, &token_ids, None, memo.as_deref());
+        events::emit(vec![NftTransferAllLog {
+            owner_id,
+            receiver_id,
+            count: token_ids.len() as u32,
+        }]);
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenCore for Contract {
+    #[payable]
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        if self.is_null_account(&receiver_id) {
+            return self.internal_burn_by_owner(token_id, memo);
+        }
+        let sender_id = env::predecessor_account_id();
+        let (previous_owner_id, approved_account_ids) =
+            self.internal_transfer(&sender_id, &receiver_id, &token_id, approval_id, memo, None);
+        self.internal_refund_cleared_approvals(&token_id, approved_account_ids, previous_owner_id);
+        self.internal_audit("transfer", Some(&token_id), None);
+    }
+
+    #[payable]
+    fn nft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        assert_one_yocto();
+        require!(
+            env::prepaid_gas() > GAS_FOR_NFT_TRANSFER_CALL,
+            "More gas is required"
+        );
+        let sender_id = env::predecessor_account_id();
+        let (previous_owner_id, approved_account_ids) =
+            self.internal_transfer(&sender_id, &receiver_id, &token_id, approval_id, memo, None);
+        // the cleared approvals are refunded on resolution, their deposit covers the snapshot
+        if let Some(approved_account_ids) = approved_account_ids {
+            self.transfer_approval_snapshots.insert(
+                &(token_id.clone(), previous_owner_id.clone()),
+                &ApprovalSnapshot {
+                    approved_account_ids,
+                },
+            );
+        }
+        self.internal_audit("transfer", Some(&token_id), None);
+        ext_nft_receiver::ext(receiver_id.clone())
+            .with_static_gas(env::prepaid_gas() - GAS_FOR_NFT_TRANSFER_CALL)
+            .nft_on_transfer(sender_id, previous_owner_id.clone(), token_id.clone(), msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .nft_resolve_transfer(previous_owner_id, receiver_id, token_id, None),
+            )
+            .into()
+    }
+
+    fn nft_token(&self, token_id: TokenId) -> Option<Token> {
+        self.tokens
+            .nft_token(token_id)
+            .map(|token| self.with_default_media(self.with_reveal(token)))
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenResolver for Contract {
+    #[private]
+    fn nft_resolve_transfer(
+        &mut self,
+        previous_owner_id: AccountId,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approved_account_ids: Option<HashMap<AccountId, u64>>,
+    ) -> bool {
+        // the snapshot takes the place of approvals passed along by the standard implementation
+        let approved_account_ids = match self
+            .transfer_approval_snapshots
+            .remove(&(token_id.clone(), previous_owner_id.clone()))
+        {
+            Some(snapshot) => Some(snapshot.approved_account_ids),
+            None => approved_account_ids,
+        };
+
+        // approvals used up by this transfer aren't restored if the receiver returns the token
+        let mut restored_account_ids = approved_account_ids.clone();
+        if let Some(restored_account_ids) = restored_account_ids.as_mut() {
+            self.internal_retain_usable_approvals(&token_id, restored_account_ids);
+        }
+        // the standard implementation refunds approvals of a token the receiver burned and
+        // asked to return, per account only
+        let burned_on_return = !self.tokens.owner_by_id.contains_key(&token_id)
+            && !matches!(
+                env::promise_result(0),
+                PromiseResult::Successful(value) if value == b"false"
+            );
+        let transferred = self.tokens.nft_resolve_transfer(
+            previous_owner_id.clone(),
+            receiver_id.clone(),
+            token_id.clone(),
+            restored_account_ids.clone(),
+        );
+        if !transferred {
+            self.internal_record_owner(&token_id);
+            // children still attached return along with the parent
+            let children_storage_released =
+                self.internal_move_children(&token_id, &receiver_id, &previous_owner_id, None);
+            refund_released_storage_uncounted(children_storage_released, receiver_id.clone());
+        }
+
+        // approvals (and their conditions) are dropped once the token stays with the receiver
+        if let (Some(approved_account_ids), Some(restored_account_ids)) =
+            (approved_account_ids, restored_account_ids)
+        {
+            let mut storage_released = if transferred {
+                self.internal_remove_approval_conditions(&token_id, approved_account_ids.keys())
+            } else {
+                self.internal_remove_approval_conditions(
+                    &token_id,
+                    approved_account_ids
+                        .keys()
+                        .filter(|account_id| !restored_account_ids.contains_key(*account_id)),
+                )
+            };
+            storage_released += approvals_record_bytes(&token_id, &approved_account_ids);
+            if !transferred {
+                storage_released -= approvals_record_bytes(&token_id, &restored_account_ids);
+            } else if burned_on_return {
+                storage_released -= restored_account_ids
+                    .keys()
+                    .map(bytes_for_approved_account_id)
+                    .sum::<StorageUsage>();
+            }
+            refund_released_storage_uncounted(storage_released, previous_owner_id);
+        }
+        if !transferred {
+            self.internal_audit("transfer_return", Some(&token_id), None);
+        }
+        transferred
+    }
+}
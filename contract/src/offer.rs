@@ -0,0 +1,145 @@
+/*!
+Offers on listed tokens. A buyer makes an offer by depositing its amount, held by the
+contract until the seller accepts it or the buyer withdraws it. Offers below the price
+are accepted only by listings open to counter-offers, the seller may accept any offer.
+
+An offer outlives its listing, the buyer withdraws it once the token is sold elsewhere.
+Offer storage is paid by the buyer and refunded along with the offer.
+
+The offered amount is held in escrow as is. Accepting it pays the platform fee and royalties
+out of it right away and the rest goes to the seller.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::events::{self, NftOfferLog, NftOfferWithdrawLog};
+use crate::ft::PaymentToken;
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+impl Contract {
+    /// Remove the offer of `buyer_id` for `token_id`, refunding its storage to the buyer.
+    /// Returns the amount offered
+    fn internal_remove_offer(&mut self, token_id: &TokenId, buyer_id: &AccountId) -> Balance {
+        let initial_storage = env::storage_usage();
+        let amount = self
+            .offers
+            .remove(&(token_id.clone(), buyer_id.clone()))
+            .unwrap_or_else(|| env::panic_str("Offer not found"));
+        refund_released_storage(initial_storage - env::storage_usage(), buyer_id.clone());
+        amount
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Offer `amount` yoctoNEAR for the listed `token_id`. The deposit must cover the amount
+    /// and the offer storage, the excess is refunded
+    #[payable]
+    pub fn nft_make_offer(&mut self, token_id: TokenId, amount: U128) {
+        let buyer_id = env::predecessor_account_id();
+        let sale = self
+            .sales
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token is not listed"));
+        require!(!sale.is_expired(), "Listing expired");
+        require!(
+            sale.payment_token() == PaymentToken::Near,
+            "Token is priced in another currency"
+        );
+        require!(
+            sale.owner_id != buyer_id,
+            "Cannot make an offer on own token"
+        );
+        require!(amount.0 > 0, "Offer must be positive");
+        require!(
+            sale.accept_counter_offers || amount.0 >= sale.current_price(),
+            "Offer is below the ask price"
+        );
+        let key = (token_id.clone(), buyer_id.clone());
+        require!(
+            !self.offers.contains_key(&key),
+            "Offer already made, withdraw it first"
+        );
+
+        let initial_storage = env::storage_usage();
+        self.offers.insert(&key, &amount.0);
+        let storage_cost =
+            Balance::from(env::storage_usage() - initial_storage) * env::storage_byte_cost();
+        let required = amount.0 + storage_cost;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= required,
+            format!(
+                "Must attach {} yoctoNEAR to cover offer and storage",
+                required
+            )
+        );
+        if deposit > required {
+            Promise::new(buyer_id.clone()).transfer(deposit - required);
+        }
+        self.internal_audit("offer", Some(&token_id), Some(amount.0));
+        events::emit(vec![NftOfferLog {
+            token_id,
+            buyer_id,
+            amount,
+        }]);
+    }
+
+    /// Withdraw the caller's offer for `token_id`, the amount and storage are refunded
+    #[payable]
+    pub fn nft_withdraw_offer(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let buyer_id = env::predecessor_account_id();
+        let amount = self.internal_remove_offer(&token_id, &buyer_id);
+        Promise::new(buyer_id.clone()).transfer(amount);
+        self.internal_audit("withdraw_offer", Some(&token_id), Some(amount));
+        events::emit(vec![NftOfferWithdrawLog {
+            token_id,
+            buyer_id,
+            amount: U128(amount),
+        }]);
+    }
+
+    /// Sell `token_id` owned by the caller to `buyer_id` for the amount offered.
+    /// Royalties are paid out of the offer and the rest goes to the seller
+    #[payable]
+    pub fn nft_accept_offer(&mut self, token_id: TokenId, buyer_id: AccountId) {
+        assert_one_yocto();
+        let seller_id = self.assert_token_owner(&token_id);
+        let amount = self.internal_remove_offer(&token_id, &buyer_id);
+        self.internal_sell(
+            &seller_id,
+            &token_id,
+            &buyer_id,
+            &PaymentToken::Near,
+            amount,
+        );
+    }
+
+    /// How `offer_amount` held for `token_id` would be paid out if the current owner accepted it:
+    /// the platform fee, royalties and collaborator shares, and the seller's remainder
+    pub fn nft_royalty_escrow_preview(
+        &self,
+        token_id: TokenId,
+        offer_amount: U128,
+    ) -> HashMap<AccountId, U128> {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        self.internal_payout(&token_id, &owner_id, offer_amount.0)
+            .into_iter()
+            .map(|(account_id, amount)| (account_id, U128(amount)))
+            .collect()
+    }
+
+    /// Amount in yoctoNEAR `buyer_id` offers for `token_id`
+    pub fn nft_offer(&self, token_id: TokenId, buyer_id: AccountId) -> Option<U128> {
+        self.offers.get(&(token_id, buyer_id)).map(U128)
+    }
+}
@@ -0,0 +1,33 @@
+/*!
+Contract ownership. The owner hands the contract over to another account at once, the new
+owner takes over every admin method, platform fees and the mint limit exemption.
+*/
+use near_sdk::{assert_one_yocto, near_bindgen, require, AccountId};
+
+use crate::events::{self, ContractOwnershipTransferredLog};
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    /// Make `new_owner` the contract owner immediately. Requires 1 yoctoNEAR so that
+    /// a function call access key can't hand the contract over
+    #[payable]
+    pub fn nft_transfer_ownership_of_contract(&mut self, new_owner: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(
+            new_owner != self.tokens.owner_id,
+            "Account is already the owner"
+        );
+        let old_owner_id = std::mem::replace(&mut self.tokens.owner_id, new_owner);
+        self.internal_audit("transfer_ownership_of_contract", None, None);
+        events::emit(vec![ContractOwnershipTransferredLog {
+            old_owner_id,
+            new_owner_id: self.tokens.owner_id.clone(),
+        }]);
+    }
+
+    pub fn contract_owner(&self) -> AccountId {
+        self.tokens.owner_id.clone()
+    }
+}
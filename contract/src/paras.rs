@@ -0,0 +1,161 @@
+/*!
+Importing tokens minted on the Paras marketplace. Paras token IDs take the form
+`series_id:copy_number`; an imported token keeps its Paras ID and records its origin
+in the `extra` metadata field.
+
+Paras lists a token when its owner approves the marketplace with a msg such as
+`{"market_type":"sale","price":"1000","ft_token_id":"near"}`. Approve msgs sent to the Paras
+marketplace account set by the contract owner are checked before `nft_on_approve` is called,
+a sale must have a positive price in NEAR or in an FT allowed by the contract owner. Msgs sent
+to other accounts are passed along as they are.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, Token, TokenId};
+use near_sdk::serde_json::{self, json, Map, Value};
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::events::{self, ParasFtTokenUpdateLog, ParasMarketplaceUpdateLog};
+use crate::{Contract, ContractExt};
+
+/// Key of the Paras origin in the `extra` metadata field of an imported token
+const PARAS_ORIGIN_KEY: &str = "paras_origin";
+/// `ft_token_id` of a Paras sale priced in NEAR
+const PARAS_NEAR_TOKEN_ID: &str = "near";
+
+fn is_paras_number(part: &str) -> bool {
+    !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Check `token_id` is a Paras token ID of `token_series_id`
+fn assert_paras_token_id(token_series_id: &str, token_id: &str) {
+    let (series_id, copy_number) = token_id
+        .split_once(':')
+        .unwrap_or_else(|| env::panic_str("Invalid Paras token ID"));
+    require!(
+        is_paras_number(series_id) && is_paras_number(copy_number),
+        "Invalid Paras token ID"
+    );
+    require!(
+        series_id == token_series_id,
+        "Token ID doesn't belong to the Paras series"
+    );
+}
+
+/// `extra` of `metadata` extended with the Paras origin, existing `extra` must be a JSON object
+fn extra_with_paras_origin(
+    metadata: &TokenMetadata,
+    token_series_id: &str,
+    token_id: &str,
+) -> String {
+    let mut extra = match metadata.extra.as_deref() {
+        Some(extra) => serde_json::from_str::<Map<String, Value>>(extra)
+            .unwrap_or_else(|_| env::panic_str("Extra must be a JSON object")),
+        None => Map::new(),
+    };
+    extra.insert(
+        PARAS_ORIGIN_KEY.into(),
+        json!({
+            "token_series_id": token_series_id,
+            "token_id": token_id,
+        }),
+    );
+    Value::Object(extra).to_string()
+}
+
+impl Contract {
+    /// Check `msg` of an approval of `account_id` is a JSON object and a Paras sale in it has
+    /// a positive price in NEAR or an allowed FT, if `account_id` is the Paras marketplace
+    pub(crate) fn assert_approve_msg(&self, account_id: &AccountId, msg: &str) {
+        if self.paras_marketplace.as_ref() != Some(account_id) {
+            return;
+        }
+        let fields = serde_json::from_str::<Map<String, Value>>(msg)
+            .unwrap_or_else(|_| env::panic_str("Approve msg must be a JSON object"));
+        if fields.get("market_type").and_then(Value::as_str) != Some("sale") {
+            return;
+        }
+        let price = fields
+            .get("price")
+            .and_then(Value::as_str)
+            .and_then(|price| price.parse::<u128>().ok())
+            .unwrap_or_else(|| env::panic_str("Sale price must be a U128 string"));
+        require!(price > 0, "Sale price must be positive");
+        let ft_token_id = fields
+            .get("ft_token_id")
+            .and_then(Value::as_str)
+            .unwrap_or(PARAS_NEAR_TOKEN_ID);
+        if ft_token_id != PARAS_NEAR_TOKEN_ID {
+            let accepted = ft_token_id
+                .parse::<AccountId>()
+                .is_ok_and(|ft_token_id| self.paras_ft_tokens.contains(&ft_token_id));
+            require!(accepted, format!("FT {} is not accepted", ft_token_id));
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allow or disallow sales in `ft_token_id` in approve msgs
+    pub fn set_paras_ft_token(&mut self, ft_token_id: AccountId, accepted: bool) {
+        self.assert_owner();
+        if accepted {
+            self.paras_ft_tokens.insert(&ft_token_id);
+        } else {
+            self.paras_ft_tokens.remove(&ft_token_id);
+        }
+        self.internal_audit("set_paras_ft_token", None, None);
+        events::emit(vec![ParasFtTokenUpdateLog {
+            ft_token_id,
+            accepted,
+        }]);
+    }
+
+    /// FTs besides NEAR sales in approve msgs may be priced in
+    pub fn paras_ft_tokens(&self) -> Vec<AccountId> {
+        self.paras_ft_tokens.to_vec()
+    }
+
+    /// Check approve msgs sent to `paras_marketplace`, `None` disables the check
+    pub fn set_paras_marketplace(&mut self, paras_marketplace: Option<AccountId>) {
+        self.assert_owner();
+        self.paras_marketplace = paras_marketplace.clone();
+        self.internal_audit("set_paras_marketplace", None, None);
+        events::emit(vec![ParasMarketplaceUpdateLog { paras_marketplace }]);
+    }
+
+    pub fn paras_marketplace(&self) -> Option<AccountId> {
+        self.paras_marketplace.clone()
+    }
+
+    /// Mint the Paras token `token_id` of `paras_token_series_id` with its Paras `metadata`
+    /// to the contract owner. The token keeps its Paras ID
+    #[payable]
+    pub fn nft_migrate_from_paras(
+        &mut self,
+        paras_token_series_id: String,
+        token_id: TokenId,
+        metadata: TokenMetadata,
+    ) -> Token {
+        self.assert_owner();
+        assert_paras_token_id(&paras_token_series_id, &token_id);
+        let extra = extra_with_paras_origin(&metadata, &paras_token_series_id, &token_id);
+        let metadata = TokenMetadata {
+            extra: Some(extra),
+            ..metadata
+        };
+
+        let initial_storage = env::storage_usage();
+        let token = self.internal_mint_with_royalties(
+            token_id,
+            self.tokens.owner_id.clone(),
+            metadata,
+            None,
+        );
+        let storage_used = env::storage_usage() - initial_storage;
+        refund_deposit_to_account(storage_used, env::predecessor_account_id());
+        self.internal_audit("mint", Some(&token.token_id), None);
+        events::emit_mint(&token.owner_id, std::slice::from_ref(&token.token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        token
+    }
+}
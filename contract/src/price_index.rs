@@ -0,0 +1,65 @@
+/*!
+Listings at a fixed NEAR price sorted by price in a tree, so a price range is found and
+a listing indexed or removed in logarithmic time. Dutch auctions and FT prices aren't indexed.
+
+An index entry is part of the listing storage paid by the seller.
+*/
+use std::ops::Bound;
+
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, require, Balance};
+
+use crate::sale::Sale;
+use crate::{Contract, ContractExt};
+
+fn indexed_price(sale: &Sale) -> Option<Balance> {
+    (sale.dutch_auction.is_none() && sale.ft_token_id.is_none()).then_some(sale.price.0)
+}
+
+impl Contract {
+    /// Index the fixed NEAR price `sale` of `token_id`
+    pub(crate) fn internal_index_listing(&mut self, token_id: &TokenId, sale: &Sale) {
+        if let Some(price) = indexed_price(sale) {
+            self.price_index.insert(&(price, token_id.clone()), &());
+        }
+    }
+
+    /// Remove the index entry of `sale` of `token_id`, if indexed
+    pub(crate) fn internal_unindex_listing(&mut self, token_id: &TokenId, sale: &Sale) {
+        if let Some(price) = indexed_price(sale) {
+            self.price_index.remove(&(price, token_id.clone()));
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Tokens listed at a fixed price from `min` to `max` yoctoNEAR along with the price,
+    /// cheapest first. Expired listings are skipped
+    pub fn nft_tokens_by_price_range(
+        &self,
+        min: U128,
+        max: U128,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<(Token, U128)> {
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        self.price_index
+            .range((Bound::Included((min.0, String::new())), Bound::Unbounded))
+            .map(|(key, _)| key)
+            .take_while(|(price, _)| *price <= max.0)
+            .filter(|(_, token_id)| {
+                self.sales
+                    .get(token_id)
+                    .is_some_and(|sale| !sale.is_expired())
+            })
+            .skip(start_index as usize)
+            .take(limit)
+            .filter_map(|(price, token_id)| Some((self.nft_token(token_id)?, U128(price))))
+            .collect()
+    }
+}
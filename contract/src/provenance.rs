@@ -0,0 +1,83 @@
+/*!
+Provenance: the owners a token had since it was minted along with when they got it, oldest first.
+
+The history keeps every owner, long ones are read a page at a time. Mint records are paid by
+the minter. As transfers carry no deposit,
+a transfer record is paid out of the storage the transfer releases to the previous owner,
+the contract covers what that doesn't. The history is cleared when the token is burned.
+*/
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U64;
+use near_sdk::{env, near_bindgen, require, AccountId, StorageUsage};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Bounds gas used by a single page of the provenance view
+const MAX_PROVENANCE_PAGE_LEN: u64 = 100;
+
+impl Contract {
+    /// Append the current owner of `token_id` to its provenance.
+    /// Returns the storage taken by the record
+    pub(crate) fn internal_record_owner(&mut self, token_id: &TokenId) -> StorageUsage {
+        let owner_id = match self.tokens.owner_by_id.get(token_id) {
+            Some(owner_id) => owner_id,
+            None => return 0,
+        };
+        let initial_storage = env::storage_usage();
+        let mut owners = self.provenance.get(token_id).unwrap_or_else(|| {
+            Vector::new(StorageKey::ProvenancePerToken {
+                token_hash: env::sha256(token_id.as_bytes()),
+            })
+        });
+        owners.push(&(owner_id, env::block_timestamp() / 1_000_000_000));
+        self.provenance.insert(token_id, &owners);
+        env::storage_usage().saturating_sub(initial_storage)
+    }
+
+    /// Remove the history of the burned `token_id`
+    pub(crate) fn internal_clear_provenance(&mut self, token_id: &TokenId) {
+        if let Some(mut owners) = self.provenance.remove(token_id) {
+            owners.clear();
+        }
+    }
+
+    /// The latest `limit` owners of `token_id` oldest first along with the time they got it
+    pub(crate) fn internal_recent_owners(
+        &self,
+        token_id: &TokenId,
+        limit: u64,
+    ) -> Vec<(AccountId, U64)> {
+        let owners = match self.provenance.get(token_id) {
+            Some(owners) => owners,
+            None => return vec![],
+        };
+        (owners.len().saturating_sub(limit)..owners.len())
+            .filter_map(|index| owners.get(index))
+            .map(|(owner_id, acquired_at)| (owner_id, U64(acquired_at)))
+            .collect()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Owners of `token_id` oldest first along with the time in seconds they got it,
+    /// at most 100 per call
+    pub fn nft_provenance_page(
+        &self,
+        token_id: TokenId,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<(AccountId, U64)> {
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let owners = match self.provenance.get(&token_id) {
+            Some(owners) => owners,
+            None => return vec![],
+        };
+        (from_index..owners.len())
+            .take(limit.min(MAX_PROVENANCE_PAGE_LEN) as usize)
+            .filter_map(|index| owners.get(index))
+            .map(|(owner_id, acquired_at)| (owner_id, U64(acquired_at)))
+            .collect()
+    }
+}
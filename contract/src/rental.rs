@@ -0,0 +1,169 @@
+/*!
+Leases granting a temporary user of a token without transferring it, e.g. to lend out
+an AR experience.
+
+The owner offers a lease to a lessee, who starts it by paying the fee. The fee is paid out
+like a sale price, to royalty payees, collaborators and the charity, the rest to the owner.
+While the lease is active the token can't be transferred, listed or burned, it expires by
+itself. Accepting a lease removes the listing of the token. Lease storage is paid by the owner
+and refunded once the lease is replaced or the token changes hands.
+*/
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, Promise, StorageUsage};
+
+use crate::events::{self, NftLeaseAcceptLog, NftLeaseLog};
+use crate::ft::PaymentToken;
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Lease {
+    /// Owner who offered the lease and paid its storage
+    pub owner_id: AccountId,
+    pub lessee_id: AccountId,
+    /// Block timestamp in seconds the lease ends at
+    pub until: U64,
+    pub fee: U128,
+    pub accepted: bool,
+}
+
+impl Lease {
+    pub fn is_active(&self) -> bool {
+        self.accepted && env::block_timestamp() / 1_000_000_000 < self.until.0
+    }
+}
+
+impl Contract {
+    pub(crate) fn is_leased(&self, token_id: &TokenId) -> bool {
+        matches!(self.leases.get(token_id), Some(lease) if lease.is_active())
+    }
+
+    /// Remove the lease of `token_id` if any, refunding its storage to the owner who offered it
+    pub(crate) fn internal_remove_lease(&mut self, token_id: &TokenId) {
+        if let Some((lease, storage_released)) = self.internal_take_lease(token_id) {
+            refund_released_storage(storage_released, lease.owner_id);
+        }
+    }
+
+    /// `internal_remove_lease` leaving the refund of the released storage to the caller
+    pub(crate) fn internal_take_lease(
+        &mut self,
+        token_id: &TokenId,
+    ) -> Option<(Lease, StorageUsage)> {
+        let initial_storage = env::storage_usage();
+        let lease = self.leases.remove(token_id)?;
+        Some((lease, initial_storage - env::storage_usage()))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Offer the caller's `token_id` to `lessee` until `until` in seconds for `fee` yoctoNEAR.
+    /// Replaces a lease that isn't active, the deposit must cover the lease storage
+    #[payable]
+    pub fn nft_lease(&mut self, token_id: TokenId, lessee: AccountId, until: U64, fee: U128) {
+        let owner_id = self.assert_token_owner(&token_id);
+        if let Some(reason) = self.internal_transfer_lock(&token_id) {
+            env::panic_str(&reason);
+        }
+        require!(lessee != owner_id, "Cannot lease to the owner");
+        require!(
+            until.0 > env::block_timestamp() / 1_000_000_000,
+            "Lease must end in the future"
+        );
+
+        self.internal_remove_lease(&token_id);
+        let initial_storage = env::storage_usage();
+        self.leases.insert(
+            &token_id,
+            &Lease {
+                owner_id: owner_id.clone(),
+                lessee_id: lessee.clone(),
+                until,
+                fee,
+                accepted: false,
+            },
+        );
+        refund_deposit_to_account(env::storage_usage() - initial_storage, owner_id.clone());
+        self.internal_audit("lease", Some(&token_id), Some(fee.0));
+        events::emit(vec![NftLeaseLog {
+            token_id,
+            owner_id,
+            lessee_id: lessee,
+            until,
+            fee,
+        }]);
+    }
+
+    /// Start the lease of `token_id` offered to the caller paying its fee,
+    /// the excess deposit is refunded
+    #[payable]
+    pub fn nft_accept_lease(&mut self, token_id: TokenId) {
+        let lessee_id = env::predecessor_account_id();
+        let mut lease = self
+            .leases
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Lease not found"));
+        require!(
+            lease.lessee_id == lessee_id,
+            "Lease is offered to another account"
+        );
+        require!(!lease.accepted, "Lease is already accepted");
+        require!(
+            env::block_timestamp() / 1_000_000_000 < lease.until.0,
+            "Lease expired"
+        );
+        // the token may have been locked since the lease was offered
+        if let Some(reason) = self.internal_transfer_lock(&token_id) {
+            env::panic_str(&reason);
+        }
+        let fee: Balance = lease.fee.0;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= fee,
+            format!("Must attach {} yoctoNEAR to cover the lease fee", fee)
+        );
+
+        lease.accepted = true;
+        self.leases.insert(&token_id, &lease);
+        // the token can't be sold while leased
+        self.internal_remove_sale(&token_id);
+        self.internal_distribute(
+            &token_id,
+            &lease.owner_id,
+            &lessee_id,
+            &PaymentToken::Near,
+            fee,
+        );
+        if deposit > fee {
+            Promise::new(lessee_id.clone()).transfer(deposit - fee);
+        }
+        self.internal_audit("lease_accept", Some(&token_id), Some(fee));
+        events::emit(vec![NftLeaseAcceptLog {
+            token_id,
+            lessee_id,
+            until: lease.until,
+            fee: lease.fee,
+        }]);
+    }
+
+    pub fn nft_lease_of(&self, token_id: TokenId) -> Option<Lease> {
+        self.leases.get(&token_id)
+    }
+
+    /// Lessee of `token_id` while its lease is active, the owner otherwise
+    pub fn nft_current_user(&self, token_id: TokenId) -> AccountId {
+        match self.leases.get(&token_id) {
+            Some(lease) if lease.is_active() => lease.lessee_id,
+            _ => self
+                .tokens
+                .owner_by_id
+                .get(&token_id)
+                .unwrap_or_else(|| env::panic_str("Token not found")),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/*!
+Resale price caps set at mint, so a token can't be listed or sold above its cap.
+
+A cap is in yoctoNEAR, a capped token can't be listed for an FT. The owner may set a default
+cap applied to every token minted without one.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, Token, TokenId};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::{self, DefaultResaleCapUpdateLog};
+use crate::ft::PaymentToken;
+use crate::{Contract, ContractExt};
+
+impl Contract {
+    /// Whether `token_id` may be sold for `price` of `payment_token`
+    pub(crate) fn is_within_resale_cap(
+        &self,
+        token_id: &TokenId,
+        price: Balance,
+        payment_token: &PaymentToken,
+    ) -> bool {
+        match self.max_resale_prices.get(token_id) {
+            Some(max_resale_price) => {
+                *payment_token == PaymentToken::Near && price <= max_resale_price
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn assert_within_resale_cap(
+        &self,
+        token_id: &TokenId,
+        price: Balance,
+        payment_token: &PaymentToken,
+    ) {
+        if !self.is_within_resale_cap(token_id, price, payment_token) {
+            env::panic_str(&format!(
+                "Price exceeds the resale cap of {} yoctoNEAR",
+                self.max_resale_prices.get(token_id).unwrap_or_default()
+            ));
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Cap applied to tokens minted without one, `None` leaves them uncapped
+    pub fn set_default_max_resale_price(&mut self, default_max_resale_price: Option<U128>) {
+        self.assert_owner();
+        self.default_max_resale_price = default_max_resale_price.map(|price| price.0);
+        self.internal_audit("set_default_max_resale_price", None, None);
+        events::emit(vec![DefaultResaleCapUpdateLog {
+            default_max_resale_price,
+        }]);
+    }
+
+    pub fn default_max_resale_price(&self) -> Option<U128> {
+        self.default_max_resale_price.map(U128)
+    }
+
+    /// Mint a new token with ID=`token_id` belonging to `token_owner_id` that can't be
+    /// resold above `max_resale_price` yoctoNEAR
+    #[payable]
+    pub fn nft_mint_with_resale_cap(
+        &mut self,
+        token_id: TokenId,
+        token_owner_id: AccountId,
+        token_metadata: TokenMetadata,
+        max_resale_price: U128,
+    ) -> Token {
+        self.assert_owner();
+        let initial_storage = env::storage_usage();
+        self.internal_count_daily_mint(&token_owner_id);
+        let token =
+            self.internal_mint_with_royalties(token_id, token_owner_id, token_metadata, None);
+        self.max_resale_prices
+            .insert(&token.token_id, &max_resale_price.0);
+        let storage_used = env::storage_usage() - initial_storage;
+        refund_deposit_to_account(storage_used, env::predecessor_account_id());
+        self.internal_audit("mint", Some(&token.token_id), None);
+        events::emit_mint(&token.owner_id, std::slice::from_ref(&token.token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        token
+    }
+
+    /// Highest price in yoctoNEAR `token_id` can be resold for, `None` if uncapped
+    pub fn nft_max_resale_price(&self, token_id: TokenId) -> Option<U128> {
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        self.max_resale_prices.get(&token_id).map(U128)
+    }
+}
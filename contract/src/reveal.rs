@@ -0,0 +1,100 @@
+/*!
+Hidden drops: while unrevealed metadata is configured every token is shown with it instead of
+its own metadata. A holder may reveal their token early by paying the reveal fee, which goes
+to the contract owner.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, Balance, Promise};
+
+use crate::events::{self, RevealConfigUpdateLog, TokenRevealLog};
+use crate::{Contract, ContractExt};
+
+impl Contract {
+    /// Show the unrevealed metadata in place of the metadata of a hidden token
+    pub(crate) fn with_reveal(&self, mut token: Token) -> Token {
+        if let Some(unrevealed_metadata) = &self.unrevealed_metadata {
+            if !self.revealed_tokens.contains(&token.token_id) {
+                token.metadata = Some(unrevealed_metadata.clone());
+            }
+        }
+        token
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Hide every token behind `unrevealed_metadata`, `None` reveals the whole collection.
+    /// `reveal_fee` is charged by `pay_to_reveal`, `None` disables early reveals
+    pub fn set_reveal_config(
+        &mut self,
+        unrevealed_metadata: Option<TokenMetadata>,
+        reveal_fee: Option<U128>,
+    ) {
+        self.assert_owner();
+        self.unrevealed_metadata = unrevealed_metadata;
+        self.reveal_fee = reveal_fee.map(|fee| fee.0);
+        self.internal_audit("set_reveal_config", None, None);
+        events::emit(vec![RevealConfigUpdateLog {
+            hidden: self.unrevealed_metadata.is_some(),
+            reveal_fee,
+        }]);
+    }
+
+    pub fn reveal_fee(&self) -> Option<U128> {
+        self.reveal_fee.map(U128)
+    }
+
+    pub fn nft_is_revealed(&self, token_id: TokenId) -> bool {
+        self.unrevealed_metadata.is_none() || self.revealed_tokens.contains(&token_id)
+    }
+
+    /// Reveal the caller's token before the collection is revealed, charging the reveal fee
+    /// plus storage. The excess deposit is refunded
+    #[payable]
+    pub fn pay_to_reveal(&mut self, token_id: TokenId) {
+        let reveal_fee: Balance = self
+            .reveal_fee
+            .unwrap_or_else(|| env::panic_str("Pay to reveal is disabled"));
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+        require!(
+            !self.nft_is_revealed(token_id.clone()),
+            "Token is already revealed"
+        );
+
+        let initial_storage = env::storage_usage();
+        self.revealed_tokens.insert(&token_id);
+        let storage_cost =
+            Balance::from(env::storage_usage() - initial_storage) * env::storage_byte_cost();
+        let required = reveal_fee + storage_cost;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= required,
+            format!(
+                "Must attach {} yoctoNEAR to cover reveal fee and storage",
+                required
+            )
+        );
+        if reveal_fee > 0 {
+            Promise::new(self.tokens.owner_id.clone()).transfer(reveal_fee);
+        }
+        if deposit > required {
+            Promise::new(owner_id.clone()).transfer(deposit - required);
+        }
+        self.internal_audit("reveal", Some(&token_id), Some(reveal_fee));
+        events::emit(vec![TokenRevealLog {
+            token_id,
+            owner_id,
+            fee: U128(reveal_fee),
+        }]);
+    }
+}
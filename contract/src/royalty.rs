@@ -0,0 +1,486 @@
+/*!
+Perpetual royalties set at mint, in basis points of the sale price.
+
+Tokens minted without royalties of their own get the default royalties. The owner may require
+every minted token to carry a minimum total royalty, counting its collaborators, which the
+default royalties have to meet on their own.
+Donation badges are soulbound and never sold, they carry no royalties.
+
+The owner may charge a platform fee on every sale. It is taken off the top, royalties are
+paid from what's left. Royalties and collaborators of a token being minted, as well as the
+default royalties, must leave room for the platform fee: together they can't exceed 10000
+basis points. A fee raised later only shrinks what royalties are paid from.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::collaborators::Collaborator;
+use crate::events::{self, PlatformFeeUpdateLog, RoyaltyPolicyUpdateLog};
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Basis points of the whole price
+pub(crate) const FULL_BASIS_POINTS: u32 = 10_000;
+/// Bounds gas used to pay out royalties of a token
+pub(crate) const MAX_ROYALTY_PAYEES: usize = 10;
+
+/// NEP-199 payout: amounts of the sale price owed to each account. Marketplaces such as
+/// Mintbase read the `payout` field and the amounts as strings exactly as serialized here
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    pub payout: HashMap<AccountId, U128>,
+}
+
+/// Where the price of a sale goes
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeBreakdown {
+    /// Paid to the contract owner
+    pub platform_fee: U128,
+    /// Royalty payees and collaborators
+    pub royalties: HashMap<AccountId, U128>,
+    pub seller_net: U128,
+}
+
+/// Outcome of a hypothetical sale
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleSimulation {
+    pub seller_receives: U128,
+    /// Royalty payees and collaborators sorted by account
+    pub royalty_payouts: Vec<(AccountId, U128)>,
+    pub platform_fee: U128,
+    pub total_price: U128,
+}
+
+/// Fees and prices the contract charges, the fields it has nothing for are always 0
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSummary {
+    /// Basis points of every sale price paid to the contract owner
+    pub platform_fee_bps: u32,
+    /// Always 0, referrals aren't rewarded
+    pub referral_fee_bps: u32,
+    /// Always 0, the charity gets mint proceeds and the royalties of tokens naming it
+    /// rather than a cut of every sale
+    pub charity_fee_bps: u32,
+    /// Price of the next token minted by `nft_mint_curve`, 0 if the mint curve isn't configured
+    pub mint_price: U128,
+    /// Always 0, there's no presale
+    pub presale_price: U128,
+    /// Always 0, staking accrues points per whole day rather than yoctoNEAR
+    pub reward_rate_per_second: U128,
+    /// Always 0, royalties are paid out on every sale and there's nothing to claim
+    pub min_royalty_claim: U128,
+}
+
+fn total_bps(royalties: &HashMap<AccountId, u16>) -> u32 {
+    royalties.values().map(|bps| u32::from(*bps)).sum()
+}
+
+impl Contract {
+    /// Panics unless royalties and collaborators totaling `royalty_bps` leave room
+    /// for the platform fee
+    pub(crate) fn assert_royalties_fit_platform_fee(&self, royalty_bps: u32) {
+        require!(
+            royalty_bps + u32::from(self.platform_fee_bps) <= FULL_BASIS_POINTS,
+            "Royalties and the platform fee can't exceed 10000 basis points"
+        );
+    }
+
+    /// Mint `token_id` to `owner_id` with perpetual `royalties`, or the default royalties
+    /// if `None`. Panics if they total less than the minimum royalty
+    pub(crate) fn internal_mint_with_royalties(
+        &mut self,
+        token_id: TokenId,
+        owner_id: AccountId,
+        token_metadata: TokenMetadata,
+        royalties: Option<HashMap<AccountId, u16>>,
+    ) -> Token {
+        self.internal_mint_with_collaborators(
+            token_id,
+            owner_id,
+            token_metadata,
+            royalties,
+            Vec::new(),
+        )
+    }
+
+    /// Mint `token_id` to `owner_id` with perpetual `royalties`, or the default royalties
+    /// if `None`, next to `collaborators`. Panics unless royalties and collaborators together
+    /// total at least the minimum royalty and leave room for the platform fee
+    pub(crate) fn internal_mint_with_collaborators(
+        &mut self,
+        token_id: TokenId,
+        owner_id: AccountId,
+        token_metadata: TokenMetadata,
+        royalties: Option<HashMap<AccountId, u16>>,
+        collaborators: Vec<Collaborator>,
+    ) -> Token {
+        let royalties = royalties.unwrap_or_else(|| self.default_royalties.clone());
+        let combined_bps = collaborators
+            .iter()
+            .fold(total_bps(&royalties), |total, collaborator| {
+                total.saturating_add(collaborator.royalty_bps)
+            });
+        require!(
+            combined_bps <= FULL_BASIS_POINTS,
+            "Royalties can't exceed 10000 basis points"
+        );
+        self.assert_royalties_fit_platform_fee(combined_bps);
+        require!(
+            combined_bps >= u32::from(self.min_royalty_bps),
+            format!(
+                "Royalties must total at least {} basis points",
+                self.min_royalty_bps
+            )
+        );
+        let token = self.internal_mint(token_id, owner_id, token_metadata);
+        self.internal_set_royalties(&token.token_id, &royalties);
+        if !collaborators.is_empty() {
+            self.internal_update_token_extras(&token.token_id, |extras| {
+                extras.collaborators = collaborators;
+            });
+        }
+        token
+    }
+
+    /// Store perpetual `royalties` of the freshly minted token `token_id`
+    pub(crate) fn internal_set_royalties(
+        &mut self,
+        token_id: &TokenId,
+        royalties: &HashMap<AccountId, u16>,
+    ) {
+        require!(
+            royalties.len() <= MAX_ROYALTY_PAYEES,
+            "Too many royalty payees"
+        );
+        require!(
+            total_bps(royalties) <= FULL_BASIS_POINTS,
+            "Royalties can't exceed 10000 basis points"
+        );
+        self.assert_royalties_fit_platform_fee(total_bps(royalties));
+        if royalties.is_empty() {
+            return;
+        }
+
+        self.internal_update_token_extras(token_id, |extras| {
+            extras.royalties = royalties.clone();
+        });
+        for account_id in royalties.keys() {
+            let mut token_ids = self
+                .royalty_tokens_per_account
+                .get(account_id)
+                .unwrap_or_else(|| {
+                    UnorderedSet::new(StorageKey::RoyaltyTokensPerAccount {
+                        account_hash: env::sha256(account_id.as_bytes()),
+                    })
+                });
+            token_ids.insert(token_id);
+            self.royalty_tokens_per_account
+                .insert(account_id, &token_ids);
+        }
+    }
+}
+
+impl Contract {
+    /// Split `balance` received for `token_id` into the platform fee, amounts owed
+    /// to royalty payees and collaborators, and the seller's remainder
+    fn internal_fee_breakdown(
+        &self,
+        token_id: &TokenId,
+        balance: Balance,
+    ) -> (Balance, HashMap<AccountId, Balance>, Balance) {
+        let platform_fee =
+            balance * Balance::from(self.platform_fee_bps) / Balance::from(FULL_BASIS_POINTS);
+        let balance = balance - platform_fee;
+        let mut payees = HashMap::new();
+        let mut paid: Balance = 0;
+        let extras = self.internal_token_extras(token_id);
+        let royalties = extras
+            .royalties
+            .into_iter()
+            .map(|(account_id, bps)| (account_id, u32::from(bps)));
+        let collaborators = extras
+            .collaborators
+            .into_iter()
+            .map(|collaborator| (collaborator.account_id, collaborator.royalty_bps));
+        for (account_id, bps) in royalties.chain(collaborators) {
+            let amount = balance * Balance::from(bps) / Balance::from(FULL_BASIS_POINTS);
+            paid += amount;
+            *payees.entry(account_id).or_default() += amount;
+        }
+        (platform_fee, payees, balance - paid)
+    }
+
+    /// Split `balance` received for `token_id` between the contract owner, its royalty payees,
+    /// collaborators and `owner_id` who gets the remainder
+    pub(crate) fn internal_payout(
+        &self,
+        token_id: &TokenId,
+        owner_id: &AccountId,
+        balance: Balance,
+    ) -> HashMap<AccountId, Balance> {
+        let (platform_fee, mut payout, seller_net) = self.internal_fee_breakdown(token_id, balance);
+        if platform_fee > 0 {
+            *payout.entry(self.tokens.owner_id.clone()).or_default() += platform_fee;
+        }
+        *payout.entry(owner_id.clone()).or_default() += seller_net;
+        payout
+    }
+
+    fn internal_emit_royalty_policy(&self) {
+        events::emit(vec![RoyaltyPolicyUpdateLog {
+            min_royalty_bps: self.min_royalty_bps,
+            default_royalties: self.default_royalties.clone(),
+        }]);
+    }
+
+    pub(crate) fn internal_payout_with_limit(
+        &self,
+        token_id: &TokenId,
+        owner_id: &AccountId,
+        balance: Balance,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        let payout = self.internal_payout(token_id, owner_id, balance);
+        if let Some(max_len_payout) = max_len_payout {
+            require!(
+                payout.len() <= max_len_payout as usize,
+                "Payout exceeds max_len_payout"
+            );
+        }
+        Payout {
+            payout: payout
+                .into_iter()
+                .map(|(account_id, amount)| (account_id, U128(amount)))
+                .collect(),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Charge `platform_fee_bps` basis points of every sale price, the default royalties
+    /// must fit next to it
+    pub fn set_platform_fee(&mut self, platform_fee_bps: u16) {
+        self.assert_owner();
+        require!(
+            u32::from(platform_fee_bps) <= FULL_BASIS_POINTS,
+            "Platform fee can't exceed 10000 basis points"
+        );
+        require!(
+            u32::from(platform_fee_bps) + total_bps(&self.default_royalties) <= FULL_BASIS_POINTS,
+            "Royalties and the platform fee can't exceed 10000 basis points"
+        );
+        self.platform_fee_bps = platform_fee_bps;
+        self.internal_audit("set_platform_fee", None, None);
+        events::emit(vec![PlatformFeeUpdateLog { platform_fee_bps }]);
+    }
+
+    pub fn platform_fee_bps(&self) -> u16 {
+        self.platform_fee_bps
+    }
+
+    /// Reject mints whose royalties and collaborators total less than `min_royalty_bps`
+    /// basis points, 0 accepts royalty-free mints. The default royalties must meet it
+    pub fn set_min_royalty_bps(&mut self, min_royalty_bps: u16) {
+        self.assert_owner();
+        require!(
+            u32::from(min_royalty_bps) <= FULL_BASIS_POINTS,
+            "Minimum royalty can't exceed 10000 basis points"
+        );
+        require!(
+            total_bps(&self.default_royalties) >= u32::from(min_royalty_bps),
+            "Default royalties are below the minimum royalty"
+        );
+        self.min_royalty_bps = min_royalty_bps;
+        self.internal_audit("set_min_royalty_bps", None, None);
+        self.internal_emit_royalty_policy();
+    }
+
+    pub fn min_royalty_bps(&self) -> u16 {
+        self.min_royalty_bps
+    }
+
+    /// Royalties of tokens minted without royalties of their own, they must meet
+    /// the minimum royalty
+    pub fn set_default_royalties(&mut self, royalties: HashMap<AccountId, u16>) {
+        self.assert_owner();
+        require!(
+            royalties.len() <= MAX_ROYALTY_PAYEES,
+            "Too many royalty payees"
+        );
+        let total_bps = total_bps(&royalties);
+        require!(
+            total_bps <= FULL_BASIS_POINTS,
+            "Royalties can't exceed 10000 basis points"
+        );
+        self.assert_royalties_fit_platform_fee(total_bps);
+        require!(
+            total_bps >= u32::from(self.min_royalty_bps),
+            "Default royalties are below the minimum royalty"
+        );
+        self.default_royalties = royalties;
+        self.internal_audit("set_default_royalties", None, None);
+        self.internal_emit_royalty_policy();
+    }
+
+    pub fn default_royalties(&self) -> HashMap<AccountId, u16> {
+        self.default_royalties.clone()
+    }
+
+    pub fn nft_fee_summary(&self) -> FeeSummary {
+        let mint_price = self
+            .mint_curve
+            .as_ref()
+            .map(|mint_curve| mint_curve.price_at(self.tokens.owner_by_id.len()))
+            .unwrap_or(0);
+        FeeSummary {
+            platform_fee_bps: u32::from(self.platform_fee_bps),
+            referral_fee_bps: 0,
+            charity_fee_bps: 0,
+            mint_price: U128(mint_price),
+            presale_price: U128(0),
+            reward_rate_per_second: U128(0),
+            min_royalty_claim: U128(0),
+        }
+    }
+
+    /// Where `price` paid for `token_id` would go
+    pub fn fee_breakdown(&self, token_id: TokenId, price: U128) -> FeeBreakdown {
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        let (platform_fee, royalties, seller_net) = self.internal_fee_breakdown(&token_id, price.0);
+        FeeBreakdown {
+            platform_fee: U128(platform_fee),
+            royalties: royalties
+                .into_iter()
+                .map(|(account_id, amount)| (account_id, U128(amount)))
+                .collect(),
+            seller_net: U128(seller_net),
+        }
+    }
+
+    /// What everyone would receive if `token_id` was sold for `price`
+    pub fn nft_simulate_sale(&self, token_id: TokenId, price: U128) -> SaleSimulation {
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        let (platform_fee, royalties, seller_net) = self.internal_fee_breakdown(&token_id, price.0);
+        let mut royalty_payouts: Vec<_> = royalties
+            .into_iter()
+            .map(|(account_id, amount)| (account_id, U128(amount)))
+            .collect();
+        royalty_payouts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        SaleSimulation {
+            seller_receives: U128(seller_net),
+            royalty_payouts,
+            platform_fee: U128(platform_fee),
+            total_price: price,
+        }
+    }
+
+    /// Tokens paying royalties to `account_id` along with the account's basis points
+    pub fn royalties_for_account(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<(TokenId, u16)> {
+        let token_ids = match self.royalty_tokens_per_account.get(&account_id) {
+            Some(token_ids) => token_ids,
+            None => return vec![],
+        };
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        token_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .filter_map(|token_id| {
+                let bps = self
+                    .internal_token_extras(&token_id)
+                    .royalties
+                    .get(&account_id)
+                    .copied()?;
+                Some((token_id, bps))
+            })
+            .collect()
+    }
+
+    /// NEP-199 payout of selling `token_id` for `balance`
+    pub fn nft_payout(
+        &self,
+        token_id: TokenId,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        self.internal_payout_with_limit(&token_id, &owner_id, balance.0, max_len_payout)
+    }
+
+    /// `nft_payout` in the form Mintbase's market calls it, with a required `max_len_payout`.
+    /// Kept separate so Mintbase settlement holds even if `nft_payout` changes
+    pub fn nft_payout_mintbase(
+        &self,
+        token_id: TokenId,
+        balance: U128,
+        max_len_payout: u32,
+    ) -> Payout {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        self.internal_payout_with_limit(&token_id, &owner_id, balance.0, Some(max_len_payout))
+    }
+
+    /// NEP-199 transfer settling a sale of `token_id` for `balance`,
+    /// returns the payout the marketplace has to distribute
+    #[payable]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let (previous_owner_id, approved_account_ids) = self.internal_transfer(
+            &sender_id,
+            &receiver_id,
+            &token_id,
+            approval_id,
+            memo,
+            Some(balance.0),
+        );
+        let payout = self.internal_payout_with_limit(
+            &token_id,
+            &previous_owner_id,
+            balance.0,
+            max_len_payout,
+        );
+        self.internal_record_ft_payout_sale(&token_id, &previous_owner_id);
+        self.internal_refund_cleared_approvals(&token_id, approved_account_ids, previous_owner_id);
+        self.internal_audit("transfer", Some(&token_id), Some(balance.0));
+        payout
+    }
+}
@@ -0,0 +1,605 @@
+/*!
+Sales of tokens listed by their owners directly on this contract, either at a fixed price
+or on a Dutch auction whose price falls over time. Fixed prices may be set in an FT,
+such a token is bought through `ft_on_transfer` and payouts are made in the same FT.
+An FT payout that fails becomes claimable with `claim_ft_payout`.
+
+A listing doesn't need an approval: it is removed on every transfer of the token,
+so the seller is always the current owner. Listing storage is paid by the seller and
+refunded once the listing is removed. A listing may expire, anyone can then prune it.
+*/
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Promise, StorageUsage,
+};
+
+use crate::donation::DonationContext;
+use crate::events::{
+    self, MinSalePriceUpdateLog, NftBulkDelistLog, NftDelistLog, NftDutchListLog, NftListLog,
+    NftSaleLog,
+};
+use crate::ft::PaymentToken;
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+/// Bounds gas used by a single bulk call
+const MAX_BULK_SALE_UPDATES: usize = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Sale {
+    pub owner_id: AccountId,
+    /// Fixed price, or the start price of a Dutch auction
+    pub price: U128,
+    pub dutch_auction: Option<DutchAuction>,
+    /// FT contract the price is set in, `None` for NEAR
+    pub ft_token_id: Option<AccountId>,
+    /// Block timestamp in seconds the listing can be bought until
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Whether offers below the price are accepted, see `nft_make_offer`
+    #[serde(default)]
+    pub accept_counter_offers: bool,
+}
+
+impl Sale {
+    pub fn payment_token(&self) -> PaymentToken {
+        match &self.ft_token_id {
+            Some(ft_token_id) => PaymentToken::Ft(ft_token_id.clone()),
+            None => PaymentToken::Near,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        let now = env::block_timestamp() / 1_000_000_000;
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    /// Price in yoctoNEAR the token can be bought for now
+    pub fn current_price(&self) -> Balance {
+        match &self.dutch_auction {
+            Some(auction) => auction.price_at(env::block_timestamp() / 1_000_000_000),
+            None => self.price.0,
+        }
+    }
+}
+
+/// Price falling linearly from `start_price` to `end_price`, then staying at `end_price`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DutchAuction {
+    pub start_price: U128,
+    pub end_price: U128,
+    /// Block timestamp in seconds the price starts falling at
+    pub starts_at: u64,
+    /// Block timestamp in seconds the price reaches `end_price` at
+    pub ends_at: u64,
+}
+
+impl DutchAuction {
+    fn price_at(&self, now: u64) -> Balance {
+        if now >= self.ends_at {
+            return self.end_price.0;
+        }
+        let elapsed = Balance::from(now.saturating_sub(self.starts_at));
+        let duration = Balance::from(self.ends_at - self.starts_at);
+        let drop = self.start_price.0 - self.end_price.0;
+        // `drop * elapsed` can overflow u128, splitting the division keeps it exact while every
+        // product stays below `drop` or below `duration` squared, both of which fit
+        self.start_price.0 - (drop / duration * elapsed + drop % duration * elapsed / duration)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceUpdate {
+    pub token_id: TokenId,
+    pub new_price: U128,
+}
+
+impl Contract {
+    /// Remove the listing of `token_id` if any and refund its storage to the seller
+    pub(crate) fn internal_remove_sale(&mut self, token_id: &TokenId) -> Option<Sale> {
+        let (sale, storage_released) = self.internal_take_sale(token_id)?;
+        refund_released_storage(storage_released, sale.owner_id.clone());
+        Some(sale)
+    }
+
+    /// `internal_remove_sale` leaving the refund of the released storage to the caller
+    pub(crate) fn internal_take_sale(
+        &mut self,
+        token_id: &TokenId,
+    ) -> Option<(Sale, StorageUsage)> {
+        let initial_storage = env::storage_usage();
+        let sale = self.sales.remove(token_id)?;
+        self.internal_unindex_listing(token_id, &sale);
+        events::emit(vec![NftDelistLog {
+            token_id: token_id.clone(),
+            owner_id: sale.owner_id.clone(),
+        }]);
+        Some((sale, initial_storage - env::storage_usage()))
+    }
+
+    pub(crate) fn assert_token_owner(&self, token_id: &TokenId) -> AccountId {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+        owner_id
+    }
+
+    /// Store `sale` of `token_id`, returns the storage it takes
+    fn internal_list(&mut self, token_id: &TokenId, sale: &Sale) -> StorageUsage {
+        if let Some(reason) = self.internal_transfer_lock(token_id) {
+            env::panic_str(&reason);
+        }
+        let initial_storage = env::storage_usage();
+        if let Some(previous_sale) = self.sales.insert(token_id, sale) {
+            self.internal_unindex_listing(token_id, &previous_sale);
+        }
+        self.internal_index_listing(token_id, sale);
+        env::storage_usage().saturating_sub(initial_storage)
+    }
+
+    /// Sell the listed `token_id` to `buyer_id` paying `deposit` of `payment_token` for it.
+    /// Royalties are paid out of the current price and the rest goes to the seller,
+    /// all in `payment_token`. Returns the part of `deposit` left unspent
+    pub(crate) fn internal_buy(
+        &mut self,
+        token_id: &TokenId,
+        buyer_id: &AccountId,
+        payment_token: &PaymentToken,
+        deposit: Balance,
+    ) -> Balance {
+        let sale = self
+            .sales
+            .get(token_id)
+            .unwrap_or_else(|| env::panic_str("Token is not listed"));
+        require!(!sale.is_expired(), "Listing expired");
+        require!(
+            sale.payment_token() == *payment_token,
+            "Token is priced in another currency"
+        );
+        let price = sale.current_price();
+        require!(deposit >= price, "Attached deposit is less than the price");
+
+        self.internal_sell(&sale.owner_id, token_id, buyer_id, payment_token, price);
+        deposit - price
+    }
+
+    /// Transfer `token_id` of `seller_id` to `buyer_id` who paid `price` of `payment_token`.
+    /// Royalties are paid out of the price and the rest goes to the seller
+    pub(crate) fn internal_sell(
+        &mut self,
+        seller_id: &AccountId,
+        token_id: &TokenId,
+        buyer_id: &AccountId,
+        payment_token: &PaymentToken,
+        price: Balance,
+    ) {
+        self.assert_within_resale_cap(token_id, price, payment_token);
+        let (previous_owner_id, approved_account_ids) =
+            self.internal_transfer(seller_id, buyer_id, token_id, None, None, Some(price));
+        self.internal_refund_cleared_approvals(
+            token_id,
+            approved_account_ids,
+            previous_owner_id.clone(),
+        );
+
+        self.internal_distribute(token_id, &previous_owner_id, buyer_id, payment_token, price);
+        self.internal_audit("sale", Some(token_id), Some(price));
+        self.internal_post_social(
+            token_id,
+            format!(
+                "Ukrainian Magical #{} was sold by @{} to @{}",
+                token_id, previous_owner_id, buyer_id
+            ),
+        );
+        events::emit(vec![NftSaleLog {
+            token_id: token_id.clone(),
+            seller_id: previous_owner_id,
+            buyer_id: buyer_id.clone(),
+            price: U128(price),
+            ft_token_id: match payment_token {
+                PaymentToken::Near => None,
+                PaymentToken::Ft(ft_token_id) => Some(ft_token_id.clone()),
+            },
+        }]);
+    }
+
+    /// Pay `amount` received from `payer_id` for `token_id` out to the contract owner,
+    /// royalty payees and collaborators, the rest goes to `owner_id`
+    pub(crate) fn internal_distribute(
+        &mut self,
+        token_id: &TokenId,
+        owner_id: &AccountId,
+        payer_id: &AccountId,
+        payment_token: &PaymentToken,
+        amount: Balance,
+    ) {
+        let royalties = self.internal_token_extras(token_id).royalties;
+        let charity_account_id = self.internal_charity_account();
+        for (account_id, amount) in self.internal_payout(token_id, owner_id, amount) {
+            if amount == 0 {
+                continue;
+            }
+            // donations are tracked in NEAR only, an FT royalty is paid to the charity as is
+            if *payment_token == PaymentToken::Near
+                && charity_account_id.as_ref() == Some(&account_id)
+                && royalties.contains_key(&account_id)
+            {
+                self.internal_donate(payer_id.clone(), amount, DonationContext::Royalty);
+            } else if let PaymentToken::Ft(ft_contract) = payment_token {
+                self.internal_send_ft_payout(account_id, ft_contract.clone(), amount);
+            } else {
+                payment_token.send(account_id, amount);
+            }
+        }
+    }
+
+    /// List `token_id` owned by the caller at `price` of the FT `ft_token_id` or NEAR if `None`
+    fn internal_list_at_fixed_price(
+        &mut self,
+        token_id: TokenId,
+        price: U128,
+        ft_token_id: Option<AccountId>,
+        expires_at: Option<u64>,
+        accept_counter_offers: bool,
+    ) {
+        let owner_id = self.assert_token_owner(&token_id);
+        let sale = Sale {
+            owner_id: owner_id.clone(),
+            price,
+            dutch_auction: None,
+            ft_token_id,
+            expires_at,
+            accept_counter_offers,
+        };
+        require!(!sale.is_expired(), "Listing expired");
+        self.assert_sale_price(price, &sale.payment_token());
+        self.assert_within_resale_cap(&token_id, price.0, &sale.payment_token());
+        let storage_used = self.internal_list(&token_id, &sale);
+        refund_deposit_to_account(storage_used, env::predecessor_account_id());
+        self.internal_audit("list", Some(&token_id), Some(price.0));
+        events::emit(vec![NftListLog {
+            token_id,
+            owner_id,
+            price,
+            ft_token_id: sale.ft_token_id,
+            expires_at,
+            accept_counter_offers,
+        }]);
+    }
+
+    /// Check `price` in `payment_token`, the minimum sale price applies to NEAR prices
+    fn assert_sale_price(&self, price: U128, payment_token: &PaymentToken) {
+        require!(price.0 > 0, "Price must be positive");
+        if *payment_token != PaymentToken::Near {
+            return;
+        }
+        require!(
+            price.0 >= self.min_sale_price,
+            format!("Price must be at least {}", self.min_sale_price)
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// List `token_id` owned by the caller for sale at `price` yoctoNEAR
+    #[payable]
+    pub fn list_for_sale(&mut self, token_id: TokenId, price: U128) {
+        self.internal_list_at_fixed_price(token_id, price, None, None, false);
+    }
+
+    /// List `token_id` owned by the caller for sale at `ask_price` yoctoNEAR.
+    /// With `accept_counter_offers` buyers may also offer less than the ask price
+    #[payable]
+    pub fn nft_list_for_sale_with_offers(
+        &mut self,
+        token_id: TokenId,
+        ask_price: U128,
+        accept_counter_offers: bool,
+    ) {
+        self.internal_list_at_fixed_price(token_id, ask_price, None, None, accept_counter_offers);
+    }
+
+    /// List `token_id` owned by the caller for sale at `price` yoctoNEAR until
+    /// the block timestamp `expires_at` in seconds
+    #[payable]
+    pub fn list_for_sale_until(&mut self, token_id: TokenId, price: U128, expires_at: u64) {
+        self.internal_list_at_fixed_price(token_id, price, None, Some(expires_at), false);
+    }
+
+    /// List `token_id` owned by the caller for sale at `price` of the FT `ft_token_id`
+    #[payable]
+    pub fn list_for_sale_ft(&mut self, token_id: TokenId, price: U128, ft_token_id: AccountId) {
+        self.internal_list_at_fixed_price(token_id, price, Some(ft_token_id), None, false);
+    }
+
+    /// List `token_id` owned by the caller on a Dutch auction. Its price falls from
+    /// `start_price` to `end_price` yoctoNEAR over `duration` seconds starting now
+    #[payable]
+    pub fn list_dutch_auction(
+        &mut self,
+        token_id: TokenId,
+        start_price: U128,
+        end_price: U128,
+        duration: u64,
+    ) {
+        let owner_id = self.assert_token_owner(&token_id);
+        self.assert_sale_price(end_price, &PaymentToken::Near);
+        self.assert_within_resale_cap(&token_id, start_price.0, &PaymentToken::Near);
+        require!(
+            start_price.0 >= end_price.0,
+            "Start price must be at least the end price"
+        );
+        require!(duration > 0, "Duration must be positive");
+        let starts_at = env::block_timestamp() / 1_000_000_000;
+        let auction = DutchAuction {
+            start_price,
+            end_price,
+            starts_at,
+            ends_at: starts_at + duration,
+        };
+        let sale = Sale {
+            owner_id: owner_id.clone(),
+            price: start_price,
+            dutch_auction: Some(auction.clone()),
+            ft_token_id: None,
+            expires_at: None,
+            accept_counter_offers: false,
+        };
+        let storage_used = self.internal_list(&token_id, &sale);
+        refund_deposit_to_account(storage_used, env::predecessor_account_id());
+        self.internal_audit("list", Some(&token_id), Some(start_price.0));
+        events::emit(vec![NftDutchListLog {
+            token_id,
+            owner_id,
+            auction,
+        }]);
+    }
+
+    /// Remove the listing of `token_id` owned by the caller
+    #[payable]
+    pub fn delist(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        self.assert_token_owner(&token_id);
+        require!(
+            self.internal_remove_sale(&token_id).is_some(),
+            "Token is not listed"
+        );
+        self.internal_audit("delist", Some(&token_id), None);
+    }
+
+    /// Remove several listings of tokens owned by the caller at once,
+    /// the storage released by all of them is refunded in a single transfer
+    #[payable]
+    pub fn nft_bulk_delist(&mut self, token_ids: Vec<TokenId>) {
+        assert_one_yocto();
+        require!(
+            token_ids.len() <= MAX_BULK_SALE_UPDATES,
+            "Too many tokens to delist"
+        );
+        let initial_storage = env::storage_usage();
+        for token_id in &token_ids {
+            self.assert_token_owner(token_id);
+            let sale = self
+                .sales
+                .remove(token_id)
+                .unwrap_or_else(|| env::panic_str("Token is not listed"));
+            self.internal_unindex_listing(token_id, &sale);
+        }
+        let owner_id = env::predecessor_account_id();
+        refund_released_storage(initial_storage - env::storage_usage(), owner_id.clone());
+        for token_id in &token_ids {
+            self.internal_audit("delist", Some(token_id), None);
+        }
+        events::emit(vec![NftBulkDelistLog {
+            owner_id,
+            token_ids,
+        }]);
+    }
+
+    /// Remove the expired listing of `token_id`, anyone may call it.
+    /// The storage is refunded to the seller
+    pub fn prune_listing(&mut self, token_id: TokenId) {
+        let sale = self
+            .sales
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token is not listed"));
+        require!(sale.is_expired(), "Listing hasn't expired");
+        self.internal_remove_sale(&token_id);
+        self.internal_audit("prune_listing", Some(&token_id), None);
+    }
+
+    /// Buy the listed `token_id` attaching at least its current price, the excess is refunded.
+    /// Royalties are paid out of the price and the rest goes to the seller
+    #[payable]
+    pub fn buy(&mut self, token_id: TokenId) {
+        let buyer_id = env::predecessor_account_id();
+        let unspent = self.internal_buy(
+            &token_id,
+            &buyer_id,
+            &PaymentToken::Near,
+            env::attached_deposit(),
+        );
+        if unspent > 0 {
+            Promise::new(buyer_id).transfer(unspent);
+        }
+    }
+
+    /// Buy `token_id` on a Dutch auction at its current price and list it for `relist_price`.
+    /// The listing storage is paid from the deposit in excess of the price. The purchase
+    /// goes through even when the token can't be listed, e.g. the price is below the minimum
+    /// or the excess doesn't cover the storage. The unspent deposit is refunded
+    #[payable]
+    pub fn nft_dutch_buy_and_list(&mut self, token_id: TokenId, relist_price: U128) {
+        require!(
+            matches!(
+                self.sales.get(&token_id),
+                Some(Sale {
+                    dutch_auction: Some(_),
+                    ..
+                })
+            ),
+            "Token is not on a Dutch auction"
+        );
+        let buyer_id = env::predecessor_account_id();
+        let mut unspent = self.internal_buy(
+            &token_id,
+            &buyer_id,
+            &PaymentToken::Near,
+            env::attached_deposit(),
+        );
+
+        if relist_price.0 > 0
+            && relist_price.0 >= self.min_sale_price
+            && self.is_within_resale_cap(&token_id, relist_price.0, &PaymentToken::Near)
+        {
+            let sale = Sale {
+                owner_id: buyer_id.clone(),
+                price: relist_price,
+                dutch_auction: None,
+                ft_token_id: None,
+                expires_at: None,
+                accept_counter_offers: false,
+            };
+            let storage_cost =
+                Balance::from(self.internal_list(&token_id, &sale)) * env::storage_byte_cost();
+            if storage_cost <= unspent {
+                unspent -= storage_cost;
+                self.internal_audit("list", Some(&token_id), Some(relist_price.0));
+                events::emit(vec![NftListLog {
+                    token_id,
+                    owner_id: buyer_id.clone(),
+                    price: relist_price,
+                    ft_token_id: None,
+                    expires_at: None,
+                    accept_counter_offers: false,
+                }]);
+            } else {
+                self.sales.remove(&token_id);
+                self.internal_unindex_listing(&token_id, &sale);
+            }
+        }
+        if unspent > 0 {
+            Promise::new(buyer_id).transfer(unspent);
+        }
+    }
+
+    /// Reprice several listings at once. Each listing may be repriced by its seller
+    /// or by the contract owner, a repriced Dutch auction becomes a fixed price listing
+    pub fn nft_bulk_update_sale_prices(&mut self, updates: Vec<PriceUpdate>) {
+        require!(
+            updates.len() <= MAX_BULK_SALE_UPDATES,
+            "Too many price updates"
+        );
+        let predecessor_id = env::predecessor_account_id();
+        for update in &updates {
+            let mut sale = self
+                .sales
+                .get(&update.token_id)
+                .unwrap_or_else(|| env::panic_str("Token is not listed"));
+            self.assert_sale_price(update.new_price, &sale.payment_token());
+            self.assert_within_resale_cap(
+                &update.token_id,
+                update.new_price.0,
+                &sale.payment_token(),
+            );
+            require!(
+                predecessor_id == sale.owner_id || predecessor_id == self.tokens.owner_id,
+                "Unauthorized"
+            );
+            self.internal_unindex_listing(&update.token_id, &sale);
+            sale.price = update.new_price;
+            sale.dutch_auction = None;
+            self.sales.insert(&update.token_id, &sale);
+            self.internal_index_listing(&update.token_id, &sale);
+            self.internal_audit("reprice", Some(&update.token_id), Some(update.new_price.0));
+        }
+        events::emit(updates);
+    }
+
+    /// Set the lowest price in yoctoNEAR a token can be listed or repriced at, zero means no minimum.
+    /// Existing listings and FT prices are kept
+    pub fn set_min_sale_price(&mut self, min_sale_price: U128) {
+        self.assert_owner();
+        self.min_sale_price = min_sale_price.into();
+        self.floor_update_cursor = None;
+        self.internal_audit("set_min_sale_price", None, Some(self.min_sale_price));
+        events::emit(vec![MinSalePriceUpdateLog { min_sale_price }]);
+    }
+
+    pub fn min_sale_price(&self) -> U128 {
+        U128(self.min_sale_price)
+    }
+
+    /// Set the minimum sale price to `new_floor` and delist NEAR listings currently priced
+    /// below it, going through up to `batch_size` tokens in ID order. Returns the token to
+    /// continue after, the same floor has to be set again until it returns `None` to go through
+    /// every listing. A different floor starts over
+    pub fn nft_set_floor_price_and_delist_above(
+        &mut self,
+        new_floor: U128,
+        batch_size: u64,
+    ) -> Option<TokenId> {
+        self.assert_owner();
+        require!(batch_size != 0, "Batch size must be positive");
+        if new_floor.0 != self.min_sale_price {
+            self.floor_update_cursor = None;
+        }
+        self.min_sale_price = new_floor.0;
+
+        // token IDs stay in place as listings are removed, unlike positions among the listings
+        let token_ids: Vec<TokenId> = match self.floor_update_cursor.take() {
+            Some(cursor) => self
+                .tokens
+                .owner_by_id
+                .iter_from(cursor)
+                .take(batch_size as usize)
+                .map(|(token_id, _)| token_id)
+                .collect(),
+            None => self
+                .tokens
+                .owner_by_id
+                .iter()
+                .take(batch_size as usize)
+                .map(|(token_id, _)| token_id)
+                .collect(),
+        };
+        for token_id in &token_ids {
+            let below_floor = self.sales.get(token_id).is_some_and(|sale| {
+                sale.ft_token_id.is_none() && sale.current_price() < new_floor.0
+            });
+            if below_floor {
+                self.internal_remove_sale(token_id);
+            }
+        }
+        if token_ids.len() as u64 == batch_size {
+            self.floor_update_cursor = token_ids.last().cloned();
+        }
+        self.internal_audit("set_floor_price", None, Some(self.min_sale_price));
+        events::emit(vec![MinSalePriceUpdateLog {
+            min_sale_price: new_floor,
+        }]);
+        self.floor_update_cursor.clone()
+    }
+
+    /// Current price of the listed `token_id`, in yoctoNEAR or the smallest units of its FT.
+    /// `None` once the listing expired
+    pub fn nft_get_listing_price(&self, token_id: TokenId) -> Option<U128> {
+        self.sales
+            .get(&token_id)
+            .filter(|sale| !sale.is_expired())
+            .map(|sale| U128(sale.current_price()))
+    }
+}
@@ -0,0 +1,106 @@
+/*!
+Series: named groups of tokens, e.g. the accessories of one city, minted by the contract owner
+or by an operator the owner approved for the series. An operator of one series can't mint
+into another.
+
+Operator approvals are paid by the owner and refunded when revoked, series records of tokens
+are paid by the minter.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, Token, TokenId};
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::events::{self, SeriesOperatorApproveLog, SeriesOperatorRevokeLog};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+/// Longest series ID in bytes
+const MAX_SERIES_ID_LEN: usize = 64;
+
+impl Contract {
+    fn is_series_operator(&self, series_id: &str, account_id: &AccountId) -> bool {
+        self.series_approvals
+            .get(&(series_id.to_string(), account_id.clone()))
+            .unwrap_or(false)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Let `operator` mint tokens of `series_id`, the deposit must cover the approval storage
+    #[payable]
+    pub fn nft_approve_collection(&mut self, operator: AccountId, series_id: String) {
+        self.assert_owner();
+        require!(
+            series_id.len() <= MAX_SERIES_ID_LEN,
+            "Series ID is too long"
+        );
+        let initial_storage = env::storage_usage();
+        self.series_approvals
+            .insert(&(series_id.clone(), operator.clone()), &true);
+        refund_deposit_to_account(
+            env::storage_usage().saturating_sub(initial_storage),
+            env::predecessor_account_id(),
+        );
+        self.internal_audit("approve_series_operator", None, None);
+        events::emit(vec![SeriesOperatorApproveLog {
+            series_id,
+            operator_id: operator,
+        }]);
+    }
+
+    /// Revoke the `series_id` approval of `operator`, the approval storage is refunded
+    pub fn nft_revoke_collection(&mut self, operator: AccountId, series_id: String) {
+        self.assert_owner();
+        let initial_storage = env::storage_usage();
+        self.series_approvals
+            .remove(&(series_id.clone(), operator.clone()))
+            .unwrap_or_else(|| env::panic_str("Operator is not approved for the series"));
+        refund_released_storage(
+            initial_storage - env::storage_usage(),
+            env::predecessor_account_id(),
+        );
+        self.internal_audit("revoke_series_operator", None, None);
+        events::emit(vec![SeriesOperatorRevokeLog {
+            series_id,
+            operator_id: operator,
+        }]);
+    }
+
+    pub fn nft_is_series_operator(&self, series_id: String, operator: AccountId) -> bool {
+        self.is_series_operator(&series_id, &operator)
+    }
+
+    /// Mint a new token of `series_id` with ID=`token_id` belonging to `token_owner_id`,
+    /// callable by the contract owner or an operator of the series
+    #[payable]
+    pub fn nft_mint_series(
+        &mut self,
+        series_id: String,
+        token_id: TokenId,
+        token_owner_id: AccountId,
+        token_metadata: TokenMetadata,
+    ) -> Token {
+        let minter_id = env::predecessor_account_id();
+        require!(
+            minter_id == self.tokens.owner_id || self.is_series_operator(&series_id, &minter_id),
+            "Unauthorized"
+        );
+        let initial_storage = env::storage_usage();
+        self.internal_count_daily_mint(&token_owner_id);
+        let token =
+            self.internal_mint_with_royalties(token_id, token_owner_id, token_metadata, None);
+        self.token_series.insert(&token.token_id, &series_id);
+        let storage_used = env::storage_usage() - initial_storage;
+        refund_deposit_to_account(storage_used, minter_id);
+        self.internal_audit("mint", Some(&token.token_id), None);
+        events::emit_mint(&token.owner_id, std::slice::from_ref(&token.token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        token
+    }
+
+    /// Series `token_id` was minted in, if any
+    pub fn nft_token_series(&self, token_id: TokenId) -> Option<String> {
+        self.token_series.get(&token_id)
+    }
+}
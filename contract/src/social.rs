@@ -0,0 +1,120 @@
+/*!
+Posts on the social DB (Near.social) announcing mints and sales.
+
+Posting is off until the owner enables it. Each post attaches a deposit for its social DB
+storage, paid from a pool the owner tops up, and no post is made once the pool runs dry.
+A post that fails is only logged, the mint or sale it announces stands.
+*/
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, json};
+use near_sdk::{
+    env, ext_contract, is_promise_success, near_bindgen, require, AccountId, Balance, Gas,
+};
+
+use crate::events::{self, SocialConfigUpdateLog, SocialPoolTopUpLog, SocialPostFailedLog};
+use crate::{Contract, ContractExt};
+
+const DEFAULT_SOCIAL_DB_ID: &str = "social.near";
+const GAS_FOR_SOCIAL_POST: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_SOCIAL_POST_CALLBACK: Gas = Gas(5_000_000_000_000);
+/// Deposit attached to every post to cover its social DB storage
+pub(crate) const SOCIAL_POST_DEPOSIT: Balance = 10_000_000_000_000_000_000_000;
+
+/// Only the generated `ext_social_db` is used
+#[allow(dead_code)]
+#[ext_contract(ext_social_db)]
+trait SocialDb {
+    fn set(&mut self, data: serde_json::Value);
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SocialConfig {
+    pub contract_id: AccountId,
+    pub enabled: bool,
+    /// yoctoNEAR left to pay for posts
+    pub pool: U128,
+}
+
+impl Contract {
+    fn social_db_id(&self) -> AccountId {
+        self.social_db_id
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SOCIAL_DB_ID.parse().unwrap())
+    }
+
+    /// Post `text` about `token_id` to the social DB if posting is enabled and funded
+    pub(crate) fn internal_post_social(&mut self, token_id: &TokenId, text: String) {
+        if !self.social_posts_enabled || self.social_pool < SOCIAL_POST_DEPOSIT {
+            return;
+        }
+        self.social_pool -= SOCIAL_POST_DEPOSIT;
+        let main = json!({ "type": "md", "text": text });
+        let index = json!({ "key": "main", "value": { "type": "md" } });
+        let data = json!({
+            env::current_account_id().as_str(): {
+                "post": { "main": main.to_string() },
+                "index": { "post": index.to_string() },
+            }
+        });
+        ext_social_db::ext(self.social_db_id())
+            .with_static_gas(GAS_FOR_SOCIAL_POST)
+            .with_attached_deposit(SOCIAL_POST_DEPOSIT)
+            .set(data)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_SOCIAL_POST_CALLBACK)
+                    .on_social_post(token_id.clone(), U128(SOCIAL_POST_DEPOSIT)),
+            );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Post mints and sales to `contract_id`, `social.near` if `None`, while `enabled`
+    pub fn set_social_config(&mut self, contract_id: Option<AccountId>, enabled: bool) {
+        self.assert_owner();
+        self.social_db_id = contract_id;
+        self.social_posts_enabled = enabled;
+        self.internal_audit("set_social_config", None, None);
+        events::emit(vec![SocialConfigUpdateLog {
+            contract_id: self.social_db_id(),
+            enabled,
+        }]);
+    }
+
+    /// Add the attached deposit to the pool paying for posts
+    #[payable]
+    pub fn social_pool_top_up(&mut self) {
+        self.assert_owner();
+        let amount = env::attached_deposit();
+        require!(amount > 0, "Requires attached deposit");
+        self.social_pool += amount;
+        self.internal_audit("social_pool_top_up", None, Some(amount));
+        events::emit(vec![SocialPoolTopUpLog {
+            amount: U128(amount),
+            pool: U128(self.social_pool),
+        }]);
+    }
+
+    pub fn social_config(&self) -> SocialConfig {
+        SocialConfig {
+            contract_id: self.social_db_id(),
+            enabled: self.social_posts_enabled,
+            pool: U128(self.social_pool),
+        }
+    }
+
+    /// Log a failed post, its deposit is refunded to the contract and returned to the pool
+    #[private]
+    pub fn on_social_post(&mut self, token_id: TokenId, deposit: U128) {
+        if is_promise_success() {
+            return;
+        }
+        self.social_pool += deposit.0;
+        self.internal_audit("social_post_failed", Some(&token_id), Some(deposit.0));
+        events::emit(vec![SocialPostFailedLog { token_id, deposit }]);
+    }
+}
@@ -0,0 +1,132 @@
+/*!
+Staking: a holder locks their token in place to accrue points, credited when it's unstaked.
+
+A staked token can't be transferred, listed or burned, staking removes its listing.
+Points are `whole days staked * rate`, the rate set by the owner applies on unstaking.
+Stake storage is paid by the holder and refunded on unstaking.
+*/
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::{self, NftStakeLog, NftUnstakeLog, StakePointsRateUpdateLog};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Default points credited per whole day staked
+pub(crate) const DEFAULT_STAKE_POINTS_PER_DAY: u64 = 1;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+impl Contract {
+    pub(crate) fn is_staked(&self, token_id: &TokenId) -> bool {
+        self.staked_at.contains_key(token_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_stake_points_per_day(&mut self, points_per_day: u64) {
+        self.assert_owner();
+        self.stake_points_per_day = points_per_day;
+        self.internal_audit("set_stake_points_per_day", None, None);
+        events::emit(vec![StakePointsRateUpdateLog {
+            points_per_day: U64(points_per_day),
+        }]);
+    }
+
+    pub fn stake_points_per_day(&self) -> U64 {
+        U64(self.stake_points_per_day)
+    }
+
+    /// Stake the caller's `token_id`, the deposit must cover the stake storage
+    #[payable]
+    pub fn nft_stake(&mut self, token_id: TokenId) {
+        let owner_id = self.assert_token_owner(&token_id);
+        if let Some(reason) = self.internal_transfer_lock(&token_id) {
+            env::panic_str(&reason);
+        }
+
+        self.internal_remove_sale(&token_id);
+        let initial_storage = env::storage_usage();
+        self.staked_at
+            .insert(&token_id, &(env::block_timestamp() / 1_000_000_000));
+        let mut token_ids = self
+            .staked_tokens_per_account
+            .get(&owner_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::StakedTokensPerAccount {
+                    account_hash: env::sha256(owner_id.as_bytes()),
+                })
+            });
+        token_ids.insert(&token_id);
+        self.staked_tokens_per_account.insert(&owner_id, &token_ids);
+        refund_deposit_to_account(env::storage_usage() - initial_storage, owner_id.clone());
+        self.internal_audit("stake", Some(&token_id), None);
+        events::emit(vec![NftStakeLog { token_id, owner_id }]);
+    }
+
+    /// Unstake the caller's `token_id` crediting the points it accrued.
+    /// The stake storage is refunded
+    #[payable]
+    pub fn nft_unstake(&mut self, token_id: TokenId) -> U64 {
+        assert_one_yocto();
+        let owner_id = self.assert_token_owner(&token_id);
+        let initial_storage = env::storage_usage();
+        let staked_at = self
+            .staked_at
+            .remove(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token is not staked"));
+        if let Some(mut token_ids) = self.staked_tokens_per_account.get(&owner_id) {
+            token_ids.remove(&token_id);
+            if token_ids.is_empty() {
+                self.staked_tokens_per_account.remove(&owner_id);
+            } else {
+                self.staked_tokens_per_account.insert(&owner_id, &token_ids);
+            }
+        }
+
+        let days =
+            (env::block_timestamp() / 1_000_000_000).saturating_sub(staked_at) / SECONDS_PER_DAY;
+        let points = days * self.stake_points_per_day;
+        let total_points = self.stake_points.get(&owner_id).unwrap_or(0) + points;
+        self.stake_points.insert(&owner_id, &total_points);
+        refund_released_storage(
+            initial_storage.saturating_sub(env::storage_usage()),
+            owner_id.clone(),
+        );
+        self.internal_audit("unstake", Some(&token_id), None);
+        events::emit(vec![NftUnstakeLog {
+            token_id,
+            owner_id,
+            points: U64(points),
+        }]);
+        U64(points)
+    }
+
+    /// Tokens staked by `account_id`
+    pub fn staked_tokens(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenId> {
+        let token_ids = match self.staked_tokens_per_account.get(&account_id) {
+            Some(token_ids) => token_ids,
+            None => return vec![],
+        };
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        token_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+
+    /// Points credited to `account_id` for unstaked tokens
+    pub fn points_of(&self, account_id: AccountId) -> U64 {
+        U64(self.stake_points.get(&account_id).unwrap_or(0))
+    }
+}
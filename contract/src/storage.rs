@@ -0,0 +1,352 @@
+/*!
+Per-token storage footprint estimation and refunds of released storage.
+
+Sizes are recomputed from the stored values (Borsh encoding of keys and values plus the
+fixed per-record overhead charged by the runtime), not tracked as deltas at mutation time.
+
+A refund that fails, e.g. to a deleted account, stays on the contract and is counted
+as revenue.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::collections::{UnorderedSet, Vector};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, Promise, PromiseResult, StorageUsage};
+
+use crate::audit::TokenActivity;
+use crate::events::{self, RefundFailedLog};
+use crate::extras::{self, TokenExtras, VersionedTokenExtras};
+use crate::{Contract, ContractExt, StorageKey, NFT_DESCRIPTION};
+
+const GAS_FOR_REFUND_CALLBACK: Gas = Gas(5_000_000_000_000);
+
+/// Bytes the runtime charges for every storage record on top of its key and value.
+const STORAGE_BYTES_PER_RECORD: StorageUsage = 40;
+/// Discriminant (1) + `account_hash` length (4) + sha256 (32) of a per-account set prefix.
+const TOKENS_PER_OWNER_PREFIX_LEN: StorageUsage = 37;
+/// Collection-specific suffix appended to a prefix (e.g. `v`/`n` in `TreeMap`, `i`/`e` in `UnorderedSet`).
+const SUB_PREFIX_LEN: StorageUsage = 1;
+/// `Vector` elements are keyed by a little-endian `u64` index.
+const VECTOR_INDEX_LEN: StorageUsage = 8;
+/// `TreeMap` node fields besides the key: `id` and `ht` (8 each) and two `Option<u64>` links.
+/// A balanced tree has about one link per node, so one `Some` and one `None` are assumed.
+const TREE_NODE_FIXED_LEN: StorageUsage = 8 + 8 + 9 + 1;
+/// Longest account ID, the holder of an estimated mint is sized as if its ID was this long.
+const MAX_ACCOUNT_ID_LEN: usize = 64;
+/// Length of the Arweave transaction IDs the media and reference of a token point to.
+const ARWEAVE_ID_LEN: usize = 43;
+/// Title length assumed for an estimated mint, e.g. `#0 Mariupol` with room to spare.
+const ESTIMATED_TITLE_LEN: usize = 32;
+
+/// Size of a value as it is laid out in the contract storage.
+pub(crate) trait StorageSize {
+    fn storage_size(&self) -> StorageUsage;
+}
+
+impl<T: BorshSerialize> StorageSize for T {
+    fn storage_size(&self) -> StorageUsage {
+        self.try_to_vec()
+            .unwrap_or_else(|_| env::panic_str("Cannot serialize value"))
+            .len() as StorageUsage
+    }
+}
+
+/// Bytes charged for one record with the given key and value lengths.
+fn record_bytes(key_len: StorageUsage, value_len: StorageUsage) -> StorageUsage {
+    STORAGE_BYTES_PER_RECORD + key_len + value_len
+}
+
+/// Bytes of the `approvals_by_id` record of `token_id` holding `approvals`.
+pub(crate) fn approvals_record_bytes(
+    token_id: &TokenId,
+    approvals: &HashMap<AccountId, u64>,
+) -> StorageUsage {
+    record_bytes(
+        StorageKey::Approval.storage_size() + token_id.storage_size(),
+        approvals.storage_size(),
+    )
+}
+
+/// Bytes of the element index entry and element vector entry of a token in a per-account set.
+fn token_set_entry_bytes(token_id_len: StorageUsage) -> StorageUsage {
+    let set_prefix_len = TOKENS_PER_OWNER_PREFIX_LEN + SUB_PREFIX_LEN;
+    record_bytes(set_prefix_len + token_id_len, VECTOR_INDEX_LEN)
+        + record_bytes(set_prefix_len + VECTOR_INDEX_LEN, token_id_len)
+}
+
+/// Transfer the cost of `storage_released` bytes to `account_id`.
+pub(crate) fn refund_released_storage(storage_released: StorageUsage, account_id: AccountId) {
+    if storage_released > 0 {
+        let amount = Balance::from(storage_released) * env::storage_byte_cost();
+        Promise::new(account_id.clone()).transfer(amount).then(
+            Contract::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_REFUND_CALLBACK)
+                .on_refund_complete(account_id, U128(amount)),
+        );
+    }
+}
+
+/// `refund_released_storage` without the callback, for callbacks whose gas can't cover one.
+/// A failed refund still returns to the contract, it just isn't counted as revenue
+pub(crate) fn refund_released_storage_uncounted(
+    storage_released: StorageUsage,
+    account_id: AccountId,
+) {
+    if storage_released > 0 {
+        Promise::new(account_id)
+            .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+    }
+}
+
+impl Contract {
+    /// Bytes charged for minting the next sequential token with the metadata of a Ukrainian
+    /// Magical to a new holder along with the default royalties, marketplace approval and
+    /// resale cap
+    fn estimated_mint_storage_bytes(&self) -> StorageUsage {
+        let token_id = self.next_curve_token_id();
+        let token_id_len = token_id.storage_size();
+        let owner_id: AccountId = "a".repeat(MAX_ACCOUNT_ID_LEN).parse().unwrap();
+        let arweave_id = "a".repeat(ARWEAVE_ID_LEN);
+        let metadata = TokenMetadata {
+            title: Some("a".repeat(ESTIMATED_TITLE_LEN)),
+            description: Some(NFT_DESCRIPTION.into()),
+            media: Some(arweave_id.clone()),
+            media_hash: None,
+            copies: Some(1u64),
+            issued_at: Some(format!("{}", env::block_timestamp() / 1_000_000_000u64)),
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: None,
+            reference: Some(arweave_id),
+            reference_hash: None,
+        };
+
+        // owner_by_id: value entry and a tree node
+        let owner_prefix_len = StorageKey::NonFungibleToken.storage_size() + SUB_PREFIX_LEN;
+        let mut bytes = record_bytes(owner_prefix_len + token_id_len, owner_id.storage_size());
+        bytes += record_bytes(
+            owner_prefix_len + VECTOR_INDEX_LEN,
+            token_id_len + TREE_NODE_FIXED_LEN,
+        );
+        bytes += record_bytes(
+            StorageKey::TokenMetadata.storage_size() + token_id_len,
+            metadata.storage_size(),
+        );
+
+        // tokens_per_owner: the holder's new set and its entries
+        let new_token_set =
+            UnorderedSet::<TokenId>::new(vec![0; TOKENS_PER_OWNER_PREFIX_LEN as usize]);
+        bytes += record_bytes(
+            StorageKey::Enumeration.storage_size() + owner_id.storage_size(),
+            new_token_set.storage_size(),
+        );
+        bytes += token_set_entry_bytes(token_id_len);
+
+        bytes += record_bytes(
+            StorageKey::TokenActivity.storage_size() + token_id_len,
+            TokenActivity {
+                minted_at: 0,
+                transfer_count: 0,
+            }
+            .storage_size(),
+        );
+
+        // provenance: the history vector and its first entry
+        let owners = Vector::<(AccountId, u64)>::new(StorageKey::ProvenancePerToken {
+            token_hash: env::sha256(token_id.as_bytes()),
+        });
+        bytes += record_bytes(
+            StorageKey::Provenance.storage_size() + token_id_len,
+            owners.storage_size(),
+        );
+        bytes += record_bytes(
+            TOKENS_PER_OWNER_PREFIX_LEN + VECTOR_INDEX_LEN,
+            owner_id.storage_size() + 8,
+        );
+
+        bytes += record_bytes(
+            StorageKey::DailyMintCounts.storage_size() + owner_id.storage_size(),
+            (0u64, 0u32).storage_size(),
+        );
+
+        if let Some(marketplace_id) = &self.default_marketplace {
+            bytes +=
+                approvals_record_bytes(&token_id, &HashMap::from([(marketplace_id.clone(), 0)]));
+            bytes += record_bytes(
+                StorageKey::Approval.storage_size() + SUB_PREFIX_LEN + token_id_len,
+                0u64.storage_size(),
+            );
+        }
+
+        // royalties: the extras record and an entry in each payee's token set, new or not
+        if !self.default_royalties.is_empty() {
+            let extras = VersionedTokenExtras::V2(TokenExtras {
+                royalties: self.default_royalties.clone(),
+                collaborators: vec![],
+            });
+            bytes += record_bytes(
+                StorageKey::TokenExtras.storage_size() + token_id_len,
+                extras.storage_size(),
+            );
+            for account_id in self.default_royalties.keys() {
+                bytes += token_set_entry_bytes(token_id_len);
+                if !self.royalty_tokens_per_account.contains_key(account_id) {
+                    bytes += record_bytes(
+                        StorageKey::RoyaltyAccounts.storage_size() + account_id.storage_size(),
+                        new_token_set.storage_size(),
+                    );
+                }
+            }
+        }
+
+        if self.default_max_resale_price.is_some() {
+            bytes += record_bytes(
+                StorageKey::MaxResalePrices.storage_size() + token_id_len,
+                Balance::default().storage_size(),
+            );
+        }
+
+        bytes
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Count a failed refund to `account_id` as revenue, the amount stays on the contract
+    #[private]
+    pub fn on_refund_complete(&mut self, account_id: AccountId, amount: U128) {
+        if matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            return;
+        }
+        self.total_revenue += amount.0;
+        self.internal_audit("refund_failed", None, Some(amount.0));
+        events::emit(vec![RefundFailedLog { account_id, amount }]);
+    }
+
+    /// Refunds that failed and stayed on the contract, in yoctoNEAR
+    pub fn total_revenue(&self) -> U128 {
+        U128(self.total_revenue)
+    }
+
+    /// Estimated number of storage bytes attributable to the token `token_id`:
+    /// owner entry, metadata, enumeration, royalty, collaborator, activity, provenance
+    /// and approval records
+    pub fn token_storage_bytes(&self, token_id: TokenId) -> u64 {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        let token_id_len = token_id.storage_size();
+
+        // owner_by_id: value entry and a tree node
+        let owner_prefix_len = StorageKey::NonFungibleToken.storage_size() + SUB_PREFIX_LEN;
+        let mut bytes = record_bytes(owner_prefix_len + token_id_len, owner_id.storage_size());
+        bytes += record_bytes(
+            owner_prefix_len + VECTOR_INDEX_LEN,
+            token_id_len + TREE_NODE_FIXED_LEN,
+        );
+
+        if let Some(metadata) = self
+            .tokens
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+        {
+            bytes += record_bytes(
+                StorageKey::TokenMetadata.storage_size() + token_id_len,
+                metadata.storage_size(),
+            );
+        }
+
+        // tokens_per_owner: element index entry and element vector entry of the owner's set
+        if self.tokens.tokens_per_owner.is_some() {
+            bytes += token_set_entry_bytes(token_id_len);
+        }
+
+        // extras: the royalties and collaborators record and an entry in each payee's token set
+        if let Some(extras) = self.token_extras.get(&token_id) {
+            bytes += record_bytes(
+                StorageKey::TokenExtras.storage_size() + token_id_len,
+                extras.storage_size(),
+            );
+        }
+        // royalties written before records were versioned
+        if let Some(royalties) = extras::legacy_royalties().get(&token_id) {
+            bytes += record_bytes(
+                StorageKey::Royalties.storage_size() + token_id_len,
+                royalties.storage_size(),
+            );
+        }
+        bytes += self.internal_token_extras(&token_id).royalties.len() as StorageUsage
+            * token_set_entry_bytes(token_id_len);
+
+        // collaborators of extras in the `V1` layout or written before records were versioned
+        if let Some(collaborators) = self.token_collaborators.get(&token_id) {
+            bytes += record_bytes(
+                StorageKey::TokenCollaborators.storage_size() + token_id_len,
+                collaborators.storage_size(),
+            );
+        }
+        if let Some(activity) = self.token_activity.get(&token_id) {
+            bytes += record_bytes(
+                StorageKey::TokenActivity.storage_size() + token_id_len,
+                activity.storage_size(),
+            );
+        }
+
+        // provenance: the history vector and its entries, sized as if every owner's ID
+        // was as long as the current one's
+        if let Some(owners) = self.provenance.get(&token_id) {
+            bytes += record_bytes(
+                StorageKey::Provenance.storage_size() + token_id_len,
+                owners.storage_size(),
+            );
+            bytes += owners.len()
+                * record_bytes(
+                    TOKENS_PER_OWNER_PREFIX_LEN + VECTOR_INDEX_LEN,
+                    owner_id.storage_size() + 8,
+                );
+        }
+
+        if let Some(approvals) = self
+            .tokens
+            .approvals_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+        {
+            bytes += approvals_record_bytes(&token_id, &approvals);
+        }
+        if let Some(next_approval_id) = self
+            .tokens
+            .next_approval_id_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+        {
+            bytes += record_bytes(
+                StorageKey::Approval.storage_size() + SUB_PREFIX_LEN + token_id_len,
+                next_approval_id.storage_size(),
+            );
+        }
+
+        bytes
+    }
+
+    /// Cost in yoctoNEAR of the storage estimated by `token_storage_bytes`
+    pub fn token_storage_cost(&self, token_id: TokenId) -> U128 {
+        let bytes = self.token_storage_bytes(token_id);
+        U128(Balance::from(bytes) * env::storage_byte_cost())
+    }
+
+    /// Estimated deposit in yoctoNEAR covering the storage of a typical mint: the next
+    /// sequential token with the metadata of a Ukrainian Magical minted to a holder with an
+    /// account ID of up to 64 bytes. The price of a paid mint comes on top
+    pub fn nft_estimated_mint_cost(&self) -> U128 {
+        let bytes = self.estimated_mint_storage_bytes();
+        U128(Balance::from(bytes) * env::storage_byte_cost())
+    }
+}
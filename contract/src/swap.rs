@@ -0,0 +1,248 @@
+/*!
+Swaps of one of our tokens for a token of another NFT contract.
+
+The owner of our token proposes the swap naming the token they want and its owner. The
+counterparty completes it with `nft_transfer_call` of their token to this contract with the
+proposal ID as the message. If the incoming token matches, our token goes to the counterparty
+and theirs is forwarded to the proposer. Anything else is returned to its sender. If the forward
+fails the token stays with this contract and the proposer may retry it.
+
+Proposal storage is paid by the proposer and refunded once the counterparty's token is forwarded
+or the swap is cancelled.
+*/
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, is_promise_success, near_bindgen, require, AccountId, Gas,
+    Promise, PromiseOrValue,
+};
+
+use crate::events::{
+    self, ExternalSwapCancelLog, ExternalSwapCompleteLog, ExternalSwapForwardFailedLog,
+    ExternalSwapForwardRetryLog, ExternalSwapProposeLog,
+};
+use crate::storage::{refund_released_storage, refund_released_storage_uncounted};
+use crate::{Contract, ContractExt};
+
+const GAS_FOR_FORWARD_TRANSFER: Gas = Gas(10_000_000_000_000);
+/// Covers removing the forwarded swap and refunding its storage
+pub(crate) const GAS_FOR_FORWARD_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+/// Only the generated `ext_nft` is used
+#[allow(dead_code)]
+#[ext_contract(ext_nft)]
+trait NonFungibleToken {
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    );
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalSwap {
+    pub proposer_id: AccountId,
+    pub my_token_id: TokenId,
+    pub their_contract: AccountId,
+    pub their_token_id: TokenId,
+    pub counterparty: AccountId,
+}
+
+impl Contract {
+    /// Remove swap `swap_id`, refunding its storage to the proposer
+    fn internal_remove_external_swap(&mut self, swap_id: u64) -> ExternalSwap {
+        let initial_storage = env::storage_usage();
+        let swap = self
+            .external_swaps
+            .remove(&swap_id)
+            .unwrap_or_else(|| env::panic_str("Swap not found"));
+        refund_released_storage(
+            initial_storage - env::storage_usage(),
+            swap.proposer_id.clone(),
+        );
+        swap
+    }
+
+    /// The swap `msg` refers to if `their_token_id` of the calling contract sent by
+    /// `previous_owner_id` completes it and the proposer can still hand over their token
+    fn internal_matching_swap(
+        &self,
+        previous_owner_id: &AccountId,
+        their_token_id: &TokenId,
+        msg: &str,
+    ) -> Option<u64> {
+        let swap_id: u64 = msg.parse().ok()?;
+        let swap = self.external_swaps.get(&swap_id)?;
+        let matches = swap.their_contract == env::predecessor_account_id()
+            && swap.their_token_id == *their_token_id
+            && swap.counterparty == *previous_owner_id
+            && self.tokens.owner_by_id.get(&swap.my_token_id) == Some(swap.proposer_id)
+            && self.internal_transfer_lock(&swap.my_token_id).is_none();
+        matches.then_some(swap_id)
+    }
+
+    /// Forward the counterparty's token of completed swap `swap_id` to the proposer
+    fn internal_forward_swap_token(&self, swap_id: u64, swap: &ExternalSwap) -> Promise {
+        ext_nft::ext(swap.their_contract.clone())
+            .with_static_gas(GAS_FOR_FORWARD_TRANSFER)
+            .with_attached_deposit(1)
+            .nft_transfer(
+                swap.proposer_id.clone(),
+                swap.their_token_id.clone(),
+                None,
+                None,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_FORWARD_CALLBACK)
+                    .on_external_swap_forward(U64(swap_id), swap.clone()),
+            )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Propose swapping `my_token_id` owned by the caller for `their_token_id` of `their_contract`
+    /// owned by `counterparty`. Returns the proposal ID the counterparty sends their token with
+    #[payable]
+    pub fn propose_external_swap(
+        &mut self,
+        my_token_id: TokenId,
+        their_contract: AccountId,
+        their_token_id: TokenId,
+        counterparty: AccountId,
+    ) -> U64 {
+        let proposer_id = self.assert_token_owner(&my_token_id);
+        require!(
+            their_contract != env::current_account_id(),
+            "Swap must be with another contract"
+        );
+        require!(counterparty != proposer_id, "Cannot swap with yourself");
+        let swap_id = self.next_external_swap_id;
+        self.next_external_swap_id += 1;
+        let swap = ExternalSwap {
+            proposer_id: proposer_id.clone(),
+            my_token_id,
+            their_contract,
+            their_token_id,
+            counterparty,
+        };
+        let initial_storage = env::storage_usage();
+        self.external_swaps.insert(&swap_id, &swap);
+        refund_deposit_to_account(env::storage_usage() - initial_storage, proposer_id);
+        self.internal_audit("propose_external_swap", Some(&swap.my_token_id), None);
+        events::emit(vec![ExternalSwapProposeLog {
+            swap_id: U64(swap_id),
+            swap,
+        }]);
+        U64(swap_id)
+    }
+
+    /// Cancel swap `swap_id` proposed by the caller, its storage is refunded
+    #[payable]
+    pub fn cancel_external_swap(&mut self, swap_id: U64) {
+        assert_one_yocto();
+        let swap = self
+            .external_swaps
+            .get(&swap_id.0)
+            .unwrap_or_else(|| env::panic_str("Swap not found"));
+        require!(
+            env::predecessor_account_id() == swap.proposer_id,
+            "Predecessor must be the proposer"
+        );
+        self.internal_remove_external_swap(swap_id.0);
+        self.internal_audit("cancel_external_swap", Some(&swap.my_token_id), None);
+        events::emit(vec![ExternalSwapCancelLog { swap_id }]);
+    }
+
+    pub fn external_swap(&self, swap_id: U64) -> Option<ExternalSwap> {
+        self.external_swaps.get(&swap_id.0)
+    }
+
+    /// Completed swap `swap_id` whose counterparty's token wasn't forwarded to the proposer yet
+    pub fn external_swap_forward(&self, swap_id: U64) -> Option<ExternalSwap> {
+        self.external_swap_forwards.get(&swap_id.0)
+    }
+
+    /// Retry forwarding the counterparty's token of completed swap `swap_id` to the caller
+    #[payable]
+    pub fn retry_external_swap_forward(&mut self, swap_id: U64) {
+        assert_one_yocto();
+        let swap = self
+            .external_swap_forwards
+            .get(&swap_id.0)
+            .unwrap_or_else(|| env::panic_str("Swap forward not found"));
+        require!(
+            env::predecessor_account_id() == swap.proposer_id,
+            "Predecessor must be the proposer"
+        );
+        self.internal_forward_swap_token(swap_id.0, &swap);
+        self.internal_audit("retry_external_swap_forward", Some(&swap.my_token_id), None);
+        events::emit(vec![ExternalSwapForwardRetryLog { swap_id }]);
+    }
+
+    /// Refund the swap storage once the counterparty's token is forwarded, keep the swap
+    /// for a retry otherwise
+    #[private]
+    pub fn on_external_swap_forward(&mut self, swap_id: U64, swap: ExternalSwap) {
+        if is_promise_success() {
+            let initial_storage = env::storage_usage();
+            if self.external_swap_forwards.remove(&swap_id.0).is_some() {
+                refund_released_storage_uncounted(
+                    initial_storage - env::storage_usage(),
+                    swap.proposer_id,
+                );
+            }
+            return;
+        }
+        self.internal_audit("external_swap_forward_failed", None, None);
+        events::emit(vec![ExternalSwapForwardFailedLog { swap_id, swap }]);
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for Contract {
+    /// Complete the swap with ID `msg` by the incoming token, returns it unless it matches
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        // an operator of the counterparty may send the token on their behalf
+        let _ = sender_id;
+        let swap_id = match self.internal_matching_swap(&previous_owner_id, &token_id, &msg) {
+            Some(swap_id) => swap_id,
+            None => return PromiseOrValue::Value(true),
+        };
+        // the proposal storage is held until the counterparty's token is forwarded
+        let swap = self.external_swaps.remove(&swap_id).unwrap();
+        self.external_swap_forwards.insert(&swap_id, &swap);
+        let (_, approved_account_ids) = self.internal_transfer(
+            &swap.proposer_id,
+            &swap.counterparty,
+            &swap.my_token_id,
+            None,
+            None,
+            None,
+        );
+        self.internal_refund_cleared_approvals(
+            &swap.my_token_id,
+            approved_account_ids,
+            swap.proposer_id.clone(),
+        );
+        self.internal_forward_swap_token(swap_id, &swap);
+        self.internal_audit("external_swap", Some(&swap.my_token_id), None);
+        events::emit(vec![ExternalSwapCompleteLog {
+            swap_id: U64(swap_id),
+        }]);
+        PromiseOrValue::Value(false)
+    }
+}
@@ -0,0 +1,115 @@
+/*!
+Token tags read at mint from the `tags` list of the metadata `extra` JSON, e.g.
+`{"tags": ["kyiv", "sunflower"]}`, and an index of the tokens carrying each tag.
+
+Tags and index entries are part of the mint storage paid by the minter.
+*/
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, serde_json};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Longest tag in bytes
+const MAX_TAG_LEN: usize = 32;
+const MAX_TAGS_PER_TOKEN: usize = 10;
+
+/// Tags listed in the `extra` JSON of `metadata`, none if `extra` isn't a JSON object.
+/// Panics if a tag isn't lowercase alphanumeric or there are too many of them
+fn metadata_tags(metadata: &TokenMetadata) -> Vec<String> {
+    let extra = match metadata
+        .extra
+        .as_deref()
+        .and_then(|extra| serde_json::from_str::<serde_json::Value>(extra).ok())
+    {
+        Some(serde_json::Value::Object(extra)) => extra,
+        _ => return vec![],
+    };
+    let tags: Vec<String> = match extra.get("tags") {
+        Some(tags) => serde_json::from_value(tags.clone())
+            .unwrap_or_else(|_| env::panic_str("Tags must be a list of strings")),
+        None => return vec![],
+    };
+    require!(tags.len() <= MAX_TAGS_PER_TOKEN, "Too many tags");
+    for (index, tag) in tags.iter().enumerate() {
+        require!(
+            !tag.is_empty()
+                && tag.len() <= MAX_TAG_LEN
+                && tag
+                    .bytes()
+                    .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit()),
+            format!(
+                "Tag {:?} must be 1 to {} lowercase letters or digits",
+                tag, MAX_TAG_LEN
+            )
+        );
+        require!(!tags[..index].contains(tag), "Duplicate tag");
+    }
+    tags
+}
+
+impl Contract {
+    /// Tag the freshly minted `token_id` as listed in its `metadata`
+    pub(crate) fn internal_record_tags(&mut self, token_id: &TokenId, metadata: &TokenMetadata) {
+        let tags = metadata_tags(metadata);
+        if tags.is_empty() {
+            return;
+        }
+        for tag in &tags {
+            let mut token_ids = self.tag_index.get(tag).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::TagIndexPerTag {
+                    tag_hash: env::sha256(tag.as_bytes()),
+                })
+            });
+            token_ids.insert(token_id);
+            self.tag_index.insert(tag, &token_ids);
+        }
+        self.token_tags.insert(token_id, &tags);
+    }
+
+    /// Remove the tags of `token_id` and its index entries
+    pub(crate) fn internal_remove_tags(&mut self, token_id: &TokenId) {
+        for tag in self.token_tags.remove(token_id).unwrap_or_default() {
+            if let Some(mut token_ids) = self.tag_index.get(&tag) {
+                token_ids.remove(token_id);
+                if token_ids.is_empty() {
+                    self.tag_index.remove(&tag);
+                } else {
+                    self.tag_index.insert(&tag, &token_ids);
+                }
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn nft_token_tags(&self, token_id: TokenId) -> Vec<String> {
+        self.token_tags.get(&token_id).unwrap_or_default()
+    }
+
+    /// Tokens tagged `tag` as shown by `nft_token`
+    pub fn nft_tokens_by_tag(
+        &self,
+        tag: String,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let token_ids = match self.tag_index.get(&tag) {
+            Some(token_ids) => token_ids,
+            None => return vec![],
+        };
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        token_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .filter_map(|token_id| self.nft_token(token_id))
+            .collect()
+    }
+}
@@ -0,0 +1,47 @@
+/*!
+Faucet-style methods compiled only into testnet builds, `cargo build --features testnet`.
+The default (mainnet) wasm must not export any of them: `nft_mint_test` is expected in the
+exports of the testnet wasm only.
+*/
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::{refund_deposit_to_account, Token};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::events;
+use crate::{Contract, ContractExt, NFT_DESCRIPTION, NFT_NAME};
+
+#[near_bindgen]
+impl Contract {
+    /// Mint the next token with generated metadata to `receiver_id`. Anyone may call it,
+    /// only storage is charged
+    #[payable]
+    pub fn nft_mint_test(&mut self, receiver_id: AccountId) -> Token {
+        let token_id = self.next_curve_token_id();
+        let initial_storage = env::storage_usage();
+        let token = self.internal_mint_with_royalties(
+            token_id.clone(),
+            receiver_id.clone(),
+            TokenMetadata {
+                title: Some(format!("{} test #{}", NFT_NAME, token_id)),
+                description: Some(NFT_DESCRIPTION.into()),
+                media: None,
+                media_hash: None,
+                copies: Some(1u64),
+                issued_at: Some(format!("{}", env::block_timestamp() / 1_000_000_000u64)),
+                expires_at: None,
+                starts_at: None,
+                updated_at: None,
+                extra: None,
+                reference: None,
+                reference_hash: None,
+            },
+            None,
+        );
+        let storage_used = env::storage_usage() - initial_storage;
+        refund_deposit_to_account(storage_used, env::predecessor_account_id());
+        self.internal_audit("mint", Some(&token_id), None);
+        events::emit_mint(&receiver_id, std::slice::from_ref(&token_id), None);
+        events::emit_mint_receipt(&token, storage_used);
+        token
+    }
+}
@@ -0,0 +1,127 @@
+/*!
+Self-upgrade. The contract account holds no access keys, so new code is deployed by the
+contract itself: the owner stages it, anyone can verify its hash, and it can be deployed
+only once `UPGRADE_DELAY` has elapsed since staging. The owner confirms the hash of the code
+being deployed, so code restaged in the meantime can't slip through.
+
+`migrate` is private: only the deploy made by `upgrade` can call it.
+*/
+use near_contract_standards::non_fungible_token::refund_deposit_to_account;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, Base64VecU8};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, CryptoHash, Gas, Promise};
+
+use crate::events::{self, CodeDeployLog, CodeDiscardLog, CodeStageLog};
+use crate::storage::refund_released_storage;
+use crate::{Contract, ContractExt};
+
+/// Seconds between staging code and deploying it
+pub(crate) const UPGRADE_DELAY: u64 = 72 * 60 * 60;
+/// Raw storage key of the staged code, kept out of the contract state so it isn't
+/// deserialized on every call
+const STAGED_CODE_KEY: &[u8] = b"STAGED_CODE";
+const GAS_FOR_MIGRATE: Gas = Gas(100_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StagedCode {
+    /// SHA-256 of the code, as reported for a deployed contract
+    pub hash: Base58CryptoHash,
+    /// Block timestamp in seconds the code was staged at
+    pub staged_at: u64,
+    /// Block timestamp in seconds the code can be deployed from
+    pub deployable_at: u64,
+}
+
+impl Contract {
+    /// Remove the staged code, returns it along with the released storage
+    fn internal_discard_staged_code(&mut self) -> Option<(StagedCode, u64)> {
+        let staged_code = self.staged_code.take()?;
+        let initial_storage = env::storage_usage();
+        env::storage_remove(STAGED_CODE_KEY);
+        Some((staged_code, initial_storage - env::storage_usage()))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stage `code` to be deployed by `upgrade`, replacing the code staged before
+    /// and restarting the delay. Storage is paid by the owner
+    #[payable]
+    pub fn stage_code(&mut self, code: Base64VecU8) {
+        self.assert_owner();
+        let code: Vec<u8> = code.into();
+        require!(!code.is_empty(), "Code is empty");
+        if let Some((_, released)) = self.internal_discard_staged_code() {
+            refund_released_storage(released, env::predecessor_account_id());
+        }
+
+        let initial_storage = env::storage_usage();
+        let hash: CryptoHash = env::sha256(&code)
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("Invalid code hash"));
+        let staged_at = env::block_timestamp() / 1_000_000_000;
+        let staged_code = StagedCode {
+            hash: hash.into(),
+            staged_at,
+            deployable_at: staged_at + UPGRADE_DELAY,
+        };
+        env::storage_write(STAGED_CODE_KEY, &code);
+        self.staged_code = Some(staged_code.clone());
+        refund_deposit_to_account(
+            env::storage_usage() - initial_storage,
+            env::predecessor_account_id(),
+        );
+        self.internal_audit("stage_code", None, None);
+        events::emit(vec![CodeStageLog { staged_code }]);
+    }
+
+    /// Remove the staged code, its storage is refunded to the owner
+    pub fn discard_staged_code(&mut self) {
+        self.assert_owner();
+        let (staged_code, released) = self
+            .internal_discard_staged_code()
+            .unwrap_or_else(|| env::panic_str("No code staged"));
+        refund_released_storage(released, env::predecessor_account_id());
+        self.internal_audit("discard_staged_code", None, None);
+        events::emit(vec![CodeDiscardLog {
+            hash: staged_code.hash,
+        }]);
+    }
+
+    /// Deploy the staged code with `hash` to this contract and migrate the state.
+    /// The staged code storage is refunded to the owner and the build info is cleared
+    pub fn upgrade(&mut self, hash: Base58CryptoHash) -> Promise {
+        self.assert_owner();
+        let staged_code = self
+            .staged_code
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No code staged"));
+        require!(staged_code.hash == hash, "Staged code hash mismatch");
+        require!(
+            env::block_timestamp() / 1_000_000_000 >= staged_code.deployable_at,
+            "Upgrade delay hasn't elapsed"
+        );
+        let code =
+            env::storage_read(STAGED_CODE_KEY).unwrap_or_else(|| env::panic_str("No code staged"));
+        let (_, released) = self
+            .internal_discard_staged_code()
+            .unwrap_or_else(|| env::panic_str("No code staged"));
+        refund_released_storage(released, env::predecessor_account_id());
+        self.build_info = None;
+        self.internal_audit("upgrade", None, None);
+        events::emit(vec![CodeDeployLog {
+            hash: staged_code.hash,
+        }]);
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".into(), Vec::new(), 0, GAS_FOR_MIGRATE)
+    }
+
+    /// Code waiting to be deployed by `upgrade`, its hash lets anyone verify the code
+    /// before the delay elapses
+    pub fn staged_code(&self) -> Option<StagedCode> {
+        self.staged_code.clone()
+    }
+}
@@ -0,0 +1,114 @@
+//! `nft_transfer_call` to the reference receiver contract, one test per `msg` it understands
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+const NFT_WASM: &str = "../res/ukrainian_magicals_nft.wasm";
+const RECEIVER_WASM: &str = "../res/receiver_example.wasm";
+
+/// NFT contract with token "0" of alice approved for bob, and the receiver contract
+async fn setup() -> anyhow::Result<(Contract, Contract, Account, Account)> {
+    let worker = near_workspaces::sandbox().await?;
+    let nft = worker.dev_deploy(&std::fs::read(NFT_WASM)?).await?;
+    nft.call("new").transact().await?.into_result()?;
+    let receiver = worker.dev_deploy(&std::fs::read(RECEIVER_WASM)?).await?;
+    receiver
+        .call("new")
+        .args_json(json!({ "nft_account_id": nft.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    nft.call("nft_mint")
+        .args_json(json!({
+            "token_id": "0",
+            "token_owner_id": alice.id(),
+            "token_metadata": { "title": "Olympus Mons" },
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .transact()
+        .await?
+        .into_result()?;
+    alice
+        .call(nft.id(), "nft_approve")
+        .args_json(json!({ "token_id": "0", "account_id": bob.id() }))
+        .deposit(NearToken::from_millinear(10))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok((nft, receiver, alice, bob))
+}
+
+/// Send token "0" from alice to the receiver with `msg`, returns whether it was transferred
+async fn transfer_call(
+    nft: &Contract,
+    receiver: &Contract,
+    alice: &Account,
+    msg: &str,
+) -> anyhow::Result<bool> {
+    let transferred = alice
+        .call(nft.id(), "nft_transfer_call")
+        .args_json(json!({
+            "receiver_id": receiver.id(),
+            "token_id": "0",
+            "msg": msg,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    Ok(transferred)
+}
+
+async fn owner_of(nft: &Contract) -> anyhow::Result<String> {
+    let token: serde_json::Value = nft
+        .view("nft_token")
+        .args_json(json!({ "token_id": "0" }))
+        .await?
+        .json()?;
+    Ok(token["owner_id"].as_str().unwrap_or_default().to_string())
+}
+
+async fn is_approved(nft: &Contract, account: &Account) -> anyhow::Result<bool> {
+    Ok(nft
+        .view("nft_is_approved")
+        .args_json(json!({ "token_id": "0", "approved_account_id": account.id() }))
+        .await?
+        .json()?)
+}
+
+#[tokio::test]
+async fn test_receiver_keeps_token() -> anyhow::Result<()> {
+    let (nft, receiver, alice, bob) = setup().await?;
+    assert!(transfer_call(&nft, &receiver, &alice, "keep").await?);
+    assert_eq!(owner_of(&nft).await?, receiver.id().as_str());
+    assert!(!is_approved(&nft, &bob).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_receiver_returns_token() -> anyhow::Result<()> {
+    let (nft, receiver, alice, bob) = setup().await?;
+    assert!(!transfer_call(&nft, &receiver, &alice, "return").await?);
+    assert_eq!(owner_of(&nft).await?, alice.id().as_str());
+    // approvals cleared by the transfer are restored
+    assert!(is_approved(&nft, &bob).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_receiver_panics() -> anyhow::Result<()> {
+    let (nft, receiver, alice, bob) = setup().await?;
+    let balance = alice.view_account().await?.balance;
+    assert!(!transfer_call(&nft, &receiver, &alice, "panic").await?);
+    assert_eq!(owner_of(&nft).await?, alice.id().as_str());
+    assert!(is_approved(&nft, &bob).await?);
+    // the deposit comes back apart from gas
+    let spent = balance.saturating_sub(alice.view_account().await?.balance);
+    assert!(spent < NearToken::from_millinear(10));
+    Ok(())
+}
@@ -0,0 +1,97 @@
+/*!
+Reference contract receiving Ukrainian Magicals tokens through `nft_transfer_call`.
+
+The `msg` of the transfer picks what happens to the token:
+- `keep`: the token stays with this contract
+- `return`: the token goes back to its previous owner
+- `panic`: the call fails, so the token goes back as well
+*/
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{env, near_bindgen, require, AccountId, PanicOnDefault, PromiseOrValue};
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Receiver {
+    /// The only NFT contract tokens are accepted from
+    nft_account_id: AccountId,
+}
+
+#[near_bindgen]
+impl Receiver {
+    #[init]
+    pub fn new(nft_account_id: AccountId) -> Self {
+        Self { nft_account_id }
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for Receiver {
+    /// Returns whether the token has to be returned to `previous_owner_id`
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        require!(
+            env::predecessor_account_id() == self.nft_account_id,
+            "Only tokens of the configured NFT contract are accepted"
+        );
+        env::log_str(&format!(
+            "Received token {} of {} sent by {} with msg {}",
+            token_id, previous_owner_id, sender_id, msg
+        ));
+        match msg.as_str() {
+            "keep" => PromiseOrValue::Value(false),
+            "return" => PromiseOrValue::Value(true),
+            "panic" => env::panic_str("Refusing the token"),
+            _ => env::panic_str("Unknown msg, expected keep, return or panic"),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    fn on_transfer(msg: &str) -> bool {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut receiver = Receiver::new(accounts(0));
+        match receiver.nft_on_transfer(accounts(1), accounts(1), "0".to_string(), msg.into()) {
+            PromiseOrValue::Value(return_token) => return_token,
+            PromiseOrValue::Promise(_) => panic!("Unexpected promise"),
+        }
+    }
+
+    #[test]
+    fn test_keep() {
+        assert!(!on_transfer("keep"));
+    }
+
+    #[test]
+    fn test_return() {
+        assert!(on_transfer("return"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Refusing the token")]
+    fn test_panic() {
+        on_transfer("panic");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only tokens of the configured NFT contract are accepted")]
+    fn test_other_nft_contract() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let mut receiver = Receiver::new(accounts(0));
+        let _ = receiver.nft_on_transfer(accounts(1), accounts(1), "0".to_string(), "keep".into());
+    }
+}